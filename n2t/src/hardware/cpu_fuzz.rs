@@ -0,0 +1,335 @@
+//! Differential fuzzing of two `HackCpu` implementations.
+//!
+//! `fuzz` generates random, valid Hack programs and runs each one lock-step on both CPUs. After every cycle the
+//! registers and the RAM cell the instruction could have written are compared, so the first cycle where the two
+//! disagree is known exactly. The diverging program is then shrunk by dropping instructions (and simplifying jumps)
+//! for as long as the CPUs still disagree, which usually leaves a reproducer of a handful of instructions.
+//!
+//! A run stops early once the PC leaves the program, ROM past the end is not part of what's being compared. It also
+//! stops before an instruction reads or writes M at the keyboard or past it: the gate-level computer maps those to the
+//! read-only keyboard, the native one to plain RAM.
+
+use crate::{hardware::hack_cpu::HackCpu, KEYBOARD};
+use std::fmt;
+
+/// The computations a C-instruction can select, as the 7 `a c1..c6` bits
+const COMPUTATIONS: [u16; 28] = [
+    0b0_101010, // 0
+    0b0_111111, // 1
+    0b0_111010, // -1
+    0b0_001100, // D
+    0b0_110000, // A
+    0b0_001101, // !D
+    0b0_110001, // !A
+    0b0_001111, // -D
+    0b0_110011, // -A
+    0b0_011111, // D+1
+    0b0_110111, // A+1
+    0b0_001110, // D-1
+    0b0_110010, // A-1
+    0b0_000010, // D+A
+    0b0_010011, // D-A
+    0b0_000111, // A-D
+    0b0_000000, // D&A
+    0b0_010101, // D|A
+    0b1_110000, // M
+    0b1_110001, // !M
+    0b1_110011, // -M
+    0b1_110111, // M+1
+    0b1_110010, // M-1
+    0b1_000010, // D+M
+    0b1_010011, // D-M
+    0b1_000111, // M-D
+    0b1_000000, // D&M
+    0b1_010101, // D|M
+];
+
+/// xorshift, good enough to generate random programs
+#[derive(Debug, Clone)]
+pub struct Rng(u32);
+
+impl Rng {
+    /// `seed` must not be 0
+    pub fn new(seed: u32) -> Self {
+        assert_ne!(seed, 0, "xorshift can't be seeded with 0");
+        Rng(seed)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+
+    /// A number in `0..n`
+    pub fn below(&mut self, n: u32) -> u32 {
+        self.next_u32() % n
+    }
+}
+
+/// A random program of `len` valid Hack instructions. A-instructions mostly load small addresses so the program keeps
+/// reading and writing the same few RAM cells, or addresses inside the program so jumps land somewhere. Addresses stay
+/// below the keyboard: it's read-only on the gate-level computer and plain RAM on the native one.
+pub fn random_program(rng: &mut Rng, len: usize) -> Vec<u16> {
+    (0..len)
+        .map(|_| match rng.below(10) {
+            0..=1 => rng.below(16) as u16,
+            2 => rng.below(len as u32) as u16,
+            3 => rng.below(KEYBOARD as u32) as u16,
+            _ => {
+                let comp = COMPUTATIONS[rng.below(COMPUTATIONS.len() as u32) as usize];
+                let dest = rng.below(8) as u16;
+                let jump = if rng.below(4) == 0 {
+                    rng.below(8) as u16
+                } else {
+                    0
+                };
+                0b111 << 13 | comp << 6 | dest << 3 | jump
+            }
+        })
+        .collect()
+}
+
+/// The registers after a cycle, and the RAM cell the instruction could have written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuState {
+    pub a: u16,
+    pub d: u16,
+    pub pc: u16,
+    pub address: u16,
+    pub m: u16,
+}
+
+impl CpuState {
+    fn read(cpu: &impl HackCpu, address: u16) -> Self {
+        CpuState {
+            a: cpu.a(),
+            d: cpu.d(),
+            pc: cpu.pc(),
+            address,
+            m: cpu.ram(address),
+        }
+    }
+}
+
+impl fmt::Display for CpuState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (a, d, pc, address, m) = (self.a, self.d, self.pc, self.address, self.m);
+        write!(f, "A={a} D={d} PC={pc} RAM[{address}]={m}")
+    }
+}
+
+/// The first cycle after which the two CPUs disagree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// counting from 1
+    pub cycle: usize,
+    /// PC of the instruction executed in that cycle
+    pub pc: u16,
+    pub instruction: u16,
+    pub left: CpuState,
+    pub right: CpuState,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "cycle {} executed `{}` at PC {}",
+            self.cycle,
+            disassemble(self.instruction),
+            self.pc
+        )?;
+        writeln!(f, "    left:  {}", self.left)?;
+        write!(f, "    right: {}", self.right)
+    }
+}
+
+/// A diverging random program and its minimized version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub left: &'static str,
+    pub right: &'static str,
+    pub seed: u32,
+    pub program: Vec<u16>,
+    pub divergence: Divergence,
+    pub reproducer: Vec<u16>,
+    pub reproducer_divergence: Divergence,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} and {} diverge", self.left, self.right)?;
+        writeln!(
+            f,
+            "random program of {} instructions (seed {}), {}",
+            self.program.len(),
+            self.seed,
+            self.divergence
+        )?;
+        write!(
+            f,
+            "minimized to {} instructions, {}",
+            self.reproducer.len(),
+            self.reproducer_divergence
+        )?;
+        for (i, &instruction) in self.reproducer.iter().enumerate() {
+            write!(
+                f,
+                "\n{i:>5}: {instruction:016b}  {}",
+                disassemble(instruction)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `program` on a fresh `L` and `R` side by side for up to `cycles` cycles, or until the PC leaves the program
+pub fn run_lockstep<L: HackCpu, R: HackCpu>(program: &[u16], cycles: usize) -> Option<Divergence> {
+    let mut left = L::with_program(program.to_vec());
+    let mut right = R::with_program(program.to_vec());
+
+    for cycle in 1..=cycles {
+        // both CPUs agreed so far, so the PC and A are the same on both
+        let pc = left.pc();
+        let &instruction = program.get(pc as usize)?;
+        let address = left.a() & 0b0111_1111_1111_1111;
+        // a C-instruction with the `a` bit or the M destination set
+        let uses_m = instruction >> 15 == 1 && instruction & 0b0001_0000_0000_1000 != 0;
+        if uses_m && address >= KEYBOARD as u16 {
+            return None;
+        }
+
+        left.step();
+        right.step();
+
+        let (l, r) = (
+            CpuState::read(&left, address),
+            CpuState::read(&right, address),
+        );
+        if l != r {
+            return Some(Divergence {
+                cycle,
+                pc,
+                instruction,
+                left: l,
+                right: r,
+            });
+        }
+    }
+
+    None
+}
+
+/// Shrinks a diverging program: drops chunks of instructions, halving the chunk size down to single instructions, then
+/// removes the jumps that aren't needed, as long as the CPUs still disagree
+pub fn minimize<L: HackCpu, R: HackCpu>(program: &[u16], cycles: usize) -> Vec<u16> {
+    let diverges = |program: &[u16]| run_lockstep::<L, R>(program, cycles).is_some();
+    assert!(diverges(program), "the program doesn't diverge");
+
+    let mut program = program.to_vec();
+    let mut chunk = program.len().div_ceil(2);
+    while chunk > 0 {
+        let mut start = 0;
+        while start < program.len() {
+            let mut candidate = program.clone();
+            candidate.drain(start..(start + chunk).min(program.len()));
+            if !candidate.is_empty() && diverges(&candidate) {
+                program = candidate;
+            } else {
+                start += chunk;
+            }
+        }
+        chunk /= 2;
+    }
+
+    for i in 0..program.len() {
+        if program[i] & 0b1000_0000_0000_0111 > 0b1000_0000_0000_0000 {
+            let mut candidate = program.clone();
+            candidate[i] &= !0b111;
+            if diverges(&candidate) {
+                program = candidate;
+            }
+        }
+    }
+
+    program
+}
+
+/// Runs `programs` random programs of `len` instructions, for up to `cycles` cycles each, on `L` and `R`. Reports the
+/// first one they disagree on.
+pub fn fuzz<L: HackCpu, R: HackCpu>(
+    seed: u32,
+    programs: usize,
+    len: usize,
+    cycles: usize,
+) -> Result<(), Box<Report>> {
+    let mut rng = Rng::new(seed);
+
+    for _ in 0..programs {
+        let program = random_program(&mut rng, len);
+        let Some(divergence) = run_lockstep::<L, R>(&program, cycles) else {
+            continue;
+        };
+
+        let reproducer = minimize::<L, R>(&program, cycles);
+        let reproducer_divergence = run_lockstep::<L, R>(&reproducer, cycles).unwrap();
+        return Err(Box::new(Report {
+            left: std::any::type_name::<L>(),
+            right: std::any::type_name::<R>(),
+            seed,
+            program,
+            divergence,
+            reproducer,
+            reproducer_divergence,
+        }));
+    }
+
+    Ok(())
+}
+
+/// Hack assembly for a single instruction
+pub fn disassemble(instruction: u16) -> String {
+    if instruction & 0b1000_0000_0000_0000 == 0 {
+        return format!("@{instruction}");
+    }
+
+    let comp = match (instruction >> 6) & 0b111_1111 {
+        0b0_101010 => "0",
+        0b0_111111 => "1",
+        0b0_111010 => "-1",
+        0b0_001100 => "D",
+        0b0_110000 => "A",
+        0b0_001101 => "!D",
+        0b0_110001 => "!A",
+        0b0_001111 => "-D",
+        0b0_110011 => "-A",
+        0b0_011111 => "D+1",
+        0b0_110111 => "A+1",
+        0b0_001110 => "D-1",
+        0b0_110010 => "A-1",
+        0b0_000010 => "D+A",
+        0b0_010011 => "D-A",
+        0b0_000111 => "A-D",
+        0b0_000000 => "D&A",
+        0b0_010101 => "D|A",
+        0b1_110000 => "M",
+        0b1_110001 => "!M",
+        0b1_110011 => "-M",
+        0b1_110111 => "M+1",
+        0b1_110010 => "M-1",
+        0b1_000010 => "D+M",
+        0b1_010011 => "D-M",
+        0b1_000111 => "M-D",
+        0b1_000000 => "D&M",
+        0b1_010101 => "D|M",
+        _ => return format!("{instruction:016b}"),
+    };
+    let dest =
+        ["", "M=", "D=", "MD=", "A=", "AM=", "AD=", "AMD="][(instruction >> 3) as usize & 0b111];
+    let jump =
+        ["", ";JGT", ";JEQ", ";JGE", ";JLT", ";JNE", ";JLE", ";JMP"][instruction as usize & 0b111];
+
+    format!("{dest}{comp}{jump}")
+}
@@ -0,0 +1,47 @@
+//! The interface shared by the Hack CPU implementations, so the gate-level and native computers can be driven by the
+//! same code (see `cpu_fuzz` and `vcd`).
+
+use crate::hardware::logic_gate::alu::ControlBits;
+
+/// A Hack computer: the CPU with its ROM and RAM. Every implementation follows the Hack CPU spec, an instruction reads
+/// and writes `RAM[A]` and jumps to `A` as they were before the instruction executed.
+pub trait HackCpu {
+    /// A computer with `program` in ROM, the registers and RAM zeroed
+    fn with_program(program: Vec<u16>) -> Self
+    where
+        Self: Sized;
+
+    /// Executes the instruction at the PC, one clock cycle
+    fn step(&mut self);
+
+    /// Sets the PC to 0 without executing anything, the registers and RAM keep their values
+    fn reset(&mut self);
+
+    fn a(&self) -> u16;
+    fn d(&self) -> u16;
+    fn pc(&self) -> u16;
+
+    /// `RAM[address]`, only the low 15 bits of the address are used
+    fn ram(&self, address: u16) -> u16;
+    fn set_ram(&mut self, address: u16, value: u16);
+
+    /// The CPU's pins during the last cycle
+    fn signals(&self) -> CpuSignals;
+}
+
+/// What the CPU chip's pins and internal lines carried during a cycle: the registers are read after the clock edge,
+/// everything else is what the instruction computed before it
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CpuSignals {
+    pub a: u16,
+    pub d: u16,
+    pub pc: u16,
+    /// the ALU's control inputs from the instruction, and its `zr`/`ng` outputs
+    pub control: ControlBits,
+    /// ALU output
+    pub out_m: u16,
+    /// the 15 bit RAM address
+    pub address_m: u16,
+    /// the RAM's load line
+    pub write_m: u8,
+}
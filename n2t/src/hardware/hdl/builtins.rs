@@ -0,0 +1,340 @@
+//! Chips the HDL simulator can run without an .hdl file, implemented with the `logic_gate` functions. A part whose
+//! chip has no .hdl file next to the chip using it falls back to these, the same way the official simulator falls
+//! back to its `builtInChips` folder.
+//!
+//! `Nand` and `DFF` are the primitives everything else is elaborated down to, the rest only exist so chips can be
+//! built and tested one project at a time. The clocked ones keep their state in the `memory` chips, the RAMs in the
+//! `RAM8`..`RAM16K` of the same size, and the computer's in `Screen`, `Keyboard`, `ROM32K`, `Memory` and, for the CPU,
+//! the registers `cpu::Computer` has.
+
+use crate::hardware::{
+    hdl::parser::{parse_hdl, Chip},
+    logic_gate::{
+        alu::{self, ControlBits},
+        arithmetic::{adder, full_adder, half_adder, incrementer, is_zero},
+        cpu::CpuOutputs,
+        gates::*,
+        memory,
+    },
+};
+use crate::utils::int_from_bitvec;
+use lazy_static::lazy_static;
+use std::{collections::HashMap, str::FromStr};
+use strum_macros::EnumString;
+
+#[derive(Debug, Clone, Copy, EnumString, strum_macros::Display, PartialEq, Eq, Hash)]
+pub enum Builtin {
+    Nand,
+    DFF,
+    Not,
+    And,
+    Or,
+    Xor,
+    Mux,
+    DMux,
+    Not16,
+    And16,
+    Or16,
+    Mux16,
+    Or8Way,
+    Mux4Way16,
+    Mux8Way16,
+    DMux4Way,
+    DMux8Way,
+    HalfAdder,
+    FullAdder,
+    Add16,
+    Inc16,
+    ALU,
+    Bit,
+    Register,
+    ARegister,
+    DRegister,
+    PC,
+    RAM8,
+    RAM64,
+    RAM512,
+    RAM4K,
+    RAM16K,
+    Screen,
+    Keyboard,
+    ROM32K,
+    Memory,
+    CPU,
+}
+
+/// Interfaces of the builtin chips, pins are passed to `Builtin::eval` in the order they're declared here
+const INTERFACES: &str = "
+CHIP Nand { IN a, b; OUT out; BUILTIN Nand; }
+CHIP DFF { IN in; OUT out; BUILTIN DFF; CLOCKED in; }
+CHIP Not { IN in; OUT out; BUILTIN Not; }
+CHIP And { IN a, b; OUT out; BUILTIN And; }
+CHIP Or { IN a, b; OUT out; BUILTIN Or; }
+CHIP Xor { IN a, b; OUT out; BUILTIN Xor; }
+CHIP Mux { IN a, b, sel; OUT out; BUILTIN Mux; }
+CHIP DMux { IN in, sel; OUT a, b; BUILTIN DMux; }
+CHIP Not16 { IN in[16]; OUT out[16]; BUILTIN Not16; }
+CHIP And16 { IN a[16], b[16]; OUT out[16]; BUILTIN And16; }
+CHIP Or16 { IN a[16], b[16]; OUT out[16]; BUILTIN Or16; }
+CHIP Mux16 { IN a[16], b[16], sel; OUT out[16]; BUILTIN Mux16; }
+CHIP Or8Way { IN in[8]; OUT out; BUILTIN Or8Way; }
+CHIP Mux4Way16 { IN a[16], b[16], c[16], d[16], sel[2]; OUT out[16]; BUILTIN Mux4Way16; }
+CHIP Mux8Way16 {
+    IN a[16], b[16], c[16], d[16], e[16], f[16], g[16], h[16], sel[3];
+    OUT out[16];
+    BUILTIN Mux8Way16;
+}
+CHIP DMux4Way { IN in, sel[2]; OUT a, b, c, d; BUILTIN DMux4Way; }
+CHIP DMux8Way { IN in, sel[3]; OUT a, b, c, d, e, f, g, h; BUILTIN DMux8Way; }
+CHIP HalfAdder { IN a, b; OUT sum, carry; BUILTIN HalfAdder; }
+CHIP FullAdder { IN a, b, c; OUT sum, carry; BUILTIN FullAdder; }
+CHIP Add16 { IN a[16], b[16]; OUT out[16]; BUILTIN Add16; }
+CHIP Inc16 { IN in[16]; OUT out[16]; BUILTIN Inc16; }
+CHIP ALU { IN x[16], y[16], zx, nx, zy, ny, f, no; OUT out[16], zr, ng; BUILTIN ALU; }
+CHIP Bit { IN in, load; OUT out; BUILTIN Bit; CLOCKED in, load; }
+CHIP Register { IN in[16], load; OUT out[16]; BUILTIN Register; CLOCKED in, load; }
+CHIP ARegister { IN in[16], load; OUT out[16]; BUILTIN ARegister; CLOCKED in, load; }
+CHIP DRegister { IN in[16], load; OUT out[16]; BUILTIN DRegister; CLOCKED in, load; }
+CHIP PC { IN in[16], load, inc, reset; OUT out[16]; BUILTIN PC; CLOCKED in, load, inc, reset; }
+CHIP RAM8 { IN in[16], load, address[3]; OUT out[16]; BUILTIN RAM8; CLOCKED in, load; }
+CHIP RAM64 { IN in[16], load, address[6]; OUT out[16]; BUILTIN RAM64; CLOCKED in, load; }
+CHIP RAM512 { IN in[16], load, address[9]; OUT out[16]; BUILTIN RAM512; CLOCKED in, load; }
+CHIP RAM4K { IN in[16], load, address[12]; OUT out[16]; BUILTIN RAM4K; CLOCKED in, load; }
+CHIP RAM16K { IN in[16], load, address[14]; OUT out[16]; BUILTIN RAM16K; CLOCKED in, load; }
+CHIP Screen { IN in[16], load, address[13]; OUT out[16]; BUILTIN Screen; CLOCKED in, load; }
+CHIP Keyboard { OUT out[16]; BUILTIN Keyboard; }
+CHIP ROM32K { IN address[15]; OUT out[16]; BUILTIN ROM32K; }
+CHIP Memory { IN in[16], load, address[15]; OUT out[16]; BUILTIN Memory; CLOCKED in, load; }
+CHIP CPU {
+    IN inM[16], instruction[16], reset;
+    OUT outM[16], writeM, addressM[15], pc[15];
+    BUILTIN CPU;
+    CLOCKED inM, instruction, reset;
+}
+";
+
+lazy_static! {
+    static ref CHIPS: HashMap<Builtin, Chip> = INTERFACES
+        .split_inclusive("}\n")
+        .map(|source| {
+            let source = source.trim();
+            let chip = parse_hdl("builtin.hdl", source).unwrap();
+            (Builtin::from_str(&chip.name).unwrap(), chip)
+        })
+        .collect();
+}
+
+/// Memory of a clocked builtin
+#[derive(Debug, Clone)]
+pub enum State {
+    None,
+    Bit(memory::DFF),
+    Register(memory::Register),
+    PC(memory::InstPtr),
+    RAM8(memory::RAM8),
+    RAM64(Box<memory::RAM64>),
+    RAM512(Box<memory::RAM512>),
+    RAM4K(Box<memory::RAM4K>),
+    RAM16K(Box<memory::RAM16K>),
+    Screen(Box<memory::Screen>),
+    Keyboard(memory::Keyboard),
+    /// empty until a program is loaded, see `Simulator::set_rom`
+    ROM32K(memory::ROM32K),
+    Memory(memory::Memory),
+    /// A, D and the PC
+    CPU(memory::Register, memory::Register, memory::InstPtr),
+}
+
+/// `width` bits of `x`, most significant first like the `logic_gate` functions expect
+fn bits(x: u16, width: usize) -> Vec<u8> {
+    (0..width).rev().map(|i| (x >> i & 1) as u8).collect()
+}
+
+fn word(bits: &[u8]) -> u16 {
+    int_from_bitvec(bits)
+}
+
+impl Builtin {
+    /// Pins of the chip, as if it had been read from a `BUILTIN` .hdl file
+    pub fn interface(&self) -> &'static Chip {
+        &CHIPS[self]
+    }
+
+    pub fn new_state(&self) -> State {
+        use Builtin::*;
+        match self {
+            DFF | Bit => State::Bit(memory::DFF::new()),
+            Register | ARegister | DRegister => State::Register(memory::Register::new()),
+            PC => State::PC(memory::InstPtr::new()),
+            RAM8 => State::RAM8(memory::RAM8::new()),
+            RAM64 => State::RAM64(Box::new(memory::RAM64::new())),
+            RAM512 => State::RAM512(Box::new(memory::RAM512::new())),
+            RAM4K => State::RAM4K(Box::new(memory::RAM4K::new())),
+            RAM16K => State::RAM16K(Box::new(memory::RAM16K::new())),
+            Screen => State::Screen(Box::new(memory::Screen::new())),
+            Keyboard => State::Keyboard(memory::Keyboard::new()),
+            ROM32K => State::ROM32K(memory::ROM32K::new()),
+            Memory => State::Memory(memory::Memory::new()),
+            CPU => State::CPU(
+                memory::Register::new(),
+                memory::Register::new(),
+                memory::InstPtr::new(),
+            ),
+            _ => State::None,
+        }
+    }
+
+    /// True if outputs depend on inputs declared `CLOCKED`. The CPU's `outM` follows `inM` and the instruction, they
+    /// are only clocked so a computer's CPU, memory and ROM don't form a combinational loop.
+    pub fn reads_clocked_inputs(&self) -> bool {
+        matches!(self, Builtin::CPU)
+    }
+
+    /// Values of the output pins given the input pins, bit `i` of a value is pin `[i]`
+    pub fn eval(&self, inputs: &[u16], state: &State) -> Vec<u16> {
+        use Builtin::*;
+        let bit = |i: usize| inputs[i] as u8;
+        let bus = |i: usize| bits(inputs[i], 16);
+
+        match (self, state) {
+            (Nand, _) => vec![NAND(bit(0), bit(1)) as u16],
+            (Not, _) => vec![NOT(bit(0)) as u16],
+            (And, _) => vec![AND(bit(0), bit(1)) as u16],
+            (Or, _) => vec![OR(bit(0), bit(1)) as u16],
+            (Xor, _) => vec![XOR(bit(0), bit(1)) as u16],
+            (Mux, _) => vec![MUX(bit(0), bit(1), bit(2)) as u16],
+            (DMux, _) => DEMUX(bit(0), bit(1)).map(u16::from).to_vec(),
+            (Not16, _) => vec![word(&multi_NOT(&bus(0)))],
+            (And16, _) => vec![word(&multi_AND(&bus(0), &bus(1)))],
+            (Or16, _) => vec![word(&multi_OR(&bus(0), &bus(1)))],
+            (Mux16, _) => vec![word(&multi_MUX(&bus(0), &bus(1), bit(2)))],
+            (Or8Way, _) => vec![NOT(is_zero(&bits(inputs[0], 8))) as u16],
+            (Mux4Way16, _) => {
+                let sel = bits(inputs[4], 2);
+                let out = multi_MUX_4(&bus(0), &bus(1), &bus(2), &bus(3), sel[0], sel[1]);
+                vec![word(&out)]
+            }
+            (Mux8Way16, _) => {
+                let sel = bits(inputs[8], 3);
+                let out = multi_MUX_8(
+                    &bus(0),
+                    &bus(1),
+                    &bus(2),
+                    &bus(3),
+                    &bus(4),
+                    &bus(5),
+                    &bus(6),
+                    &bus(7),
+                    sel[0],
+                    sel[1],
+                    sel[2],
+                );
+                vec![word(&out)]
+            }
+            (DMux4Way, _) => {
+                let sel = bits(inputs[1], 2);
+                DEMUX_4(bit(0), sel[0], sel[1]).map(u16::from).to_vec()
+            }
+            (DMux8Way, _) => {
+                let sel = bits(inputs[1], 3);
+                DEMUX_8(bit(0), sel[0], sel[1], sel[2])
+                    .map(u16::from)
+                    .to_vec()
+            }
+            (HalfAdder, _) => {
+                let add = half_adder(bit(0), bit(1));
+                vec![add.sum as u16, add.carry as u16]
+            }
+            (FullAdder, _) => {
+                let add = full_adder(bit(0), bit(1), bit(2));
+                vec![add.sum as u16, add.carry as u16]
+            }
+            (Add16, _) => vec![word(&adder(&bus(0), &bus(1)))],
+            (Inc16, _) => vec![word(&incrementer(&bus(0)))],
+            (ALU, _) => {
+                let mut control = ControlBits {
+                    zx: bit(2),
+                    nx: bit(3),
+                    zy: bit(4),
+                    ny: bit(5),
+                    f: bit(6),
+                    no: bit(7),
+                    ..ControlBits::new()
+                };
+                let out = alu::ALU(&bus(0), &bus(1), &mut control);
+                // the flags come straight from the output, most significant bit first
+                vec![word(&out), is_zero(&out) as u16, out[0] as u16]
+            }
+            (DFF | Bit, State::Bit(dff)) => vec![dff.data as u16],
+            (Register | ARegister | DRegister, State::Register(register)) => {
+                vec![register.data]
+            }
+            (PC, State::PC(pc)) => vec![pc.val.data],
+            // reading a memory doesn't need the clock, `peek` is the Mux tree picking the addressed register
+            (RAM8, State::RAM8(ram)) => vec![ram.peek(inputs[2])],
+            (RAM64, State::RAM64(ram)) => vec![ram.peek(inputs[2])],
+            (RAM512, State::RAM512(ram)) => vec![ram.peek(inputs[2])],
+            (RAM4K, State::RAM4K(ram)) => vec![ram.peek(inputs[2])],
+            (RAM16K, State::RAM16K(ram)) => vec![ram.peek(inputs[2])],
+            (Screen, State::Screen(screen)) => vec![screen.peek(inputs[2])],
+            (Keyboard, State::Keyboard(keyboard)) => vec![keyboard.out()],
+            (ROM32K, State::ROM32K(rom)) => vec![rom.peek(inputs[0])],
+            (Memory, State::Memory(memory)) => vec![memory.peek(inputs[2])],
+            (CPU, State::CPU(a, d, pc)) => {
+                let out = CpuOutputs::new(a.data, d.data, inputs[0], inputs[1]);
+                vec![
+                    out.out_m,
+                    out.write_m as u16,
+                    out.address_m,
+                    pc.val.data & 0b0111_1111_1111_1111,
+                ]
+            }
+            (chip, state) => unreachable!("{chip} has no {state:?}"),
+        }
+    }
+
+    /// Clocks the chip with the inputs it read on the rising edge
+    pub fn tock(&self, inputs: &[u16], state: &mut State) {
+        use Builtin::*;
+        let bit = |i: usize| inputs[i] as u8;
+
+        match (self, state) {
+            (DFF, State::Bit(dff)) => dff.cycle(bit(0), 1),
+            (Bit, State::Bit(dff)) => dff.cycle(bit(0), bit(1)),
+            (Register | ARegister | DRegister, State::Register(register)) => {
+                register.cycle(inputs[0], bit(1))
+            }
+            (PC, State::PC(pc)) => {
+                pc.cycle(inputs[0], bit(1), bit(2), bit(3));
+            }
+            (RAM8, State::RAM8(ram)) => {
+                ram.cycle(inputs[0], inputs[2], bit(1));
+            }
+            (RAM64, State::RAM64(ram)) => {
+                ram.cycle(inputs[0], inputs[2], bit(1));
+            }
+            (RAM512, State::RAM512(ram)) => {
+                ram.cycle(inputs[0], inputs[2], bit(1));
+            }
+            (RAM4K, State::RAM4K(ram)) => {
+                ram.cycle(inputs[0], inputs[2], bit(1));
+            }
+            (RAM16K, State::RAM16K(ram)) => {
+                ram.cycle(inputs[0], inputs[2], bit(1));
+            }
+            (Screen, State::Screen(screen)) => {
+                screen.cycle(inputs[0], inputs[2], bit(1));
+            }
+            (Memory, State::Memory(memory)) => {
+                memory.cycle(inputs[0], inputs[2], bit(1));
+            }
+            (CPU, State::CPU(a, d, pc)) => {
+                let out = CpuOutputs::new(a.data, d.data, inputs[0], inputs[1]);
+                out.clock(a, d, pc, bit(2));
+            }
+            // the keyboard is set from outside and the ROM can't be written
+            (_, State::None | State::Keyboard(_) | State::ROM32K(_)) => {}
+            (chip, state) => unreachable!("{chip} has no {state:?}"),
+        }
+    }
+}
@@ -0,0 +1,27 @@
+//! The `logic_gate` chips in HDL, part for part down to `Nand` and `DFF`. The .hdl files are in `n2t/hdl`, `cost`
+//! counts their gates and `timing` simulates them gate by gate. Only `Keyboard` and `ROM32K` are left builtin, like
+//! in the book.
+
+use crate::hardware::hdl::simulator::Simulator;
+
+macro_rules! chips {
+    ($($chip:literal),* $(,)?) => {
+        [$((concat!($chip, ".hdl"), include_str!(concat!("../../../hdl/", $chip, ".hdl")))),*]
+    };
+}
+
+/// (file name, source) of every chip, the way `Simulator::from_sources` takes them
+#[rustfmt::skip]
+pub const CHIPS: [(&str, &str); 38] = chips![
+    "Not", "And", "Or", "Xor", "Mux", "DMux", "Mux4Way", "Mux8Way", "DMux4Way", "DMux8Way",
+    "Not16", "And16", "Xor16", "Mux16", "Mux4Way16", "Mux8Way16",
+    "HalfAdder", "FullAdder", "Add16", "Inc16", "LookaheadAdd16", "IsZero16",
+    "ALU", "LookaheadALU", "MuxALU",
+    "Bit", "Register", "PC", "RAM8", "RAM64", "RAM512", "RAM4K", "RAM16K", "RAM32K", "Screen", "Memory",
+    "CPU", "Computer",
+];
+
+/// `chip` elaborated from the library
+pub fn elaborate(chip: &str) -> Simulator {
+    Simulator::from_sources(chip, &CHIPS).unwrap_or_else(|e| panic!("{}", e.summary()))
+}
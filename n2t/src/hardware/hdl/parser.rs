@@ -0,0 +1,434 @@
+//! .hdl -> chip definition. Reads the nand2tetris hardware description language:
+//!
+//! ```text
+//! CHIP Mux16 {
+//!     IN a[16], b[16], sel;
+//!     OUT out[16];
+//!
+//!     PARTS:
+//!     Not(in=sel, out=notsel);
+//!     And16(a=a, b[0..15]=true, out=x);
+//!     ...
+//! }
+//! ```
+//!
+//! Chips the simulator implements natively say `BUILTIN Name;` in place of (or after) their parts, and list the inputs
+//! that only take effect on the clock with `CLOCKED a, b;`. Parsing stops at the first error.
+
+use crate::software::diagnostics::{Diagnostic, Span};
+
+/// A chip's input or output pin, `width` is 1 for single bit pins
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Port {
+    pub name: String,
+    pub width: usize,
+    pub span: Span,
+}
+
+/// `name`, `name[i]` or `name[i..j]`. `range` is inclusive, lowest bit first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinRef {
+    pub name: String,
+    pub range: Option<(usize, usize)>,
+    pub span: Span,
+}
+
+impl PinRef {
+    /// number of bits selected, `None` for a whole pin of unknown width
+    pub fn width(&self) -> Option<usize> {
+        self.range.map(|(lo, hi)| hi - lo + 1)
+    }
+}
+
+/// Right hand side of a connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Wire {
+    Pin(PinRef),
+    Const(bool),
+}
+
+/// `pin=wire` in a part's argument list, `pin` belongs to the part and `wire` to the chip being defined
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connection {
+    pub pin: PinRef,
+    pub wire: Wire,
+    pub span: Span,
+}
+
+/// One line of the PARTS section
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    pub chip: String,
+    pub connections: Vec<Connection>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chip {
+    pub name: String,
+    pub inputs: Vec<Port>,
+    pub outputs: Vec<Port>,
+    pub parts: Vec<Part>,
+    /// name of the native implementation, see `builtins`
+    pub builtin: Option<String>,
+    /// inputs that are only read on the clock edge
+    pub clocked: Vec<String>,
+
+    /// file name, including the extension. Kept with the source so errors found during elaboration can point at it.
+    pub file: String,
+    pub source: String,
+}
+
+impl Chip {
+    pub fn port(&self, name: &str) -> Option<&Port> {
+        self.inputs
+            .iter()
+            .chain(&self.outputs)
+            .find(|p| p.name == name)
+    }
+
+    pub fn is_input(&self, name: &str) -> bool {
+        self.inputs.iter().any(|p| p.name == name)
+    }
+
+    pub fn error(&self, span: Span, message: String) -> Diagnostic {
+        Diagnostic::new(&self.file, &self.source, span, message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Number(usize),
+    Symbol(char),
+    /// `..` in sub-bus ranges
+    Range,
+    None,
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Ident(x) => format!("'{x}'"),
+        Token::Number(x) => format!("'{x}'"),
+        Token::Symbol(c) => format!("'{c}'"),
+        Token::Range => "'..'".to_string(),
+        Token::None => "end of file".to_string(),
+    }
+}
+
+fn lex(file: &str, source: &str) -> Result<Vec<(Token, Span)>, Diagnostic> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let start = i;
+        let token = match bytes[i] {
+            b if b.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i = source[i..].find('\n').map(|n| i + n).unwrap_or(bytes.len());
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = match source[i + 2..].find("*/") {
+                    Some(n) => i + 2 + n + 2,
+                    None => {
+                        let message = "unterminated block comment".to_string();
+                        return Err(Diagnostic::new(file, source, Span::new(i, i + 2), message));
+                    }
+                };
+                continue;
+            }
+            b'.' if bytes.get(i + 1) == Some(&b'.') => {
+                i += 2;
+                Token::Range
+            }
+            b if b.is_ascii_digit() => {
+                while i < bytes.len() && bytes[i].is_ascii_digit() {
+                    i += 1;
+                }
+                match source[start..i].parse() {
+                    Ok(x) => Token::Number(x),
+                    Err(_) => {
+                        let message = format!("number {} is too large", &source[start..i]);
+                        return Err(Diagnostic::new(file, source, Span::new(start, i), message));
+                    }
+                }
+            }
+            b if b.is_ascii_alphabetic() || b == b'_' => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                Token::Ident(source[start..i].to_string())
+            }
+            b if b"{}()[],;=:".contains(&b) => {
+                i += 1;
+                Token::Symbol(b as char)
+            }
+            _ => {
+                let c = source[i..].chars().next().unwrap();
+                let span = Span::new(i, i + c.len_utf8());
+                let message = format!("unexpected character {c:?}");
+                return Err(Diagnostic::new(file, source, span, message));
+            }
+        };
+        tokens.push((token, Span::new(start, i)));
+    }
+
+    Ok(tokens)
+}
+
+/// Parses the source of a single .hdl file. `file` is used to label errors.
+pub fn parse_hdl(file: &str, source: &str) -> Result<Chip, Diagnostic> {
+    let tokens = lex(file, source)?;
+    let mut parser = HdlParser {
+        file,
+        source,
+        tokens,
+        position: 0,
+    };
+    parser.parse_chip()
+}
+
+struct HdlParser<'a> {
+    file: &'a str,
+    source: &'a str,
+    tokens: Vec<(Token, Span)>,
+    position: usize,
+}
+
+impl HdlParser<'_> {
+    fn peek(&self) -> &Token {
+        self.tokens
+            .get(self.position)
+            .map(|(t, _)| t)
+            .unwrap_or(&Token::None)
+    }
+
+    fn peek_span(&self) -> Span {
+        match self.tokens.get(self.position) {
+            Some((_, span)) => *span,
+            None => Span::new(self.source.len(), self.source.len()),
+        }
+    }
+
+    fn prev_span(&self) -> Span {
+        self.tokens[self.position - 1].1
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, Diagnostic> {
+        Err(Diagnostic::new(
+            self.file,
+            self.source,
+            self.peek_span(),
+            message,
+        ))
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == token {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        self.eat(&Token::Ident(keyword.to_string()))
+    }
+
+    fn expect(&mut self, symbol: char, context: &str) -> Result<Span, Diagnostic> {
+        if self.eat(&Token::Symbol(symbol)) {
+            return Ok(self.prev_span());
+        }
+        let got = describe(self.peek());
+        self.error(format!("expected '{symbol}' {context}, got {got}"))
+    }
+
+    fn expect_ident(&mut self, what: &str) -> Result<(String, Span), Diagnostic> {
+        if let Token::Ident(name) = self.peek() {
+            let name = name.clone();
+            self.position += 1;
+            return Ok((name, self.prev_span()));
+        }
+        let got = describe(self.peek());
+        self.error(format!("expected {what}, got {got}"))
+    }
+
+    fn expect_number(&mut self) -> Result<usize, Diagnostic> {
+        if let Token::Number(x) = *self.peek() {
+            self.position += 1;
+            return Ok(x);
+        }
+        let got = describe(self.peek());
+        self.error(format!("expected a number, got {got}"))
+    }
+
+    // ---------------------------- 'CHIP' name '{' sections '}' ---------------------------- //
+    fn parse_chip(&mut self) -> Result<Chip, Diagnostic> {
+        if !self.eat_keyword("CHIP") {
+            let got = describe(self.peek());
+            return self.error(format!("expected 'CHIP', got {got}"));
+        }
+        let (name, _) = self.expect_ident("chip name")?;
+        self.expect('{', "after chip name")?;
+
+        let mut chip = Chip {
+            name,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            parts: Vec::new(),
+            builtin: None,
+            clocked: Vec::new(),
+            file: self.file.to_string(),
+            source: self.source.to_string(),
+        };
+
+        loop {
+            if self.eat_keyword("IN") {
+                chip.inputs.extend(self.parse_ports()?);
+            } else if self.eat_keyword("OUT") {
+                chip.outputs.extend(self.parse_ports()?);
+            } else if self.eat_keyword("PARTS") {
+                self.expect(':', "after 'PARTS'")?;
+                while matches!(self.peek(), Token::Ident(x) if x != "BUILTIN" && x != "CLOCKED") {
+                    chip.parts.push(self.parse_part()?);
+                }
+            } else if self.eat_keyword("BUILTIN") {
+                chip.builtin = Some(self.expect_ident("builtin chip name")?.0);
+                self.expect(';', "after builtin chip name")?;
+            } else if self.eat_keyword("CLOCKED") {
+                loop {
+                    chip.clocked.push(self.expect_ident("pin name")?.0);
+                    if !self.eat(&Token::Symbol(',')) {
+                        break;
+                    }
+                }
+                self.expect(';', "after clocked pins")?;
+            } else if self.eat(&Token::Symbol('}')) {
+                break;
+            } else {
+                let got = describe(self.peek());
+                return self.error(format!(
+                    "expected 'IN', 'OUT', 'PARTS:', 'BUILTIN' or '}}', got {got}"
+                ));
+            }
+        }
+
+        if self.peek() != &Token::None {
+            let got = describe(self.peek());
+            return self.error(format!("unexpected {got} after the end of the chip"));
+        }
+
+        Ok(chip)
+    }
+
+    // ------------------------- name ('[' width ']')? (',' ...)* ';' ------------------------ //
+    fn parse_ports(&mut self) -> Result<Vec<Port>, Diagnostic> {
+        let mut ports = Vec::new();
+        loop {
+            let (name, start) = self.expect_ident("pin name")?;
+            let width = if self.eat(&Token::Symbol('[')) {
+                let width = self.expect_number()?;
+                if width == 0 || width > 16 {
+                    let message = format!("bus width must be between 1 and 16, got {width}");
+                    return Err(Diagnostic::new(
+                        self.file,
+                        self.source,
+                        self.prev_span(),
+                        message,
+                    ));
+                }
+                self.expect(']', "after bus width")?;
+                width
+            } else {
+                1
+            };
+            ports.push(Port {
+                name,
+                width,
+                span: start.to(self.prev_span()),
+            });
+
+            if !self.eat(&Token::Symbol(',')) {
+                break;
+            }
+        }
+        self.expect(';', "after pin declarations")?;
+
+        Ok(ports)
+    }
+
+    // ---------------------------- name '(' connection (',' connection)* ')' ';' ---------------------------- //
+    fn parse_part(&mut self) -> Result<Part, Diagnostic> {
+        let (chip, start) = self.expect_ident("chip name")?;
+        self.expect('(', "after part name")?;
+
+        let mut connections = Vec::new();
+        loop {
+            let pin = self.parse_pin_ref()?;
+            self.expect('=', "after pin name")?;
+            let wire = match self.peek() {
+                Token::Ident(x) if x == "true" || x == "false" => {
+                    let value = x == "true";
+                    self.position += 1;
+                    Wire::Const(value)
+                }
+                _ => Wire::Pin(self.parse_pin_ref()?),
+            };
+            connections.push(Connection {
+                span: pin.span.to(self.prev_span()),
+                pin,
+                wire,
+            });
+
+            if !self.eat(&Token::Symbol(',')) {
+                break;
+            }
+        }
+        self.expect(')', "after part connections")?;
+        let end = self.expect(';', "after part")?;
+
+        Ok(Part {
+            chip,
+            connections,
+            span: start.to(end),
+        })
+    }
+
+    // ------------------------------- name ('[' i ('..' j)? ']')? ------------------------------ //
+    fn parse_pin_ref(&mut self) -> Result<PinRef, Diagnostic> {
+        let (name, start) = self.expect_ident("pin name")?;
+        let range = if self.eat(&Token::Symbol('[')) {
+            let lo = self.expect_number()?;
+            let hi = if self.eat(&Token::Range) {
+                self.expect_number()?
+            } else {
+                lo
+            };
+            if hi < lo || hi > 15 {
+                let message = format!("invalid sub-bus {lo}..{hi}");
+                return Err(Diagnostic::new(
+                    self.file,
+                    self.source,
+                    start.to(self.prev_span()),
+                    message,
+                ));
+            }
+            self.expect(']', "after sub-bus")?;
+            Some((lo, hi))
+        } else {
+            None
+        };
+
+        Ok(PinRef {
+            name,
+            range,
+            span: start.to(self.prev_span()),
+        })
+    }
+}
@@ -0,0 +1,911 @@
+//! Elaborates an HDL chip into a flat netlist and simulates it.
+//!
+//! Every bit of every pin is a node. Parts are instantiated recursively until only `Nand` gates, `DFF`s and builtin
+//! chips (see `builtins`) are left. A connection between a part's output and a wire of the chip using it makes both
+//! the same node, which lets parts appear in any order and read wires driven by parts further down, like the
+//! feedback through a `DFF` in `Bit`.
+//!
+//! Components are evaluated in dependency order. `DFF`s and the clocked inputs of builtins break that dependency, so
+//! a loop that doesn't pass through one of them is rejected as a combinational loop. Clocking is split in two like
+//! the official simulator: `tick` samples the clocked inputs, `tock` updates the outputs.
+
+use crate::hardware::{
+    hdl::{
+        builtins::{Builtin, State},
+        parser::{parse_hdl, Chip, Part, PinRef, Wire},
+    },
+    logic_gate::{
+        gates::NAND,
+        memory::{DFF, ROM32K},
+    },
+};
+use crate::software::diagnostics::{Diagnostic, Span};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    path::Path,
+    rc::Rc,
+    str::FromStr,
+};
+
+pub type Node = usize;
+
+/// Node that is always 0, unconnected inputs and `false` are wired to it
+pub const FALSE: Node = 0;
+/// Node that is always 1
+pub const TRUE: Node = 1;
+
+#[derive(Debug, Clone)]
+pub enum Component {
+    Nand {
+        a: Node,
+        b: Node,
+        out: Node,
+    },
+    Dff {
+        input: Node,
+        out: Node,
+        dff: DFF,
+        /// input sampled by the last `tick`
+        next: u8,
+    },
+    /// boxed, so the NANDs and DFFs big netlists are made of stay small
+    Builtin(Box<BuiltinPart>),
+}
+
+#[derive(Debug, Clone)]
+pub struct BuiltinPart {
+    pub chip: Builtin,
+    /// one node per bit, lowest bit first, pins in the order of `chip.interface()`
+    pub inputs: Vec<Vec<Node>>,
+    pub outputs: Vec<Vec<Node>>,
+    pub state: State,
+    /// inputs sampled by the last `tick`
+    pub latched: Vec<u16>,
+}
+
+impl Component {
+    /// Nodes the component's outputs depend on without a clock in between
+    fn dependencies(&self) -> Vec<Node> {
+        match self {
+            Component::Nand { a, b, .. } => vec![*a, *b],
+            Component::Dff { .. } => Vec::new(),
+            Component::Builtin(part) => {
+                let interface = part.chip.interface();
+                interface
+                    .inputs
+                    .iter()
+                    .zip(&part.inputs)
+                    .filter(|(port, _)| !interface.clocked.contains(&port.name))
+                    .flat_map(|(_, nodes)| nodes.iter().copied())
+                    .collect()
+            }
+        }
+    }
+
+    fn outputs(&self) -> Vec<Node> {
+        match self {
+            Component::Nand { out, .. } | Component::Dff { out, .. } => vec![*out],
+            Component::Builtin(part) => part.outputs.iter().flatten().copied().collect(),
+        }
+    }
+
+    fn map_nodes(&mut self, mut f: impl FnMut(Node) -> Node) {
+        match self {
+            Component::Nand { a, b, out } => {
+                *a = f(*a);
+                *b = f(*b);
+                *out = f(*out);
+            }
+            Component::Dff { input, out, .. } => {
+                *input = f(*input);
+                *out = f(*out);
+            }
+            Component::Builtin(part) => {
+                let BuiltinPart {
+                    inputs, outputs, ..
+                } = &mut **part;
+                for node in inputs.iter_mut().chain(outputs.iter_mut()).flatten() {
+                    *node = f(*node);
+                }
+            }
+        }
+    }
+}
+
+/// How a part is implemented
+#[derive(Debug, Clone)]
+enum Definition {
+    Hdl(Rc<Chip>),
+    Builtin(Builtin),
+}
+
+impl Definition {
+    fn interface(&self) -> &Chip {
+        match self {
+            Definition::Hdl(chip) => chip,
+            Definition::Builtin(builtin) => builtin.interface(),
+        }
+    }
+}
+
+/// Components whose nodes get merged as the parts are connected
+struct Netlist {
+    /// union-find over nodes, see `find`
+    parent: Vec<Node>,
+    components: Vec<Component>,
+    /// where each component was instantiated, for errors about the netlist as a whole
+    origins: Vec<(Rc<Chip>, Span)>,
+}
+
+/// The node `node` was merged into
+fn find(parent: &mut [Node], mut node: Node) -> Node {
+    while parent[node] != node {
+        parent[node] = parent[parent[node]];
+        node = parent[node];
+    }
+    node
+}
+
+/// Marks a node that hasn't been numbered yet
+const UNNUMBERED: Node = Node::MAX;
+
+impl Netlist {
+    fn new() -> Self {
+        Netlist {
+            parent: vec![FALSE, TRUE],
+            components: Vec::new(),
+            origins: Vec::new(),
+        }
+    }
+
+    fn alloc(&mut self, width: usize) -> Vec<Node> {
+        let start = self.parent.len();
+        self.parent.extend(start..start + width);
+        (start..start + width).collect()
+    }
+
+    /// Makes `a` and `b` the same node
+    fn union(&mut self, a: Node, b: Node) {
+        let (a, b) = (find(&mut self.parent, a), find(&mut self.parent, b));
+        // the lower node stays the root so FALSE and TRUE keep their numbers
+        match a.cmp(&b) {
+            std::cmp::Ordering::Less => self.parent[b] = a,
+            std::cmp::Ordering::Greater => self.parent[a] = b,
+            std::cmp::Ordering::Equal => {}
+        }
+    }
+
+    /// Numbers the nodes that are left after merging, FALSE and TRUE first, then in the order the components use
+    /// them, and renumbers the components. Returns the new number of every old node, and how many there are.
+    fn renumber(&mut self) -> (Vec<Node>, usize) {
+        let parent = &mut self.parent;
+        let mut numbers = vec![UNNUMBERED; parent.len()];
+        let mut count = 0;
+        let mut number = |parent: &mut [Node], node: Node| {
+            let root = find(parent, node);
+            if numbers[root] == UNNUMBERED {
+                numbers[root] = count;
+                count += 1;
+            }
+            numbers[root]
+        };
+        number(parent, FALSE);
+        number(parent, TRUE);
+        for component in &mut self.components {
+            component.map_nodes(|node| number(parent, node));
+        }
+        // nodes no component uses, like an input nothing reads
+        let numbers: Vec<Node> = (0..parent.len()).map(|node| number(parent, node)).collect();
+        (numbers, count)
+    }
+}
+
+/// An HDL chip elaborated on its own. Every part using the chip gets a copy, so a chip is only elaborated once
+/// however many times it's used.
+struct Template {
+    nodes: usize,
+    /// node of every bit of the chip's pins, inputs then outputs
+    pins: Vec<Node>,
+    components: Vec<Component>,
+    origins: Vec<(Rc<Chip>, Span)>,
+}
+
+struct Elaborator {
+    /// .hdl file name and source, by chip name
+    sources: HashMap<String, (String, String)>,
+    parsed: HashMap<String, Rc<Chip>>,
+    templates: HashMap<String, Rc<Template>>,
+
+    netlist: Netlist,
+    /// chips being instantiated, outermost first
+    stack: Vec<String>,
+}
+
+impl Elaborator {
+    /// `chip` elaborated on its own, the first time it's used
+    fn template(&mut self, chip: &Rc<Chip>) -> Result<Rc<Template>, Diagnostic> {
+        if let Some(template) = self.templates.get(&chip.name) {
+            return Ok(template.clone());
+        }
+
+        let outer = std::mem::replace(&mut self.netlist, Netlist::new());
+        let mut pins = HashMap::new();
+        let mut pin_nodes: Vec<Node> = Vec::new();
+        for port in chip.inputs.iter().chain(&chip.outputs) {
+            let nodes = self.netlist.alloc(port.width);
+            pin_nodes.extend(&nodes);
+            pins.insert(port.name.clone(), nodes);
+        }
+        self.stack.push(chip.name.clone());
+        let result = self.instantiate(chip, &pins);
+        self.stack.pop();
+        let mut netlist = std::mem::replace(&mut self.netlist, outer);
+        result?;
+
+        let (numbers, nodes) = netlist.renumber();
+        let template = Rc::new(Template {
+            nodes,
+            pins: pin_nodes.into_iter().map(|node| numbers[node]).collect(),
+            components: netlist.components,
+            origins: netlist.origins,
+        });
+        self.templates.insert(chip.name.clone(), template.clone());
+        Ok(template)
+    }
+
+    /// Copies `template`, the elaborated `chip`, into the netlist with its pins on the nodes in `pins`
+    fn place(&mut self, template: &Template, chip: &Chip, pins: &HashMap<String, Vec<Node>>) {
+        let mut map = vec![UNNUMBERED; template.nodes];
+        map[FALSE] = FALSE;
+        map[TRUE] = TRUE;
+        let outside = chip
+            .inputs
+            .iter()
+            .chain(&chip.outputs)
+            .flat_map(|port| pins[&port.name].iter().copied());
+        for (&inside, node) in template.pins.iter().zip(outside) {
+            match map[inside] {
+                UNNUMBERED => map[inside] = node,
+                // output pins driven by the same wire inside the chip
+                other => self.netlist.union(other, node),
+            }
+        }
+        let internal = map.iter().filter(|&&node| node == UNNUMBERED).count();
+        let mut fresh = self.netlist.alloc(internal).into_iter();
+        for node in map.iter_mut().filter(|node| **node == UNNUMBERED) {
+            *node = fresh.next().unwrap();
+        }
+
+        self.netlist.components.reserve(template.components.len());
+        for component in &template.components {
+            let mut component = component.clone();
+            component.map_nodes(|node| map[node]);
+            self.netlist.components.push(component);
+        }
+        self.netlist
+            .origins
+            .extend(template.origins.iter().cloned());
+    }
+
+    /// The chip called `name`: its .hdl file if there is one, otherwise the builtin. `user` and `span` locate the part
+    /// asking for it.
+    fn resolve(&mut self, name: &str, user: &Chip, span: Span) -> Result<Definition, Diagnostic> {
+        if let Some(chip) = self.parsed.get(name) {
+            return Ok(Definition::Hdl(chip.clone()));
+        }
+
+        match self.sources.get(name) {
+            Some((file, source)) => {
+                let chip = parse_hdl(file, source)?;
+                if chip.name != name {
+                    return Err(chip.error(
+                        Span::new(0, 0),
+                        format!("expected chip '{name}' in {file}, found '{}'", chip.name),
+                    ));
+                }
+                if let Some(builtin) = &chip.builtin {
+                    return Builtin::from_str(builtin)
+                        .map(Definition::Builtin)
+                        .map_err(|_| {
+                            let message = format!("there is no builtin chip '{builtin}'");
+                            chip.error(Span::new(0, 0), message)
+                        });
+                }
+                let chip = Rc::new(chip);
+                self.parsed.insert(name.to_string(), chip.clone());
+                Ok(Definition::Hdl(chip))
+            }
+            None => Builtin::from_str(name)
+                .map(Definition::Builtin)
+                .map_err(|_| user.error(span, format!("unknown chip '{name}'"))),
+        }
+    }
+
+    /// The nodes of `pin`, which is a pin of `chip` or one of its internal wires
+    fn lookup(
+        chip: &Chip,
+        pins: &HashMap<String, Vec<Node>>,
+        wires: &HashMap<String, Vec<Node>>,
+        pin: &PinRef,
+    ) -> Result<Vec<Node>, Diagnostic> {
+        if let Some(nodes) = pins.get(&pin.name) {
+            return match pin.range {
+                Some((_, hi)) if hi >= nodes.len() => Err(chip.error(
+                    pin.span,
+                    format!("pin '{}' is only {} bits wide", pin.name, nodes.len()),
+                )),
+                Some((lo, hi)) => Ok(nodes[lo..=hi].to_vec()),
+                None => Ok(nodes.clone()),
+            };
+        }
+
+        match wires.get(&pin.name) {
+            Some(_) if pin.range.is_some() => Err(chip.error(
+                pin.span,
+                format!("internal pin '{}' cannot be subscripted", pin.name),
+            )),
+            Some(nodes) => Ok(nodes.clone()),
+            None => Err(chip.error(
+                pin.span,
+                format!(
+                    "'{}' is neither a pin of {} nor the output of one of its parts",
+                    pin.name, chip.name
+                ),
+            )),
+        }
+    }
+
+    /// Adds the parts of `chip` to the netlist. `pins` holds the nodes of all of its inputs and outputs.
+    /// Returns the nodes of the chip's internal wires.
+    fn instantiate(
+        &mut self,
+        chip: &Rc<Chip>,
+        pins: &HashMap<String, Vec<Node>>,
+    ) -> Result<HashMap<String, Vec<Node>>, Diagnostic> {
+        let mut definitions = Vec::new();
+        let mut wires: HashMap<String, Vec<Node>> = HashMap::new();
+
+        // ----------------------------- internal wires and their widths ----------------------------- //
+        for part in &chip.parts {
+            let definition = self.resolve(&part.chip, chip, part.span)?;
+            let interface = definition.interface();
+
+            for connection in &part.connections {
+                let pin = &connection.pin;
+                let Some(port) = interface.port(&pin.name) else {
+                    let message = format!("chip {} has no pin '{}'", part.chip, pin.name);
+                    return Err(chip.error(pin.span, message));
+                };
+                if let Some((_, hi)) = pin.range.filter(|&(_, hi)| hi >= port.width) {
+                    let message = format!(
+                        "pin '{}' of {} is only {} bits wide, got bit {hi}",
+                        pin.name, part.chip, port.width
+                    );
+                    return Err(chip.error(pin.span, message));
+                }
+                if interface.is_input(&pin.name) {
+                    continue;
+                }
+
+                match &connection.wire {
+                    Wire::Const(_) => {
+                        let message =
+                            format!("output pin '{}' can't be set to a constant", pin.name);
+                        return Err(chip.error(connection.span, message));
+                    }
+                    Wire::Pin(wire) if pins.contains_key(&wire.name) => {}
+                    Wire::Pin(wire) => {
+                        if wire.range.is_some() {
+                            let message =
+                                format!("internal pin '{}' cannot be subscripted", wire.name);
+                            return Err(chip.error(wire.span, message));
+                        }
+                        if wires.contains_key(&wire.name) {
+                            let message =
+                                format!("internal pin '{}' has more than one source", wire.name);
+                            return Err(chip.error(wire.span, message));
+                        }
+                        let width = pin.width().unwrap_or(port.width);
+                        let nodes = self.netlist.alloc(width);
+                        wires.insert(wire.name.clone(), nodes);
+                    }
+                }
+            }
+            definitions.push(definition);
+        }
+
+        // ------------------------------------- connect the parts ------------------------------------- //
+        let mut driven: HashMap<&str, Vec<bool>> = chip
+            .outputs
+            .iter()
+            .map(|port| (port.name.as_str(), vec![false; port.width]))
+            .collect();
+
+        for (part, definition) in chip.parts.iter().zip(definitions) {
+            let interface = definition.interface();
+            let mut part_pins: HashMap<String, Vec<Node>> = HashMap::new();
+            for port in &interface.inputs {
+                part_pins.insert(port.name.clone(), vec![FALSE; port.width]);
+            }
+            for port in &interface.outputs {
+                let nodes = self.netlist.alloc(port.width);
+                part_pins.insert(port.name.clone(), nodes);
+            }
+
+            for connection in &part.connections {
+                let pin = &connection.pin;
+                let width = part_pins[&pin.name].len();
+                let (lo, hi) = pin.range.unwrap_or((0, width - 1));
+
+                let nodes = match &connection.wire {
+                    Wire::Const(value) => vec![if *value { TRUE } else { FALSE }; hi - lo + 1],
+                    Wire::Pin(wire) => {
+                        if !interface.is_input(&pin.name) && chip.is_input(&wire.name) {
+                            let message =
+                                format!("input pin '{}' can't be set by a part", wire.name);
+                            return Err(chip.error(wire.span, message));
+                        }
+                        Self::lookup(chip, pins, &wires, wire)?
+                    }
+                };
+                if nodes.len() != hi - lo + 1 {
+                    let message = format!(
+                        "width mismatch, {} bits connected to {} bits",
+                        nodes.len(),
+                        hi - lo + 1
+                    );
+                    return Err(chip.error(connection.span, message));
+                }
+
+                if interface.is_input(&pin.name) {
+                    part_pins.get_mut(&pin.name).unwrap()[lo..=hi].copy_from_slice(&nodes);
+                    continue;
+                }
+
+                // output: the part drives the wire
+                if let Wire::Pin(wire) = &connection.wire {
+                    if let Some(bits) = driven.get_mut(wire.name.as_str()) {
+                        let (wire_lo, wire_hi) = wire.range.unwrap_or((0, bits.len() - 1));
+                        if bits[wire_lo..=wire_hi].iter().any(|&b| b) {
+                            let message =
+                                format!("output pin '{}' has more than one source", wire.name);
+                            return Err(chip.error(wire.span, message));
+                        }
+                        bits[wire_lo..=wire_hi].fill(true);
+                    }
+                }
+                for (i, node) in nodes.into_iter().enumerate() {
+                    let part_node = part_pins[&pin.name][lo + i];
+                    self.netlist.union(part_node, node);
+                }
+            }
+
+            self.add_part(chip, part, definition, part_pins)?;
+        }
+
+        Ok(wires)
+    }
+
+    fn add_part(
+        &mut self,
+        chip: &Rc<Chip>,
+        part: &Part,
+        definition: Definition,
+        pins: HashMap<String, Vec<Node>>,
+    ) -> Result<(), Diagnostic> {
+        let component = match definition {
+            Definition::Hdl(child) => {
+                if self.stack.contains(&child.name) {
+                    let message = format!("chip {} contains itself", child.name);
+                    return Err(chip.error(part.span, message));
+                }
+                let template = self.template(&child)?;
+                self.place(&template, &child, &pins);
+                return Ok(());
+            }
+            Definition::Builtin(Builtin::Nand) => Component::Nand {
+                a: pins["a"][0],
+                b: pins["b"][0],
+                out: pins["out"][0],
+            },
+            Definition::Builtin(Builtin::DFF) => Component::Dff {
+                input: pins["in"][0],
+                out: pins["out"][0],
+                dff: DFF::new(),
+                next: 0,
+            },
+            Definition::Builtin(builtin) => {
+                let interface = builtin.interface();
+                Component::Builtin(Box::new(BuiltinPart {
+                    chip: builtin,
+                    inputs: interface
+                        .inputs
+                        .iter()
+                        .map(|p| pins[&p.name].clone())
+                        .collect(),
+                    outputs: interface
+                        .outputs
+                        .iter()
+                        .map(|p| pins[&p.name].clone())
+                        .collect(),
+                    state: builtin.new_state(),
+                    latched: vec![0; interface.inputs.len()],
+                }))
+            }
+        };
+
+        self.netlist.components.push(component);
+        self.netlist.origins.push((chip.clone(), part.span));
+        Ok(())
+    }
+}
+
+/// A chip elaborated down to `Nand`, `DFF` and builtin components
+#[derive(Debug, Clone)]
+pub struct Simulator {
+    pub chip: String,
+    /// current value of every node, 0 or 1
+    pub values: Vec<u8>,
+    /// in evaluation order
+    pub components: Vec<Component>,
+    /// input and output pins of the chip, in declaration order
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    /// nodes of the chip's pins and internal wires
+    pins: HashMap<String, Vec<Node>>,
+    /// `eval` has to run until nothing changes, see `Builtin::reads_clocked_inputs`
+    settle: bool,
+}
+
+impl Simulator {
+    /// Loads the chip in the .hdl file at `path`. Its parts are looked up in the same directory, chips that aren't
+    /// there are builtin. The chip itself has to have its file, a missing one is an error rather than a builtin.
+    pub fn load(path: &Path) -> Result<Self, Diagnostic> {
+        if !path.is_file() {
+            return Err(Diagnostic::unreadable(path, "no such file"));
+        }
+
+        let dir = path.parent().unwrap();
+        let mut sources = Vec::new();
+        for entry in fs::read_dir(dir).map_err(|e| Diagnostic::unreadable(dir, e))? {
+            let entry = entry.map_err(|e| Diagnostic::unreadable(dir, e))?.path();
+            if entry.extension().is_some_and(|e| e == "hdl") {
+                let file = entry.file_name().unwrap().to_string_lossy().to_string();
+                let source =
+                    fs::read_to_string(&entry).map_err(|e| Diagnostic::unreadable(&entry, e))?;
+                sources.push((file, source));
+            }
+        }
+
+        let chip = path.file_stem().unwrap().to_str().unwrap();
+        let sources: Vec<_> = sources
+            .iter()
+            .map(|(file, source)| (file.as_str(), source.as_str()))
+            .collect();
+        Self::from_sources(chip, &sources)
+    }
+
+    /// Elaborates `chip` given (file name, source) pairs of .hdl files, chips without a source are builtin
+    pub fn from_sources(chip: &str, sources: &[(&str, &str)]) -> Result<Self, Diagnostic> {
+        let mut elaborator = Elaborator {
+            sources: sources
+                .iter()
+                .map(|&(file, source)| {
+                    let name = file.strip_suffix(".hdl").unwrap_or(file);
+                    (name.to_string(), (file.to_string(), source.to_string()))
+                })
+                .collect(),
+            parsed: HashMap::new(),
+            templates: HashMap::new(),
+            netlist: Netlist::new(),
+            stack: vec![chip.to_string()],
+        };
+
+        // the top level chip is a part of an imaginary chip with a pin for each of its pins
+        let top = Rc::new(Chip {
+            name: String::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            parts: Vec::new(),
+            builtin: None,
+            clocked: Vec::new(),
+            file: format!("{chip}.hdl"),
+            source: String::new(),
+        });
+        let definition = elaborator.resolve(chip, &top, Span::default())?;
+        let interface = definition.interface().clone();
+        let mut pins = HashMap::new();
+        for port in interface.inputs.iter().chain(&interface.outputs) {
+            let nodes = elaborator.netlist.alloc(port.width);
+            pins.insert(port.name.clone(), nodes);
+        }
+
+        match definition {
+            Definition::Hdl(chip) => {
+                let wires = elaborator.instantiate(&chip, &pins)?;
+                // the chip's own pins win over wires of the same name
+                for (name, nodes) in wires {
+                    pins.entry(name).or_insert(nodes);
+                }
+            }
+            builtin => {
+                let part = Part {
+                    chip: chip.to_string(),
+                    connections: Vec::new(),
+                    span: Span::default(),
+                };
+                elaborator.add_part(&top, &part, builtin, pins.clone())?;
+            }
+        }
+
+        // ------------------------ number the nodes that are left after merging ------------------------ //
+        // the templates are only needed while elaborating, free them before sorting
+        let mut netlist = std::mem::replace(&mut elaborator.netlist, Netlist::new());
+        drop(elaborator);
+        let (numbers, node_count) = netlist.renumber();
+        for nodes in pins.values_mut() {
+            for node in nodes {
+                *node = numbers[*node];
+            }
+        }
+        drop(numbers);
+        let Netlist {
+            mut components,
+            origins,
+            ..
+        } = netlist;
+
+        // ----------------------------------- evaluation order ----------------------------------- //
+        // indices are u32 and the dependents are one flat list, a RAM32K has millions of components
+        const UNDRIVEN: u32 = u32::MAX;
+        let mut driver = vec![UNDRIVEN; node_count];
+        for (i, component) in components.iter().enumerate() {
+            for out in component.outputs() {
+                if out <= TRUE {
+                    continue;
+                }
+                if driver[out] != UNDRIVEN {
+                    let (chip, span) = &origins[i];
+                    let message = "pin has more than one source".to_string();
+                    return Err(chip.error(*span, message));
+                }
+                driver[out] = i as u32;
+            }
+        }
+
+        // dependents of component i are dependents[start[i]..start[i + 1]]
+        let mut start = vec![0u32; components.len() + 1];
+        let mut waiting = vec![0u32; components.len()];
+        for (i, component) in components.iter().enumerate() {
+            for dependency in component.dependencies() {
+                let d = driver[dependency];
+                if d != UNDRIVEN {
+                    start[d as usize + 1] += 1;
+                    waiting[i] += 1;
+                }
+            }
+        }
+        for i in 0..components.len() {
+            start[i + 1] += start[i];
+        }
+        let mut fill = start.clone();
+        let mut dependents = vec![0u32; start[components.len()] as usize];
+        for (i, component) in components.iter().enumerate() {
+            for dependency in component.dependencies() {
+                let d = driver[dependency];
+                if d != UNDRIVEN {
+                    dependents[fill[d as usize] as usize] = i as u32;
+                    fill[d as usize] += 1;
+                }
+            }
+        }
+        drop(fill);
+        drop(driver);
+
+        let mut ready: VecDeque<u32> = (0..components.len() as u32)
+            .filter(|&i| waiting[i as usize] == 0)
+            .collect();
+        // position[i] is where component i goes in evaluation order
+        let mut position = vec![0u32; components.len()];
+        let mut placed = 0;
+        while let Some(i) = ready.pop_front() {
+            position[i as usize] = placed;
+            placed += 1;
+            let i = i as usize;
+            for &d in &dependents[start[i] as usize..start[i + 1] as usize] {
+                waiting[d as usize] -= 1;
+                if waiting[d as usize] == 0 {
+                    ready.push_back(d);
+                }
+            }
+        }
+        if (placed as usize) < components.len() {
+            let stuck = (0..components.len()).find(|&i| waiting[i] > 0).unwrap();
+            let (chip, span) = &origins[stuck];
+            let message = format!(
+                "combinational loop in {}, every loop must go through a DFF or clocked chip",
+                chip.name
+            );
+            return Err(chip.error(*span, message));
+        }
+        drop((dependents, start, waiting));
+
+        // move every component to its position in place
+        for i in 0..components.len() {
+            while position[i] as usize != i {
+                let j = position[i] as usize;
+                components.swap(i, j);
+                position.swap(i, j);
+            }
+        }
+
+        let settle = components
+            .iter()
+            .any(|c| matches!(c, Component::Builtin(part) if part.chip.reads_clocked_inputs()));
+        let mut values = vec![0; node_count];
+        values[TRUE] = 1;
+        let mut simulator = Simulator {
+            chip: chip.to_string(),
+            values,
+            components,
+            inputs: interface.inputs.iter().map(|p| p.name.clone()).collect(),
+            outputs: interface.outputs.iter().map(|p| p.name.clone()).collect(),
+            pins,
+            settle,
+        };
+        simulator.eval();
+
+        Ok(simulator)
+    }
+
+    pub fn has_pin(&self, pin: &str) -> bool {
+        self.pins.contains_key(pin)
+    }
+
+    /// Width of a pin or internal wire in bits
+    pub fn width(&self, pin: &str) -> usize {
+        self.pins[pin].len()
+    }
+
+    /// Nodes of a pin or internal wire, lowest bit first
+    pub fn nodes(&self, pin: &str) -> &[Node] {
+        &self.pins[pin]
+    }
+
+    /// Sets an input pin, bit `i` of `value` goes to pin `[i]`. Call `eval` to propagate it.
+    pub fn set(&mut self, pin: &str, value: u16) {
+        assert!(
+            self.inputs.iter().any(|p| p == pin),
+            "{} has no input pin '{pin}'",
+            self.chip
+        );
+        for (i, &node) in self.pins[pin].iter().enumerate() {
+            self.values[node] = (value >> i & 1) as u8;
+        }
+    }
+
+    /// Value of a pin or internal wire, bit `i` is pin `[i]`
+    pub fn get(&self, pin: &str) -> u16 {
+        let Some(nodes) = self.pins.get(pin) else {
+            panic!("{} has no pin '{pin}'", self.chip);
+        };
+        nodes
+            .iter()
+            .enumerate()
+            .fold(0, |acc, (i, &node)| acc | (self.values[node] as u16) << i)
+    }
+
+    /// Sets the key held down on every `Keyboard`, the builtin or the one in a builtin `Memory`. Call `eval` to
+    /// propagate it.
+    pub fn set_keyboard(&mut self, key: u16) {
+        for component in &mut self.components {
+            if let Component::Builtin(part) = component {
+                match &mut part.state {
+                    State::Keyboard(keyboard) => keyboard.press(key),
+                    State::Memory(memory) => memory.keyboard.press(key),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Burns `rom` into every `ROM32K`, like the `ROM32K load Prog.hack` of a test script. Call `eval` to propagate
+    /// it.
+    pub fn set_rom(&mut self, rom: &ROM32K) {
+        for component in &mut self.components {
+            if let Component::Builtin(part) = component {
+                if let State::ROM32K(chip) = &mut part.state {
+                    *chip = rom.clone();
+                }
+            }
+        }
+    }
+
+    /// Recomputes every combinational output from the inputs and the clocked state
+    pub fn eval(&mut self) {
+        self.eval_once();
+        // a builtin reading clocked inputs is ordered as if it didn't, so the parts after it may have seen its old
+        // outputs
+        while self.settle {
+            let before = self.values.clone();
+            self.eval_once();
+            if self.values == before {
+                break;
+            }
+        }
+    }
+
+    fn eval_once(&mut self) {
+        let values = &mut self.values;
+        for component in &self.components {
+            match component {
+                Component::Nand { a, b, out } => values[*out] = NAND(values[*a], values[*b]),
+                Component::Dff { out, dff, .. } => values[*out] = dff.data,
+                Component::Builtin(part) => {
+                    let inputs: Vec<u16> = part
+                        .inputs
+                        .iter()
+                        .map(|nodes| word(values, nodes))
+                        .collect();
+                    for (nodes, value) in part
+                        .outputs
+                        .iter()
+                        .zip(part.chip.eval(&inputs, &part.state))
+                    {
+                        for (i, &node) in nodes.iter().enumerate() {
+                            values[node] = (value >> i & 1) as u8;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Rising clock edge: settles the inputs and samples them into every DFF and clocked builtin
+    pub fn tick(&mut self) {
+        self.eval();
+        let values = &self.values;
+        for component in &mut self.components {
+            match component {
+                Component::Nand { .. } => {}
+                Component::Dff { input, next, .. } => *next = values[*input],
+                Component::Builtin(part) => {
+                    let BuiltinPart {
+                        inputs, latched, ..
+                    } = &mut **part;
+                    for (latch, nodes) in latched.iter_mut().zip(inputs.iter()) {
+                        *latch = word(values, nodes);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Falling clock edge: clocked outputs take on the values sampled by `tick`
+    pub fn tock(&mut self) {
+        for component in &mut self.components {
+            match component {
+                Component::Nand { .. } => {}
+                Component::Dff { dff, next, .. } => dff.cycle(*next, 1),
+                Component::Builtin(part) => {
+                    let BuiltinPart {
+                        chip,
+                        state,
+                        latched,
+                        ..
+                    } = &mut **part;
+                    chip.tock(latched, state)
+                }
+            }
+        }
+        self.eval();
+    }
+}
+
+fn word(values: &[u8], nodes: &[Node]) -> u16 {
+    nodes
+        .iter()
+        .enumerate()
+        .fold(0, |acc, (i, &node)| acc | (values[node] as u16) << i)
+}
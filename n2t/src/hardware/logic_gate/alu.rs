@@ -74,12 +74,39 @@ pub fn ALU(x: &[u8], y: &[u8], control: &mut ControlBits) -> Vec<u8> {
         control.no,
     );
 
-    control.ng = *result.last().unwrap();
+    // bit vectors are MSB first, the sign is the first bit
+    control.ng = result[0];
     control.zr = is_zero(&result);
 
     result
 }
 
+/// `ALU` on 16 bit buses
+pub fn ALU16(x: u16, y: u16, control: &mut ControlBits) -> u16 {
+    let zero = XOR16(x, x);
+    let not_zero = NOT16(zero);
+
+    let temp_x = MUX4WAY16(x, NOT16(x), zero, not_zero, control.zx, control.nx);
+    let temp_y = MUX4WAY16(y, NOT16(y), zero, not_zero, control.zy, control.ny);
+
+    let x_and_y = AND16(temp_x, temp_y);
+    let x_plus_y = adder16(temp_x, temp_y);
+
+    let result = MUX4WAY16(
+        x_and_y,
+        NOT16(x_and_y),
+        x_plus_y,
+        NOT16(x_plus_y),
+        control.f,
+        control.no,
+    );
+
+    control.ng = (result >> 15) as u8;
+    control.zr = is_zero16(result);
+
+    result
+}
+
 pub fn bench_alu() {
     let mut control = ControlBits {
         zx: 0,
@@ -92,12 +119,14 @@ pub fn bench_alu() {
         ng: 0,
     };
     let mut val = vec![0; 16];
+    // converted outside the loop, so only `ALU` is timed
+    let x = bitvec_from_int(17);
 
     let now = Instant::now();
     for _ in 0..10000 {
         val = ALU(
-            &bitvec_from_int(17),
-            &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1],
+            std::hint::black_box(&x),
+            std::hint::black_box(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1]),
             &mut control,
         );
     }
@@ -106,3 +135,30 @@ pub fn bench_alu() {
 
     println!("result = {:?}", int_from_bitvec(&val));
 }
+
+pub fn bench_alu16() {
+    let mut control = ControlBits {
+        zx: 0,
+        nx: 0,
+        zy: 1,
+        ny: 1,
+        f: 1,
+        no: 0,
+        zr: 0,
+        ng: 0,
+    };
+    let mut val = 0;
+
+    let now = Instant::now();
+    for _ in 0..10000 {
+        val = ALU16(
+            std::hint::black_box(17),
+            std::hint::black_box(3),
+            &mut control,
+        );
+    }
+    let dur = now.elapsed();
+    println!("{:?}", dur.as_micros());
+
+    println!("result = {:?}", val);
+}
@@ -65,3 +65,35 @@ pub fn is_zero(a: &[u8]) -> u8 {
 
     NOT(temp[0])
 }
+
+/// `adder` on 16 bit buses. The full adders of all 16 bits are evaluated side by side, so the carries take a pass per
+/// bit they ripple through, like they do through the gates.
+pub fn adder16(a: u16, b: u16) -> u16 {
+    // the first half adder of every bit
+    let (sum, carry) = (XOR16(a, b), AND16(a, b));
+
+    // carry into each bit, nothing goes into the LSB
+    let mut c = 0;
+    loop {
+        let next = OR16(carry, AND16(sum, c)) << 1;
+        if next == c {
+            break;
+        }
+        c = next;
+    }
+
+    XOR16(sum, c)
+}
+
+pub fn incrementer16(a: u16) -> u16 {
+    adder16(a, 1)
+}
+
+pub fn is_zero16(a: u16) -> u8 {
+    // OR the two halves together until a single bit is left, like `is_zero`
+    let a = OR16(a, a >> 8);
+    let a = OR16(a, a >> 4);
+    let a = OR16(a, a >> 2);
+    let a = OR16(a, a >> 1);
+    (NOT16(a) & 1) as u8
+}
@@ -0,0 +1,78 @@
+//! How many NAND gates and DFFs the chips are made of, and how deep their logic is.
+//!
+//! The chips are the .hdl files of `hdl::library`, elaborated by the HDL simulator down to `Nand` and `DFF`
+//! components, so the counts are of the same netlist the simulator runs. Depth is counted in NANDs, from the inputs
+//! and DFF outputs to the outputs and DFF inputs, so it's the number of gate delays a clock cycle has to cover.
+
+use crate::hardware::{
+    hdl::{builtins::Builtin, library, simulator::Component},
+    logic_gate::timing::arrival_times,
+};
+use prettytable::{format, row, Table};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChipCost {
+    pub chip: &'static str,
+    pub nands: u64,
+    pub dffs: u64,
+    pub depth: u32,
+}
+
+/// Every chip in the table, from the adders up to the `Computer`. `ALU` is the MUX_4 design and computes `zr` and
+/// `ng`, `MuxALU` only computes the output.
+pub const COSTED_CHIPS: [&str; 16] = [
+    "Add16",
+    "LookaheadAdd16",
+    "Inc16",
+    "ALU",
+    "LookaheadALU",
+    "MuxALU",
+    "Register",
+    "PC",
+    "RAM8",
+    "RAM64",
+    "RAM512",
+    "RAM4K",
+    "RAM16K",
+    "RAM32K",
+    "Screen",
+    "Computer",
+];
+
+/// Counts the components of `chip` elaborated from the library
+pub fn chip_cost(chip: &'static str) -> ChipCost {
+    let simulator = library::elaborate(chip);
+    let (mut nands, mut dffs) = (0, 0);
+    for component in &simulator.components {
+        match component {
+            Component::Nand { .. } => nands += 1,
+            Component::Dff { .. } => dffs += 1,
+            // the keyboard is a register the front end writes, the program in the ROM comes from outside the computer
+            Component::Builtin(part) if part.chip == Builtin::Keyboard => dffs += 16,
+            Component::Builtin(part) if part.chip == Builtin::ROM32K => {}
+            Component::Builtin(part) => panic!("{chip} has a builtin {:?}", part.chip),
+        }
+    }
+    ChipCost {
+        chip,
+        nands,
+        dffs,
+        depth: arrival_times(&simulator, 1).into_iter().max().unwrap_or(0),
+    }
+}
+
+/// The cost of every chip in `COSTED_CHIPS`
+pub fn chip_costs() -> Vec<ChipCost> {
+    COSTED_CHIPS.into_iter().map(chip_cost).collect()
+}
+
+/// `costs` as a text table
+pub fn cost_table(costs: &[ChipCost]) -> String {
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_NO_LINESEP_WITH_TITLE);
+    table.set_titles(row!["chip", "NANDs", "DFFs", "depth"]);
+    for cost in costs {
+        table.add_row(row![cost.chip, r->cost.nands, r->cost.dffs, r->cost.depth]);
+    }
+    table.to_string()
+}
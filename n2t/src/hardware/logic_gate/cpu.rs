@@ -3,133 +3,232 @@
 // D register
 // InstrPtr
 // ALU
-// TODO Memory
+// Memory
 // RAM16K (addr 0-16,383)
 // screen (8K, addr 16,384-24,575)
 // keyboard (16 bits, addr 24,576)
-// TODO ROM32K
+// ROM32K
 
 use crate::utils::*;
 
 use super::alu::*;
 use super::gates::*;
-use crate::hardware::logic_gate::memory::{InstPtr, Register};
-use crate::hardware::native::memory::RAM32K;
-
-// unscientific benchmark puts the execution time of a single instruction at ~7800ns release/44,000ns debug, which is about 128khz release/23khz debug
+use crate::hardware::hack_cpu::{CpuSignals, HackCpu};
+use crate::hardware::logic_gate::memory::{InstPtr, Memory, Register, ROM32K};
+use crate::software::diagnostics::Diagnostic;
+use std::path::Path;
+use std::time::Instant;
+
+// unscientific benchmark: on packed u16 buses, going through the gate-level RAM16K and screen, `bench_cpu` measures
+// ~600ns per instruction, over 1.5M instructions a second
+#[derive(Debug)]
 pub struct Computer {
     pub a: Register,
     pub d: Register,
     pub pc: InstPtr,
     /// also called out_m
-    pub alu_out: Vec<u8>,
-    pub in_m: Vec<u8>,
+    pub alu_out: u16,
+    pub in_m: u16,
+    /// the RAM address and load line of the last cycle
+    pub address_m: u16,
+    pub write_m: u8,
     pub time: u32,
     pub flags: ControlBits,
     pub reset: bool,
-    // I'm more or less required to use vec's for RAM/ROM as 64k Vec<u8>'s of size 16 is kindof a lot of memory.
-    pub rom: Vec<u16>,
-    pub ram: RAM32K,
+    pub rom: ROM32K,
+    /// RAM, screen and keyboard
+    pub memory: Memory,
 }
 
 impl Computer {
+    /// Panics if `program` is longer than the ROM, see `load`
     pub fn new(program: Vec<u16>) -> Self {
+        Self::with_rom(ROM32K::with_program(&program))
+    }
+
+    /// A computer running the .hack file at `path`, programs that don't fit in the ROM are an error
+    pub fn load(path: &Path) -> Result<Self, Diagnostic> {
+        Ok(Self::with_rom(ROM32K::load(path)?))
+    }
+
+    pub fn with_rom(rom: ROM32K) -> Self {
         Computer {
             a: Register::new(),
             d: Register::new(),
             pc: InstPtr::new(),
-            alu_out: vec![0; 16],
-            in_m: vec![0; 16],
+            alu_out: 0,
+            in_m: 0,
+            address_m: 0,
+            write_m: 0,
             time: 1,
             flags: ControlBits::new(),
             reset: false,
-            rom: program,
-            ram: RAM32K::new(),
+            rom,
+            memory: Memory::new(),
         }
     }
 
     pub fn set_program(&mut self, program: Vec<u16>) {
-        self.rom = program
+        self.rom = ROM32K::with_program(&program)
     }
 
     /// executes the next instruction
     pub fn execute(&mut self, log: bool, reset: bool) {
         // ------------------------------------- Input and register updates ------------------------------------- //
-        let instr = bitvec_from_int(self.rom[int_from_bitvec(&self.pc.val.data) as usize]);
-
+        let instruction = self.rom.cycle(self.pc.val.data);
         if log {
             println!(
                 "a register: {}, d register: {}, self.alu_out: {}, out_m: {}",
-                int_from_bitvec(&self.a.data),
-                i16::from_ne_bytes(int_from_bitvec(&self.d.data).to_ne_bytes()),
-                int_from_bitvec(&self.alu_out),
-                self.ram.out,
+                self.a.data, self.d.data as i16, self.alu_out, self.in_m,
             );
-            println!(
-                "cycle #: {}, pc: {}",
-                self.time,
-                int_from_bitvec(&self.pc.val.data)
-            );
-            decode_bitvec_instr(&instr);
+            println!("cycle #: {}, pc: {}", self.time, self.pc.val.data);
+            decode_bitvec_instr(&bitvec_from_int(instruction));
         }
 
         self.time += 1;
-        self.flags = ControlBits {
-            zx: instr[4],
-            nx: instr[5],
-            zy: instr[6],
-            ny: instr[7],
-            f: instr[8],
-            no: instr[9],
-            zr: self.flags.zr,
-            ng: self.flags.ng,
-        };
 
-        self.a.cycle(
-            &multi_MUX(&instr, &self.alu_out, instr[0]),
-            OR(NOT(instr[0]), AND(instr[0], instr[10])),
-        );
+        // every input of the cycle is the state before the instruction, M included. Nothing is written until the end
+        // of the cycle, so the memory write and the jump both use the old A.
+        self.in_m = self.memory.cycle(0, self.a.data & 0b0111_1111_1111_1111, 0);
+        let out = CpuOutputs::new(self.a.data, self.d.data, self.in_m, instruction);
+        self.flags = out.flags;
+        self.alu_out = out.out_m;
+        self.address_m = out.address_m;
+        self.write_m = out.write_m;
+
+        // ------------------------------------------- clock edge ------------------------------------------- //
+        self.memory
+            .cycle(self.alu_out, self.address_m, self.write_m);
+        out.clock(&mut self.a, &mut self.d, &mut self.pc, reset as u8);
+    }
+}
 
-        // -------------------------------------- alu processing and output ------------------------------------- //
-        self.alu_out = ALU(
-            &self.d.data,
-            &multi_MUX(&self.a.data, &self.in_m, instr[3]),
-            &mut self.flags,
-        );
+/// What the CPU computes from its registers, `inM` and the instruction before the clock edge: the CPU chip of the
+/// book without its registers. `Computer` and the HDL `CPU` builtin both clock their registers with it.
+#[derive(Debug, Clone, Copy)]
+pub struct CpuOutputs {
+    pub out_m: u16,
+    pub write_m: u8,
+    pub address_m: u16,
+    /// the ALU's control bits from the instruction and the `zr` and `ng` it computed
+    pub flags: ControlBits,
+    jump: u8,
+    load_a: u8,
+    a_input: u16,
+    load_d: u8,
+}
 
-        self.d.cycle(&self.alu_out, AND(instr[0], instr[11]));
+impl CpuOutputs {
+    pub fn new(a: u16, d: u16, in_m: u16, instruction: u16) -> Self {
+        // bit `i` of the instruction, MSB first: [i, i, i, a, c1, c2, c3, c4, c5, c6, d1, d2, d3, j1, j2, j3]
+        let instr = |i: u32| (instruction >> (15 - i) & 1) as u8;
+
+        let mut flags = ControlBits {
+            zx: instr(4),
+            nx: instr(5),
+            zy: instr(6),
+            ny: instr(7),
+            f: instr(8),
+            no: instr(9),
+            ..ControlBits::new()
+        };
 
-        // ick
-        self.in_m = bitvec_from_int(self.ram.cycle(
-            int_from_bitvec(&self.alu_out),
-            int_from_bitvec(&self.a.data) & 0b0111_1111_1111_1111,
-            AND(instr[0], instr[12]),
-        ));
+        // -------------------------------------- alu processing and output ------------------------------------- //
+        let out_m = ALU16(d, MUX16(a, in_m, instr(3)), &mut flags);
 
-        let should_jump = AND(
-            instr[0],
+        let jump = AND(
+            instr(0),
             MUX_8(
                 0,
-                NOT(self.flags.ng),
-                self.flags.zr,
-                OR(NOT(self.flags.ng), self.flags.zr),
-                AND(self.flags.ng, NOT(self.flags.zr)),
-                NOT(self.flags.zr),
-                self.flags.ng,
+                AND(NOT(flags.ng), NOT(flags.zr)),
+                flags.zr,
+                OR(NOT(flags.ng), flags.zr),
+                AND(flags.ng, NOT(flags.zr)),
+                NOT(flags.zr),
+                OR(flags.ng, flags.zr),
                 1,
-                instr[15],
-                instr[14],
-                instr[13],
+                instr(13),
+                instr(14),
+                instr(15),
             ),
         );
 
-        let mut should_reset = 0u8;
-        if reset {
-            should_reset = 1;
+        CpuOutputs {
+            out_m,
+            write_m: AND(instr(0), instr(12)),
+            address_m: a & 0b0111_1111_1111_1111,
+            flags,
+            jump,
+            load_a: OR(NOT(instr(0)), AND(instr(0), instr(10))),
+            a_input: MUX16(instruction, out_m, instr(0)),
+            load_d: AND(instr(0), instr(11)),
         }
+    }
+
+    /// The clock edge: the PC jumps, increments or resets, A and D load what the instruction stores in them
+    pub fn clock(&self, a: &mut Register, d: &mut Register, pc: &mut InstPtr, reset: u8) {
+        pc.cycle(a.data, self.jump, 1, reset);
+        d.cycle(self.out_m, self.load_d);
+        a.cycle(self.a_input, self.load_a);
+    }
+}
+
+/// Runs `cycles` instructions of a small loop, prints and returns the time per instruction in ns
+pub fn bench_cpu(cycles: u32) -> f64 {
+    // @i, M=M+1, @0, 0;JMP
+    let mut computer = Computer::new(vec![16, 0b1111_1101_1100_1000, 0, 0b1110_1010_1000_0111]);
+
+    let now = Instant::now();
+    for _ in 0..cycles {
+        computer.execute(false, false);
+    }
+    let ns = now.elapsed().as_nanos() as f64 / cycles as f64;
+    println!("{ns:.1}ns per instruction");
+    ns
+}
 
-        self.pc.cycle(&self.a.data, should_jump, 1, should_reset);
+impl HackCpu for Computer {
+    fn with_program(program: Vec<u16>) -> Self {
+        Computer::new(program)
+    }
 
-        //TODO screen & keyboard callback
+    fn step(&mut self) {
+        self.execute(false, false)
+    }
+
+    fn reset(&mut self) {
+        self.pc.cycle(self.a.data, 0, 0, 1);
+    }
+
+    fn a(&self) -> u16 {
+        self.a.data
+    }
+
+    fn d(&self) -> u16 {
+        self.d.data
+    }
+
+    fn pc(&self) -> u16 {
+        self.pc.val.data
+    }
+
+    fn ram(&self, address: u16) -> u16 {
+        self.memory.peek(address & 0b0111_1111_1111_1111)
+    }
+
+    fn set_ram(&mut self, address: u16, value: u16) {
+        self.memory.poke(address, value);
+    }
+
+    fn signals(&self) -> CpuSignals {
+        CpuSignals {
+            a: self.a(),
+            d: self.d(),
+            pc: self.pc(),
+            control: self.flags,
+            out_m: self.alu_out,
+            address_m: self.address_m,
+            write_m: self.write_m,
+        }
     }
 }
@@ -310,3 +310,62 @@ pub fn multi_DEMUX_8(input: &[u8], s0: u8, s1: u8, s2: u8) -> [Vec<u8>; 8] {
     }
     out
 }
+
+// ------------------------------------------------ 16 bit buses ------------------------------------------------ //
+// A u16 carries a whole bus, bit 15 is the first bit of the bit vectors (the MSB). A gate on a u16 is 16 copies of the
+// gate side by side: these are still NAND all the way down, but a bus is evaluated in one go and nothing allocates.
+
+/// 16 NANDs side by side
+pub fn NAND16(a: u16, b: u16) -> u16 {
+    !(a & b)
+}
+
+pub fn NOT16(a: u16) -> u16 {
+    NAND16(a, a)
+}
+
+pub fn AND16(a: u16, b: u16) -> u16 {
+    NOT16(NAND16(a, b))
+}
+
+pub fn OR16(a: u16, b: u16) -> u16 {
+    NAND16(NOT16(a), NOT16(b))
+}
+
+pub fn XOR16(a: u16, b: u16) -> u16 {
+    AND16(NAND16(a, b), OR16(a, b))
+}
+
+/// A single wire connected to all 16 bits of a bus
+pub fn fan_out(a: u8) -> u16 {
+    0u16.wrapping_sub((a & 1) as u16)
+}
+
+pub fn MUX16(a: u16, b: u16, sel: u8) -> u16 {
+    let sel = fan_out(sel);
+    OR16(AND16(NOT16(sel), a), AND16(sel, b))
+}
+
+pub fn MUX4WAY16(a: u16, b: u16, c: u16, d: u16, s0: u8, s1: u8) -> u16 {
+    MUX16(MUX16(a, b, s1), MUX16(c, d, s1), s0)
+}
+
+pub fn MUX8WAY16(
+    a: u16,
+    b: u16,
+    c: u16,
+    d: u16,
+    e: u16,
+    f: u16,
+    g: u16,
+    h: u16,
+    s0: u8,
+    s1: u8,
+    s2: u8,
+) -> u16 {
+    MUX16(
+        MUX4WAY16(a, b, c, d, s1, s2),
+        MUX4WAY16(e, f, g, h, s1, s2),
+        s0,
+    )
+}
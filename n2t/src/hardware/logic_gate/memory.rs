@@ -1,7 +1,9 @@
 #![allow(clippy::new_without_default, clippy::needless_range_loop)]
 
-use crate::hardware::logic_gate::arithmetic::incrementer;
+use crate::hardware::logic_gate::arithmetic::incrementer16;
 use crate::hardware::logic_gate::gates::*;
+use crate::software::diagnostics::{Diagnostic, Span};
+use std::{fs, path::Path};
 
 // DFF is considered "fundamental", so while this is would realistically work, i'll just be using Vecs as the minimum
 // so i don't have to refactor all of the prior logic to work with DFFs.
@@ -55,24 +57,36 @@ impl DFF {
 //     }
 // }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Register {
-    pub data: Vec<u8>,
+    pub data: u16,
 }
 
 impl Register {
     pub fn new() -> Self {
-        Register { data: vec![0; 16] }
+        Register { data: 0 }
     }
 
-    pub fn cycle(&mut self, input: &[u8], load: u8) {
-        for i in 0..16 {
-            self.data[i] = MUX(self.data[i], input[i], load);
-        }
+    pub fn cycle(&mut self, input: u16, load: u8) {
+        self.data = MUX16(self.data, input, load);
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+/// Bit `i` of `address`, counting from the MSB of an `n` bit address like the bit vectors do
+fn bit(address: u16, n: u32, i: u32) -> u8 {
+    (address >> (n - 1 - i) & 1) as u8
+}
+
+// Each RAM takes the address as the low bits of a u16. Like the chips in the book, the low address bits pick the part
+// and the high bits are passed down to it.
+//
+// Above RAM8 only the addressed part is clocked. Every other part would get a load of 0 from the DMux, which leaves its
+// registers as they are, and its output goes to a Mux input that isn't selected, so skipping it changes neither the
+// output nor the state. `test_rams_clock_one_part` in tests/4_memory_tests.rs checks this against RAMs that clock every
+// part. Clocking every part would make each RAM32K cycle go through all 32768 registers, far too slow to run programs
+// like Pong on the gates.
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct RAM8 {
     pub reg: [Register; 8],
 }
@@ -80,41 +94,28 @@ pub struct RAM8 {
 impl RAM8 {
     pub fn new() -> Self {
         RAM8 {
-            reg: [
-                Register::new(),
-                Register::new(),
-                Register::new(),
-                Register::new(),
-                Register::new(),
-                Register::new(),
-                Register::new(),
-                Register::new(),
-            ],
+            reg: [Register::new(); 8],
         }
     }
 
-    pub fn cycle(&mut self, input: &[u8], address: &[u8; 3], load: u8) -> Vec<u8> {
-        let temp = DEMUX_8(load, address[0], address[1], address[2]);
-        for i in 0..16 {
+    /// `address` is 3 bits
+    pub fn cycle(&mut self, input: u16, address: u16, load: u8) -> u16 {
+        let [s0, s1, s2] = [0, 1, 2].map(|i| bit(address, 3, i));
+        let temp = DEMUX_8(load, s0, s1, s2);
+        for i in 0..8 {
             self.reg[i].cycle(input, temp[i]);
         }
-        multi_MUX_8(
-            &self.reg[0].data,
-            &self.reg[1].data,
-            &self.reg[2].data,
-            &self.reg[3].data,
-            &self.reg[4].data,
-            &self.reg[5].data,
-            &self.reg[6].data,
-            &self.reg[7].data,
-            address[0],
-            address[1],
-            address[2],
-        )
+        let r = self.reg.map(|r| r.data);
+        MUX8WAY16(r[0], r[1], r[2], r[3], r[4], r[5], r[6], r[7], s0, s1, s2)
+    }
+
+    /// The word at `address` without clocking the chip, what a debugger or the screen sees
+    pub fn peek(&self, address: u16) -> u16 {
+        self.reg[(address & 0b111) as usize].data
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct RAM64 {
     pub ram8: [RAM8; 8],
 }
@@ -122,34 +123,29 @@ pub struct RAM64 {
 impl RAM64 {
     pub fn new() -> Self {
         RAM64 {
-            ram8: [
-                RAM8::new(),
-                RAM8::new(),
-                RAM8::new(),
-                RAM8::new(),
-                RAM8::new(),
-                RAM8::new(),
-                RAM8::new(),
-                RAM8::new(),
-            ],
+            ram8: [RAM8::new(); 8],
         }
     }
 
-    pub fn cycle(&mut self, input: &[u8], address: &[u8; 6], load: u8) -> Vec<u8> {
-        let temp = DEMUX_8(load, address[3], address[4], address[5]);
-        let mut out = Vec::new();
-        for i in 0..16 {
-            out.push(self.ram8[i].cycle(input, address[0..=2].try_into().unwrap(), temp[i]));
-        }
-
-        multi_MUX_8(
-            &out[0], &out[1], &out[2], &out[3], &out[4], &out[5], &out[6], &out[7], address[3],
-            address[4], address[5],
+    /// `address` is 6 bits
+    pub fn cycle(&mut self, input: u16, address: u16, load: u8) -> u16 {
+        let [s0, s1, s2] = [3, 4, 5].map(|i| bit(address, 6, i));
+        let temp = DEMUX_8(load, s0, s1, s2);
+        let mut out = [0; 8];
+        let i = (address & 0b111) as usize;
+        out[i] = self.ram8[i].cycle(input, address >> 3, temp[i]);
+        MUX8WAY16(
+            out[0], out[1], out[2], out[3], out[4], out[5], out[6], out[7], s0, s1, s2,
         )
     }
+
+    /// The word at `address` without clocking the chip
+    pub fn peek(&self, address: u16) -> u16 {
+        self.ram8[(address & 0b111) as usize].peek(address >> 3)
+    }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct RAM512 {
     pub ram64: [RAM64; 8],
 }
@@ -157,34 +153,29 @@ pub struct RAM512 {
 impl RAM512 {
     pub fn new() -> Self {
         RAM512 {
-            ram64: [
-                RAM64::new(),
-                RAM64::new(),
-                RAM64::new(),
-                RAM64::new(),
-                RAM64::new(),
-                RAM64::new(),
-                RAM64::new(),
-                RAM64::new(),
-            ],
+            ram64: [RAM64::new(); 8],
         }
     }
 
-    pub fn cycle(&mut self, input: &[u8], address: &[u8; 9], load: u8) -> Vec<u8> {
-        let temp = DEMUX_8(load, address[6], address[7], address[8]);
-        let mut out = Vec::new();
-        for i in 0..16 {
-            out.push(self.ram64[i].cycle(input, address[0..=5].try_into().unwrap(), temp[i]));
-        }
-
-        multi_MUX_8(
-            &out[0], &out[1], &out[2], &out[3], &out[4], &out[5], &out[6], &out[7], address[6],
-            address[7], address[8],
+    /// `address` is 9 bits
+    pub fn cycle(&mut self, input: u16, address: u16, load: u8) -> u16 {
+        let [s0, s1, s2] = [6, 7, 8].map(|i| bit(address, 9, i));
+        let temp = DEMUX_8(load, s0, s1, s2);
+        let mut out = [0; 8];
+        let i = (address & 0b111) as usize;
+        out[i] = self.ram64[i].cycle(input, address >> 3, temp[i]);
+        MUX8WAY16(
+            out[0], out[1], out[2], out[3], out[4], out[5], out[6], out[7], s0, s1, s2,
         )
     }
+
+    /// The word at `address` without clocking the chip
+    pub fn peek(&self, address: u16) -> u16 {
+        self.ram64[(address & 0b111) as usize].peek(address >> 3)
+    }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct RAM4K {
     pub ram512: [RAM512; 8],
 }
@@ -192,98 +183,293 @@ pub struct RAM4K {
 impl RAM4K {
     pub fn new() -> Self {
         RAM4K {
-            ram512: [
-                RAM512::new(),
-                RAM512::new(),
-                RAM512::new(),
-                RAM512::new(),
-                RAM512::new(),
-                RAM512::new(),
-                RAM512::new(),
-                RAM512::new(),
-            ],
+            ram512: [RAM512::new(); 8],
         }
     }
 
-    pub fn cycle(&mut self, input: &[u8], address: &[u8; 12], load: u8) -> Vec<u8> {
-        let temp = DEMUX_8(load, address[9], address[10], address[11]);
-        let mut out = Vec::new();
-        for i in 0..16 {
-            out.push(self.ram512[i].cycle(input, address[0..=8].try_into().unwrap(), temp[i]));
-        }
-
-        multi_MUX_8(
-            &out[0],
-            &out[1],
-            &out[2],
-            &out[3],
-            &out[4],
-            &out[5],
-            &out[6],
-            &out[7],
-            address[9],
-            address[10],
-            address[11],
+    /// `address` is 12 bits
+    pub fn cycle(&mut self, input: u16, address: u16, load: u8) -> u16 {
+        let [s0, s1, s2] = [9, 10, 11].map(|i| bit(address, 12, i));
+        let temp = DEMUX_8(load, s0, s1, s2);
+        let mut out = [0; 8];
+        let i = (address & 0b111) as usize;
+        out[i] = self.ram512[i].cycle(input, address >> 3, temp[i]);
+        MUX8WAY16(
+            out[0], out[1], out[2], out[3], out[4], out[5], out[6], out[7], s0, s1, s2,
         )
     }
+
+    /// The word at `address` without clocking the chip
+    pub fn peek(&self, address: u16) -> u16 {
+        self.ram512[(address & 0b111) as usize].peek(address >> 3)
+    }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct RAM16K {
-    pub ram4k: [RAM4K; 8],
+    pub ram4k: [RAM4K; 4],
 }
 
 impl RAM16K {
     pub fn new() -> Self {
         RAM16K {
-            ram4k: [
-                RAM4K::new(),
-                RAM4K::new(),
-                RAM4K::new(),
-                RAM4K::new(),
-                RAM4K::new(),
-                RAM4K::new(),
-                RAM4K::new(),
-                RAM4K::new(),
-            ],
+            ram4k: [RAM4K::new(); 4],
         }
     }
 
-    pub fn cycle(&mut self, input: &[u8], address: &[u8; 14], load: u8) -> Vec<u8> {
-        let temp = DEMUX_4(load, address[12], address[13]);
-        let mut out = Vec::new();
-        for i in 0..16 {
-            out.push(self.ram4k[i].cycle(input, address[0..=11].try_into().unwrap(), temp[i]));
-        }
+    /// `address` is 14 bits
+    pub fn cycle(&mut self, input: u16, address: u16, load: u8) -> u16 {
+        let [s0, s1] = [12, 13].map(|i| bit(address, 14, i));
+        let temp = DEMUX_4(load, s0, s1);
+        let mut out = [0; 4];
+        let i = (address & 0b11) as usize;
+        out[i] = self.ram4k[i].cycle(input, address >> 2, temp[i]);
+        MUX4WAY16(out[0], out[1], out[2], out[3], s0, s1)
+    }
 
-        multi_MUX_4(&out[0], &out[1], &out[2], &out[3], address[12], address[13])
+    /// The word at `address` without clocking the chip
+    pub fn peek(&self, address: u16) -> u16 {
+        self.ram4k[(address & 0b11) as usize].peek(address >> 2)
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct RAM32K {
-    pub ram4k: [RAM16K; 2],
+    pub ram16k: [RAM16K; 2],
 }
 
 impl RAM32K {
     pub fn new() -> Self {
         RAM32K {
-            ram4k: [RAM16K::new(), RAM16K::new()],
+            ram16k: [RAM16K::new(); 2],
+        }
+    }
+
+    /// `address` is 15 bits
+    pub fn cycle(&mut self, input: u16, address: u16, load: u8) -> u16 {
+        let s0 = bit(address, 15, 14);
+        let temp = DEMUX(load, s0);
+        let mut out = [0; 2];
+        let i = (address & 0b1) as usize;
+        out[i] = self.ram16k[i].cycle(input, address >> 1, temp[i]);
+        MUX16(out[0], out[1], s0)
+    }
+
+    /// The word at `address` without clocking the chip
+    pub fn peek(&self, address: u16) -> u16 {
+        self.ram16k[(address & 0b1) as usize].peek(address >> 1)
+    }
+}
+
+/// The 512x256 screen memory map, one bit per pixel with 32 words per row and the LSB of each word on the left
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Screen {
+    pub ram4k: [RAM4K; 2],
+}
+
+impl Screen {
+    pub fn new() -> Self {
+        Screen {
+            ram4k: [RAM4K::new(); 2],
+        }
+    }
+
+    /// `address` is 13 bits
+    pub fn cycle(&mut self, input: u16, address: u16, load: u8) -> u16 {
+        let s0 = bit(address, 13, 12);
+        let temp = DEMUX(load, s0);
+        let mut out = [0; 2];
+        let i = (address & 0b1) as usize;
+        out[i] = self.ram4k[i].cycle(input, address >> 1, temp[i]);
+        MUX16(out[0], out[1], s0)
+    }
+
+    /// The word at `address` without clocking the chip
+    pub fn peek(&self, address: u16) -> u16 {
+        self.ram4k[(address & 0b1) as usize].peek(address >> 1)
+    }
+}
+
+/// The scan code of the key being held down, 0 if there isn't one. The CPU can only read it, the front end sets it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Keyboard {
+    pub key: Register,
+}
+
+impl Keyboard {
+    pub fn new() -> Self {
+        Keyboard {
+            key: Register::new(),
         }
     }
 
-    pub fn cycle(&mut self, input: &[u8], address: &[u8; 15], load: u8) -> Vec<u8> {
-        let temp = DEMUX(load, address[14]);
-        let mut out = Vec::new();
-        for i in 0..16 {
-            out.push(self.ram4k[i].cycle(input, address[0..=13].try_into().unwrap(), temp[i]));
+    pub fn press(&mut self, key_code: u16) {
+        self.key.cycle(key_code, 1);
+    }
+
+    pub fn out(&self) -> u16 {
+        self.key.data
+    }
+}
+
+/// The data memory of the Hack computer: RAM16K at 0-16383, the screen at 16384-24575 and the keyboard at 24576. The
+/// top two address bits pick the device, like the Memory chip in the book: 00 and 01 are RAM, 10 the screen and 11 the
+/// keyboard, so the unused addresses past 24576 read the keyboard too and writes to them are dropped.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Memory {
+    pub ram: Box<RAM16K>,
+    pub screen: Box<Screen>,
+    pub keyboard: Keyboard,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Memory {
+            ram: Box::new(RAM16K::new()),
+            screen: Box::new(Screen::new()),
+            keyboard: Keyboard::new(),
         }
+    }
+
+    /// `address` is 15 bits
+    pub fn cycle(&mut self, input: u16, address: u16, load: u8) -> u16 {
+        let [s0, s1] = [0, 1].map(|i| bit(address, 15, i));
+        let [ram_low, ram_high, screen, _] = DEMUX_4(load, s0, s1);
+        let ram = self.ram.cycle(
+            input,
+            address & 0b0011_1111_1111_1111,
+            OR(ram_low, ram_high),
+        );
+        let screen = self
+            .screen
+            .cycle(input, address & 0b0001_1111_1111_1111, screen);
+        MUX4WAY16(ram, ram, screen, self.keyboard.out(), s0, s1)
+    }
 
-        multi_MUX(&out[0], &out[1], address[14])
+    /// The word at `address` without clocking the chip
+    pub fn peek(&self, address: u16) -> u16 {
+        let [s0, s1] = [0, 1].map(|i| bit(address, 15, i));
+        MUX4WAY16(
+            self.ram.peek(address & 0b0011_1111_1111_1111),
+            self.ram.peek(address & 0b0011_1111_1111_1111),
+            self.screen.peek(address & 0b0001_1111_1111_1111),
+            self.keyboard.out(),
+            s0,
+            s1,
+        )
+    }
+
+    /// Sets the word at `address` from outside the computer, like a debugger would. The keyboard addresses set the
+    /// key being pressed.
+    pub fn poke(&mut self, address: u16, value: u16) {
+        match address & 0b0111_1111_1111_1111 {
+            0..=0x5FFF => {
+                self.cycle(value, address, 1);
+            }
+            _ => self.keyboard.press(value),
+        }
     }
 }
 
+/// 32K words of program memory: a RAM32K with its load line wired to 0, so the CPU can only read it. The program is
+/// burned in before the computer runs, by `with_program` or by loading a .hack file.
 #[derive(Clone, Debug, PartialEq)]
+pub struct ROM32K {
+    pub words: Box<RAM32K>,
+}
+
+impl ROM32K {
+    /// Instructions the ROM holds, one for each 15 bit address
+    pub const SIZE: usize = 32768;
+
+    pub fn new() -> Self {
+        ROM32K {
+            words: Box::new(RAM32K::new()),
+        }
+    }
+
+    /// A ROM holding `program` from address 0, the rest is 0. Panics if the program doesn't fit, `load` and
+    /// `load_program` report that as an error instead.
+    pub fn with_program(program: &[u16]) -> Self {
+        assert!(
+            program.len() <= Self::SIZE,
+            "the program is {} instructions long, ROM32K only holds {}",
+            program.len(),
+            Self::SIZE
+        );
+        let mut rom = ROM32K::new();
+        for (address, &instruction) in program.iter().enumerate() {
+            rom.burn(address as u16, instruction);
+        }
+        rom
+    }
+
+    /// Loads the .hack file at `path`, a file that can't be read is an error too
+    pub fn load(path: &Path) -> Result<Self, Diagnostic> {
+        Ok(Self::with_program(&Self::load_program(path)?))
+    }
+
+    /// Reads a .hack file, see `parse`
+    pub fn parse(file: &str, source: &str) -> Result<Self, Diagnostic> {
+        Ok(Self::with_program(&Self::parse_program(file, source)?))
+    }
+
+    /// The instructions in the .hack file at `path`, checked like `parse_program` so they fit in the ROM
+    pub fn load_program(path: &Path) -> Result<Vec<u16>, Diagnostic> {
+        let source = fs::read_to_string(path).map_err(|e| Diagnostic::unreadable(path, e))?;
+        let file = path.file_name().unwrap_or_default().to_string_lossy();
+        Self::parse_program(&file, &source)
+    }
+
+    /// Reads a .hack file, one 16 bit binary instruction per line. Blank lines are skipped, anything else that isn't
+    /// an instruction, and any instruction past the 32768th, is an error pointing at its line.
+    pub fn parse_program(file: &str, source: &str) -> Result<Vec<u16>, Diagnostic> {
+        let mut program = Vec::new();
+        let mut start = 0;
+        for line in source.split_inclusive('\n') {
+            let word = line.trim();
+            let offset = start + line.find(word).unwrap_or(0);
+            start += line.len();
+            if word.is_empty() {
+                continue;
+            }
+
+            let span = Span::new(offset, offset + word.len());
+            if word.len() != 16 || !word.bytes().all(|b| b == b'0' || b == b'1') {
+                let message = format!("expected a 16 bit binary instruction, found `{word}`");
+                return Err(Diagnostic::new(file, source, span, message));
+            }
+            if program.len() == Self::SIZE {
+                let length = source.lines().filter(|l| !l.trim().is_empty()).count();
+                let message = format!(
+                    "the program is {length} instructions long, ROM32K only holds {}",
+                    Self::SIZE
+                );
+                return Err(Diagnostic::new(file, source, span, message));
+            }
+
+            program.push(u16::from_str_radix(word, 2).unwrap());
+        }
+        Ok(program)
+    }
+
+    /// Raises the load line for a single word, only while the program is burned in
+    fn burn(&mut self, address: u16, instruction: u16) {
+        self.words.cycle(instruction, address, 1);
+    }
+
+    /// The instruction at `address`. The ROM has 15 address pins, so the top bit of a 16 bit PC isn't connected.
+    pub fn cycle(&mut self, address: u16) -> u16 {
+        self.words.cycle(0, address & 0b0111_1111_1111_1111, 0)
+    }
+
+    /// The instruction at `address` without clocking the chip
+    pub fn peek(&self, address: u16) -> u16 {
+        self.words.peek(address & 0b0111_1111_1111_1111)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct InstPtr {
     pub val: Register,
 }
@@ -295,13 +481,13 @@ impl InstPtr {
         }
     }
 
-    pub fn cycle(&mut self, input: &[u8], load: u8, inc: u8, reset: u8) -> &Vec<u8> {
-        let plus_one = incrementer(&self.val.data);
-        let temp1 = multi_MUX(&self.val.data, &plus_one, inc);
-        let temp2 = multi_MUX(&temp1, input, load);
-        let out = multi_MUX(&temp2, &[0; 16], reset);
+    pub fn cycle(&mut self, input: u16, load: u8, inc: u8, reset: u8) -> u16 {
+        let plus_one = incrementer16(self.val.data);
+        let temp1 = MUX16(self.val.data, plus_one, inc);
+        let temp2 = MUX16(temp1, input, load);
+        let out = MUX16(temp2, 0, reset);
 
-        self.val.cycle(&out, 1);
-        &self.val.data
+        self.val.cycle(out, 1);
+        self.val.data
     }
 }
@@ -0,0 +1,264 @@
+//! The HDL library chips simulated event by event, with a propagation delay per NAND gate.
+//!
+//! `hdl::simulator::Simulator` elaborates a chip down to `Nand` and `DFF` components and evaluates them all at once.
+//! Here the same components get a delay. `arrival_times` and `critical_path` answer the static questions (when does
+//! each node settle, which path is the slowest) and `EventSim` replays what happens when an input changes: every node
+//! change is an event, a NAND whose input changed schedules its new output `delay` time units later. Since the paths
+//! through a chip have different lengths, a node can change several times before it settles, those glitches are kept
+//! in the trace.
+//!
+//! Nodes and buses are the simulator's, lowest bit first. DFFs hold their output, there is no clock.
+
+use crate::hardware::{
+    hdl::simulator::{Component, Node, Simulator, TRUE},
+    logic_gate::gates::NAND,
+};
+use std::{cmp::Reverse, collections::BinaryHeap, fmt::Write};
+
+/// The slowest path through a chip
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CriticalPath {
+    /// sum of the delays along the path
+    pub delay: u32,
+    /// the NANDs on the path, as indices into the chip's components, from an input to the output
+    pub gates: Vec<usize>,
+}
+
+/// When each node settles after the inputs change, if every NAND takes `delay`. Inputs, constants, DFF and builtin
+/// outputs are there at 0.
+pub fn arrival_times(chip: &Simulator, delay: u32) -> Vec<u32> {
+    let mut arrival = vec![0; chip.values.len()];
+    // components are in evaluation order, so a NAND's inputs already have their time
+    for component in &chip.components {
+        if let Component::Nand { a, b, out } = *component {
+            arrival[out] = arrival[a].max(arrival[b]) + delay;
+        }
+    }
+    arrival
+}
+
+/// The slowest path to any of `outputs`
+pub fn critical_path(chip: &Simulator, outputs: &[Node], delay: u32) -> CriticalPath {
+    let arrival = arrival_times(chip, delay);
+    let Some(mut node) = outputs.iter().copied().max_by_key(|&n| arrival[n]) else {
+        return CriticalPath {
+            delay: 0,
+            gates: Vec::new(),
+        };
+    };
+
+    let mut driver = vec![None; chip.values.len()];
+    for (i, component) in chip.components.iter().enumerate() {
+        if let Component::Nand { out, .. } = *component {
+            driver[out] = Some(i);
+        }
+    }
+
+    let mut gates = Vec::new();
+    while let Some(index) = driver[node] {
+        gates.push(index);
+        let Component::Nand { a, b, .. } = chip.components[index] else {
+            unreachable!()
+        };
+        node = if arrival[a] >= arrival[b] { a } else { b };
+    }
+    gates.reverse();
+
+    CriticalPath {
+        delay: gates.len() as u32 * delay,
+        gates,
+    }
+}
+
+/// A node changing value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transition {
+    pub time: u64,
+    pub node: Node,
+    pub value: u8,
+}
+
+/// Event-driven simulation of an elaborated chip with transport delays: every change of a NAND's inputs reaches its
+/// output after `delay`, even pulses shorter than the delay.
+#[derive(Debug, Clone)]
+pub struct EventSim<'a> {
+    pub chip: &'a Simulator,
+    /// delay of every NAND, change it before the first `run`
+    pub delay: u32,
+    pub values: Vec<u8>,
+    /// time of the last event
+    pub time: u64,
+    /// every node change so far
+    pub trace: Vec<Transition>,
+
+    /// the NANDs reading each node
+    fanout: Vec<Vec<usize>>,
+    /// nodes driven by a component, which `set` can't change
+    driven: Vec<bool>,
+    /// (time, order of scheduling, node, value)
+    events: BinaryHeap<Reverse<(u64, u64, Node, u8)>>,
+    scheduled: u64,
+    /// start of the last `run` in `trace`, and its time
+    run_start: (usize, u64),
+}
+
+impl<'a> EventSim<'a> {
+    /// Starts from the values `chip` settled on, every NAND takes 1 time unit
+    pub fn new(chip: &'a Simulator) -> Self {
+        let mut fanout = vec![Vec::new(); chip.values.len()];
+        let mut driven = vec![false; chip.values.len()];
+        for (i, component) in chip.components.iter().enumerate() {
+            match *component {
+                Component::Nand { a, b, out } => {
+                    fanout[a].push(i);
+                    if b != a {
+                        fanout[b].push(i);
+                    }
+                    driven[out] = true;
+                }
+                Component::Dff { out, .. } => driven[out] = true,
+                Component::Builtin(ref part) => {
+                    panic!(
+                        "{} has a builtin {:?}, only NANDs have a delay",
+                        chip.chip, part.chip
+                    )
+                }
+            }
+        }
+
+        EventSim {
+            chip,
+            delay: 1,
+            values: chip.values.clone(),
+            time: 0,
+            trace: Vec::new(),
+            fanout,
+            driven,
+            events: BinaryHeap::new(),
+            scheduled: 0,
+            run_start: (0, 0),
+        }
+    }
+
+    /// Changes an input at the current time, takes effect on the next `run`
+    pub fn set(&mut self, node: Node, value: u8) {
+        assert!(
+            node > TRUE && !self.driven[node],
+            "node {node} isn't an input"
+        );
+        self.schedule(self.time, node, value);
+    }
+
+    pub fn set_bus(&mut self, bus: &[Node], value: u16) {
+        for (i, &node) in bus.iter().enumerate() {
+            self.set(node, (value >> i & 1) as u8);
+        }
+    }
+
+    pub fn get(&self, node: Node) -> u8 {
+        self.values[node]
+    }
+
+    pub fn get_bus(&self, bus: &[Node]) -> u16 {
+        bus.iter()
+            .rev()
+            .fold(0, |acc, &node| acc << 1 | self.values[node] as u16)
+    }
+
+    fn schedule(&mut self, time: u64, node: Node, value: u8) {
+        self.events
+            .push(Reverse((time, self.scheduled, node, value)));
+        self.scheduled += 1;
+    }
+
+    /// Processes events until the chip is stable again. Returns how long that took.
+    pub fn run(&mut self) -> u64 {
+        let start = self.time;
+        self.run_start = (self.trace.len(), start);
+
+        while let Some(&Reverse((time, ..))) = self.events.peek() {
+            self.time = time;
+
+            // apply every change at this time before re-evaluating the NANDs, so a NAND whose inputs change together
+            // is only evaluated once
+            let mut affected: Vec<usize> = Vec::new();
+            while let Some(&Reverse((t, _, node, value))) = self.events.peek() {
+                if t != time {
+                    break;
+                }
+                self.events.pop();
+                if self.values[node] != value {
+                    self.values[node] = value;
+                    self.trace.push(Transition { time, node, value });
+                    affected.extend(&self.fanout[node]);
+                }
+            }
+            affected.sort_unstable();
+            affected.dedup();
+
+            for index in affected {
+                let Component::Nand { a, b, out } = self.chip.components[index] else {
+                    unreachable!()
+                };
+                let value = NAND(self.values[a], self.values[b]);
+                self.schedule(time + self.delay as u64, out, value);
+            }
+        }
+
+        self.time - start
+    }
+
+    /// The changes during the last `run`, times relative to its start
+    pub fn last_run(&self) -> impl Iterator<Item = Transition> + '_ {
+        let (index, start) = self.run_start;
+        self.trace[index..].iter().map(move |t| Transition {
+            time: t.time - start,
+            ..*t
+        })
+    }
+
+    /// Changes of `node` during the last run. More than one means the node glitched on its way to the final value.
+    pub fn transitions(&self, node: Node) -> Vec<Transition> {
+        self.last_run().filter(|t| t.node == node).collect()
+    }
+
+    /// The nodes among `nodes` that changed more than once during the last run
+    pub fn glitches(&self, nodes: &[Node]) -> Vec<Node> {
+        nodes
+            .iter()
+            .copied()
+            .filter(|&node| self.transitions(node).len() > 1)
+            .collect()
+    }
+
+    /// The last run as text, one line per signal, `_` for 0 and `#` for 1 at each time unit
+    pub fn waveform(&self, signals: &[(&str, Node)]) -> String {
+        let end = self.time - self.run_start.1;
+        let width = signals
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or(0);
+
+        let mut out = String::new();
+        for &(name, node) in signals {
+            let transitions = self.transitions(node);
+            // the value before the run is the opposite of its first change
+            let mut value = match transitions.first() {
+                Some(first) => 1 - first.value,
+                None => self.values[node],
+            };
+            let mut changes = transitions.iter().peekable();
+
+            write!(out, "{name:>width$} ").unwrap();
+            for time in 0..=end {
+                while let Some(change) = changes.next_if(|c| c.time <= time) {
+                    value = change.value;
+                }
+                out.push(if value == 1 { '#' } else { '_' });
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
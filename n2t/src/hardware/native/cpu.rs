@@ -1,11 +1,14 @@
 use super::alu::ALU;
 use crate::{
-    hardware::native::os::{Block, OS},
+    hardware::{
+        hack_cpu::{CpuSignals, HackCpu},
+        logic_gate::alu,
+        native::os::{Block, OS},
+    },
     utils::{decode_instr, BuiltInFunc},
 };
 use enumflags2::{bitflags, BitFlags};
 use prettytable::ptable;
-use std::collections::LinkedList;
 
 #[derive(Debug, PartialEq)]
 pub enum InstrType {
@@ -52,6 +55,9 @@ pub struct Computer {
     pub time: usize,
     pub alu_out: u16,
     pub m_in: u16,
+    /// the RAM address and load line of the last cycle
+    pub address_m: u16,
+    pub write_m: bool,
     pub os: OS,
 }
 
@@ -70,6 +76,8 @@ impl Computer {
             time: 0,
             alu_out: 0,
             m_in: 0,
+            address_m: 0,
+            write_m: false,
             os: Default::default(),
         }
     }
@@ -136,6 +144,9 @@ impl Computer {
         let in_bits = ((instr & 0b0000_1111_1100_0000) >> 4) as u8;
         self.flags = BitFlags::from_bits(out_bits | in_bits).unwrap();
 
+        self.address_m = self.a & 0b0111_1111_1111_1111;
+        self.write_m = false;
+
         let instr_type = match instr & 0b1000_0000_0000_0000 {
             0 => InstrType::A,
             _ => match instr & 0b1110_0000_0000_0000 {
@@ -199,18 +210,22 @@ impl Computer {
                 }
             }
             InstrType::C => {
+                // M and the jump target are both the A from before the instruction, RAM is addressed with 15 bits
+                let addr = (self.a & 0b0111_1111_1111_1111) as usize;
+                let jump_target = self.a;
+
                 let input = match (0b0001_0000_0000_0000 & instr) == 0 {
                     true => self.a,
-                    false => self.ram[self.a as usize],
+                    false => self.ram[addr],
                 };
 
                 // calc
                 self.alu_out = ALU(self.d, input, &mut self.flags);
 
                 // set output values
-                let addr = self.a as usize;
 
                 if (instr & 0b0000_0000_0000_1000) != 0 {
+                    self.write_m = true;
                     self.ram[addr] = self.alu_out;
                 }
 
@@ -244,7 +259,7 @@ impl Computer {
                         // dbg!(self.a);
                         // dbg!(self.pc);
                     }
-                    self.pc = self.a;
+                    self.pc = jump_target;
                 } else {
                     self.pc += 1;
                 }
@@ -494,3 +509,59 @@ impl Computer {
         self.os_dealloc()
     }
 }
+
+impl HackCpu for Computer {
+    fn with_program(program: Vec<u16>) -> Self {
+        Computer::new(program)
+    }
+
+    fn step(&mut self) {
+        Computer::step(self, false, false)
+    }
+
+    fn reset(&mut self) {
+        self.pc = 0;
+    }
+
+    fn a(&self) -> u16 {
+        self.a
+    }
+
+    fn d(&self) -> u16 {
+        self.d
+    }
+
+    fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    fn ram(&self, address: u16) -> u16 {
+        self.ram[(address & 0b0111_1111_1111_1111) as usize]
+    }
+
+    fn set_ram(&mut self, address: u16, value: u16) {
+        self.ram[(address & 0b0111_1111_1111_1111) as usize] = value;
+    }
+
+    fn signals(&self) -> CpuSignals {
+        let flag = |bit| self.flags.contains(bit) as u8;
+        CpuSignals {
+            a: self.a,
+            d: self.d,
+            pc: self.pc,
+            control: alu::ControlBits {
+                zx: flag(ControlBits::ZeroX),
+                nx: flag(ControlBits::NotX),
+                zy: flag(ControlBits::ZeroY),
+                ny: flag(ControlBits::NotY),
+                f: flag(ControlBits::FSelect),
+                no: flag(ControlBits::NotOut),
+                zr: flag(ControlBits::Zero),
+                ng: flag(ControlBits::Neg),
+            },
+            out_m: self.alu_out,
+            address_m: self.address_m,
+            write_m: self.write_m as u8,
+        }
+    }
+}
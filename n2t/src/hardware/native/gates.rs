@@ -1,7 +1,7 @@
 #![allow(non_snake_case, clippy::too_many_arguments)]
 
-/// most of the functions of gates are supplanted by basics like &, |, ^, etc.
-/// for consistency i'll go ahead and write it out though
+//! most of the functions of gates are supplanted by basics like &, |, ^, etc.
+//! for consistency i'll go ahead and write it out though
 
 pub fn NAND(a: u16, b: u16) -> u16 {
     !(a & b)
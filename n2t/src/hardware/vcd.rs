@@ -0,0 +1,181 @@
+//! Value Change Dump files, the waveform format read by GTKWave, Surfer and most other waveform viewers.
+//!
+//! `Vcd` writes the header for a list of signals, then only the values that changed at each time step. `dump_cpu`
+//! records the pins of any `HackCpu` once per cycle, `dump_trace` writes out the node changes of a gate-level
+//! `EventSim`. Chips without a ready-made dump, like the `logic_gate` memory chips, can call `Vcd::change` directly
+//! after every `cycle`.
+
+use crate::hardware::{hack_cpu::HackCpu, hdl::simulator::Node, logic_gate::timing::EventSim};
+use std::io::{self, Write};
+
+/// Writes a VCD file. Every signal is a wire of up to 16 bits, times are in `timescale` units.
+pub struct Vcd<W: Write> {
+    out: W,
+    widths: Vec<u8>,
+    /// the last value written for each signal
+    values: Vec<Option<u16>>,
+    time: Option<u64>,
+}
+
+impl<W: Write> Vcd<W> {
+    /// Writes the header, declaring `signals` as (name, width in bits) in a single scope
+    pub fn new(
+        mut out: W,
+        timescale: &str,
+        scope: &str,
+        signals: &[(&str, u8)],
+    ) -> io::Result<Self> {
+        writeln!(out, "$version n2t $end")?;
+        writeln!(out, "$timescale {timescale} $end")?;
+        writeln!(out, "$scope module {scope} $end")?;
+        for (i, &(name, width)) in signals.iter().enumerate() {
+            assert!((1..=16).contains(&width), "{name} is {width} bits wide");
+            let name = name.replace(' ', "_");
+            match width {
+                1 => writeln!(out, "$var wire 1 {} {name} $end", identifier(i))?,
+                _ => writeln!(
+                    out,
+                    "$var wire {width} {} {name} [{}:0] $end",
+                    identifier(i),
+                    width - 1
+                )?,
+            }
+        }
+        writeln!(out, "$upscope $end")?;
+        writeln!(out, "$enddefinitions $end")?;
+
+        Ok(Vcd {
+            out,
+            widths: signals.iter().map(|&(_, width)| width).collect(),
+            values: vec![None; signals.len()],
+            time: None,
+        })
+    }
+
+    /// Records the value of every signal at `time`, in the order they were declared. Only the changes are written.
+    pub fn change(&mut self, time: u64, values: &[u16]) -> io::Result<()> {
+        assert_eq!(values.len(), self.widths.len(), "one value per signal");
+        for (i, &value) in values.iter().enumerate() {
+            self.set(time, i, value)?;
+        }
+        Ok(())
+    }
+
+    /// Records a single signal changing at `time`, times must not go backwards
+    pub fn set(&mut self, time: u64, signal: usize, value: u16) -> io::Result<()> {
+        let width = self.widths[signal];
+        let value = if width == 16 {
+            value
+        } else {
+            value & ((1 << width) - 1)
+        };
+        if self.values[signal] == Some(value) {
+            return Ok(());
+        }
+
+        if let Some(last) = self.time {
+            assert!(last <= time, "time went back from {last} to {time}");
+        }
+        if self.time != Some(time) {
+            writeln!(self.out, "#{time}")?;
+            self.time = Some(time);
+        }
+
+        match width {
+            1 => writeln!(self.out, "{value}{}", identifier(signal))?,
+            _ => writeln!(self.out, "b{value:b} {}", identifier(signal))?,
+        }
+        self.values[signal] = Some(value);
+        Ok(())
+    }
+
+    /// Writes the final time stamp and hands back the writer
+    pub fn finish(mut self, time: u64) -> io::Result<W> {
+        if self.time.is_some_and(|last| last < time) {
+            writeln!(self.out, "#{time}")?;
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// Short identifier codes made of the printable ASCII characters `!` to `~`
+fn identifier(mut index: usize) -> String {
+    let mut id = String::new();
+    loop {
+        id.push((b'!' + (index % 94) as u8) as char);
+        index /= 94;
+        if index == 0 {
+            return id;
+        }
+        index -= 1;
+    }
+}
+
+/// The CPU signals `dump_cpu` records
+pub const CPU_SIGNALS: [(&str, u8); 14] = [
+    ("A", 16),
+    ("D", 16),
+    ("PC", 16),
+    ("zx", 1),
+    ("nx", 1),
+    ("zy", 1),
+    ("ny", 1),
+    ("f", 1),
+    ("no", 1),
+    ("zr", 1),
+    ("ng", 1),
+    ("outM", 16),
+    ("addressM", 15),
+    ("writeM", 1),
+];
+
+/// Runs `cpu` for `cycles` cycles, dumping its signals after each one. Time 0 is the state before the first cycle,
+/// one time unit is a clock cycle.
+pub fn dump_cpu<C: HackCpu, W: Write>(cpu: &mut C, cycles: u64, out: W) -> io::Result<W> {
+    let mut vcd = Vcd::new(out, "1 us", "cpu", &CPU_SIGNALS)?;
+    for time in 0..=cycles {
+        if time > 0 {
+            cpu.step();
+        }
+        let s = cpu.signals();
+        let c = s.control;
+        #[rustfmt::skip]
+        vcd.change(time, &[
+            s.a, s.d, s.pc,
+            c.zx as u16, c.nx as u16, c.zy as u16, c.ny as u16, c.f as u16, c.no as u16, c.zr as u16, c.ng as u16,
+            s.out_m, s.address_m, s.write_m as u16,
+        ])?;
+    }
+    vcd.finish(cycles)
+}
+
+/// Writes every change of `nodes` recorded by `sim` so far, one time unit is a gate delay. Time 0 has the values
+/// before the first change, so the trace is shifted by one time unit.
+pub fn dump_trace<W: Write>(sim: &EventSim, nodes: &[(&str, Node)], out: W) -> io::Result<W> {
+    let signals: Vec<_> = nodes.iter().map(|&(name, _)| (name, 1)).collect();
+    let mut vcd = Vcd::new(out, "1 ns", "netlist", &signals)?;
+
+    // the value before a node's first change is the opposite of that change
+    let initial: Vec<u16> = nodes
+        .iter()
+        .map(
+            |&(_, node)| match sim.trace.iter().find(|t| t.node == node) {
+                Some(first) => 1 - first.value as u16,
+                None => sim.get(node) as u16,
+            },
+        )
+        .collect();
+    vcd.change(0, &initial)?;
+
+    for transition in &sim.trace {
+        for (i, _) in nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, &(_, n))| n == transition.node)
+        {
+            vcd.set(transition.time + 1, i, transition.value as u16)?;
+        }
+    }
+    vcd.finish(sim.time + 1)
+}
@@ -1,11 +1,28 @@
 /// logical implementations using only NAND chip + manually constructed chips
 pub mod hardware {
+    /// random programs run lock-step on two CPU implementations to find where they disagree
+    pub mod cpu_fuzz;
+    /// the interface shared by the gate-level and native CPUs
+    pub mod hack_cpu;
+
+    /// nand2tetris HDL chips, parsed and simulated down to NAND gates and DFFs
+    pub mod hdl {
+        pub mod builtins;
+        pub mod library;
+        pub mod parser;
+        pub mod simulator;
+    }
+
     pub mod logic_gate {
         pub mod alu;
         pub mod arithmetic;
+        /// NAND and DFF counts and logic depth of the HDL library chips
+        pub mod cost;
         pub mod cpu;
         pub mod gates;
         pub mod memory;
+        /// the HDL library chips simulated event by event, with a propagation delay per NAND
+        pub mod timing;
     }
 
     /// shortcut implementations in native rust to speed up processing
@@ -17,19 +34,38 @@ pub mod hardware {
         pub mod memory;
         pub mod os;
     }
+
+    /// Value Change Dump waveforms of the cpus and netlists
+    pub mod vcd;
 }
 
 pub mod software {
     pub mod assembler;
     pub mod compiler;
     pub mod compiler_utils;
+    pub mod diagnostics;
+    pub mod jack_ast;
+    pub mod jack_checker;
+    pub mod jack_lexer;
+    pub mod jack_optimizer;
+    pub mod jack_parser;
+    pub mod peephole;
     pub mod tokenizer;
     pub mod tokenizer_utils;
     pub mod vm;
     pub mod vm_instructions;
+    pub mod vm_interpreter;
+    pub mod vm_ir;
+    pub mod vm_optimizer;
     pub mod writer_impl;
 }
 
+/// nand2tetris .tst test scripts for chips and Hack programs
+pub mod test_script {
+    pub mod parser;
+    pub mod runner;
+}
+
 pub mod utils;
 
 pub const STACK_START: usize = 256;
@@ -54,7 +90,7 @@ pub const SCREEN_END: usize = 0x6000;
 
 pub const KEYBOARD: usize = 0x6000;
 
-use std::{fs::File, path::PathBuf};
+use std::path::PathBuf;
 
 use bitvec::prelude::*;
 
@@ -88,22 +124,29 @@ pub fn u16_to_u8_array(vals: &mut [u16]) -> &mut [u8] {
     unsafe { std::slice::from_raw_parts_mut(ptr, len) }
 }
 
-use hardware::native::cpu::Computer;
-use software::{assembler::asm_to_hack, compiler::JackCompiler, vm::vm_to_asm};
-use std::io::Write;
-use utils::{decode_instr, hack_to_vec};
-
+use hardware::{hack_cpu::HackCpu, logic_gate::memory::ROM32K, native::cpu::Computer};
+use software::{
+    assembler::asm_to_hack,
+    compiler::JackCompiler,
+    diagnostics::{render, Diagnostic},
+    vm::vm_to_asm,
+};
+use utils::hack_to_vec;
+
+/// A program running on one of the computers, the native `Computer` by default. The front end only goes through
+/// `HackCpu`, so the gate-level `logic_gate::cpu::Computer` can stand in for it.
 #[derive(Debug)]
-pub struct HackEmulator {
+pub struct HackEmulator<C = Computer> {
     pub program: PathBuf,
     // pub instr: Vec<String>,
-    pub cpu: Computer,
+    pub cpu: C,
 }
 
 impl HackEmulator {
     /// Accepts a path to a .jack file or a folder containing .jack files.
     pub fn new(program: PathBuf) -> Self {
-        let vm_path = JackCompiler::compile(&program);
+        let vm_path = JackCompiler::compile_opt(&program, true)
+            .unwrap_or_else(|errors| panic!("{}", render(&errors)));
         let asm_path = vm_to_asm(&vm_path);
         let hack_path = asm_to_hack(&asm_path);
         let machine_code = hack_to_vec(&hack_path);
@@ -124,16 +167,34 @@ impl HackEmulator {
             // instr,
         }
     }
+}
+
+impl<C: HackCpu> HackEmulator<C> {
+    /// Loads an assembled .hack file as is. Programs that bring their own OS, like Pong from chapter 6, run on any
+    /// computer. Files that can't be read, aren't machine code or don't fit in the 32K ROM are an error.
+    pub fn from_hack(program: PathBuf) -> Result<Self, Diagnostic> {
+        let cpu = C::with_program(ROM32K::load_program(&program)?);
+        Ok(HackEmulator { program, cpu })
+    }
+
+    /// Steps exactly `cycles` times
+    pub fn run(&mut self, cycles: usize) {
+        for _ in 0..cycles {
+            self.cpu.step();
+        }
+    }
 
-    pub fn get_screen(&self) -> &[u16] {
-        &self.cpu.ram[0x4000..0x6000]
+    pub fn get_screen(&self) -> Vec<u16> {
+        (SCREEN_START..SCREEN_END)
+            .map(|address| self.cpu.ram(address as u16))
+            .collect()
     }
 
-    pub fn get_keyboard(&self) -> &u16 {
-        &self.cpu.ram[0x6000]
+    pub fn get_keyboard(&self) -> u16 {
+        self.cpu.ram(KEYBOARD as u16)
     }
 
     pub fn set_keyboard(&mut self, key_code: u16) {
-        self.cpu.ram[0x6000] = key_code
+        self.cpu.set_ram(KEYBOARD as u16, key_code)
     }
 }
@@ -1,13 +1,4 @@
-use std::{
-    fs::File,
-    io::Read,
-    iter::zip,
-    path::{Path, PathBuf},
-    str::FromStr,
-    time::Duration,
-};
-
-use n2t::{pixels_from_bitplane, HackEmulator};
+use n2t::{hardware::hack_cpu::HackCpu, hardware::logic_gate, pixels_from_bitplane, HackEmulator};
 
 use minifb::{Key, Window, WindowOptions};
 
@@ -15,6 +6,15 @@ const WIDTH: usize = 512;
 const HEIGHT: usize = 256;
 
 fn main() {
+    // a .hack file, like `test_files/ch 6/test/Pong.hack`, runs on the gate-level computer
+    if let Some(path) = std::env::args().nth(1).filter(|path| path.ends_with(".hack")) {
+        match HackEmulator::<logic_gate::cpu::Computer>::from_hack(path.into()) {
+            Ok(emu) => show(emu, 100_000),
+            Err(error) => eprintln!("{error}"),
+        }
+        return;
+    }
+
     let mut emu = HackEmulator::new(
         r"G:\Coding and Programming\My Projects\VSC\nand_2_tetris\test_files\ch 11\Square".into(),
     );
@@ -29,9 +29,15 @@ fn main() {
     }
     emu.cpu.run_until(600000, false, false);
 
-    let blank = emu.get_screen().iter().enumerate().filter(|(_, x)| **x != 0).collect::<Vec<_>>();
+    let screen = emu.get_screen();
+    let blank = screen.iter().enumerate().filter(|(_, x)| **x != 0).collect::<Vec<_>>();
     println!("{:?}", blank);
 
+    show(emu, 1_000_000);
+}
+
+/// Runs `cycles_per_frame` cycles per frame until the window is closed, passing the key being held to the keyboard
+fn show<C: HackCpu>(mut emu: HackEmulator<C>, cycles_per_frame: usize) {
     let mut window = Window::new(
         "Test - ESC to exit",
         WIDTH,
@@ -47,11 +53,41 @@ fn main() {
     let mut buffer: Vec<u32> = vec![u32::MAX; WIDTH * HEIGHT];
 
     while window.is_open() && !window.is_key_down(Key::Escape) {
-        emu.cpu.run_exact(1_000_000, false, false);
-        let screen = &mut emu.cpu.ram[0x4000..0x6000];
-        pixels_from_bitplane(screen, &mut buffer);
+        let key = window.get_keys().into_iter().find_map(key_code).unwrap_or(0);
+        emu.set_keyboard(key);
+
+        emu.run(cycles_per_frame);
+        pixels_from_bitplane(&emu.get_screen(), &mut buffer);
 
         // We unwrap here as we want this code to exit if it fails. Real applications may want to handle this in a different way
         window.update_with_buffer(&buffer, WIDTH, HEIGHT).unwrap();
     }
 }
+
+/// The Hack keyboard's code for `key`
+fn key_code(key: Key) -> Option<u16> {
+    let code = match key {
+        Key::Space => 32,
+        Key::Enter => 128,
+        Key::Backspace => 129,
+        Key::Left => 130,
+        Key::Up => 131,
+        Key::Right => 132,
+        Key::Down => 133,
+        Key::Home => 134,
+        Key::End => 135,
+        Key::PageUp => 136,
+        Key::PageDown => 137,
+        Key::Insert => 138,
+        Key::Delete => 139,
+        // the digits and letters are in order in minifb
+        key if (Key::Key0 as u16..=Key::Key9 as u16).contains(&(key as u16)) => {
+            b'0' as u16 + key as u16 - Key::Key0 as u16
+        }
+        key if (Key::A as u16..=Key::Z as u16).contains(&(key as u16)) => {
+            b'A' as u16 + key as u16 - Key::A as u16
+        }
+        _ => return None,
+    };
+    Some(code)
+}
@@ -2,9 +2,8 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::{prelude::*, BufWriter};
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
 
-use crate::utils::{get_file_buffers, BuiltInFunc};
+use crate::utils::get_file_buffers;
 
 #[derive(Debug, Clone, Copy)]
 pub enum Offset {
@@ -14,9 +13,9 @@ pub enum Offset {
     OS,
 }
 
-impl Into<u16> for Offset {
-    fn into(self) -> u16 {
-        match self {
+impl From<Offset> for u16 {
+    fn from(value: Offset) -> Self {
+        match value {
             Offset::Label(x) => x,
             Offset::Var(x) => x,
             Offset::BuiltIn(x) => x,
@@ -37,6 +36,15 @@ pub fn asm_to_hack(path: &Path) -> PathBuf {
     let out_file = File::create(out_path.clone()).unwrap();
     let mut output = BufWriter::new(out_file);
 
+    write!(output, "{}", assemble(buffer)).unwrap();
+
+    output.flush().unwrap();
+
+    out_path
+}
+
+/// Returns a symbol table containing only the predefined symbols (`SP`, `R0`-`R15`, `SCREEN`, etc.)
+pub fn builtin_symbols() -> HashMap<String, Offset> {
     let mut symbol_table: HashMap<String, Offset> = HashMap::new();
 
     symbol_table.insert("SP".to_string(), Offset::BuiltIn(0));
@@ -63,6 +71,14 @@ pub fn asm_to_hack(path: &Path) -> PathBuf {
     symbol_table.insert("SCREEN".to_string(), Offset::BuiltIn(16384));
     symbol_table.insert("KBD".to_string(), Offset::BuiltIn(24576));
 
+    symbol_table
+}
+
+/// Assembles a buffer of Hack assembly, returns the machine code with one instruction per line
+/// (represented as a string of 1's and 0's, same as the contents of a `.hack` file)
+pub fn assemble(buffer: impl BufRead) -> String {
+    let mut symbol_table = builtin_symbols();
+
     // ------------------------------- add labels to symbol table ------------------------------- //
     let mut first_pass: Vec<String> = Vec::new();
     let mut lines = buffer.lines();
@@ -102,13 +118,12 @@ pub fn asm_to_hack(path: &Path) -> PathBuf {
         panic!("Program is longer than 64k and cannot be run on the hack cpu")
     }
 
+    let mut output = String::new();
     for instr in second_pass {
-        write!(output, "{}", translate_instruction(instr, &symbol_table)).unwrap();
+        output.push_str(&translate_instruction(instr, &symbol_table));
     }
 
-    output.flush().unwrap();
-
-    out_path
+    output
 }
 
 /// First pass of the assembler. Takes a single line of Hack VM code, trims it, and adds any labels
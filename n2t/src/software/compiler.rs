@@ -3,11 +3,17 @@
 
 use crate::{
     software::{
-        compiler_utils::{
-            Keyword::{self, *},
-            Symbol::*,
-            Token,
+        compiler_utils::{Dialect, Keyword::*, Token},
+        diagnostics::Diagnostic,
+        jack_ast::{
+            BinaryOp, Class, Expression, Ident, Statement, Subroutine, SubroutineCall, Term,
+            TermKind, UnaryOp, VarDec,
         },
+        jack_optimizer::{
+            doublings, halvings, inline_consts, is_non_negative, is_non_negative_after,
+            optimize_class,
+        },
+        jack_parser::parse_dialect,
         writer_impl::Segment,
     },
     utils::get_file_buffers,
@@ -15,14 +21,12 @@ use crate::{
 use std::{
     collections::HashMap,
     fs::File,
-    io::{BufWriter, Cursor, Read, Write},
+    io::{BufWriter, Read, Write},
     path::{Path, PathBuf},
 };
 
 use maplit::hashmap;
 
-use super::compiler_utils::Symbol;
-
 #[derive(Debug, Clone)]
 pub struct SymbolDef {
     pub segment: Segment,
@@ -73,7 +77,7 @@ impl SymbolTable {
     }
 
     pub fn has(&self, name: &str) -> bool {
-        self.func.get(name).is_some() || self.cls.get(name).is_some()
+        self.func.contains_key(name) || self.cls.contains_key(name)
     }
 
     pub fn insert(&mut self, name: &str, dtype: Token, segment: Segment) {
@@ -107,18 +111,39 @@ impl SymbolTable {
 
 #[derive(Debug)]
 pub struct JackCompiler {
-    pub stream: Cursor<String>,
     pub output: BufWriter<File>,
 
     pub class_name: String,
     pub symbol_table: SymbolTable,
     pub label_count: usize,
+    /// fold constants and replace cheap multiplications, see `jack_optimizer`
+    pub optimize: bool,
+    /// (break, continue) labels of the loops around the statement being compiled, innermost last
+    pub loop_labels: Vec<(usize, usize)>,
 }
 
 impl JackCompiler {
     /// Takes a path to a .jack file or a folder containing .jack files, compiles those files into
-    /// .vm files, and returns the path to the file(s).
-    pub fn compile(path: &Path) -> PathBuf {
+    /// .vm files, and returns the path to the file(s). The output matches the reference compiler,
+    /// see `compile_opt` to optimize it.
+    ///
+    /// A file with errors isn't written, the errors of every file are returned instead.
+    pub fn compile(path: &Path) -> Result<PathBuf, Vec<Diagnostic>> {
+        Self::compile_opt(path, false)
+    }
+
+    /// Same as `compile`, `optimize` toggles constant folding and strength reduction
+    pub fn compile_opt(path: &Path, optimize: bool) -> Result<PathBuf, Vec<Diagnostic>> {
+        Self::compile_with(path, optimize, Dialect::Standard)
+    }
+
+    /// Same as `compile_opt`, for source written in `dialect`. Extended Jack is lowered to the same labels and gotos
+    /// as `if` and `while`, so the output runs on any VM.
+    pub fn compile_with(
+        path: &Path,
+        optimize: bool,
+        dialect: Dialect,
+    ) -> Result<PathBuf, Vec<Diagnostic>> {
         let in_path = PathBuf::from(path);
 
         let out_dir = if in_path.is_file() {
@@ -128,236 +153,115 @@ impl JackCompiler {
         };
 
         let files = get_file_buffers(path, "jack");
+        let mut errors = Vec::new();
 
         for (mut file, file_name) in files {
             let mut output_path = out_dir.clone();
             output_path.push(file_name.clone());
             output_path.set_extension("vm");
 
+            let mut source = String::new();
+            file.read_to_string(&mut source).unwrap();
+            let jack_file = format!("{file_name}.jack");
+            let mut class = match parse_dialect(&jack_file, &source, dialect) {
+                Ok(class) => class,
+                Err(file_errors) => {
+                    errors.extend(file_errors);
+                    continue;
+                }
+            };
+            let mut file_errors: Vec<_> = inline_consts(&mut class)
+                .into_iter()
+                .map(|(span, message)| Diagnostic::new(&jack_file, &source, span, message))
+                .collect();
+            if class.name.name != file_name {
+                let message = format!(
+                    "class {} has to be in {}.jack",
+                    class.name.name, class.name.name
+                );
+                file_errors.push(Diagnostic::new(
+                    &jack_file,
+                    &source,
+                    class.name.span,
+                    message,
+                ));
+            }
+            if !file_errors.is_empty() {
+                errors.extend(file_errors);
+                continue;
+            }
+            if optimize {
+                optimize_class(&mut class);
+            }
+
             let out_file = File::create(output_path).unwrap();
             let output = BufWriter::new(out_file);
 
-            let mut stream = String::new();
-            file.read_to_string(&mut stream).unwrap();
-
             let mut compiler = JackCompiler {
-                stream: Cursor::new(stream),
                 output,
                 class_name: file_name,
                 symbol_table: SymbolTable::default(),
                 label_count: 0,
+                optimize,
+                loop_labels: Vec::new(),
             };
 
-            compiler.tokenize();
+            compiler.compile_class(&class);
 
             compiler.output.flush().unwrap();
         }
 
-        out_dir
-    }
-
-    /// Tokenizes the given file buffer. Acts as an entrypoint, looking only for one `class`
-    /// declaration, it then proceeds to recursively parse the contents of the class.
-    pub fn tokenize(&mut self) {
-        // i attempted to parse by .lines() and by .split_whitespace(), but both lacked a bit of
-        // granularity that i felt i needed so i don't mind doing it byte-by-byte
-        let mut buff = Token::None;
-        let mut err_counter = 0;
-
-        // --------------------------------------- 'class' -------------------------------------- //
-        while buff != Token::Keyword(Class) {
-            buff = self.get_next_token().unwrap();
-            err_counter += 1;
-            if err_counter >= 10000 {
-                // 10k seems like a good enough number of iterations to panic on just in case i mess
-                // something up.
-                panic!("unable to find identifier 'class'")
-            }
-        }
-
-        // -------------------------------------- className ------------------------------------- //
-        let identifier = self.get_next_token().unwrap();
-        assert!(matches!(identifier, Token::Identifier(_)));
-        assert_eq!(identifier, Token::Identifier(self.class_name.clone()));
-
-        // ----------------------------------------- '{' ---------------------------------------- //
-        let bracket = self.get_next_token().unwrap();
-        assert_eq!(bracket, Token::Symbol(BracketOp));
-
-        // ----------------------------- classVarDec* subroutineDec* ---------------------------- //
-        while let Ok(token) = self.get_next_token() {
-            if token == Token::Symbol(BracketCl) {
-                break;
-            }
-            self.keyword_dispatch(token);
-        }
-
-        // ----------------------------------------- '}' ---------------------------------------- //
-    }
-
-    pub fn keyword_dispatch(&mut self, token: Token) {
-        // let output = &mut self.output;
-        // let stream = &mut self.stream;
-        if let Token::Keyword(keyword) = token {
-            match keyword {
-                Comment | MComment | APIComment => self.skip_comment(keyword),
-                Static | Field | Var => {
-                    self.compile_decl(keyword);
-                }
-                Function => {
-                    self.symbol_table.clear();
-                    self.compile_func(keyword);
-                }
-                Method => {
-                    self.symbol_table.clear();
-                    self.compile_func(keyword);
-                }
-                Constructor => {
-                    self.symbol_table.clear();
-                    self.compile_func(keyword);
-                }
-                Let => {
-                    self.compile_let();
-                }
-                Do => {
-                    self.compile_func_call();
-                    let semicolon = self.get_next_token().unwrap();
-                    assert_eq!(semicolon, Token::Symbol(SemiColon));
-                    self.pop_seg(Segment::Temp, 0);
-                }
-                Return => {
-                    self.compile_return();
-                }
-                If => {
-                    self.compile_if();
-                }
-                While => {
-                    self.compile_while();
-                }
-                Else => panic!("Dangling Else"),
-                t => panic!("Invalid statement keyword: {:?}", t),
-            }
+        if errors.is_empty() {
+            Ok(out_dir)
         } else {
-            panic!("Invalid token: {:?}", token);
+            Err(errors)
         }
     }
 
-    pub fn compile_return(&mut self) {
-        // -------------------------------------- 'return' -------------------------------------- //
-
-        let (next_token, read_pos) = self.peek_next_token().unwrap();
-
-        if next_token != Token::Symbol(SemiColon) {
-            // ----------------------------------- expression? ---------------------------------- //
-            self.compile_expression(&Token::Symbol(SemiColon));
-        } else {
-            self.stream.set_position(read_pos);
-            self.push_seg(Segment::Constant, 0);
+    pub fn compile_class(&mut self, class: &Class) {
+        // ----------------------------------- classVarDec* ------------------------------------ //
+        for dec in &class.vars {
+            self.compile_decl(dec);
         }
 
-        self.write_return();
+        // ---------------------------------- subroutineDec* ------------------------------------ //
+        for subroutine in &class.subroutines {
+            self.symbol_table.clear();
+            self.compile_func(subroutine);
+        }
     }
 
-    pub fn compile_decl(&mut self, decl_type: Keyword) {
-        // ---------------------------- ('static' | 'field' | 'var') ---------------------------- //
-
-        // ---------------------------------------- type ---------------------------------------- //
-        let dtype = self.get_next_token().unwrap();
-        assert!(dtype.is_type());
-
-        // ------------------------------- varName (',' varName)* ------------------------------- //
-        while let Ok(token) = self.get_next_token() {
-            if token == Token::Symbol(Comma) {
-                continue;
-            }
-
-            if token == Token::Symbol(SemiColon) {
-                break;
-            }
-            // covers patterns `var int i` and `var int i, j, k;
-            assert!(matches!(token, Token::Identifier(_)));
-
+    pub fn compile_decl(&mut self, dec: &VarDec) {
+        for name in &dec.names {
             self.symbol_table
-                .insert(&token.to_string(), dtype.clone(), decl_type.into());
+                .insert(&name.name, dec.dtype.clone(), dec.kind.into());
         }
     }
 
-    pub fn compile_func(&mut self, func_type: Keyword) {
+    pub fn compile_func(&mut self, func: &Subroutine) {
         // ----------------------- ('constructor' | 'function' | 'method') ---------------------- //
-
-        let is_method = func_type == Method;
-        if is_method {
+        if func.kind == Method {
             self.symbol_table.insert(
                 "this",
                 Token::Identifier(self.class_name.clone()),
                 Segment::Argument,
             )
         }
-        // ----------------------------------- ('void' | type) ---------------------------------- //
-        let ret_type = self.get_next_token().unwrap();
-        assert!(ret_type.is_type());
-
-        // ----------------------------------- subroutineName ----------------------------------- //
-        let func_name = self.get_next_token().unwrap();
-        assert!(func_name.is_identifier());
-
-        // ----------------------------------------- '(' ---------------------------------------- //
-        let open_paren = self.get_next_token().unwrap();
-        assert_eq!(open_paren, Token::Symbol(ParenOp));
 
         // ------------------------------------ parameterList ----------------------------------- //
-        // ------------------------ ((type varName) (',' type varName)*)? ----------------------- //
-        while let Ok(token) = self.get_next_token() {
-            // if the first token is a ParenCl, the paramlist is empty, but still writes tags
-            if token == Token::Symbol(ParenCl) {
-                break;
-            }
-
-            if token == Token::Symbol(Comma) {
-                continue;
-            }
-
-            assert!(matches!(
-                token,
-                Token::Identifier(_)
-                    | Token::Keyword(Int)
-                    | Token::Keyword(Char)
-                    | Token::Keyword(Boolean)
-            ));
-
-            let name = self.get_next_token().unwrap();
-            assert!(matches!(name, Token::Identifier(_)));
-            self.symbol_table
-                .insert(&name.to_string(), token, Segment::Argument);
+        for dec in func.params.iter().chain(&func.locals) {
+            self.compile_decl(dec);
         }
 
-        // ----------------------------------------- ')' ---------------------------------------- //
         // ----------------------------------- subroutineBody ----------------------------------- //
-        self.compile_func_body(func_name, func_type);
-    }
-
-    pub fn compile_func_body(&mut self, name: Token, func_type: Keyword) {
-        // ----------------------------------------- '{' ---------------------------------------- //
-        let open_brac = self.get_next_token().unwrap();
-        assert_eq!(open_brac, Token::Symbol(BracketOp));
-
-        // --------------------------------------- varDec* -------------------------------------- //
-        while let Ok((next_token, read_pos)) = self.peek_next_token() {
-            if next_token != Token::Keyword(Var) {
-                break;
-            }
-            self.stream.set_position(read_pos);
-            self.keyword_dispatch(next_token);
-        }
-
         let arg_count = *self.symbol_table.counts.get(&Segment::Local).unwrap();
-        self.write_function(&name.to_string(), arg_count);
+        self.write_function(&func.name.name, arg_count);
 
-        if func_type == Method {
+        if func.kind == Method {
             self.push_seg(Segment::Argument, 0);
             self.pop_seg(Segment::Pointer, 0);
         }
-        if func_type == Constructor {
+        if func.kind == Constructor {
             self.push_seg(
                 Segment::Constant,
                 *self.symbol_table.counts.get(&Segment::This).unwrap(),
@@ -366,63 +270,77 @@ impl JackCompiler {
             self.pop_seg(Segment::Pointer, 0)
         }
 
-        // ------------------------------------- statements ------------------------------------- //
+        self.compile_statements(&func.statements);
+    }
 
-        while let Ok(token) = self.get_next_token() {
-            if token == Token::Symbol(BracketCl) {
-                break;
+    pub fn compile_statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Let {
+                    target,
+                    index,
+                    value,
+                    ..
+                } => self.compile_let(target, index.as_ref(), value),
+                Statement::If {
+                    cond,
+                    then_branch,
+                    else_branch,
+                    ..
+                } => self.compile_if(cond, then_branch, else_branch.as_deref()),
+                Statement::While { cond, body, .. } => self.compile_while(cond, body),
+                Statement::Do { call, .. } => {
+                    self.compile_call(call);
+                    self.pop_seg(Segment::Temp, 0);
+                }
+                Statement::Return { value, .. } => self.compile_return(value.as_ref()),
+                Statement::For {
+                    init,
+                    cond,
+                    step,
+                    body,
+                    ..
+                } => self.compile_for(init.as_deref(), cond.as_ref(), step.as_deref(), body),
+                Statement::Break { .. } => {
+                    let (break_label, _) =
+                        *self.loop_labels.last().expect("break outside of a loop");
+                    self.write_else(break_label);
+                }
+                Statement::Continue { .. } => {
+                    let (_, continue_label) =
+                        *self.loop_labels.last().expect("continue outside of a loop");
+                    self.write_else(continue_label);
+                }
             }
-
-            assert!(token.is_statement(), "got: {token:?}");
-            self.keyword_dispatch(token);
         }
-
-        // ----------------------------------------- '}' ---------------------------------------- //
     }
 
-    pub fn compile_func_call(&mut self) {
-        // ---------------------------------- 'do' or from term --------------------------------- //
+    pub fn compile_return(&mut self, value: Option<&Expression>) {
+        match value {
+            Some(expr) => self.compile_expression(expr),
+            None => self.push_seg(Segment::Constant, 0),
+        }
 
-        // ----------------------------------- subroutineCall ----------------------------------- //
-        let identifier = self.get_next_token().unwrap();
-        self.compile_term(&identifier);
+        self.write_return();
     }
 
-    pub fn compile_let(&mut self) {
-        // ---------------------------------------- 'let' --------------------------------------- //
-
-        let mut array_handling = false;
-        // --------------------------------------- varName -------------------------------------- //
-        let id = self.get_next_token().unwrap();
-        assert!(matches!(id, Token::Identifier(_)));
-
-        let (next_token, read_pos) = self.peek_next_token().unwrap();
-
+    pub fn compile_let(&mut self, target: &Ident, index: Option<&Expression>, value: &Expression) {
         // ---------------------------------------- ('[' ---------------------------------------- //
-        if next_token == Token::Symbol(BraceOp) {
-            array_handling = true;
-            self.stream.set_position(read_pos);
-            // ----------------------------------- expression ----------------------------------- //
-            self.compile_expression(&Token::Symbol(BraceCl));
-            // -------------------------------------- ']')? ------------------------------------- //
-            self.push_name(&id.to_string());
-            self.write_operators(&[Token::Symbol(Plus)]);
+        if let Some(index) = index {
+            self.compile_expression(index);
+            self.push_name(&target.name);
+            self.write_operator(BinaryOp::Add);
         }
 
-        // ----------------------------------------- '=' ---------------------------------------- //
-        let token = self.get_next_token().unwrap();
-        assert_eq!(token, Token::Symbol(Equals));
-
         // ------------------------------------- expression ------------------------------------- //
-        self.compile_expression(&Token::Symbol(SemiColon));
+        self.compile_expression(value);
 
-        // ----------------------------------------- ';' ---------------------------------------- //
         let var = self
             .symbol_table
-            .get(&id.to_string())
-            .unwrap_or_else(|| panic!("Undefined symbol name: {id}"));
+            .get(&target.name)
+            .unwrap_or_else(|| panic!("Undefined symbol name: {}", target.name));
 
-        if array_handling {
+        if index.is_some() {
             self.pop_seg(Segment::Temp, 0);
             self.pop_seg(Segment::Pointer, 1);
             self.push_seg(Segment::Temp, 0);
@@ -432,15 +350,13 @@ impl JackCompiler {
         }
     }
 
-    pub fn compile_if(&mut self) {
-        // ---------------------------------------- 'if' ---------------------------------------- //
-
-        // ----------------------------------------- '(' ---------------------------------------- //
-        let open_paren = self.get_next_token().unwrap();
-        assert_eq!(open_paren, Token::Symbol(ParenOp));
-
-        // ------------------------------------- expression ------------------------------------- //
-        self.compile_expression(&Token::Symbol(ParenCl));
+    pub fn compile_if(
+        &mut self,
+        cond: &Expression,
+        then_branch: &[Statement],
+        else_branch: Option<&[Statement]>,
+    ) {
+        self.compile_expression(cond);
 
         let if_label = self.label_count;
         let else_label = self.label_count + 1;
@@ -449,268 +365,185 @@ impl JackCompiler {
         self.write_not();
         self.write_if(if_label);
 
-        // ----------------------------------------- ')' ---------------------------------------- //
-
-        // ----------------------------------------- '{' ---------------------------------------- //
-        let open_brac = self.get_next_token().unwrap();
-        assert_eq!(open_brac, Token::Symbol(BracketOp));
-
-        // ------------------------------------- statements ------------------------------------- //
-
-        while let Ok(token) = self.get_next_token() {
-            if token == Token::Symbol(BracketCl) {
-                break;
-            }
+        self.compile_statements(then_branch);
 
-            assert!(token.is_statement());
-            self.keyword_dispatch(token)
-        }
-
-        // ----------------------------------------- '}' ---------------------------------------- //
-
-        // --------------------------------------- ('else' -------------------------------------- //
-        let (maybe_else, read_pos) = self.peek_next_token().unwrap();
-
-        if maybe_else == Token::Keyword(Else) {
-            self.stream.set_position(read_pos);
-
-            // -------------------------------- [rest of else])? -------------------------------- //
+        if let Some(else_branch) = else_branch {
             self.write_else(else_label);
             self.write_label(if_label);
-            self.compile_else();
+            self.compile_statements(else_branch);
             self.write_label(else_label);
         } else {
             self.write_label(if_label);
         }
     }
 
-    pub fn compile_else(&mut self) {
-        // --------------------------------------- ('else' -------------------------------------- //
-
-        // ----------------------------------------- '{' ---------------------------------------- //
-        let open_brac = self.get_next_token().unwrap();
-        assert_eq!(open_brac, Token::Symbol(BracketOp));
-
-        // ------------------------------------- statements ------------------------------------- //
-
-        while let Ok(token) = self.get_next_token() {
-            if token == Token::Symbol(BracketCl) {
-                break;
-            }
-            self.keyword_dispatch(token)
-        }
-
-        // ----------------------------------------- '}' ---------------------------------------- //
-
-        // ----------------------------------------- )? ----------------------------------------- //
-    }
-
-    pub fn compile_while(&mut self) {
-        // --------------------------------------- 'while' -------------------------------------- //
-
-        // ----------------------------------------- '(' ---------------------------------------- //
-        let open_paren = self.get_next_token().unwrap();
-        assert_eq!(open_paren, Token::Symbol(ParenOp));
-
-        // ------------------------------------- expression ------------------------------------- //
+    pub fn compile_while(&mut self, cond: &Expression, body: &[Statement]) {
         let if_label = self.label_count;
         let else_label = self.label_count + 1;
         self.label_count += 2;
 
         self.write_label(else_label);
-        self.compile_expression(&Token::Symbol(ParenCl));
+        self.compile_expression(cond);
         self.write_not();
         self.write_if(if_label);
 
-        // ----------------------------------------- '{' ---------------------------------------- //
-        let open_brac = self.get_next_token().unwrap();
-        assert_eq!(open_brac, Token::Symbol(BracketOp));
-
-        // ------------------------------------- statements ------------------------------------- //
-
-        while let Ok(token) = self.get_next_token() {
-            if token == Token::Symbol(BracketCl) {
-                break;
-            }
-            self.keyword_dispatch(token)
-        }
-
-        // ----------------------------------------- '}' ---------------------------------------- //
+        self.loop_labels.push((if_label, else_label));
+        self.compile_statements(body);
+        self.loop_labels.pop();
 
         self.write_else(else_label);
         self.write_label(if_label);
     }
 
-    pub fn compile_expression(&mut self, delim: &Token) -> Token {
-        /*
-        Brain's not working so i'll leave a note for later: i'm going to assume that there's always 1
-        term, followed by [something]. [Something] can either be the delimeter or an operator, but the
-        the sequence must *start* with a term, and *end* with a term (just before the delimiter). That
-        means i can loop through "token" pairs, which should allow me to be a bit more strict with
-        asserts and narrow the match statements.
-        */
-
-        let token = self.get_next_token().unwrap();
-        if token == *delim || token == Token::Symbol(ParenCl) || token == Token::Symbol(BraceCl)
-        // || token == Token::Symbol(SemiColon)
-        {
-            return token;
+    /// Lowered like `init; while (cond) { body; step; }`, except that `continue` jumps to the step
+    pub fn compile_for(
+        &mut self,
+        init: Option<&Statement>,
+        cond: Option<&Expression>,
+        step: Option<&Statement>,
+        body: &[Statement],
+    ) {
+        let end_label = self.label_count;
+        let cond_label = self.label_count + 1;
+        let step_label = self.label_count + 2;
+        self.label_count += 3;
+
+        if let Some(init) = init {
+            self.compile_statements(std::slice::from_ref(init));
         }
-        // -------------------------------------- term -------------------------------------- //
-        assert!(
-            matches!(
-                token,
-                Token::ConstInt(_)
-                    | Token::ConstString(_)
-                    | Token::Keyword(True)
-                    | Token::Keyword(False)
-                    | Token::Keyword(Null)
-                    | Token::Keyword(This)
-                    | Token::Identifier(_)
-                    | Token::Symbol(Minus)
-                    | Token::Symbol(Tilde)
-                    | Token::Symbol(ParenOp)
-            ),
-            "got {token:?}"
-        );
-
-        let mut ops = Vec::new();
-
-        self.compile_term(&token);
-
-        let mut op = self.get_next_token().unwrap();
-        // ------------------------------------- (op term)* ------------------------------------- //
-        while op.is_operator() {
-            ops.push(op);
-
-            let token = self.get_next_token().unwrap();
-            assert!(
-                matches!(
-                    token,
-                    Token::ConstInt(_)
-                        | Token::ConstString(_)
-                        | Token::Keyword(True)
-                        | Token::Keyword(False)
-                        | Token::Keyword(Null)
-                        | Token::Keyword(This)
-                        | Token::Identifier(_)
-                        | Token::Symbol(Minus)
-                        | Token::Symbol(Tilde)
-                        | Token::Symbol(ParenOp)
-                ),
-                "got {token:?}"
-            );
 
-            self.compile_term(&token);
-
-            op = self.get_next_token().unwrap();
+        self.write_label(cond_label);
+        if let Some(cond) = cond {
+            self.compile_expression(cond);
+            self.write_not();
+            self.write_if(end_label);
         }
 
-        self.write_operators(&ops);
+        self.loop_labels.push((end_label, step_label));
+        self.compile_statements(body);
+        self.loop_labels.pop();
+
+        self.write_label(step_label);
+        if let Some(step) = step {
+            self.compile_statements(std::slice::from_ref(step));
+        }
 
-        op
+        self.write_else(cond_label);
+        self.write_label(end_label);
     }
 
-    pub fn compile_term(&mut self, token: &Token) {
-        let (look_ahead, read_pos) = self.peek_next_token().unwrap();
+    pub fn compile_expression(&mut self, expr: &Expression) {
+        self.compile_term(&expr.first);
 
-        match token {
-            Token::ConstInt(x) => self.push_seg(Segment::Constant, *x as usize),
-            Token::ConstString(x) => {
-                self.push_seg(Segment::Constant, x.len());
+        // ------------------------------------- (op term)* ------------------------------------- //
+        // jack has no precedence, operators are applied left to right as soon as their right hand
+        // operand is on the stack
+        let mut non_negative = is_non_negative(&expr.first);
+        for (op, term) in &expr.rest {
+            let lhs_non_negative = non_negative;
+            non_negative = is_non_negative_after(non_negative, *op, term);
+
+            match (op, &term.kind) {
+                (BinaryOp::Mul, TermKind::IntConst(c)) => {
+                    if let Some(k) = doublings(*c).filter(|_| self.optimize) {
+                        for _ in 0..k {
+                            self.write_double();
+                        }
+                        continue;
+                    }
+                }
+                // a shift only rounds like Math.divide when there's no sign
+                (BinaryOp::Div, TermKind::IntConst(c)) if lhs_non_negative => {
+                    if let Some(k) = halvings(*c).filter(|_| self.optimize) {
+                        self.write_shift_right(k);
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+
+            self.compile_term(term);
+            self.write_operator(*op);
+        }
+    }
+
+    pub fn compile_term(&mut self, term: &Term) {
+        match &term.kind {
+            // source constants are 0..32767, folded ones can be negative
+            TermKind::IntConst(i16::MIN) => {
+                self.push_seg(Segment::Constant, i16::MAX as usize);
+                self.write_not();
+            }
+            TermKind::IntConst(x) if *x < 0 => {
+                self.push_seg(Segment::Constant, x.unsigned_abs() as usize);
+                self.write_negate();
+            }
+            TermKind::IntConst(x) => self.push_seg(Segment::Constant, *x as usize),
+            TermKind::StringConst(x) => {
+                self.push_seg(Segment::Constant, x.chars().count());
                 self.write_function_call("String", "new", 1);
                 for char in x.chars() {
                     self.push_seg(Segment::Constant, char as usize);
                     self.write_function_call("String", "appendChar", 2);
                 }
             }
+            TermKind::KeywordConst(k) => self.push_name(&k.to_string()),
+            TermKind::Var(x) => self.push_name(&x.name),
             // ------------------------------- '(' expression ')' ------------------------------- //
-            Token::Symbol(ParenOp) => {
-                self.compile_expression(&Token::Symbol(ParenCl));
-            }
+            TermKind::Paren(expr) => self.compile_expression(expr),
             // --------------------------- varName '[' expression ']' --------------------------- //
-            Token::Identifier(x) if look_ahead == Token::Symbol(BraceOp) => {
-                self.stream.set_position(read_pos);
-
-                self.compile_expression(&Token::Symbol(BraceCl));
-                self.push_name(x);
-                self.write_operators(&[Token::Symbol(Symbol::Plus)]);
+            TermKind::Index(x, index) => {
+                self.compile_expression(index);
+                self.push_name(&x.name);
+                self.write_operator(BinaryOp::Add);
                 self.pop_seg(Segment::Pointer, 1);
                 self.push_seg(Segment::That, 0);
             }
+            TermKind::Call(call) => self.compile_call(call),
+            // --------------------------------- (unaryOp term) --------------------------------- //
+            TermKind::Unary(UnaryOp::Neg, inner) => {
+                self.compile_term(inner);
+                self.write_negate();
+            }
+            TermKind::Unary(UnaryOp::Not, inner) => {
+                self.compile_term(inner);
+                self.write_not();
+            }
+        }
+    }
+
+    pub fn compile_call(&mut self, call: &SubroutineCall) {
+        match &call.receiver {
             // ----------------------- subroutineName'('expressionList')' ----------------------- //
-            Token::Identifier(func_name) if look_ahead == Token::Symbol(ParenOp) => {
+            None => {
                 // all non-identifier subroutine names are treated as **method** calls in the
                 // current class.
-                self.stream.set_position(read_pos);
-
                 self.push_seg(Segment::Pointer, 0);
-                let arg_count = self.compile_expr_list();
+                let arg_count = self.compile_expr_list(&call.args);
 
-                self.write_function_call(&self.class_name.clone(), func_name, arg_count + 1);
+                self.write_function_call(&self.class_name.clone(), &call.name.name, arg_count + 1);
             }
             // ------------ (className|varName)'.'subroutineName'('expressionList')' ------------ //
-            Token::Identifier(x) if look_ahead == Token::Symbol(Period) => {
-                self.stream.set_position(read_pos);
-
-                let func_name = self.get_next_token().unwrap();
-                assert!(matches!(func_name, Token::Identifier(_)));
-
-                let paren_open = self.get_next_token().unwrap();
-                assert_eq!(paren_open, Token::Symbol(ParenOp));
-
-                let mut id_or_type = x.clone();
-                let is_method = self.symbol_table.has(x);
+            Some(x) => {
+                let mut id_or_type = x.name.clone();
+                let is_method = self.symbol_table.has(&x.name);
                 if is_method {
-                    self.push_name(x);
-                    id_or_type = self.symbol_table.get(x).unwrap().dtype.to_string();
+                    self.push_name(&x.name);
+                    id_or_type = self.symbol_table.get(&x.name).unwrap().dtype.to_string();
                 }
 
-                let arg_count = self.compile_expr_list() + is_method as usize;
-
-                self.write_function_call(&id_or_type, &func_name.to_string(), arg_count);
-            }
-            // --------------------------------- (unaryOp term) --------------------------------- //
-            Token::Symbol(Minus) => {
-                self.stream.set_position(read_pos);
-
-                self.compile_term(&look_ahead);
-                self.write_negate();
-            }
-            Token::Symbol(Tilde) => {
-                self.stream.set_position(read_pos);
+                let arg_count = self.compile_expr_list(&call.args) + is_method as usize;
 
-                self.compile_term(&look_ahead);
-                self.write_not();
-            }
-            Token::Identifier(_)
-            | Token::Keyword(False)
-            | Token::Keyword(True)
-            | Token::Keyword(This)
-            | Token::Keyword(Null) => {
-                self.push_name(&token.to_string());
+                self.write_function_call(&id_or_type, &call.name.name, arg_count);
             }
-            _ => {}
         }
     }
 
-    pub fn compile_expr_list(&mut self) -> usize {
-        let mut arg_count = 0;
-        let mut delim;
-
-        while let Ok((token, read_pos)) = self.peek_next_token() {
-            if token == Token::Symbol(ParenCl) {
-                self.stream.set_position(read_pos);
-                break;
-            }
-
-            arg_count += 1;
-            delim = self.compile_expression(&Token::Symbol(Comma));
-            if delim == Token::Symbol(ParenCl) {
-                break;
-            }
+    pub fn compile_expr_list(&mut self, args: &[Expression]) -> usize {
+        for arg in args {
+            self.compile_expression(arg);
         }
 
-        arg_count
+        args.len()
     }
 }
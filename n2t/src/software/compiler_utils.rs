@@ -3,26 +3,10 @@
 use lazy_static::lazy_static;
 use std::collections::HashMap;
 
-use std::io::BufRead;
-
-use std::{error, io::Read, str::FromStr};
+use std::str::FromStr;
 
 use strum_macros::EnumString;
 
-use crate::software::compiler::JackCompiler;
-
-type Result<T> = std::result::Result<T, Box<dyn error::Error>>;
-
-// const COMMENT: &str = "//";
-// const COMMENT_OPEN: &str = "/*";
-// const API_COMMENT_OPEN: &str = "/**";
-// const COMMENT_CLOSE: &str = "*/";
-// ASCII encodings
-const SPACE: u8 = 0x20;
-const TAB: u8 = 0x09;
-const NEWLINE: u8 = 0x0a;
-const C_RETURN: u8 = 0x0d; // thanks windows
-const DBL_QUOTE: u8 = "\"".as_bytes()[0];
 lazy_static! {
     pub static ref DELIM_MAP: HashMap<Token, Token> = {
         use Symbol::*;
@@ -56,6 +40,11 @@ pub enum Keyword {
     While,
     Return,
     Var,
+    // extended Jack only, see `Dialect`
+    For,
+    Break,
+    Continue,
+    Const,
     // not technically a keyword, but it'll do
     Arg,
     Int,
@@ -71,6 +60,25 @@ pub enum Keyword {
     CommentEnd,
 }
 
+impl Keyword {
+    /// keywords that only exist in extended Jack, they're plain identifiers in standard Jack
+    pub fn is_extended(&self) -> bool {
+        matches!(
+            self,
+            Keyword::For | Keyword::Break | Keyword::Continue | Keyword::Const
+        )
+    }
+}
+
+/// The flavor of Jack being compiled. `Standard` is the language from the book, `Extended` adds `for` loops, `break`
+/// and `continue`, class-level `const` declarations, `'c'` character literals and escape sequences in strings.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Dialect {
+    #[default]
+    Standard,
+    Extended,
+}
+
 #[derive(Debug, Clone, Copy, EnumString, strum_macros::Display, PartialEq, Eq, Hash)]
 pub enum Symbol {
     #[strum(serialize = "{")]
@@ -198,6 +206,9 @@ impl Token {
                 | Token::Keyword(While)
                 | Token::Keyword(Do)
                 | Token::Keyword(Return)
+                | Token::Keyword(For)
+                | Token::Keyword(Break)
+                | Token::Keyword(Continue)
         )
     }
 
@@ -228,8 +239,10 @@ impl std::fmt::Display for Token {
 
 /// Tries to match Token::Keyword or Token::ConstInt, then falls back to Token::Identifier
 pub fn get_token_type(token: &str) -> Token {
-    if let Ok(t) = Keyword::from_str(token) {
-        return Token::Keyword(t);
+    // `Arg` only exists to tag parameters in the symbol table, `arg` is a valid identifier
+    match Keyword::from_str(token) {
+        Ok(Keyword::Arg) | Err(_) => {}
+        Ok(t) => return Token::Keyword(t),
     }
     if token.chars().next().unwrap().is_numeric() {
         return Token::ConstInt(token.parse().unwrap());
@@ -258,165 +271,3 @@ pub fn expect_bytes(expected: &str, got: &[u8]) {
 //     expression,
 //     expressionList,
 // }
-
-impl JackCompiler {
-    pub fn peek(&mut self) -> Result<[u8; 1]> {
-        let mut buff = [0];
-        self.stream.read_exact(&mut buff)?;
-
-        self.stream.set_position(self.stream.position() - 1);
-
-        Ok(buff)
-    }
-
-    pub fn peek_eq(&mut self, val: &str) -> Result<bool> {
-        assert_eq!(val.len(), 1, "peek_eq requires a str input of length 1");
-        let byte = self.peek().unwrap();
-
-        if byte[0] == val.bytes().next().unwrap() {
-            Ok(true)
-        } else {
-            Ok(false)
-        }
-    }
-
-    pub fn read_byte(&mut self) -> Result<[u8; 1]> {
-        let mut buff: [u8; 1] = [0];
-        self.stream.read_exact(&mut buff)?;
-
-        Ok(buff)
-    }
-
-    /// Accepts a stream, reads spaces until the first non-space character is found, returns a [u8; 1]
-    /// containing the non-space character
-    pub fn skip_spaces(&mut self) -> Result<[u8; 1]> {
-        let mut character = [SPACE];
-        while character == [SPACE] {
-            self.stream.read_exact(&mut character)?;
-        }
-
-        Ok(character)
-    }
-
-    /// Accepts a stream, reads and discards until a newline character is found
-    pub fn skip_to_newline(&mut self) {
-        let mut character = [0];
-        while character != [NEWLINE] {
-            self.stream.read_exact(&mut character).unwrap();
-        }
-    }
-
-    /// Accepts a stream, reads and discards characters (at least 1) until the first non-newline, non-space character is
-    /// found. Returns that character.
-    pub fn skip_whitespace(&mut self) -> Result<[u8; 1]> {
-        let mut character = [SPACE];
-        while character == [SPACE]
-            || character == [NEWLINE]
-            || character == [C_RETURN]
-            || character == [TAB]
-        {
-            self.stream.read_exact(&mut character)?;
-        }
-
-        Ok(character)
-    }
-
-    pub fn skip_to_comment_end(&mut self) {
-        let mut character = [0];
-
-        while !(character == ["*".as_bytes()[0]] && self.peek_eq("/").unwrap()) {
-            self.stream.read_exact(&mut character).unwrap();
-        }
-        self.read_byte().unwrap(); // consume the peeked '/' character
-    }
-
-    pub fn skip_comment(&mut self, com_type: Keyword) {
-        match com_type {
-            Keyword::Comment => self.skip_to_newline(),
-            Keyword::MComment | Keyword::APIComment => self.skip_to_comment_end(),
-            _ => panic!("Invalid Comment Type"),
-        };
-    }
-
-    /// Reads characters until a space is found, returns a String containing the characters read
-    /// WITHOUT a trailing space. Skips comments entirely.
-    pub fn get_next_token(&mut self) -> Result<Token> {
-        let mut character = self.skip_whitespace()?;
-        let mut token = Vec::new();
-
-        while character != [SPACE] && character != [NEWLINE] && character != [C_RETURN] {
-            let next_byte = self.peek().unwrap();
-
-            let next_res = Symbol::from_str(std::str::from_utf8(&next_byte).unwrap());
-
-            // if we currently have a symbol
-            if let Ok(curr_symbol) = Symbol::from_str(std::str::from_utf8(&character).unwrap()) {
-                // and the next byte is a symbol
-                if let Ok(next_symbol) = next_res {
-                    // and the next symbol is forward slash or astersik, return "//" or "/*"
-                    // this is kindof a hack due to ignoring API comments
-                    if curr_symbol == Symbol::FwdSlash && next_symbol == Symbol::FwdSlash {
-                        self.skip_comment(Keyword::Comment);
-                        return self.get_next_token();
-                    }
-                    if curr_symbol == Symbol::FwdSlash && next_symbol == Symbol::Asterisk {
-                        self.skip_comment(Keyword::MComment);
-                        return self.get_next_token();
-                    }
-                    // and it's not a comment, return the symbol
-                    return Ok(Token::Symbol(curr_symbol));
-                }
-                if curr_symbol == Symbol::DblQuote {
-                    // string constants, treats the whole constant as 1 token
-                    let mut buff = Vec::new();
-                    self.stream.read_until(DBL_QUOTE, &mut buff).unwrap();
-                    buff.pop(); // remove trailing quote
-
-                    let const_string = std::string::String::from_utf8(buff).unwrap();
-
-                    return Ok(Token::ConstString(const_string));
-                }
-                // if the next byte is not a symbol, return the current byte
-                return Ok(Token::Symbol(curr_symbol));
-            }
-
-            token.push(character[0]);
-
-            if next_res.is_ok() {
-                break;
-            }
-
-            self.stream.read_exact(&mut character)?;
-        }
-
-        Ok(get_token_type(std::str::from_utf8(&token).unwrap()))
-    }
-
-    /// Returns a Result containing a Tuple of the next **non-comment** Token, and the read position
-    /// corresponding to the end of the peeked token. If the peeked token is used, calling
-    /// `stream.set_position()` with the returned u64 will update the stream to the proper location
-    /// without having to re-read the peeked token.
-    pub fn peek_next_token(&mut self) -> Result<(Token, u64)> {
-        let position = self.stream.position();
-
-        let mut next_token = self.get_next_token()?;
-
-        use Keyword::*;
-        while next_token == Token::Keyword(Comment)
-            || next_token == Token::Keyword(MComment)
-            || next_token == Token::Keyword(APIComment)
-        {
-            if next_token == Token::Keyword(Comment) {
-                self.skip_to_newline();
-            } else {
-                self.skip_to_comment_end();
-            }
-            next_token = self.get_next_token()?
-        }
-
-        let post_pos = self.stream.position();
-        self.stream.set_position(position);
-
-        Ok((next_token, post_pos))
-    }
-}
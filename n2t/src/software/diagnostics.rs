@@ -0,0 +1,118 @@
+//! Compiler errors that point at the offending source, e.g.
+//!
+//! ```text
+//! Main.jack:7:18: expected ';' after let statement
+//!     let x = 3
+//!              ^
+//! ```
+//!
+//! Every front end reports its errors this way: the Jack compiler, the HDL and test script parsers and the .hack
+//! loader. Their parsers record a `Span` for whatever they read.
+
+use std::{fmt, path::Path};
+
+/// Byte offsets into the source file, `start..end`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// Smallest span covering both `self` and `other`
+    pub fn to(self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    /// 1-indexed (line, column) of the start of the span, the column is counted in characters
+    pub fn line_col(&self, source: &str) -> (usize, usize) {
+        let before = &source[..self.start.min(source.len())];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+        (line, before[line_start..].chars().count() + 1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// file name, including the extension
+    pub file: String,
+    /// 1-indexed
+    pub line: usize,
+    /// 1-indexed, in characters
+    pub column: usize,
+    pub message: String,
+    /// the full source line the error is on, without the line break
+    pub source_line: String,
+}
+
+impl Diagnostic {
+    pub fn new(file: &str, source: &str, span: Span, message: String) -> Self {
+        let (line, column) = span.line_col(source);
+        let source_line = source
+            .lines()
+            .nth(line - 1)
+            .unwrap_or("")
+            .trim_end()
+            .to_string();
+
+        Self {
+            file: file.to_string(),
+            line,
+            column,
+            message,
+            source_line,
+        }
+    }
+
+    /// `path` couldn't be read. There's no source to point at, so it's reported at the start of the file.
+    pub fn unreadable(path: &Path, reason: impl fmt::Display) -> Self {
+        let file = path.file_name().unwrap_or_default().to_string_lossy();
+        let message = format!("can't read {}: {reason}", path.display());
+        Self::new(&file, "", Span::default(), message)
+    }
+
+    /// `file:line:column: message`, without the source excerpt
+    pub fn summary(&self) -> String {
+        format!(
+            "{}:{}:{}: {}",
+            self.file, self.line, self.column, self.message
+        )
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.source_line.is_empty() {
+            return write!(f, "{}", self.summary());
+        }
+
+        // tabs are kept so the caret lines up regardless of tab width
+        let padding: String = self
+            .source_line
+            .chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        writeln!(f, "{}", self.summary())?;
+        writeln!(f, "    {}", self.source_line)?;
+        write!(f, "    {padding}^")
+    }
+}
+
+impl std::error::Error for Diagnostic {}
+
+/// Renders a list of diagnostics, separated by blank lines
+pub fn render(diagnostics: &[Diagnostic]) -> String {
+    diagnostics
+        .iter()
+        .map(|d| d.to_string())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
@@ -0,0 +1,216 @@
+//! Typed syntax tree for a single Jack class. Produced by `jack_parser`, consumed by the VM code generator
+//! (`compiler`) and the XML writer (`tokenizer`). Every node records the span of source it was parsed from.
+
+use crate::software::{
+    compiler_utils::{Keyword, Symbol, Token},
+    diagnostics::Span,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Class {
+    pub name: Ident,
+    pub vars: Vec<VarDec>,
+    /// extended Jack only
+    pub consts: Vec<ConstDec>,
+    pub subroutines: Vec<Subroutine>,
+    pub span: Span,
+}
+
+/// One name of a `const type name = expression, ...;` declaration in extended Jack. The parser evaluates the
+/// expression, uses of the constant are replaced by `value` (see `jack_optimizer::inline_consts`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstDec {
+    pub dtype: Token,
+    pub name: Ident,
+    pub value: i16,
+    /// the whole declaration
+    pub span: Span,
+}
+
+/// `static`/`field`/`var` declarations, `kind` is the declaring keyword. Parameters are stored as one `VarDec` each
+/// with `Keyword::Arg`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VarDec {
+    pub kind: Keyword,
+    /// `int`, `char`, `boolean` or a class name
+    pub dtype: Token,
+    pub names: Vec<Ident>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subroutine {
+    /// `constructor`, `function` or `method`
+    pub kind: Keyword,
+    /// `void` or a type
+    pub return_type: Token,
+    pub name: Ident,
+    pub params: Vec<VarDec>,
+    pub locals: Vec<VarDec>,
+    pub statements: Vec<Statement>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Let {
+        target: Ident,
+        index: Option<Expression>,
+        value: Expression,
+        span: Span,
+    },
+    If {
+        cond: Expression,
+        then_branch: Vec<Statement>,
+        else_branch: Option<Vec<Statement>>,
+        span: Span,
+    },
+    While {
+        cond: Expression,
+        body: Vec<Statement>,
+        span: Span,
+    },
+    Do {
+        call: SubroutineCall,
+        span: Span,
+    },
+    Return {
+        value: Option<Expression>,
+        span: Span,
+    },
+    /// `for (init; cond; step) { body }`, extended Jack only. `init` and `step` are `let` or `do` statements, a
+    /// missing `cond` loops until a `break`.
+    For {
+        init: Option<Box<Statement>>,
+        cond: Option<Expression>,
+        step: Option<Box<Statement>>,
+        body: Vec<Statement>,
+        span: Span,
+    },
+    /// extended Jack only
+    Break {
+        span: Span,
+    },
+    /// extended Jack only
+    Continue {
+        span: Span,
+    },
+}
+
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Let { span, .. }
+            | Statement::If { span, .. }
+            | Statement::While { span, .. }
+            | Statement::Do { span, .. }
+            | Statement::Return { span, .. }
+            | Statement::For { span, .. }
+            | Statement::Break { span }
+            | Statement::Continue { span } => *span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    And,
+    Or,
+    Lt,
+    Gt,
+    Eq,
+}
+
+impl BinaryOp {
+    pub fn from_symbol(symbol: Symbol) -> Option<BinaryOp> {
+        use Symbol::*;
+        Some(match symbol {
+            Plus => BinaryOp::Add,
+            Minus => BinaryOp::Sub,
+            Asterisk => BinaryOp::Mul,
+            FwdSlash => BinaryOp::Div,
+            And => BinaryOp::And,
+            Pipe => BinaryOp::Or,
+            LessThan => BinaryOp::Lt,
+            GreaterThan => BinaryOp::Gt,
+            Equals => BinaryOp::Eq,
+            _ => return None,
+        })
+    }
+
+    pub fn symbol(&self) -> Symbol {
+        use Symbol::*;
+        match self {
+            BinaryOp::Add => Plus,
+            BinaryOp::Sub => Minus,
+            BinaryOp::Mul => Asterisk,
+            BinaryOp::Div => FwdSlash,
+            BinaryOp::And => And,
+            BinaryOp::Or => Pipe,
+            BinaryOp::Lt => LessThan,
+            BinaryOp::Gt => GreaterThan,
+            BinaryOp::Eq => Equals,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+impl UnaryOp {
+    pub fn symbol(&self) -> Symbol {
+        match self {
+            UnaryOp::Neg => Symbol::Minus,
+            UnaryOp::Not => Symbol::Tilde,
+        }
+    }
+}
+
+/// `term (op term)*`. Jack has no operator precedence, so the expression is kept flat in source order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    pub first: Term,
+    pub rest: Vec<(BinaryOp, Term)>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Term {
+    pub kind: TermKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TermKind {
+    IntConst(i16),
+    StringConst(String),
+    /// `true`, `false`, `null` or `this`
+    KeywordConst(Keyword),
+    Var(Ident),
+    Index(Ident, Box<Expression>),
+    Call(SubroutineCall),
+    Paren(Box<Expression>),
+    Unary(UnaryOp, Box<Term>),
+}
+
+/// `name(args)` or `receiver.name(args)`, where the receiver is a class or variable name
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubroutineCall {
+    pub receiver: Option<Ident>,
+    pub name: Ident,
+    pub args: Vec<Expression>,
+    pub span: Span,
+}
@@ -0,0 +1,529 @@
+//! Whole-program semantic checks for Jack. Every class of a program is parsed up front so calls can be resolved
+//! across files: undeclared names, missing subroutines, argument counts, methods called as functions (and vice versa),
+//! and `return` statements that don't match the subroutine's return type. Scoping follows the compiler by building the
+//! same `SymbolTable`.
+
+use crate::{
+    software::{
+        compiler::SymbolTable,
+        compiler_utils::{Keyword, Keyword::*, Token},
+        diagnostics::{Diagnostic, Span},
+        jack_ast::{
+            Class, Expression, Ident, Statement, Subroutine, SubroutineCall, TermKind, VarDec,
+        },
+        jack_parser::parse,
+        writer_impl::Segment,
+    },
+    utils::get_file_buffers,
+};
+use std::{collections::HashMap, io::Read, path::Path};
+
+/// The parts of a subroutine declaration that matter to its callers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+    pub kind: Keyword,
+    pub n_params: usize,
+    pub is_void: bool,
+}
+
+/// (subroutine, kind, parameter count, returns void)
+type OsSubroutine = (&'static str, Keyword, usize, bool);
+
+/// The standard library's subroutines, by class
+const OS_API: &[(&str, &[OsSubroutine])] = &[
+    (
+        "Math",
+        &[
+            ("init", Function, 0, true),
+            ("abs", Function, 1, false),
+            ("multiply", Function, 2, false),
+            ("divide", Function, 2, false),
+            ("min", Function, 2, false),
+            ("max", Function, 2, false),
+            ("sqrt", Function, 1, false),
+        ],
+    ),
+    (
+        "String",
+        &[
+            ("new", Constructor, 1, false),
+            ("dispose", Method, 0, true),
+            ("length", Method, 0, false),
+            ("charAt", Method, 1, false),
+            ("setCharAt", Method, 2, true),
+            ("appendChar", Method, 1, false),
+            ("eraseLastChar", Method, 0, true),
+            ("intValue", Method, 0, false),
+            ("setInt", Method, 1, true),
+            ("backSpace", Function, 0, false),
+            ("doubleQuote", Function, 0, false),
+            ("newLine", Function, 0, false),
+        ],
+    ),
+    (
+        "Array",
+        &[("new", Function, 1, false), ("dispose", Method, 0, true)],
+    ),
+    (
+        "Output",
+        &[
+            ("init", Function, 0, true),
+            ("moveCursor", Function, 2, true),
+            ("printChar", Function, 1, true),
+            ("printString", Function, 1, true),
+            ("printInt", Function, 1, true),
+            ("println", Function, 0, true),
+            ("backSpace", Function, 0, true),
+        ],
+    ),
+    (
+        "Screen",
+        &[
+            ("init", Function, 0, true),
+            ("clearScreen", Function, 0, true),
+            ("setColor", Function, 1, true),
+            ("drawPixel", Function, 2, true),
+            ("drawLine", Function, 4, true),
+            ("drawRectangle", Function, 4, true),
+            ("drawCircle", Function, 3, true),
+        ],
+    ),
+    (
+        "Keyboard",
+        &[
+            ("init", Function, 0, true),
+            ("keyPressed", Function, 0, false),
+            ("readChar", Function, 0, false),
+            ("readLine", Function, 1, false),
+            ("readInt", Function, 1, false),
+        ],
+    ),
+    (
+        "Memory",
+        &[
+            ("init", Function, 0, true),
+            ("peek", Function, 1, false),
+            ("poke", Function, 2, true),
+            ("alloc", Function, 1, false),
+            ("deAlloc", Function, 1, true),
+        ],
+    ),
+    (
+        "Sys",
+        &[
+            ("init", Function, 0, true),
+            ("halt", Function, 0, true),
+            ("error", Function, 1, true),
+            ("wait", Function, 1, true),
+        ],
+    ),
+];
+
+type ClassSignatures = HashMap<String, HashMap<String, Signature>>;
+
+/// Checks every .jack file in the folder (or the single file) as one program. Classes that aren't part of the program
+/// are looked up in the standard library.
+pub fn check(path: &Path) -> Vec<Diagnostic> {
+    let mut sources = Vec::new();
+    for (mut file, name) in get_file_buffers(path, "jack") {
+        let mut source = String::new();
+        file.read_to_string(&mut source).unwrap();
+        sources.push((name, source));
+    }
+    sources.sort();
+
+    check_sources(
+        &sources
+            .iter()
+            .map(|(name, source)| (name.as_str(), source.as_str()))
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// Checks (file stem, source) pairs as one program. Files with syntax errors report those and are left out of the
+/// semantic checks.
+pub fn check_sources(sources: &[(&str, &str)]) -> Vec<Diagnostic> {
+    let mut errors = Vec::new();
+    let mut parsed = Vec::new();
+    for &(file, source) in sources {
+        match parse(&format!("{file}.jack"), source) {
+            Ok(class) => parsed.push((file, source, class)),
+            Err(mut syntax_errors) => errors.append(&mut syntax_errors),
+        }
+    }
+
+    let mut signatures: ClassSignatures = HashMap::new();
+    for (class, subroutines) in OS_API {
+        signatures.insert(
+            class.to_string(),
+            subroutines
+                .iter()
+                .map(|&(name, kind, n_params, is_void)| {
+                    (
+                        name.to_string(),
+                        Signature {
+                            kind,
+                            n_params,
+                            is_void,
+                        },
+                    )
+                })
+                .collect(),
+        );
+    }
+    // program classes replace the standard library ones of the same name
+    for (_, _, class) in &parsed {
+        signatures.insert(
+            class.name.name.clone(),
+            class
+                .subroutines
+                .iter()
+                .map(|s| {
+                    (
+                        s.name.name.clone(),
+                        Signature {
+                            kind: s.kind,
+                            n_params: s.params.len(),
+                            is_void: s.return_type == Token::Keyword(Void),
+                        },
+                    )
+                })
+                .collect(),
+        );
+    }
+
+    for (file, source, class) in &parsed {
+        let mut checker = Checker {
+            file: format!("{file}.jack"),
+            source,
+            signatures: &signatures,
+            class_name: &class.name.name,
+            symbol_table: SymbolTable::default(),
+            subroutine: None,
+            errors: Vec::new(),
+        };
+
+        if class.name.name != *file {
+            checker.error(
+                class.name.span,
+                format!(
+                    "class '{}' must be declared in '{}.jack'",
+                    class.name.name, class.name.name
+                ),
+            );
+        }
+        checker.check_class(class);
+        errors.append(&mut checker.errors);
+    }
+
+    errors
+}
+
+struct Checker<'a> {
+    file: String,
+    source: &'a str,
+    signatures: &'a ClassSignatures,
+    class_name: &'a str,
+    symbol_table: SymbolTable,
+    /// the subroutine currently being checked
+    subroutine: Option<&'a Subroutine>,
+    errors: Vec<Diagnostic>,
+}
+
+impl<'a> Checker<'a> {
+    fn error(&mut self, span: Span, message: String) {
+        let diagnostic = Diagnostic::new(&self.file, self.source, span, message);
+        self.errors.push(diagnostic);
+    }
+
+    fn check_type(&mut self, dtype: &Token, span: Span) {
+        if let Token::Identifier(class) = dtype {
+            if !self.signatures.contains_key(class) {
+                self.error(span, format!("unknown type '{class}'"));
+            }
+        }
+    }
+
+    fn declare(&mut self, dec: &VarDec) {
+        self.check_type(&dec.dtype, dec.span);
+
+        let segment: Segment = dec.kind.into();
+        for name in &dec.names {
+            let scope = match segment {
+                Segment::Local | Segment::Argument => &self.symbol_table.func,
+                _ => &self.symbol_table.cls,
+            };
+            if scope.contains_key(&name.name) {
+                self.error(name.span, format!("'{}' is already declared", name.name));
+                continue;
+            }
+            self.symbol_table
+                .insert(&name.name, dec.dtype.clone(), segment);
+        }
+    }
+
+    fn check_class(&mut self, class: &'a Class) {
+        for dec in &class.vars {
+            self.declare(dec);
+        }
+
+        let mut seen = HashMap::new();
+        for subroutine in &class.subroutines {
+            if seen.insert(&subroutine.name.name, ()).is_some() {
+                self.error(
+                    subroutine.name.span,
+                    format!("subroutine '{}' is already declared", subroutine.name.name),
+                );
+            }
+
+            self.symbol_table.clear();
+            self.subroutine = Some(subroutine);
+            if subroutine.kind == Method {
+                self.symbol_table.insert(
+                    "this",
+                    Token::Identifier(self.class_name.to_owned()),
+                    Segment::Argument,
+                );
+            }
+            if subroutine.kind == Constructor
+                && subroutine.return_type != Token::Identifier(self.class_name.to_owned())
+            {
+                self.error(
+                    subroutine.name.span,
+                    format!(
+                        "constructor '{}' must return '{}'",
+                        subroutine.name.name, self.class_name
+                    ),
+                );
+            }
+            self.check_type(&subroutine.return_type, subroutine.name.span);
+
+            for dec in subroutine.params.iter().chain(&subroutine.locals) {
+                self.declare(dec);
+            }
+            self.check_statements(&subroutine.statements);
+        }
+    }
+
+    fn check_statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            match statement {
+                Statement::Let {
+                    target,
+                    index,
+                    value,
+                    ..
+                } => {
+                    self.check_var(target);
+                    if let Some(index) = index {
+                        self.check_expression(index);
+                    }
+                    self.check_expression(value);
+                }
+                Statement::If {
+                    cond,
+                    then_branch,
+                    else_branch,
+                    ..
+                } => {
+                    self.check_expression(cond);
+                    self.check_statements(then_branch);
+                    if let Some(else_branch) = else_branch {
+                        self.check_statements(else_branch);
+                    }
+                }
+                Statement::While { cond, body, .. } => {
+                    self.check_expression(cond);
+                    self.check_statements(body);
+                }
+                Statement::Do { call, .. } => {
+                    self.check_call(call);
+                }
+                Statement::Return { value, span } => {
+                    let subroutine = self.subroutine.unwrap();
+                    let name = format!("{}.{}", self.class_name, subroutine.name.name);
+                    let is_void = subroutine.return_type == Token::Keyword(Void);
+                    match value {
+                        Some(value) => {
+                            if is_void {
+                                self.error(
+                                    value.span,
+                                    format!("void subroutine '{name}' cannot return a value"),
+                                );
+                            }
+                            self.check_expression(value);
+                        }
+                        None if !is_void => {
+                            self.error(*span, format!("subroutine '{name}' must return a value"))
+                        }
+                        None => (),
+                    }
+                }
+                Statement::For {
+                    init,
+                    cond,
+                    step,
+                    body,
+                    ..
+                } => {
+                    if let Some(init) = init {
+                        self.check_statements(std::slice::from_ref(init));
+                    }
+                    if let Some(cond) = cond {
+                        self.check_expression(cond);
+                    }
+                    if let Some(step) = step {
+                        self.check_statements(std::slice::from_ref(step));
+                    }
+                    self.check_statements(body);
+                }
+                Statement::Break { .. } | Statement::Continue { .. } => (),
+            }
+        }
+    }
+
+    fn check_var(&mut self, var: &Ident) {
+        match self.symbol_table.get(&var.name) {
+            None => self.error(var.span, format!("undeclared variable '{}'", var.name)),
+            Some(def)
+                if def.segment == Segment::This && self.subroutine.unwrap().kind == Function =>
+            {
+                self.error(
+                    var.span,
+                    format!("field '{}' cannot be used in a function", var.name),
+                )
+            }
+            _ => (),
+        }
+    }
+
+    fn check_expression(&mut self, expr: &Expression) {
+        let terms = std::iter::once(&expr.first).chain(expr.rest.iter().map(|(_, t)| t));
+        for term in terms {
+            self.check_term(&term.kind, term.span);
+        }
+    }
+
+    fn check_term(&mut self, term: &TermKind, span: Span) {
+        match term {
+            TermKind::IntConst(_) | TermKind::StringConst(_) => (),
+            TermKind::KeywordConst(This) => {
+                if self.subroutine.unwrap().kind == Function {
+                    self.error(span, "'this' cannot be used in a function".to_string());
+                }
+            }
+            TermKind::KeywordConst(_) => (),
+            TermKind::Var(var) => self.check_var(var),
+            TermKind::Index(var, index) => {
+                self.check_var(var);
+                self.check_expression(index);
+            }
+            TermKind::Call(call) => {
+                if self.check_call(call) == Some(true) {
+                    self.error(
+                        call.span,
+                        format!(
+                            "'{}' returns void and cannot be used in an expression",
+                            call_name(call)
+                        ),
+                    );
+                }
+            }
+            TermKind::Paren(expr) => self.check_expression(expr),
+            TermKind::Unary(_, inner) => self.check_term(&inner.kind, inner.span),
+        }
+    }
+
+    /// Returns whether the called subroutine is void, or `None` if it couldn't be resolved
+    fn check_call(&mut self, call: &SubroutineCall) -> Option<bool> {
+        for arg in &call.args {
+            self.check_expression(arg);
+        }
+
+        // the class the subroutine is looked up in, and whether it's called on an object
+        let (class, on_object) = match &call.receiver {
+            None => (self.class_name.to_owned(), true),
+            Some(receiver) => match self
+                .symbol_table
+                .get(&receiver.name)
+                .map(|s| s.dtype.clone())
+            {
+                Some(Token::Identifier(class)) => (class, true),
+                Some(dtype) => {
+                    self.error(
+                        receiver.span,
+                        format!(
+                            "cannot call '{}' on '{}' of type '{dtype}'",
+                            call.name.name, receiver.name
+                        ),
+                    );
+                    return None;
+                }
+                None if self.signatures.contains_key(&receiver.name) => {
+                    (receiver.name.clone(), false)
+                }
+                None => {
+                    self.error(
+                        receiver.span,
+                        format!("undeclared class or variable '{}'", receiver.name),
+                    );
+                    return None;
+                }
+            },
+        };
+
+        let name = format!("{class}.{}", call.name.name);
+        // unknown variable types are reported at the declaration
+        let Some(&signature) = self.signatures.get(&class)?.get(&call.name.name) else {
+            self.error(
+                call.name.span,
+                format!("class '{class}' has no subroutine '{}'", call.name.name),
+            );
+            return None;
+        };
+
+        match (signature.kind, on_object) {
+            (Method, false) => self.error(
+                call.name.span,
+                format!("method '{name}' called as a function"),
+            ),
+            (Method, true)
+                if call.receiver.is_none() && self.subroutine.unwrap().kind == Function =>
+            {
+                self.error(
+                    call.name.span,
+                    format!("method '{name}' called from a function without an object"),
+                )
+            }
+            (Function | Constructor, true) => self.error(
+                call.name.span,
+                format!(
+                    "{} '{name}' called as a method, use '{name}(...)'",
+                    signature.kind
+                ),
+            ),
+            _ => (),
+        }
+
+        if signature.n_params != call.args.len() {
+            self.error(
+                call.span,
+                format!(
+                    "'{name}' expects {} argument{}, got {}",
+                    signature.n_params,
+                    if signature.n_params == 1 { "" } else { "s" },
+                    call.args.len()
+                ),
+            );
+        }
+
+        Some(signature.is_void)
+    }
+}
+
+fn call_name(call: &SubroutineCall) -> String {
+    match &call.receiver {
+        Some(receiver) => format!("{}.{}", receiver.name, call.name.name),
+        None => call.name.name.clone(),
+    }
+}
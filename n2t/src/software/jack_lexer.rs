@@ -0,0 +1,321 @@
+//! jack source -> tokens. The one lexer for the Jack tools: `jack_parser` builds the syntax tree from it, and
+//! `JackTokenizer` writes it out directly as the ch 10 token xml. Whitespace and comments are skipped, every token
+//! carries the span of source it was read from.
+//!
+//! Malformed input (integer constants outside 0..32767, unterminated strings and block comments, stray characters) is
+//! recorded in `Lexer::errors` and lexing carries on, so the parser can report them alongside its own errors.
+//!
+//! Extended Jack adds `'c'` character literals, which lex as the character's integer code, and the escape sequences
+//! `\n` (newline), `\b` (backspace), `\\`, `\'` and `\"` in both character literals and string constants.
+
+use crate::software::{
+    compiler_utils::{get_token_type, Dialect, Symbol, Token},
+    diagnostics::Span,
+};
+use std::str::FromStr;
+
+/// Largest integer constant allowed in Jack source
+pub const MAX_INT_CONST: u32 = 32767;
+
+/// `String.newLine()` in the Jack character set
+pub const NEWLINE: u16 = 128;
+/// `String.backSpace()` in the Jack character set
+pub const BACKSPACE: u16 = 129;
+
+/// A token along with the bytes of source it was read from
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct LexError {
+    pub span: Span,
+    pub message: String,
+}
+
+/// Iterator over the tokens of a .jack file
+#[derive(Debug, Clone)]
+pub struct Lexer<'a> {
+    pub errors: Vec<LexError>,
+
+    source: &'a str,
+    /// byte offset of the next unread character
+    position: usize,
+    dialect: Dialect,
+}
+
+fn is_symbol(byte: u8) -> bool {
+    b"{}()[].,;+-*/&|<>=~".contains(&byte)
+}
+
+fn is_word(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+impl<'a> Lexer<'a> {
+    /// Lexes standard Jack
+    pub fn new(source: &'a str) -> Self {
+        Self::with_dialect(source, Dialect::Standard)
+    }
+
+    pub fn with_dialect(source: &'a str, dialect: Dialect) -> Self {
+        Self {
+            errors: Vec::new(),
+            source,
+            position: 0,
+            dialect,
+        }
+    }
+
+    fn byte_at(&self, offset: usize) -> Option<u8> {
+        self.source.as_bytes().get(self.position + offset).copied()
+    }
+
+    fn error(&mut self, span: Span, message: String) {
+        self.errors.push(LexError { span, message });
+    }
+
+    /// Advances past whitespace, `// line comments`, `/* block comments */` and `/** API comments */`
+    fn skip_trivia(&mut self) {
+        loop {
+            match (self.byte_at(0), self.byte_at(1)) {
+                (Some(b), _) if b.is_ascii_whitespace() => self.position += 1,
+                (Some(b'/'), Some(b'/')) => {
+                    self.position = match self.source[self.position..].find('\n') {
+                        Some(i) => self.position + i + 1,
+                        None => self.source.len(),
+                    };
+                }
+                (Some(b'/'), Some(b'*')) => {
+                    let start = self.position;
+                    self.position = match self.source[start + 2..].find("*/") {
+                        Some(i) => start + 2 + i + 2,
+                        None => {
+                            self.error(
+                                Span::new(start, start + 2),
+                                "unterminated block comment".to_string(),
+                            );
+                            self.source.len()
+                        }
+                    };
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// The opening quote has been read. Strings end at the closing quote, or at the end of the line if there isn't one.
+    fn string_const(&mut self, start: usize) -> Token {
+        let bytes = self.source.as_bytes();
+        let mut value = String::new();
+        let mut i = start + 1;
+        loop {
+            match bytes.get(i) {
+                Some(b'"') => {
+                    self.position = i + 1;
+                    break;
+                }
+                Some(b'\n') => {
+                    // a '\r' before the line break is part of the line break, not the string
+                    let end = match bytes[i - 1] {
+                        b'\r' if i - 1 > start => i - 1,
+                        _ => i,
+                    };
+                    if end < i {
+                        value.pop();
+                    }
+                    self.error(
+                        Span::new(end, end),
+                        "newline in string constant, strings must close on the line they start"
+                            .to_string(),
+                    );
+                    self.position = end;
+                    break;
+                }
+                None => {
+                    self.error(
+                        Span::new(start, start + 1),
+                        "unterminated string constant".to_string(),
+                    );
+                    self.position = self.source.len();
+                    break;
+                }
+                Some(b'\\')
+                    if self.dialect == Dialect::Extended
+                        && !matches!(bytes.get(i + 1), Some(b'\r' | b'\n') | None) =>
+                {
+                    let (code, len) = self.escape(i);
+                    value.push(char::from_u32(code as u32).unwrap());
+                    i += len;
+                }
+                Some(_) => {
+                    let c = self.source[i..].chars().next().unwrap();
+                    value.push(c);
+                    i += c.len_utf8();
+                }
+            }
+        }
+
+        Token::ConstString(value)
+    }
+
+    /// `'c'` in extended Jack, the opening quote has been read. Lexes as the character's code.
+    fn char_const(&mut self, start: usize) -> Token {
+        let body = start + 1;
+        let (code, len) = match self.source[body..].chars().next() {
+            Some('\\') => self.escape(body),
+            Some('\'') => {
+                self.error(
+                    Span::new(start, body + 1),
+                    "empty character literal".to_string(),
+                );
+                self.position = body + 1;
+                return Token::ConstInt(0);
+            }
+            Some(c) if c != '\r' && c != '\n' => {
+                if !(' '..='~').contains(&c) {
+                    self.error(
+                        Span::new(body, body + c.len_utf8()),
+                        format!("character {c:?} is not in the Jack character set"),
+                    );
+                }
+                (c as u16, c.len_utf8())
+            }
+            _ => {
+                self.error(
+                    Span::new(start, body),
+                    "unterminated character literal".to_string(),
+                );
+                self.position = body;
+                return Token::ConstInt(0);
+            }
+        };
+
+        let end = body + len;
+        let rest = &self.source[end..];
+        let word = &rest[..rest.find(char::is_whitespace).unwrap_or(rest.len())];
+        match word.find('\'') {
+            Some(0) => self.position = end + 1,
+            // 'ab', skip to the closing quote so it doesn't start another literal
+            Some(i) => {
+                self.position = end + i + 1;
+                self.error(
+                    Span::new(start, self.position),
+                    "character literals hold a single character".to_string(),
+                );
+            }
+            None => {
+                self.error(
+                    Span::new(start, body),
+                    "unterminated character literal".to_string(),
+                );
+                self.position = end;
+            }
+        }
+        Token::ConstInt(code as i16)
+    }
+
+    /// The escape sequence starting with the backslash at byte `at`, returns the Jack character code and the length of
+    /// the sequence in bytes
+    fn escape(&mut self, at: usize) -> (u16, usize) {
+        let Some(c) = self.source[at + 1..].chars().next() else {
+            return (b'\\' as u16, 1);
+        };
+        let code = match c {
+            'n' => NEWLINE,
+            'b' => BACKSPACE,
+            '\\' | '\'' | '"' => c as u16,
+            _ => {
+                self.error(
+                    Span::new(at, at + 1 + c.len_utf8()),
+                    format!("unknown escape sequence '\\{c}'"),
+                );
+                c as u16
+            }
+        };
+
+        (code, 1 + c.len_utf8())
+    }
+
+    /// Keywords, identifiers and integer constants: a run of letters, digits and underscores
+    fn word(&mut self, start: usize) -> Token {
+        let end = self.source.as_bytes()[start..]
+            .iter()
+            .position(|&b| !is_word(b))
+            .map(|i| start + i)
+            .unwrap_or(self.source.len());
+        self.position = end;
+
+        let word = &self.source[start..end];
+        let span = Span::new(start, end);
+        if !word.as_bytes()[0].is_ascii_digit() {
+            return match get_token_type(word) {
+                Token::Keyword(k) if k.is_extended() && self.dialect == Dialect::Standard => {
+                    Token::Identifier(word.to_owned())
+                }
+                t => t,
+            };
+        }
+
+        if !word.bytes().all(|b| b.is_ascii_digit()) {
+            self.error(
+                span,
+                format!("invalid integer constant '{word}', identifiers cannot start with a digit"),
+            );
+            return Token::ConstInt(0);
+        }
+        match word.parse::<u32>() {
+            Ok(x) if x <= MAX_INT_CONST => Token::ConstInt(x as i16),
+            _ => {
+                self.error(
+                    span,
+                    format!("integer constant {word} is out of range 0..{MAX_INT_CONST}"),
+                );
+                Token::ConstInt(0)
+            }
+        }
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = SpannedToken;
+
+    fn next(&mut self) -> Option<SpannedToken> {
+        loop {
+            self.skip_trivia();
+
+            let start = self.position;
+            let first = self.byte_at(0)?;
+
+            let token = if first == b'"' {
+                // ------------------------------- string constant ------------------------------ //
+                self.string_const(start)
+            } else if first == b'\'' && self.dialect == Dialect::Extended {
+                // ----------------------------- character literal ----------------------------- //
+                self.char_const(start)
+            } else if is_symbol(first) {
+                // ----------------------------------- symbol ----------------------------------- //
+                self.position += 1;
+                Token::Symbol(Symbol::from_str(&self.source[start..start + 1]).unwrap())
+            } else if is_word(first) {
+                // ---------------------- keyword, identifier or integer constant ---------------------- //
+                self.word(start)
+            } else {
+                let c = self.source[start..].chars().next().unwrap();
+                self.position += c.len_utf8();
+                self.error(
+                    Span::new(start, self.position),
+                    format!("unexpected character {c:?}"),
+                );
+                continue;
+            };
+
+            return Some(SpannedToken {
+                token,
+                span: Span::new(start, self.position),
+            });
+        }
+    }
+}
@@ -0,0 +1,375 @@
+//! Syntax tree optimizations run by `JackCompiler` before code generation: constant subexpressions are folded, and
+//! `+ 0`, `- 0`, `* 1` and `/ 1` are dropped. Multiplication by a constant power of two is moved to the right hand side
+//! of the `*`, where the code generator turns it into a sequence of adds (see `JackCompiler::compile_expression`).
+//!
+//! Division by a constant power of two becomes a right shift, built from bit tests since the VM has no shifts, but
+//! only when the dividend is known to be non-negative, like `(x & 255) / 16`. `Math.divide` truncates toward zero and
+//! a shift rounds down, so they disagree on odd negative numbers, and those divisions still go to the OS.
+//!
+//! Folding follows the Hack platform's 16 bit arithmetic: results wrap, comparisons produce -1 (true) or 0 (false), and
+//! division truncates toward zero like `Math.divide`. Division by a constant 0 is left for the OS to report.
+//!
+//! Extended Jack's `const`s are also replaced here, by `inline_consts`. That one isn't optional, constants have no
+//! storage to fall back on.
+
+use crate::software::{
+    compiler_utils::Keyword,
+    diagnostics::Span,
+    jack_ast::{
+        BinaryOp, Class, ConstDec, Expression, Statement, SubroutineCall, Term, TermKind, UnaryOp,
+    },
+};
+use std::collections::HashMap;
+
+/// Largest power of two (as an exponent) that is multiplied by repeated doubling instead of calling `Math.multiply`.
+/// Each doubling costs 4 VM commands, so past this point the code size isn't worth it.
+pub const MAX_DOUBLINGS: u32 = 8;
+
+/// Returns the exponent if `x * c` should be compiled as repeated doubling
+pub fn doublings(c: i16) -> Option<u32> {
+    let k = c.trailing_zeros();
+    (c > 1 && c.count_ones() == 1 && k <= MAX_DOUBLINGS).then_some(k)
+}
+
+/// Returns the exponent if `x / c` can be compiled as a right shift, as long as `x` is non-negative
+pub fn halvings(c: i16) -> Option<u32> {
+    (c > 1 && c.count_ones() == 1).then_some(c.trailing_zeros())
+}
+
+/// Whether `term` is known to be non-negative at compile time
+pub fn is_non_negative(term: &Term) -> bool {
+    match &term.kind {
+        TermKind::Paren(expr) => expr
+            .rest
+            .iter()
+            .fold(is_non_negative(&expr.first), |x, (op, term)| {
+                is_non_negative_after(x, *op, term)
+            }),
+        _ => const_value(term).is_some_and(|c| c >= 0),
+    }
+}
+
+/// Whether `x op term` is known to be non-negative, given whether `x` is. Masking with a non-negative number clears the
+/// sign, anything that can overflow or produces `true` might set it.
+pub fn is_non_negative_after(x: bool, op: BinaryOp, term: &Term) -> bool {
+    match op {
+        BinaryOp::And => x || is_non_negative(term),
+        BinaryOp::Or | BinaryOp::Div => x && is_non_negative(term),
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul => false,
+        BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Eq => false,
+    }
+}
+
+pub fn optimize_class(class: &mut Class) {
+    for subroutine in &mut class.subroutines {
+        optimize_statements(&mut subroutine.statements);
+    }
+}
+
+fn optimize_statements(statements: &mut [Statement]) {
+    for statement in statements {
+        match statement {
+            Statement::Let { index, value, .. } => {
+                if let Some(index) = index {
+                    optimize_expression(index);
+                }
+                optimize_expression(value);
+            }
+            Statement::If {
+                cond,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                optimize_expression(cond);
+                optimize_statements(then_branch);
+                if let Some(else_branch) = else_branch {
+                    optimize_statements(else_branch);
+                }
+            }
+            Statement::While { cond, body, .. } => {
+                optimize_expression(cond);
+                optimize_statements(body);
+            }
+            Statement::Do { call, .. } => optimize_call(call),
+            Statement::Return { value, .. } => {
+                if let Some(value) = value {
+                    optimize_expression(value);
+                }
+            }
+            Statement::For {
+                init,
+                cond,
+                step,
+                body,
+                ..
+            } => {
+                if let Some(init) = init {
+                    optimize_statements(std::slice::from_mut(init));
+                }
+                if let Some(cond) = cond {
+                    optimize_expression(cond);
+                }
+                if let Some(step) = step {
+                    optimize_statements(std::slice::from_mut(step));
+                }
+                optimize_statements(body);
+            }
+            Statement::Break { .. } | Statement::Continue { .. } => {}
+        }
+    }
+}
+
+/// The value of `expr` if it only uses constants and `consts`
+pub fn const_eval(expr: &Expression, consts: &[ConstDec]) -> Option<i16> {
+    let consts = consts
+        .iter()
+        .map(|c| (c.name.name.as_str(), c.value))
+        .collect();
+    let mut expr = expr.clone();
+    inline_expression(&mut expr, &consts, &mut Vec::new());
+    optimize_expression(&mut expr);
+
+    match expr.rest.is_empty() {
+        true => const_value(&expr.first),
+        false => None,
+    }
+}
+
+/// Replaces uses of the class's constants with their values. Parameters and locals shadow constants of the same name.
+/// Returns the places a constant is assigned to.
+pub fn inline_consts(class: &mut Class) -> Vec<(Span, String)> {
+    let mut errors = Vec::new();
+    if class.consts.is_empty() {
+        return errors;
+    }
+
+    for subroutine in &mut class.subroutines {
+        let consts = class
+            .consts
+            .iter()
+            .filter(|c| {
+                !subroutine
+                    .params
+                    .iter()
+                    .chain(&subroutine.locals)
+                    .any(|dec| dec.names.iter().any(|n| n.name == c.name.name))
+            })
+            .map(|c| (c.name.name.as_str(), c.value))
+            .collect();
+        inline_statements(&mut subroutine.statements, &consts, &mut errors);
+    }
+
+    errors
+}
+
+type Consts<'a> = HashMap<&'a str, i16>;
+
+fn inline_statements(
+    statements: &mut [Statement],
+    consts: &Consts,
+    errors: &mut Vec<(Span, String)>,
+) {
+    for statement in statements {
+        match statement {
+            Statement::Let {
+                target,
+                index,
+                value,
+                ..
+            } => {
+                if consts.contains_key(target.name.as_str()) {
+                    errors.push((
+                        target.span,
+                        format!("cannot assign to constant '{}'", target.name),
+                    ));
+                }
+                if let Some(index) = index {
+                    inline_expression(index, consts, errors);
+                }
+                inline_expression(value, consts, errors);
+            }
+            Statement::If {
+                cond,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                inline_expression(cond, consts, errors);
+                inline_statements(then_branch, consts, errors);
+                if let Some(else_branch) = else_branch {
+                    inline_statements(else_branch, consts, errors);
+                }
+            }
+            Statement::While { cond, body, .. } => {
+                inline_expression(cond, consts, errors);
+                inline_statements(body, consts, errors);
+            }
+            Statement::Do { call, .. } => inline_call(call, consts, errors),
+            Statement::Return { value, .. } => {
+                if let Some(value) = value {
+                    inline_expression(value, consts, errors);
+                }
+            }
+            Statement::For {
+                init,
+                cond,
+                step,
+                body,
+                ..
+            } => {
+                if let Some(init) = init {
+                    inline_statements(std::slice::from_mut(init), consts, errors);
+                }
+                if let Some(cond) = cond {
+                    inline_expression(cond, consts, errors);
+                }
+                if let Some(step) = step {
+                    inline_statements(std::slice::from_mut(step), consts, errors);
+                }
+                inline_statements(body, consts, errors);
+            }
+            Statement::Break { .. } | Statement::Continue { .. } => {}
+        }
+    }
+}
+
+fn inline_call(call: &mut SubroutineCall, consts: &Consts, errors: &mut Vec<(Span, String)>) {
+    for arg in &mut call.args {
+        inline_expression(arg, consts, errors);
+    }
+}
+
+fn inline_expression(expr: &mut Expression, consts: &Consts, errors: &mut Vec<(Span, String)>) {
+    inline_term(&mut expr.first, consts, errors);
+    for (_, term) in &mut expr.rest {
+        inline_term(term, consts, errors);
+    }
+}
+
+fn inline_term(term: &mut Term, consts: &Consts, errors: &mut Vec<(Span, String)>) {
+    match &mut term.kind {
+        TermKind::Var(name) => {
+            if let Some(&value) = consts.get(name.name.as_str()) {
+                term.kind = TermKind::IntConst(value);
+            }
+        }
+        TermKind::Index(name, index) => {
+            if consts.contains_key(name.name.as_str()) {
+                errors.push((
+                    name.span,
+                    format!("constant '{}' cannot be indexed", name.name),
+                ));
+            }
+            inline_expression(index, consts, errors);
+        }
+        TermKind::Paren(expr) => inline_expression(expr, consts, errors),
+        TermKind::Unary(_, inner) => inline_term(inner, consts, errors),
+        TermKind::Call(call) => inline_call(call, consts, errors),
+        TermKind::IntConst(_) | TermKind::StringConst(_) | TermKind::KeywordConst(_) => {}
+    }
+}
+
+fn optimize_call(call: &mut SubroutineCall) {
+    for arg in &mut call.args {
+        optimize_expression(arg);
+    }
+}
+
+/// The value of a term known at compile time
+fn const_value(term: &Term) -> Option<i16> {
+    match term.kind {
+        TermKind::IntConst(x) => Some(x),
+        TermKind::KeywordConst(Keyword::True) => Some(-1),
+        TermKind::KeywordConst(Keyword::False | Keyword::Null) => Some(0),
+        _ => None,
+    }
+}
+
+/// `x op y` the way the Hack VM and OS compute it, `None` if it can't be folded
+pub fn fold(op: BinaryOp, x: i16, y: i16) -> Option<i16> {
+    Some(match op {
+        BinaryOp::Add => x.wrapping_add(y),
+        BinaryOp::Sub => x.wrapping_sub(y),
+        BinaryOp::Mul => x.wrapping_mul(y),
+        BinaryOp::Div if y == 0 => return None,
+        BinaryOp::Div => x.wrapping_div(y),
+        BinaryOp::And => x & y,
+        BinaryOp::Or => x | y,
+        BinaryOp::Lt => -((x < y) as i16),
+        BinaryOp::Gt => -((x > y) as i16),
+        BinaryOp::Eq => -((x == y) as i16),
+    })
+}
+
+/// `term op c` is just `term`
+fn is_identity(op: BinaryOp, c: i16) -> bool {
+    matches!(
+        (op, c),
+        (BinaryOp::Add | BinaryOp::Sub, 0) | (BinaryOp::Mul | BinaryOp::Div, 1)
+    )
+}
+
+fn optimize_expression(expr: &mut Expression) {
+    optimize_term(&mut expr.first);
+    for (_, term) in &mut expr.rest {
+        optimize_term(term);
+    }
+
+    // operators apply left to right, so only a constant prefix can be folded
+    while let (Some(x), Some((op, rhs))) = (const_value(&expr.first), expr.rest.first()) {
+        let Some(y) = const_value(rhs) else {
+            break;
+        };
+        let Some(value) = fold(*op, x, y) else {
+            break;
+        };
+
+        let (_, rhs) = expr.rest.remove(0);
+        expr.first = Term {
+            kind: TermKind::IntConst(value),
+            span: expr.first.span.to(rhs.span),
+        };
+    }
+
+    // 0 + x, 1 * x
+    if let (Some(c), Some((op, _))) = (const_value(&expr.first), expr.rest.first()) {
+        if matches!((op, c), (BinaryOp::Add, 0) | (BinaryOp::Mul, 1)) {
+            expr.first = expr.rest.remove(0).1;
+        } else if *op == BinaryOp::Mul && doublings(c).is_some() {
+            // c * x == x * c, and the constant has no side effects to reorder
+            std::mem::swap(&mut expr.first, &mut expr.rest[0].1);
+        }
+    }
+
+    expr.rest
+        .retain(|(op, term)| !const_value(term).is_some_and(|c| is_identity(*op, c)));
+}
+
+fn optimize_term(term: &mut Term) {
+    match &mut term.kind {
+        TermKind::Paren(expr) => {
+            optimize_expression(expr);
+            // (x) -> x
+            if expr.rest.is_empty() {
+                let inner = std::mem::replace(&mut expr.first.kind, TermKind::IntConst(0));
+                term.kind = inner;
+            }
+        }
+        TermKind::Unary(op, inner) => {
+            optimize_term(inner);
+            if let Some(x) = const_value(inner) {
+                term.kind = TermKind::IntConst(match op {
+                    UnaryOp::Neg => x.wrapping_neg(),
+                    UnaryOp::Not => !x,
+                });
+            }
+        }
+        TermKind::Index(_, index) => optimize_expression(index),
+        TermKind::Call(call) => optimize_call(call),
+        TermKind::IntConst(_)
+        | TermKind::StringConst(_)
+        | TermKind::KeywordConst(_)
+        | TermKind::Var(_) => {}
+    }
+}
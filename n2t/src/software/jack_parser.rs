@@ -0,0 +1,773 @@
+//! jack -> syntax tree. The single parser for the Jack grammar, both the VM code generator and the XML writer work
+//! off of its output. Divider comments are the program structure of the jack language.
+//!
+//! Syntax errors don't stop the parser: a missing token is reported and parsing continues as if it had been there, so
+//! several errors can be reported for the same file. After an error the parser is in panic mode, where further errors
+//! are dropped until it resynchronizes at the next statement or subroutine boundary. This keeps one mistake from
+//! cascading into a screen of follow-on errors.
+
+use crate::software::{
+    compiler_utils::{Dialect, Keyword, Keyword::*, Symbol, Symbol::*, Token},
+    diagnostics::{Diagnostic, Span},
+    jack_ast::{
+        BinaryOp, Class, ConstDec, Expression, Ident, Statement, Subroutine, SubroutineCall, Term,
+        TermKind, UnaryOp, VarDec,
+    },
+    jack_lexer::{Lexer, SpannedToken},
+    jack_optimizer::const_eval,
+};
+
+/// Parses the source of a single .jack file. `file` is only used to label errors.
+pub fn parse(file: &str, source: &str) -> Result<Class, Vec<Diagnostic>> {
+    parse_dialect(file, source, Dialect::Standard)
+}
+
+/// `parse`, accepting the extensions of `dialect`
+pub fn parse_dialect(file: &str, source: &str, dialect: Dialect) -> Result<Class, Vec<Diagnostic>> {
+    let mut parser = JackParser::new(file, source, dialect);
+    let class = parser.parse_class();
+
+    if parser.errors.is_empty() {
+        Ok(class)
+    } else {
+        // lexer and parser errors in source order
+        parser.errors.sort_by_key(|e| (e.line, e.column));
+        Err(parser.errors)
+    }
+}
+
+/// The text of the symbol as it appears in source, `Display` escapes some of them for XML
+pub fn symbol_text(symbol: Symbol) -> String {
+    match symbol {
+        Symbol::And => "&".to_string(),
+        LessThan => "<".to_string(),
+        GreaterThan => ">".to_string(),
+        DblQuote => "\"".to_string(),
+        s => s.to_string(),
+    }
+}
+
+/// How a token is referred to in error messages
+fn describe(token: &Token) -> String {
+    match token {
+        Token::None => "end of file".to_string(),
+        Token::Symbol(s) => format!("'{}'", symbol_text(*s)),
+        Token::ConstString(x) => format!("string \"{x}\""),
+        t => format!("'{t}'"),
+    }
+}
+
+#[derive(Debug)]
+pub struct JackParser {
+    pub source: String,
+    pub file: String,
+    pub errors: Vec<Diagnostic>,
+
+    tokens: Vec<SpannedToken>,
+    position: usize,
+    /// set by the first error, cleared by `synchronize`
+    panicking: bool,
+    /// number of loops around the statement being parsed, `break` and `continue` need at least one
+    loop_depth: usize,
+}
+
+impl JackParser {
+    /// `dialect` decides which keywords exist, the grammar itself accepts all of extended Jack
+    pub fn new(file: &str, source: &str, dialect: Dialect) -> Self {
+        let mut lexer = Lexer::with_dialect(source, dialect);
+        let tokens = lexer.by_ref().collect();
+        // lexer errors are always reported, panic mode only applies to the parser's own errors
+        let errors = lexer
+            .errors
+            .into_iter()
+            .map(|e| Diagnostic::new(file, source, e.span, e.message))
+            .collect();
+
+        JackParser {
+            source: source.to_owned(),
+            file: file.to_owned(),
+            errors,
+            tokens,
+            position: 0,
+            panicking: false,
+            loop_depth: 0,
+        }
+    }
+
+    fn error(&mut self, span: Span, message: String) {
+        if self.panicking {
+            return;
+        }
+        self.panicking = true;
+
+        let diagnostic = Diagnostic::new(&self.file, &self.source, span, message);
+        self.errors.push(diagnostic);
+    }
+
+    /// Reports an error in otherwise well formed source, there's nothing to recover from so panic mode is left alone
+    fn report(&mut self, span: Span, message: String) {
+        let diagnostic = Diagnostic::new(&self.file, &self.source, span, message);
+        self.errors.push(diagnostic);
+    }
+
+    fn peek_token(&self) -> &Token {
+        self.tokens
+            .get(self.position)
+            .map(|t| &t.token)
+            .unwrap_or(&Token::None)
+    }
+
+    /// span of the next token, or an empty span at the end of the file
+    fn peek_span(&self) -> Span {
+        match self.tokens.get(self.position) {
+            Some(t) => t.span,
+            None => {
+                let end = self.tokens.last().map(|t| t.span.end).unwrap_or(0);
+                Span::new(end, end)
+            }
+        }
+    }
+
+    /// empty span right after the last consumed token, where a missing token should have been
+    fn after_prev(&self) -> Span {
+        let end = match self.position {
+            0 => 0,
+            i => self.tokens[i - 1].span.end,
+        };
+        Span::new(end, end)
+    }
+
+    /// Consumes the next token. At the end of the file, returns `Token::None` without advancing.
+    fn next_token(&mut self) -> (Token, Span) {
+        let span = self.peek_span();
+        match self.tokens.get(self.position) {
+            Some(t) => {
+                self.position += 1;
+                (t.token.clone(), span)
+            }
+            None => (Token::None, span),
+        }
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek_token() == token {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consumes `symbol` if it's next, otherwise reports e.g. "expected ';' after let statement" and carries on as if
+    /// it had been there
+    fn expect(&mut self, symbol: Symbol, context: &str) -> Span {
+        if self.peek_token() == &Token::Symbol(symbol) {
+            return self.next_token().1;
+        }
+
+        let span = self.after_prev();
+        self.error(
+            span,
+            format!("expected '{}' {context}", symbol_text(symbol)),
+        );
+        span
+    }
+
+    fn expect_ident(&mut self, what: &str) -> Ident {
+        if let Token::Identifier(name) = self.peek_token() {
+            let name = name.clone();
+            let span = self.next_token().1;
+            return Ident { name, span };
+        }
+
+        let span = self.peek_span();
+        let got = describe(self.peek_token());
+        self.error(span, format!("expected {what}, got {got}"));
+        Ident {
+            name: String::new(),
+            span,
+        }
+    }
+
+    fn expect_type(&mut self, allow_void: bool) -> Token {
+        let token = self.peek_token().clone();
+        if token.is_type() && (allow_void || token != Token::Keyword(Void)) {
+            self.position += 1;
+            return token;
+        }
+
+        let span = self.peek_span();
+        let what = if allow_void { "return type" } else { "type" };
+        self.error(span, format!("expected {what}, got {}", describe(&token)));
+        Token::Identifier(String::new())
+    }
+
+    /// Leaves panic mode by skipping tokens up to the next statement or declaration keyword, the end of the current
+    /// block, or just past a ';'. Blocks opened while skipping are skipped whole.
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.peek_token() {
+                Token::None | Token::Keyword(Constructor | Function | Method) => break,
+                Token::Keyword(
+                    Let | If | While | Do | Return | For | Break | Continue | Static | Field
+                    | Const | Var,
+                )
+                | Token::Symbol(BracketCl)
+                    if depth == 0 =>
+                {
+                    break
+                }
+                Token::Symbol(SemiColon) if depth == 0 => {
+                    self.position += 1;
+                    break;
+                }
+                Token::Symbol(BracketOp) => depth += 1,
+                Token::Symbol(BracketCl) => depth -= 1,
+                _ => {}
+            }
+            self.position += 1;
+        }
+
+        self.panicking = false;
+    }
+
+    pub fn parse_class(&mut self) -> Class {
+        // --------------------------------------- 'class' -------------------------------------- //
+        let start = self.peek_span();
+        if !self.eat(&Token::Keyword(Keyword::Class)) {
+            let got = describe(self.peek_token());
+            self.error(start, format!("expected 'class', got {got}"));
+        }
+
+        // -------------------------------------- className ------------------------------------- //
+        let name = self.expect_ident("class name");
+
+        // ----------------------------------------- '{' ---------------------------------------- //
+        self.expect(BracketOp, "after class name");
+
+        let mut vars = Vec::new();
+        let mut consts = Vec::new();
+        let mut subroutines = Vec::new();
+        loop {
+            if self.panicking {
+                self.synchronize();
+            }
+
+            match self.peek_token() {
+                // ------------------------------------ classVarDec* -------------------------------- //
+                Token::Keyword(Static | Field | Const) => {
+                    if !subroutines.is_empty() {
+                        let span = self.peek_span();
+                        self.error(
+                            span,
+                            "class variables must be declared before subroutines".to_string(),
+                        );
+                    }
+                    if self.peek_token() == &Token::Keyword(Const) {
+                        self.parse_const_dec(&mut consts);
+                    } else {
+                        vars.push(self.parse_var_dec());
+                    }
+                }
+                // ---------------------------------- subroutineDec* -------------------------------- //
+                Token::Keyword(Constructor | Function | Method) => {
+                    subroutines.push(self.parse_subroutine())
+                }
+                Token::Symbol(BracketCl) | Token::None => break,
+                t => {
+                    let got = describe(t);
+                    let (_, span) = self.next_token();
+                    self.error(
+                        span,
+                        format!("expected class variable or subroutine declaration, got {got}"),
+                    );
+                }
+            }
+        }
+
+        // ----------------------------------------- '}' ---------------------------------------- //
+        let end = if self.peek_token() == &Token::Symbol(BracketCl) {
+            self.next_token().1
+        } else {
+            let span = self.peek_span();
+            let got = describe(self.peek_token());
+            self.error(span, format!("expected '}}' to close class, got {got}"));
+            span
+        };
+
+        if self.errors.is_empty() && self.peek_token() != &Token::None {
+            let span = self.peek_span();
+            let got = describe(self.peek_token());
+            self.error(span, format!("unexpected {got} after the end of the class"));
+        }
+
+        Class {
+            name,
+            vars,
+            consts,
+            subroutines,
+            span: start.to(end),
+        }
+    }
+
+    /// ('static' | 'field' | 'var') type varName (',' varName)* ';'
+    fn parse_var_dec(&mut self) -> VarDec {
+        let (token, start) = self.next_token();
+        let Token::Keyword(kind) = token else {
+            unreachable!("declarations start with a keyword")
+        };
+
+        let dtype = self.expect_type(false);
+
+        let mut names = vec![self.expect_ident("variable name")];
+        while self.eat(&Token::Symbol(Comma)) {
+            names.push(self.expect_ident("variable name"));
+        }
+        let end = self.expect(SemiColon, "after variable declaration");
+
+        VarDec {
+            kind,
+            dtype,
+            names,
+            span: start.to(end),
+        }
+    }
+
+    /// 'const' type constName '=' expression (',' constName '=' expression)* ';'
+    ///
+    /// The expressions are evaluated right away, they can only use constants and the consts declared before them.
+    fn parse_const_dec(&mut self, consts: &mut Vec<ConstDec>) {
+        let (_, start) = self.next_token();
+        let dtype = self.expect_type(false);
+
+        let first = consts.len();
+        loop {
+            let name = self.expect_ident("constant name");
+            self.expect(Equals, "after constant name");
+            let expr = self.parse_expression();
+
+            let value = match const_eval(&expr, consts) {
+                Some(value) => value,
+                None => {
+                    if !self.panicking {
+                        let message =
+                            format!("constant '{}' must have a constant value", name.name);
+                        self.report(expr.span, message);
+                    }
+                    0
+                }
+            };
+            if !self.panicking && consts.iter().any(|c| c.name.name == name.name) {
+                self.report(
+                    name.span,
+                    format!("constant '{}' is already declared", name.name),
+                );
+            }
+            consts.push(ConstDec {
+                dtype: dtype.clone(),
+                name,
+                value,
+                span: start,
+            });
+
+            if !self.eat(&Token::Symbol(Comma)) {
+                break;
+            }
+        }
+        let end = self.expect(SemiColon, "after constant declaration");
+
+        for dec in &mut consts[first..] {
+            dec.span = start.to(end);
+        }
+    }
+
+    fn parse_subroutine(&mut self) -> Subroutine {
+        // ----------------------- ('constructor' | 'function' | 'method') ---------------------- //
+        let (token, start) = self.next_token();
+        let Token::Keyword(kind) = token else {
+            unreachable!("subroutines start with a keyword")
+        };
+
+        // ----------------------------------- ('void' | type) ---------------------------------- //
+        let return_type = self.expect_type(true);
+
+        // ----------------------------------- subroutineName ----------------------------------- //
+        let name = self.expect_ident("subroutine name");
+
+        // ------------------------------------ parameterList ----------------------------------- //
+        // ------------------------ ((type varName) (',' type varName)*)? ----------------------- //
+        self.expect(ParenOp, "after subroutine name");
+        let mut params = Vec::new();
+        if self.peek_token().is_type() {
+            loop {
+                let start = self.peek_span();
+                let dtype = self.expect_type(false);
+                let name = self.expect_ident("parameter name");
+                params.push(VarDec {
+                    kind: Arg,
+                    dtype,
+                    span: start.to(name.span),
+                    names: vec![name],
+                });
+
+                if !self.eat(&Token::Symbol(Comma)) {
+                    break;
+                }
+            }
+        }
+        self.expect(ParenCl, "after parameter list");
+
+        // ----------------------------------- subroutineBody ----------------------------------- //
+        self.expect(BracketOp, "to open subroutine body");
+
+        let mut locals = Vec::new();
+        while self.peek_token() == &Token::Keyword(Var) {
+            locals.push(self.parse_var_dec());
+        }
+
+        let statements = self.parse_statements();
+        let end = self.expect(BracketCl, "to close subroutine body");
+
+        Subroutine {
+            kind,
+            return_type,
+            name,
+            params,
+            locals,
+            statements,
+            span: start.to(end),
+        }
+    }
+
+    /// statement* up to, but not including, the closing '}'
+    fn parse_statements(&mut self) -> Vec<Statement> {
+        let mut statements = Vec::new();
+        loop {
+            if self.panicking {
+                self.synchronize();
+            }
+            // a subroutine keyword means this body was never closed, leave it for the class to pick up
+            if matches!(
+                self.peek_token(),
+                Token::Symbol(BracketCl)
+                    | Token::None
+                    | Token::Keyword(Constructor | Function | Method)
+            ) {
+                break;
+            }
+
+            if let Some(statement) = self.parse_statement() {
+                statements.push(statement);
+            }
+        }
+
+        statements
+    }
+
+    /// '{' statements '}'
+    fn parse_block(&mut self, name: &str) -> (Vec<Statement>, Span) {
+        self.expect(BracketOp, &format!("to open {name}"));
+        let statements = self.parse_statements();
+        let end = self.expect(BracketCl, &format!("to close {name}"));
+
+        (statements, end)
+    }
+
+    fn parse_statement(&mut self) -> Option<Statement> {
+        let (token, start) = self.next_token();
+
+        let statement = match token {
+            // ------------ 'let' varName ('[' expression ']')? '=' expression ';' ------------ //
+            Token::Keyword(Let) => {
+                let (target, index, value) = self.parse_let();
+                let end = self.expect(SemiColon, "after let statement");
+
+                Statement::Let {
+                    target,
+                    index,
+                    value,
+                    span: start.to(end),
+                }
+            }
+            // ------ 'if' '(' expression ')' '{' statements '}' ('else' '{' statements '}')? ----- //
+            Token::Keyword(If) => {
+                self.expect(ParenOp, "after 'if'");
+                let cond = self.parse_expression();
+                self.expect(ParenCl, "after if condition");
+
+                let (then_branch, mut end) = self.parse_block("if body");
+                let else_branch = if self.eat(&Token::Keyword(Else)) {
+                    let (statements, else_end) = self.parse_block("else body");
+                    end = else_end;
+                    Some(statements)
+                } else {
+                    None
+                };
+
+                Statement::If {
+                    cond,
+                    then_branch,
+                    else_branch,
+                    span: start.to(end),
+                }
+            }
+            // -------------------- 'while' '(' expression ')' '{' statements '}' ------------------- //
+            Token::Keyword(While) => {
+                self.expect(ParenOp, "after 'while'");
+                let cond = self.parse_expression();
+                self.expect(ParenCl, "after while condition");
+                self.loop_depth += 1;
+                let (body, end) = self.parse_block("while body");
+                self.loop_depth -= 1;
+
+                Statement::While {
+                    cond,
+                    body,
+                    span: start.to(end),
+                }
+            }
+            // ------------------------------ 'do' subroutineCall ';' ------------------------------ //
+            Token::Keyword(Do) => {
+                let call = self.parse_do();
+                let end = self.expect(SemiColon, "after do statement");
+
+                Statement::Do {
+                    call,
+                    span: start.to(end),
+                }
+            }
+            // ------------------------------- 'return' expression? ';' ----------------------------- //
+            Token::Keyword(Return) => {
+                let value = if self.peek_token() != &Token::Symbol(SemiColon) {
+                    Some(self.parse_expression())
+                } else {
+                    None
+                };
+                let end = self.expect(SemiColon, "after return statement");
+
+                Statement::Return {
+                    value,
+                    span: start.to(end),
+                }
+            }
+            // ------ 'for' '(' (let | do)? ';' expression? ';' (let | do)? ')' '{' statements '}' ----- //
+            Token::Keyword(For) => {
+                self.expect(ParenOp, "after 'for'");
+                let init = self.parse_for_clause();
+                self.expect(SemiColon, "after for loop initializer");
+                let cond = if self.peek_token() != &Token::Symbol(SemiColon) {
+                    Some(self.parse_expression())
+                } else {
+                    None
+                };
+                self.expect(SemiColon, "after for loop condition");
+                let step = self.parse_for_clause();
+                self.expect(ParenCl, "after for loop step");
+
+                self.loop_depth += 1;
+                let (body, end) = self.parse_block("for body");
+                self.loop_depth -= 1;
+
+                Statement::For {
+                    init,
+                    cond,
+                    step,
+                    body,
+                    span: start.to(end),
+                }
+            }
+            // ------------------------------- ('break' | 'continue') ';' ----------------------------- //
+            Token::Keyword(k @ (Break | Continue)) => {
+                if self.loop_depth == 0 {
+                    self.report(start, format!("'{k}' outside of a loop"));
+                }
+                let end = self.expect(SemiColon, &format!("after '{k}'"));
+
+                let span = start.to(end);
+                match k {
+                    Break => Statement::Break { span },
+                    _ => Statement::Continue { span },
+                }
+            }
+            Token::Keyword(Else) => {
+                self.error(start, "'else' without a matching 'if'".to_string());
+                return None;
+            }
+            t => {
+                self.error(start, format!("expected statement, got {}", describe(&t)));
+                return None;
+            }
+        };
+
+        Some(statement)
+    }
+
+    /// varName ('[' expression ']')? '=' expression, after the 'let'
+    fn parse_let(&mut self) -> (Ident, Option<Expression>, Expression) {
+        let target = self.expect_ident("variable name after 'let'");
+        let index = if self.eat(&Token::Symbol(BraceOp)) {
+            let index = self.parse_expression();
+            self.expect(BraceCl, "after array index");
+            Some(index)
+        } else {
+            None
+        };
+        self.expect(Equals, "in let statement");
+        let value = self.parse_expression();
+
+        (target, index, value)
+    }
+
+    /// subroutineCall, after the 'do'
+    fn parse_do(&mut self) -> SubroutineCall {
+        let name = self.expect_ident("subroutine name after 'do'");
+        self.parse_call(name)
+    }
+
+    /// The initializer or step of a `for` loop: a let or do statement without its ';', or nothing
+    fn parse_for_clause(&mut self) -> Option<Box<Statement>> {
+        let start = self.peek_span();
+        let statement = match self.peek_token() {
+            Token::Keyword(Let) => {
+                self.position += 1;
+                let (target, index, value) = self.parse_let();
+                Statement::Let {
+                    target,
+                    index,
+                    value,
+                    span: start.to(self.after_prev()),
+                }
+            }
+            Token::Keyword(Do) => {
+                self.position += 1;
+                let call = self.parse_do();
+                Statement::Do {
+                    call,
+                    span: start.to(self.after_prev()),
+                }
+            }
+            _ => return None,
+        };
+
+        Some(Box::new(statement))
+    }
+
+    /// term (op term)*
+    pub fn parse_expression(&mut self) -> Expression {
+        let first = self.parse_term();
+        let mut rest = Vec::new();
+
+        while let Token::Symbol(s) = self.peek_token() {
+            let Some(op) = BinaryOp::from_symbol(*s) else {
+                break;
+            };
+            self.position += 1;
+            rest.push((op, self.parse_term()));
+        }
+
+        let end = rest.last().map(|(_, t)| t.span).unwrap_or(first.span);
+        Expression {
+            span: first.span.to(end),
+            first,
+            rest,
+        }
+    }
+
+    fn parse_term(&mut self) -> Term {
+        let start = self.peek_span();
+
+        let kind = match self.peek_token().clone() {
+            Token::ConstInt(x) => {
+                self.position += 1;
+                TermKind::IntConst(x)
+            }
+            Token::ConstString(x) => {
+                self.position += 1;
+                TermKind::StringConst(x)
+            }
+            Token::Keyword(k @ (True | False | Null | This)) => {
+                self.position += 1;
+                TermKind::KeywordConst(k)
+            }
+            // ------------------------------- '(' expression ')' ------------------------------- //
+            Token::Symbol(ParenOp) => {
+                self.position += 1;
+                let expr = self.parse_expression();
+                self.expect(ParenCl, "to close parenthesized expression");
+                TermKind::Paren(Box::new(expr))
+            }
+            // --------------------------------- (unaryOp term) --------------------------------- //
+            Token::Symbol(s @ (Minus | Tilde)) => {
+                self.position += 1;
+                let op = if s == Minus {
+                    UnaryOp::Neg
+                } else {
+                    UnaryOp::Not
+                };
+                TermKind::Unary(op, Box::new(self.parse_term()))
+            }
+            Token::Identifier(name) => {
+                self.position += 1;
+                let ident = Ident { name, span: start };
+                match self.peek_token() {
+                    // --------------------------- varName '[' expression ']' --------------------------- //
+                    Token::Symbol(BraceOp) => {
+                        self.position += 1;
+                        let index = self.parse_expression();
+                        self.expect(BraceCl, "after array index");
+                        TermKind::Index(ident, Box::new(index))
+                    }
+                    // ------------------------------- subroutineCall ------------------------------- //
+                    Token::Symbol(ParenOp | Period) => TermKind::Call(self.parse_call(ident)),
+                    _ => TermKind::Var(ident),
+                }
+            }
+            t => {
+                self.error(start, format!("expected expression, got {}", describe(&t)));
+                // leave closers for the enclosing rule to match
+                if !matches!(
+                    t,
+                    Token::None | Token::Symbol(SemiColon | ParenCl | BraceCl | BracketCl)
+                ) {
+                    self.position += 1;
+                }
+                TermKind::IntConst(0)
+            }
+        };
+
+        Term {
+            kind,
+            span: start.to(self.after_prev()),
+        }
+    }
+
+    /// subroutineName '(' expressionList ')' | (className | varName) '.' subroutineName '(' expressionList ')'
+    ///
+    /// `first` is the already consumed leading identifier
+    fn parse_call(&mut self, first: Ident) -> SubroutineCall {
+        let start = first.span;
+        let (receiver, name) = if self.eat(&Token::Symbol(Period)) {
+            (Some(first), self.expect_ident("subroutine name after '.'"))
+        } else {
+            (None, first)
+        };
+
+        self.expect(ParenOp, "before argument list");
+        let mut args = Vec::new();
+        if self.peek_token() != &Token::Symbol(ParenCl) {
+            args.push(self.parse_expression());
+            while self.eat(&Token::Symbol(Comma)) {
+                args.push(self.parse_expression());
+            }
+        }
+        let end = self.expect(ParenCl, "after argument list");
+
+        SubroutineCall {
+            receiver,
+            name,
+            args,
+            span: start.to(end),
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! Peephole optimizer for the Hack assembly generated by `vm_to_asm`. The VM instructions are
+//! translated one at a time, so the seams between them are full of redundant stack pointer
+//! bookkeeping (e.g. a push that increments SP immediately followed by a pop that decrements it).
+//!
+//! Each rewrite is a fixed window of instructions that is replaced by a shorter window with the same
+//! effect on RAM and on the D register. Rules never match across a label, so a jump can never land
+//! in the middle of a rewritten window. A few rules assume SP never points at itself (i.e.
+//! `RAM[0] != 0`), which holds for any program that ran the bootstrap.
+
+/// A single rewrite rule. `pattern` lines are matched exactly, except for `@?`, which matches any
+/// A instruction. `replacement` lines of the form `$n` are substituted with the n-th matched line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rule {
+    pub name: &'static str,
+    pub pattern: &'static [&'static str],
+    pub replacement: &'static [&'static str],
+    /// The replacement leaves a different value in the A register than the pattern does, so the
+    /// rule is only applied when the instruction directly after the window is an A instruction.
+    pub clobbers_a: bool,
+}
+
+/// Rules are applied in order, each one over the entire program, until none of them match. Order
+/// matters: collapsing a push/pop pair first exposes more opportunities than rewriting the push.
+pub const RULES: &[Rule] = &[
+    // push followed by pop: the increment and decrement cancel out
+    Rule {
+        name: "incr_decr",
+        pattern: &["@SP", "AM=M+1", "@SP", "AM=M-1"],
+        replacement: &["@SP", "A=M"],
+        clobbers_a: false,
+    },
+    // A already points at the top of the stack after storing to it
+    Rule {
+        name: "reload_stack_addr",
+        pattern: &["@SP", "A=M", "M=D", "@SP", "A=M"],
+        replacement: &["@SP", "A=M", "M=D"],
+        clobbers_a: false,
+    },
+    // M and D are equal after a store
+    Rule {
+        name: "store_reload",
+        pattern: &["M=D", "D=M"],
+        replacement: &["M=D"],
+        clobbers_a: false,
+    },
+    // pop into D followed by addressing the new top of the stack (binary arithmetic)
+    Rule {
+        name: "pop_then_top",
+        pattern: &["@SP", "AM=M-1", "D=M", "@SP", "A=M-1"],
+        replacement: &["@SP", "AM=M-1", "D=M", "A=A-1"],
+        clobbers_a: false,
+    },
+    // push D followed by addressing the new top of the stack (unary arithmetic)
+    Rule {
+        name: "push_then_top",
+        pattern: &["@SP", "A=M", "M=D", "@SP", "AM=M+1", "@SP", "A=M-1"],
+        replacement: &["@SP", "AM=M+1", "A=A-1", "M=D"],
+        clobbers_a: false,
+    },
+    // an A instruction that is immediately overwritten has no effect
+    Rule {
+        name: "dead_a_load",
+        pattern: &["@?", "@?"],
+        replacement: &["$1"],
+        clobbers_a: false,
+    },
+    // push D with a single stack pointer access
+    Rule {
+        name: "push_d",
+        pattern: &["@SP", "A=M", "M=D", "@SP", "AM=M+1"],
+        replacement: &["@SP", "M=M+1", "A=M-1", "M=D"],
+        clobbers_a: true,
+    },
+];
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PeepholeStats {
+    /// Number of times each rule in `RULES` was applied, by index
+    pub applied: Vec<usize>,
+    /// Number of lines (instructions and labels) before and after optimization
+    pub before: usize,
+    pub after: usize,
+}
+
+fn is_a_instr(line: &str) -> bool {
+    line.starts_with('@')
+}
+
+fn line_matches(pattern: &str, line: &str) -> bool {
+    if pattern == "@?" {
+        is_a_instr(line)
+    } else {
+        pattern == line
+    }
+}
+
+/// Tries to match `rule` at the start of `lines`. Returns the replacement window if it applies.
+fn try_rule(rule: &Rule, lines: &[String]) -> Option<Vec<String>> {
+    let len = rule.pattern.len();
+    if lines.len() < len {
+        return None;
+    }
+
+    for (pattern, line) in rule.pattern.iter().zip(lines) {
+        if !line_matches(pattern, line) {
+            return None;
+        }
+    }
+
+    if rule.clobbers_a && !lines.get(len).is_some_and(|next| is_a_instr(next)) {
+        return None;
+    }
+
+    Some(
+        rule.replacement
+            .iter()
+            .map(|r| match r.strip_prefix('$') {
+                Some(i) => lines[i.parse::<usize>().unwrap()].clone(),
+                None => r.to_string(),
+            })
+            .collect(),
+    )
+}
+
+/// Runs a single rule over the whole program, returns the number of times it was applied
+fn apply_rule(rule: &Rule, lines: &mut Vec<String>) -> usize {
+    let mut result = Vec::with_capacity(lines.len());
+    let mut count = 0;
+    let mut i = 0;
+
+    while i < lines.len() {
+        if let Some(replacement) = try_rule(rule, &lines[i..]) {
+            result.extend(replacement);
+            i += rule.pattern.len();
+            count += 1;
+        } else {
+            result.push(lines[i].clone());
+            i += 1;
+        }
+    }
+
+    *lines = result;
+    count
+}
+
+/// Optimizes a block of Hack assembly (one instruction or label per line), returns the optimized
+/// assembly and how often each rule was applied.
+pub fn optimize_with_stats(asm: &str) -> (String, PeepholeStats) {
+    let mut lines: Vec<String> = asm
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| !l.is_empty() && !l.starts_with("//"))
+        .map(|l| l.to_owned())
+        .collect();
+
+    let mut stats = PeepholeStats {
+        applied: vec![0; RULES.len()],
+        before: lines.len(),
+        after: 0,
+    };
+
+    loop {
+        let mut changed = false;
+        for (i, rule) in RULES.iter().enumerate() {
+            let count = apply_rule(rule, &mut lines);
+            stats.applied[i] += count;
+            changed |= count > 0;
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    stats.after = lines.len();
+
+    let mut output = String::with_capacity(asm.len());
+    for line in lines {
+        output.push_str(&line);
+        output.push('\n');
+    }
+
+    (output, stats)
+}
+
+/// Optimizes a block of Hack assembly (one instruction or label per line)
+pub fn optimize(asm: &str) -> String {
+    optimize_with_stats(asm).0
+}
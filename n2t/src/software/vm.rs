@@ -1,11 +1,14 @@
-use crate::software::vm_instructions::*;
-use crate::utils::{get_file_buffers, BuiltInFunc};
+use crate::software::{
+    peephole,
+    vm_instructions::*,
+    vm_ir::{self, VmCommand, VmModule},
+    writer_impl::Segment as WriterSegment,
+};
 use concat_string::concat_string;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{prelude::*, BufWriter};
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
 use strum_macros::{EnumString};
 
 // TODO use box str instead of String?
@@ -66,15 +69,22 @@ pub enum Instruction {
 
 #[derive(Debug, Clone, PartialEq, Default)]
 pub struct LabelCount {
-    eq: usize,
-    lt: usize,
-    gt: usize,
+    /// return labels for calls to the comparison subroutines
+    cmp: usize,
+    /// which comparison subroutines need to be emitted, indexed by `Comparison`
+    cmp_used: [bool; 3],
     ret: HashMap<String, usize>,
 }
 
 /// Accepts a Path to a `.vm` file or folder containing multiple `.vm` files, translates the instructions to Hack
-/// assembly file (`.asm`) in the same directory and returns a Path to it
+/// assembly file (`.asm`) in the same directory and returns a Path to it. The output is run through the peephole
+/// optimizer, see `vm_to_asm_opt` to disable it.
 pub fn vm_to_asm(path: &Path) -> PathBuf {
+    vm_to_asm_opt(path, true)
+}
+
+/// Same as `vm_to_asm`, `optimize` toggles the peephole optimization pass
+pub fn vm_to_asm_opt(path: &Path, optimize: bool) -> PathBuf {
     let mut out_path;
 
     if path.is_file() {
@@ -83,177 +93,230 @@ pub fn vm_to_asm(path: &Path) -> PathBuf {
         out_path = Path::new(path).join(path.file_stem().unwrap());
     }
 
-    let files = get_file_buffers(path, "vm");
+    let asm = translate(path, optimize);
 
     // Init output .asm file
     out_path.set_extension("asm");
     let out_file = File::create(out_path.clone()).unwrap();
     let mut output = BufWriter::new(out_file);
-    write!(output, "{}", BOOTSTRAP.as_str()).unwrap();
-
-    // helper variables for unique labels
-    let mut counts = LabelCount::default();
+    write!(output, "{asm}").unwrap();
 
-    for (file, module_name) in files {
-        let mut lines = file.lines();
+    output.flush().unwrap();
 
-        let mut function_name = "".to_string();
+    out_path
+}
 
-        while let Some(Ok(line)) = lines.next() {
-            // record vm instruction as comment for debug purposes
-            // writeln!(output, "// {line}").unwrap();
+/// Translates a `.vm` file or folder containing multiple `.vm` files to Hack assembly without writing anything to
+/// disk. The bootstrap code is never optimized, so it always occupies the same ROM addresses. Programs without a
+/// `Sys.init` get no bootstrap and start at address 0, with the stack and segment pointers left to the test script.
+pub fn translate(path: &Path, optimize: bool) -> String {
+    let modules = VmModule::load(path).unwrap_or_else(|e| panic!("{e}"));
+    translate_modules(&modules, optimize)
+}
 
-            if line.starts_with("//") || line.is_empty() {
-                continue;
-            }
-            if line.starts_with("function") {
-                let mut tokens = line.split_whitespace();
-                function_name = tokens.nth(1).unwrap().to_string();
-            }
+/// Same as `translate`, for already parsed VM code
+pub fn translate_modules(modules: &[VmModule], optimize: bool) -> String {
+    let Body {
+        mut asm,
+        counts,
+        sys_init,
+        ..
+    } = translate_body(modules);
 
-            write!(output, "{}", parse_line(line, &mut counts, &module_name, &function_name)).unwrap();
+    match sys_init {
+        // the comparison subroutines are placed after the program, behind a jump to the bootstrap's infinite loop in
+        // case `Sys.init` ever returns
+        Some(_) if counts.cmp_used.contains(&true) => {
+            asm.push_str(&concat_string!(load_const("INFINITE_LOOP"), JUMP_UNCOND));
+            asm.push_str(&comparison_routines(&counts));
+        }
+        Some(_) => {}
+        // without a `Sys.init` there's nothing to bootstrap, like the official chapter 7 translator the program starts
+        // at address 0 and falls through into the infinite loop when it's done
+        None => {
+            asm.push_str(INFINITE_LOOP);
+            asm.push_str(&comparison_routines(&counts));
         }
     }
 
-    output.flush().unwrap();
+    if optimize {
+        asm = peephole::optimize(&asm);
+    }
 
-    out_path
+    match sys_init {
+        Some(_) => concat_string!(BOOTSTRAP.as_str(), asm),
+        None => asm,
+    }
 }
 
-/// Parses an individual line of Hack VM code to Hack Assembly code. The resultant String is pushed to the end of the
-/// supplied `output` String
-pub fn parse_line(line: String, counts: &mut LabelCount, module_name: &str, function_name: &str) -> Box<str> {
-    use Instruction::*;
-    let mut temp = line.split_whitespace();
-    let instr = Instruction::from_str(temp.next().unwrap())
-        .unwrap_or_else(|_| panic!("Invalid instruction: {}", line));
-
-    match instr {
-        Pop => {
-            // 2 tokens: pointer and offset
-            let target =
-                Segment::from_str(temp.next().expect("Pop instruction with no location")).unwrap(); // the default should mean this never fails
-            let val = temp.next();
-
-            match target {
-                Segment::Static => concat_string! {
-                    POP_STACK,
-                    load_const(format!("{}.{}", module_name, val.unwrap())),
-                    load(Reg::M, "D")
-                }
-                .into(),
-                _ => pop(target, val).into(),
-            }
-        }
-        Push => {
-            // TODO do statics get a unique name?
-            // 2 tokens: pointer and offset (or "constant" and value)
-            let target =
-                Segment::from_str(temp.next().expect("Push instruction with no location")).unwrap(); // the default should mean this never fails
-            let val = temp.next();
-
-            match target {
-                Segment::Static => concat_string! {
-                    load_const(format!("{}.{}", module_name, val.unwrap())),
-                    load(Reg::D, "M"),
-                    PUSH_D_STACK
+/// VM code translated for running one VM command at a time on the CPU, see `translate_listing`
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmListing {
+    pub asm: String,
+    /// ROM address where the code of each VM command starts, in program order. Commands that produce no code (labels,
+    /// functions without locals) share their address with the next command.
+    pub commands: Vec<u16>,
+    /// `Sys.init` if there is one, address 0 otherwise
+    pub entry: u16,
+    /// address of the infinite loop the program falls into after its last command
+    pub end: u16,
+}
+
+/// Translates the modules the way the official VM emulator sees them: no bootstrap, no peephole optimization and
+/// execution starts at `Sys.init` (or the first command) with whatever the stack and segment pointers were set to.
+pub fn translate_listing(modules: &[VmModule]) -> VmListing {
+    let Body {
+        mut asm,
+        counts,
+        starts,
+        sys_init,
+    } = translate_body(modules);
+
+    let end = instruction_count(&asm);
+    asm.push_str(INFINITE_LOOP);
+    asm.push_str(&comparison_routines(&counts));
+
+    VmListing {
+        asm,
+        commands: starts.into_iter().map(|start| start as u16).collect(),
+        entry: sys_init.unwrap_or(0) as u16,
+        end: end as u16,
+    }
+}
+
+/// Every module's commands in order, without bootstrap or the comparison subroutines they call
+struct Body {
+    asm: String,
+    counts: LabelCount,
+    /// instruction offset of each command
+    starts: Vec<usize>,
+    /// instruction offset of `Sys.init`
+    sys_init: Option<usize>,
+}
+
+fn translate_body(modules: &[VmModule]) -> Body {
+    let mut asm = String::new();
+    let mut starts = Vec::new();
+    let mut offset = 0;
+
+    // helper variables for unique labels
+    let mut counts = LabelCount::default();
+    let mut sys_init = None;
+
+    for module in modules {
+        let mut function_name = "";
+
+        for cmd in &module.commands {
+            if let VmCommand::Function { name, .. } = cmd {
+                function_name = name;
+                if name == "Sys.init" {
+                    sys_init = Some(offset);
                 }
-                .into(),
-                _ => push(target, val).into(),
             }
+
+            let code = command_to_asm(cmd, &mut counts, &module.name, function_name);
+            starts.push(offset);
+            offset += instruction_count(&code);
+            asm.push_str(&code);
         }
-        Add => ADD.to_string().into(),
-        Sub => SUB.to_string().into(),
-        Eq => {
-            counts.eq += 1;
-            eq(counts.eq, function_name).into()
-        }
-        Lt => {
-            counts.lt += 1;
-            lt(counts.lt, function_name).into()
-        }
-        Gt => {
-            counts.gt += 1;
-            gt(counts.gt, function_name).into()
-        }
-        Neg => NEG.to_string().into(),
-        Not => NOT.to_string().into(),
-        And => AND.to_string().into(),
-        Or => OR.to_string().into(),
-        // Flow control
-        Label => {
-            // label + file name
-            let l_name = temp.next().expect("Label instruction with no label name");
-            assert!(
-                !l_name.chars().next().unwrap().is_ascii_digit(),
-                "Labels must not start with a digit. Got: {}",
-                l_name
-            );
-            format!("({function_name}${l_name})\n").into()
-        }
-        Goto => {
-            let l_name = temp.next().expect("Jump instruction with no label");
-            assert!(
-                !l_name.chars().next().unwrap().is_ascii_digit(),
-                "Labels must not start with a digit. Got: {}",
-                l_name
-            );
-
-            jump(l_name.to_string(), function_name).into()
-        } // label + file name
-        IfGoto => {
-            // label + file name
-            let l_name = temp.next().expect("Jump instruction with no label name");
-            assert!(
-                !l_name.chars().next().unwrap().is_ascii_digit(),
-                "Labels must not start with a digit. Got: {}",
-                l_name
-            );
-            jump_if_zero(l_name.to_string(), function_name).into()
-        }
-        Function => {
-            // function name + nVars
-            let l_name = temp.next().expect("Function definition with no name");
-            assert!(
-                !l_name.chars().next().unwrap().is_ascii_digit(),
-                "Function name must not start with a digit. Got: {}",
-                l_name
-            );
-            let mut result = label(l_name);
-            let n_vars: usize = temp
-                .next()
-                .expect("Function definition without nVars")
-                .parse()
-                .unwrap();
-
-            for _ in 0..n_vars {
+    }
+
+    Body {
+        asm,
+        counts,
+        starts,
+        sys_init,
+    }
+}
+
+/// The subroutines shared by every `eq`, `lt` and `gt` in the program
+fn comparison_routines(counts: &LabelCount) -> String {
+    Comparison::ALL
+        .into_iter()
+        .filter(|&cmp| counts.cmp_used[cmp as usize])
+        .map(comparison_routine)
+        .collect()
+}
+
+/// Number of instructions in a piece of assembly, labels don't take up ROM
+fn instruction_count(asm: &str) -> usize {
+    asm.lines()
+        .filter(|l| !(l.is_empty() || l.starts_with('(')))
+        .count()
+}
+
+fn comparison(cmp: Comparison, counts: &mut LabelCount, function_name: &str) -> String {
+    counts.cmp += 1;
+    counts.cmp_used[cmp as usize] = true;
+    compare(cmp, &format!("{function_name}$cmp{}", counts.cmp))
+}
+
+/// Parses an individual line of Hack VM code to Hack Assembly code. Comments and empty lines produce no code.
+pub fn parse_line(line: String, counts: &mut LabelCount, module_name: &str, function_name: &str) -> Box<str> {
+    match vm_ir::parse_line(&line) {
+        Some(cmd) => command_to_asm(&cmd, counts, module_name, function_name).into(),
+        None => "".into(),
+    }
+}
+
+/// Translates a single VM command to Hack Assembly code
+pub fn command_to_asm(cmd: &VmCommand, counts: &mut LabelCount, module_name: &str, function_name: &str) -> String {
+    match cmd {
+        VmCommand::Push(WriterSegment::Static, i) => concat_string! {
+            load_const(format!("{}.{}", module_name, i)),
+            load(Reg::D, "M"),
+            PUSH_D_STACK
+        },
+        VmCommand::Push(seg, i) => push((*seg).into(), Some(&i.to_string())),
+        VmCommand::Pop(WriterSegment::Static, i) => concat_string! {
+            POP_STACK,
+            load_const(format!("{}.{}", module_name, i)),
+            load(Reg::M, "D")
+        },
+        VmCommand::Pop(seg, i) => pop((*seg).into(), Some(&i.to_string())),
+        VmCommand::Add => ADD.to_string(),
+        VmCommand::Sub => SUB.to_string(),
+        VmCommand::Eq => comparison(Comparison::Eq, counts, function_name),
+        VmCommand::Lt => comparison(Comparison::Lt, counts, function_name),
+        VmCommand::Gt => comparison(Comparison::Gt, counts, function_name),
+        VmCommand::Neg => NEG.to_string(),
+        VmCommand::Not => NOT.to_string(),
+        VmCommand::And => AND.to_string(),
+        VmCommand::Or => OR.to_string(),
+        // Flow control, labels are scoped to the function
+        VmCommand::Label(l_name) => format!("({function_name}${l_name})\n"),
+        VmCommand::Goto(l_name) => jump(l_name.to_string(), function_name),
+        VmCommand::IfGoto(l_name) => jump_if_zero(l_name.to_string(), function_name),
+        VmCommand::Function { name, n_vars } => {
+            let mut result = label(name);
+            for _ in 0..*n_vars {
                 result.push_str(&push(Segment::Stack, Some("0")))
             }
-            result.into()
+            result
         }
-        Call => {
-            // function name + nArgs
-            let l_name = temp.next().expect("Function call with no name");
-            assert!(
-                !l_name.chars().next().unwrap().is_ascii_digit(),
-                "Function name must not start with a digit. Got: {}",
-                l_name
-            );
-            let func_name = l_name.to_string();
-
-            // if let Ok(_builtin) = BuiltInFunc::from_str(&func_name) {
-            //     concat_string!("B", func_name, "\n").into()
-            // } else {
-            let n_args = temp.next().expect("Function Call with no Arg count");
-
-            let c = counts.ret.entry(func_name.clone()).or_default();
-            let return_addr = format!("{func_name}$ret{c}");
-            let result = func_call(&func_name, &return_addr, n_args);
+        VmCommand::Call { name, n_args } => {
+            let c = counts.ret.entry(name.clone()).or_default();
+            let return_addr = format!("{name}$ret{c}");
+            let result = func_call(name, &return_addr, &n_args.to_string());
             *c += 1;
 
-            result.into()
-            // }
+            result
         }
-        Return => func_return().into(), // 0 tokens
+        VmCommand::Return => func_return(),
     }
-}
\ No newline at end of file
+}
+
+impl From<WriterSegment> for Segment {
+    fn from(value: WriterSegment) -> Self {
+        match value {
+            WriterSegment::Constant => Segment::Stack,
+            WriterSegment::Argument => Segment::Argument,
+            WriterSegment::Local => Segment::Local,
+            WriterSegment::Static => Segment::Static,
+            WriterSegment::This => Segment::This,
+            WriterSegment::That => Segment::That,
+            WriterSegment::Pointer => Segment::Pointer,
+            WriterSegment::Temp => Segment::Temp,
+        }
+    }
+}
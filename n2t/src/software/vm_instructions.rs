@@ -5,8 +5,6 @@ use concat_string::concat_string;
 use lazy_static::lazy_static;
 use strum_macros::EnumString;
 
-use crate::utils::BuiltInFunc;
-
 #[derive(Debug, Clone, PartialEq, EnumString, strum_macros::Display)]
 pub enum Reg {
     A,
@@ -83,6 +81,14 @@ pub fn jgt(comp: &str) -> String {
     concat_string!(comp, ";JGT\n")
 }
 
+/// Returns:
+/// ```no_test
+///  "{comp};JGE\n"
+/// ```
+pub fn jge(comp: &str) -> String {
+    concat_string!(comp, ";JGE\n")
+}
+
 /// Returns:
 /// ```no_test
 ///  "{comp};JNE\n"
@@ -108,7 +114,7 @@ lazy_static! {
         // "@256\nD=A\n@SP\nM=D\nBSys.Init\n",
         // func_call(&"Main.main".to_owned(), &"Main.main$ret0".to_owned(), "0"),
         "@256\nD=A\n@SP\nM=D\n",
-        func_call(&"Sys.init".to_owned(), &"Sys.init$ret0".to_owned(), "0"),
+        func_call("Sys.init", "Sys.init$ret0", "0"),
         INFINITE_LOOP
     );
 
@@ -274,49 +280,112 @@ pub fn pop(loc: Segment, val: Option<&str>) -> String {
 }
 
 // comparisons
-pub fn eq(eq_count: usize, func_name: &str) -> String {
-    let lab = concat_string!(func_name, "EQ_", eq_count.to_string());
-    concat_string!(
-        POP_STACK,
-        load(Reg::A, "A-1"),
-        load(Reg::D, "M-D"),
-        load(Reg::M, "-1"),
-        load_const(&lab),
-        jeq("D"),
-        SET_A_STACK_TOP,
-        load(Reg::M, "0"),
-        label(&lab)
-    )
+
+/// The comparisons are compiled to shared subroutines that are emitted once per program, see `comparison_routine`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Lt,
+    Gt,
+}
+
+impl Comparison {
+    pub const ALL: [Comparison; 3] = [Comparison::Eq, Comparison::Lt, Comparison::Gt];
+
+    /// label of the shared subroutine
+    pub fn label(&self) -> &'static str {
+        match self {
+            Comparison::Eq => "__EQ",
+            Comparison::Lt => "__LT",
+            Comparison::Gt => "__GT",
+        }
+    }
 }
 
-pub fn lt(lt_count: usize, func_name: &str) -> String {
-    let lab = concat_string!(func_name, "LT_", lt_count.to_string());
+/// Calls the shared subroutine for `cmp`. The return address is passed in R15.
+///
+/// Registers upon exit: SP=SP-1, RAM\[SP-1] = -1 if the comparison is true, 0 otherwise
+pub fn compare(cmp: Comparison, return_label: &str) -> String {
     concat_string!(
-        POP_STACK,
-        load(Reg::A, "A-1"),
-        load(Reg::D, "M-D"),
-        load(Reg::M, "-1"),
-        load_const(&lab),
-        jlt("D"),
-        SET_A_STACK_TOP,
-        load(Reg::M, "0"),
-        label(&lab)
+        load_const(return_label),
+        load(Reg::D, "A"),
+        load_const("R15"),
+        load(Reg::M, "D"),
+        load_const(cmp.label()),
+        JUMP_UNCOND,
+        label(return_label)
     )
 }
 
-/// compares the top 2 values on the stack, pushes -1 if
-pub fn gt(gt_count: usize, func_name: &str) -> String {
-    let lab = concat_string!(func_name, "GT_", gt_count.to_string());
+/// Body of the shared subroutine for `cmp`. Consumes the top 2 values of the stack (x = second-to-top, y = top),
+/// replaces them with -1 if `x cmp y`, 0 otherwise, then jumps to the address stored in R15.
+///
+/// `x - y` overflows when the operands have different signs (e.g. `32767 - -32768`), so in that case the sign of x
+/// alone decides the result. R13 is used as scratch space.
+pub fn comparison_routine(cmp: Comparison) -> String {
+    let name = cmp.label();
+    let x_neg = concat_string!(name, "_XNEG");
+    let diff = concat_string!(name, "_DIFF");
+    let test = concat_string!(name, "_TEST");
+    let done = concat_string!(name, "_DONE");
+
+    let jump = match cmp {
+        Comparison::Eq => jeq("D"),
+        Comparison::Lt => jlt("D"),
+        Comparison::Gt => jgt("D"),
+    };
+
+    let ordering = match cmp {
+        // x - y == 0 iff x == y even if the subtraction wraps
+        Comparison::Eq => concat_string!(POP_STACK, load(Reg::A, "A-1"), load(Reg::D, "M-D")),
+        _ => concat_string!(
+            POP_STACK,
+            load_const("R13"),
+            load(Reg::M, "D"), // R13 = y
+            SET_A_STACK_TOP,
+            load(Reg::D, "M"), // D = x
+            load_const(&x_neg),
+            jlt("D"),
+            // x >= 0
+            load_const("R13"),
+            load(Reg::D, "M"),
+            load_const(&diff),
+            jge("D"),
+            load(Reg::D, "1"), // x >= 0 > y
+            load_const(&test),
+            JUMP_UNCOND,
+            label(&x_neg),
+            // x < 0
+            load_const("R13"),
+            load(Reg::D, "M"),
+            load_const(&diff),
+            jlt("D"),
+            load(Reg::D, "-1"), // x < 0 <= y
+            load_const(&test),
+            JUMP_UNCOND,
+            // same sign, subtraction can't overflow
+            label(&diff),
+            load_const("R13"),
+            load(Reg::D, "M"),
+            SET_A_STACK_TOP,
+            load(Reg::D, "M-D")
+        ),
+    };
+
     concat_string!(
-        POP_STACK,
-        load(Reg::A, "A-1"),
-        load(Reg::D, "M-D"),
+        label(name),
+        ordering,
+        label(&test),
+        SET_A_STACK_TOP,
         load(Reg::M, "-1"),
-        load_const(&lab),
-        jgt("D"),
+        load_const(&done),
+        jump,
         SET_A_STACK_TOP,
         load(Reg::M, "0"),
-        label(&lab)
+        label(&done),
+        load_const("R15"),
+        DEREF_A,
+        JUMP_UNCOND
     )
 }
 
@@ -388,7 +457,7 @@ pub fn func_return() -> String {
     )
 }
 
-pub fn func_call(func_label: &String, return_addr: &String, n_args: &str) -> String {
+pub fn func_call(func_label: &str, return_addr: &str, n_args: &str) -> String {
     concat_string!(
         push(Segment::Stack, Some(return_addr)),
         load_const("LCL"),
@@ -0,0 +1,596 @@
+//! Interpreter that executes `.vm` programs directly, without translating and assembling them first.
+//!
+//! The program is parsed once up front into a flat list of operations with every label, function name and static
+//! variable already resolved to an index or address, so each step is a single `match` with no lookups. Memory uses the
+//! same layout as the translated program running on the native `Computer` (see the constants in `lib.rs`), and statics
+//! are allocated in the same order the assembler allocates variables, so the two can be compared RAM cell for RAM cell.
+//! The only values that differ are return addresses saved in call frames, which are operation indices here and ROM
+//! addresses there.
+
+use crate::hardware::native::os::{Block, OS};
+use crate::software::vm_ir::{VmCommand, VmModule};
+use crate::software::writer_impl::Segment;
+use crate::utils::BuiltInFunc;
+use crate::{
+    ARG, HEAP_START, LCL, SCREEN_END, SCREEN_START, STACK_POINTER, STACK_START, STATIC_START,
+    TEMP_START, THAT, THIS,
+};
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Where a push reads from or a pop writes to, resolved as far as possible at load time
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Loc {
+    Constant(u16),
+    Local(u16),
+    Argument(u16),
+    This(u16),
+    That(u16),
+    /// temp, pointer and static all map to a fixed RAM address
+    Fixed(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Push(Loc),
+    Pop(Loc),
+    Add,
+    Sub,
+    Neg,
+    Eq,
+    Lt,
+    Gt,
+    And,
+    Or,
+    Not,
+    Goto(usize),
+    IfGoto(usize),
+    Function(u16),
+    /// index into `VmProgram::functions`, nArgs
+    Call(usize, u16),
+    Return,
+}
+
+/// A function that is called somewhere in the program. `entry` is `None` if the program doesn't define it, in which
+/// case it has to be provided by the native OS.
+#[derive(Debug, Clone, PartialEq)]
+struct FunctionRef {
+    name: String,
+    entry: Option<usize>,
+    builtin: Option<BuiltInFunc>,
+}
+
+/// A parsed and linked VM program
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmProgram {
+    ops: Vec<Op>,
+    functions: Vec<FunctionRef>,
+    /// name of the function each op belongs to, only used for error messages
+    op_function: Vec<usize>,
+    statics: HashMap<String, u16>,
+}
+
+impl VmProgram {
+    /// Accepts a Path to a `.vm` file or folder containing multiple `.vm` files
+    pub fn load(path: &Path) -> Self {
+        let modules = VmModule::load(path).unwrap_or_else(|e| panic!("{e}"));
+        Self::link(&modules)
+    }
+
+    /// Parses VM code from `(module name, source)` pairs. Modules are laid out in the given order.
+    pub fn parse<'a>(sources: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        let modules: Vec<VmModule> = sources
+            .into_iter()
+            .map(|(name, source)| VmModule::parse(name, source).unwrap_or_else(|e| panic!("{e}")))
+            .collect();
+        Self::link(&modules)
+    }
+
+    /// Resolves every label, function and static in the modules. Panics on jumps to undefined labels or duplicate
+    /// definitions.
+    pub fn link(modules: &[VmModule]) -> Self {
+        let mut program = VmProgram {
+            ops: Vec::new(),
+            functions: Vec::new(),
+            op_function: Vec::new(),
+            statics: HashMap::new(),
+        };
+
+        let mut function_ids: HashMap<String, usize> = HashMap::new();
+        let mut labels: HashMap<String, usize> = HashMap::new();
+        // (op index, scoped label name) of jumps that still need their target
+        let mut jumps: Vec<(usize, String)> = Vec::new();
+
+        for module in modules {
+            let mut function_name = "";
+            let mut function_id = program.function_id(&mut function_ids, "");
+
+            for cmd in &module.commands {
+                let op = match cmd {
+                    VmCommand::Push(segment, index) => {
+                        Op::Push(program.resolve(*segment, *index, &module.name))
+                    }
+                    VmCommand::Pop(segment, index) => {
+                        Op::Pop(program.resolve(*segment, *index, &module.name))
+                    }
+                    VmCommand::Add => Op::Add,
+                    VmCommand::Sub => Op::Sub,
+                    VmCommand::Neg => Op::Neg,
+                    VmCommand::Eq => Op::Eq,
+                    VmCommand::Lt => Op::Lt,
+                    VmCommand::Gt => Op::Gt,
+                    VmCommand::And => Op::And,
+                    VmCommand::Or => Op::Or,
+                    VmCommand::Not => Op::Not,
+                    VmCommand::Label(l_name) => {
+                        let scoped = format!("{function_name}${l_name}");
+                        assert!(
+                            labels.insert(scoped, program.ops.len()).is_none(),
+                            "Duplicate label '{l_name}' in function '{function_name}'"
+                        );
+                        continue;
+                    }
+                    VmCommand::Goto(l_name) => {
+                        jumps.push((program.ops.len(), format!("{function_name}${l_name}")));
+                        Op::Goto(0)
+                    }
+                    VmCommand::IfGoto(l_name) => {
+                        jumps.push((program.ops.len(), format!("{function_name}${l_name}")));
+                        Op::IfGoto(0)
+                    }
+                    VmCommand::Function { name, n_vars } => {
+                        function_name = name;
+                        function_id = program.function_id(&mut function_ids, name);
+                        let func = &mut program.functions[function_id];
+                        assert!(
+                            func.entry.is_none(),
+                            "Duplicate function definition: {function_name}"
+                        );
+                        func.entry = Some(program.ops.len());
+
+                        Op::Function(*n_vars)
+                    }
+                    VmCommand::Call { name, n_args } => {
+                        Op::Call(program.function_id(&mut function_ids, name), *n_args)
+                    }
+                    VmCommand::Return => Op::Return,
+                };
+
+                program.ops.push(op);
+                program.op_function.push(function_id);
+            }
+        }
+
+        for (i, label) in jumps {
+            let target = *labels
+                .get(&label)
+                .unwrap_or_else(|| panic!("Jump to undefined label: {label}"));
+            match &mut program.ops[i] {
+                Op::Goto(t) | Op::IfGoto(t) => *t = target,
+                _ => unreachable!(),
+            }
+        }
+
+        program
+    }
+
+    fn function_id(&mut self, ids: &mut HashMap<String, usize>, name: &str) -> usize {
+        *ids.entry(name.to_string()).or_insert_with(|| {
+            self.functions.push(FunctionRef {
+                name: name.to_string(),
+                entry: None,
+                builtin: BuiltInFunc::from_str(name).ok(),
+            });
+            self.functions.len() - 1
+        })
+    }
+
+    /// Statics are allocated in order of first use, which matches how the assembler allocates variables
+    fn resolve(&mut self, segment: Segment, index: u16, module_name: &str) -> Loc {
+        match segment {
+            Segment::Constant => Loc::Constant(index),
+            Segment::Local => Loc::Local(index),
+            Segment::Argument => Loc::Argument(index),
+            Segment::This => Loc::This(index),
+            Segment::That => Loc::That(index),
+            Segment::Temp => Loc::Fixed(TEMP_START as u16 + index),
+            Segment::Pointer => match index {
+                0 => Loc::Fixed(THIS as u16),
+                1 => Loc::Fixed(THAT as u16),
+                _ => unreachable!("the parser checks pointer indices"),
+            },
+            Segment::Static => {
+                let next = (STATIC_START + self.statics.len()) as u16;
+                let addr = *self
+                    .statics
+                    .entry(format!("{module_name}.{index}"))
+                    .or_insert(next);
+                Loc::Fixed(addr)
+            }
+        }
+    }
+
+    /// Number of operations, labels don't count
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// RAM address of a static variable, e.g. `"Main.0"`
+    pub fn static_addr(&self, name: &str) -> Option<u16> {
+        self.statics.get(name).copied()
+    }
+}
+
+#[derive(Debug)]
+pub struct VmInterpreter {
+    pub ram: Vec<u16>,
+    /// index of the next operation
+    pub pc: usize,
+    /// number of operations executed
+    pub time: usize,
+    /// Calls to `BuiltInFunc`s with a native implementation skip the program's definition (if any)
+    pub native_os: bool,
+    pub halted: bool,
+    /// Why the program halted, if it did something the VM can't execute, e.g. returning without a call frame or
+    /// peeking outside of RAM
+    pub error: Option<String>,
+    pub program: VmProgram,
+    os: OS,
+}
+
+impl VmInterpreter {
+    pub fn new(program: VmProgram) -> Self {
+        VmInterpreter {
+            ram: vec![0; 32768],
+            pc: 0,
+            time: 0,
+            native_os: false,
+            halted: program.is_empty(),
+            error: None,
+            program,
+            os: OS {
+                free_list: vec![Block::new(HEAP_START, SCREEN_START - HEAP_START)],
+            },
+        }
+    }
+
+    /// Does the same thing as the translator's bootstrap code: sets SP to `STACK_START` and calls `Sys.init` if the
+    /// program defines it. The interpreter halts when `Sys.init` returns.
+    pub fn bootstrap(&mut self) {
+        self.ram[STACK_POINTER] = STACK_START as u16;
+        let sys_init = self
+            .program
+            .functions
+            .iter()
+            .position(|f| f.name == "Sys.init" && f.entry.is_some());
+        if let Some(id) = sys_init {
+            self.pc = self.program.ops.len();
+            self.call(id, 0);
+        }
+    }
+
+    /// Name of the function the next operation belongs to
+    pub fn current_function(&self) -> &str {
+        let id = self.program.op_function[self.pc.min(self.program.ops.len().saturating_sub(1))];
+        &self.program.functions[id].name
+    }
+
+    /// True if any pixel on the screen is set
+    pub fn screen_drawn(&self) -> bool {
+        self.ram[SCREEN_START..SCREEN_END].iter().any(|&w| w != 0)
+    }
+
+    /// The working stack, from `STACK_START` to SP
+    pub fn stack(&self) -> &[u16] {
+        &self.ram[STACK_START..self.ram[STACK_POINTER] as usize]
+    }
+
+    #[inline]
+    fn push(&mut self, val: u16) {
+        let sp = self.ram[STACK_POINTER];
+        self.ram[sp as usize] = val;
+        self.ram[STACK_POINTER] = sp.wrapping_add(1);
+    }
+
+    #[inline]
+    fn pop(&mut self) -> u16 {
+        let sp = self.ram[STACK_POINTER].wrapping_sub(1);
+        self.ram[STACK_POINTER] = sp;
+        self.ram[sp as usize]
+    }
+
+    #[inline]
+    fn top(&mut self) -> &mut u16 {
+        let sp = self.ram[STACK_POINTER].wrapping_sub(1);
+        &mut self.ram[sp as usize]
+    }
+
+    #[inline]
+    fn addr(&self, loc: Loc) -> usize {
+        match loc {
+            Loc::Local(i) => self.ram[LCL].wrapping_add(i) as usize,
+            Loc::Argument(i) => self.ram[ARG].wrapping_add(i) as usize,
+            Loc::This(i) => self.ram[THIS].wrapping_add(i) as usize,
+            Loc::That(i) => self.ram[THAT].wrapping_add(i) as usize,
+            Loc::Fixed(a) => a as usize,
+            Loc::Constant(_) => unreachable!(),
+        }
+    }
+
+    #[inline]
+    fn binary(&mut self, f: impl Fn(u16, u16) -> u16) {
+        let y = self.pop();
+        let x = self.top();
+        *x = f(*x, y);
+    }
+
+    /// Executes the operation pointed to by `self.pc`. Does nothing once the program has halted.
+    pub fn step(&mut self) {
+        if self.halted {
+            return;
+        }
+
+        let op = self.program.ops[self.pc];
+        self.time += 1;
+        self.pc += 1;
+
+        match op {
+            Op::Push(Loc::Constant(val)) => self.push(val),
+            Op::Push(loc) => {
+                let val = self.ram[self.addr(loc)];
+                self.push(val);
+            }
+            Op::Pop(loc) => {
+                let val = self.pop();
+                let addr = self.addr(loc);
+                self.ram[addr] = val;
+            }
+            Op::Add => self.binary(u16::wrapping_add),
+            Op::Sub => self.binary(u16::wrapping_sub),
+            Op::And => self.binary(|x, y| x & y),
+            Op::Or => self.binary(|x, y| x | y),
+            Op::Eq => self.binary(|x, y| bool_val(x == y)),
+            Op::Lt => self.binary(|x, y| bool_val((x as i16) < (y as i16))),
+            Op::Gt => self.binary(|x, y| bool_val((x as i16) > (y as i16))),
+            Op::Neg => {
+                let x = self.top();
+                *x = x.wrapping_neg();
+            }
+            Op::Not => {
+                let x = self.top();
+                *x = !*x;
+            }
+            Op::Goto(target) => {
+                // `label X; goto X` never does anything again
+                if target == self.pc - 1 {
+                    self.halted = true;
+                }
+                self.pc = target;
+            }
+            Op::IfGoto(target) => {
+                if self.pop() != 0 {
+                    self.pc = target;
+                }
+            }
+            Op::Function(n_vars) => {
+                for _ in 0..n_vars {
+                    self.push(0);
+                }
+            }
+            Op::Call(id, n_args) => {
+                let native = match self.program.functions[id].builtin {
+                    Some(builtin) if self.native_os => self.native_call(builtin),
+                    _ => false,
+                };
+                if !native {
+                    self.call(id, n_args);
+                }
+            }
+            Op::Return => self.ret(),
+        }
+
+        if self.pc >= self.program.ops.len() {
+            self.halted = true;
+        }
+    }
+
+    fn call(&mut self, id: usize, n_args: u16) {
+        let func = &self.program.functions[id];
+        let Some(entry) = func.entry else {
+            return self.fail(format!("call to undefined function '{}'", func.name));
+        };
+
+        self.push(self.pc as u16);
+        self.push(self.ram[LCL]);
+        self.push(self.ram[ARG]);
+        self.push(self.ram[THIS]);
+        self.push(self.ram[THAT]);
+
+        let sp = self.ram[STACK_POINTER];
+        self.ram[ARG] = sp.wrapping_sub(5).wrapping_sub(n_args);
+        self.ram[LCL] = sp;
+        self.pc = entry;
+    }
+
+    fn ret(&mut self) {
+        let frame = self.ram[LCL] as usize;
+        // the saved return address, LCL, ARG, THIS and THAT sit right below LCL
+        let Some(saved) = frame.checked_sub(5) else {
+            return self.fail(format!(
+                "return with LCL = {frame}, there's no call frame below it"
+            ));
+        };
+        let ret_addr = self.ram[saved];
+
+        let val = self.pop();
+        let arg = self.ram[ARG];
+        self.ram[arg as usize] = val;
+        self.ram[STACK_POINTER] = arg.wrapping_add(1);
+
+        self.ram[THAT] = self.ram[frame - 1];
+        self.ram[THIS] = self.ram[frame - 2];
+        self.ram[ARG] = self.ram[frame - 3];
+        self.ram[LCL] = self.ram[frame - 4];
+        self.pc = ret_addr as usize;
+    }
+
+    /// Halts the program with `message`, naming the function it happened in. `pc` is left on the operation that
+    /// failed.
+    fn fail(&mut self, message: String) {
+        self.pc = self.pc.saturating_sub(1);
+        self.error = Some(format!("{message} in '{}'", self.current_function()));
+        self.halted = true;
+    }
+
+    /// Runs until `self.time == time` or the program halts
+    pub fn run_until(&mut self, time: usize) {
+        while self.time < time && !self.halted {
+            self.step();
+        }
+    }
+
+    // ------------------------------------------------------------------------------------------ //
+    //                                          Native OS                                         //
+    // ------------------------------------------------------------------------------------------ //
+
+    /// Executes `func` in place of a call. The arguments are replaced by the return value, just like a VM function
+    /// returning would, void functions return 0.
+    ///
+    /// Returns false if `func` has no native implementation
+    fn native_call(&mut self, func: BuiltInFunc) -> bool {
+        use BuiltInFunc::*;
+        match func {
+            MathMul => self.binary(|x, y| (x as i16).wrapping_mul(y as i16) as u16),
+            MathDiv => {
+                let y = self.pop() as i16;
+                if y == 0 {
+                    self.fail("Math.divide: division by zero".to_string());
+                    return true;
+                }
+                let x = self.top();
+                *x = (*x as i16).wrapping_div(y) as u16;
+            }
+            MathMin => self.binary(|x, y| (x as i16).min(y as i16) as u16),
+            MathMax => self.binary(|x, y| (x as i16).max(y as i16) as u16),
+            MathSqrt => {
+                let x = *self.top() as i16;
+                if x < 0 {
+                    self.fail(format!("Math.sqrt: negative argument {x}"));
+                    return true;
+                }
+                *self.top() = (x as f64).sqrt() as u16;
+            }
+            MemPeek => {
+                let addr = *self.top() as usize;
+                match self.ram.get(addr) {
+                    Some(&val) => *self.top() = val,
+                    None => self.fail(format!("Memory.peek: address {addr} is outside of RAM")),
+                }
+            }
+            MemPoke => {
+                let val = self.pop();
+                let addr = *self.top() as usize;
+                match self.ram.get_mut(addr) {
+                    Some(word) => *word = val,
+                    None => self.fail(format!("Memory.poke: address {addr} is outside of RAM")),
+                }
+                *self.top() = 0;
+            }
+            MemAlloc | ArrayNew => {
+                let size = *self.top() as i16;
+                if size <= 0 {
+                    self.fail(format!("{func}: size must be positive, got {size}"));
+                    return true;
+                }
+                match self.alloc(size as usize) {
+                    Some(addr) => *self.top() = addr as u16,
+                    None => self.fail(format!(
+                        "{func}: heap overflow, unable to allocate {size} words"
+                    )),
+                }
+            }
+            MemDealloc | ArrayDispose => {
+                let addr = *self.top() as usize;
+                // the block's length is stored right before it
+                match addr
+                    .checked_sub(1)
+                    .filter(|&offset| offset < self.ram.len())
+                {
+                    Some(offset) => {
+                        self.dealloc(offset);
+                        *self.top() = 0;
+                    }
+                    None => self.fail(format!("{func}: there's no block at address {addr}")),
+                }
+            }
+            SysHalt => {
+                self.halted = true;
+                // the halted program still looks like it made the call
+                self.push(0);
+            }
+            SysWait => *self.top() = 0,
+            SysError => {
+                let code = *self.top() as i16;
+                self.fail(format!("Sys.error: {code}"));
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// First fit allocation, each block is preceded by its length like the Jack OS does it. `None` if no free block
+    /// is big enough, even after merging neighbours.
+    fn alloc(&mut self, size: usize) -> Option<usize> {
+        let length = size + 1;
+        for defrag in [false, true] {
+            if defrag {
+                self.defrag();
+            }
+
+            if let Some(i) = self.os.free_list.iter().position(|b| b.len >= length) {
+                let block = &mut self.os.free_list[i];
+                let offset = block.offset;
+                block.offset += length;
+                block.len -= length;
+                if block.len == 0 {
+                    self.os.free_list.remove(i);
+                }
+                self.ram[offset] = length as u16;
+                return Some(offset + 1);
+            }
+        }
+        None
+    }
+
+    /// Frees the block whose length is stored at `offset`
+    fn dealloc(&mut self, offset: usize) {
+        let length = self.ram[offset] as usize;
+        self.os.free_list.push(Block::new(offset, length));
+    }
+
+    fn defrag(&mut self) {
+        self.os.free_list.sort();
+        let mut merged: Vec<Block> = Vec::with_capacity(self.os.free_list.len());
+        for block in self.os.free_list.drain(..) {
+            match merged.last_mut() {
+                Some(prev) if prev.offset + prev.len == block.offset => prev.len += block.len,
+                _ => merged.push(block),
+            }
+        }
+        self.os.free_list = merged;
+    }
+}
+
+#[inline]
+fn bool_val(b: bool) -> u16 {
+    if b {
+        0xFFFF
+    } else {
+        0
+    }
+}
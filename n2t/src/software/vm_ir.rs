@@ -0,0 +1,331 @@
+//! Typed representation of VM code. `.vm` text is parsed into `VmCommand`s once, after which the translator,
+//! interpreter and optimizers work on the commands instead of re-tokenizing text.
+
+use crate::software::vm::Instruction;
+use crate::software::writer_impl::Segment;
+use crate::utils::get_file_buffers;
+use std::fmt;
+use std::io::BufRead;
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum VmCommand {
+    Push(Segment, u16),
+    Pop(Segment, u16),
+    Add,
+    Sub,
+    Neg,
+    Eq,
+    Gt,
+    Lt,
+    And,
+    Or,
+    Not,
+    Label(String),
+    Goto(String),
+    IfGoto(String),
+    Function { name: String, n_vars: u16 },
+    Call { name: String, n_args: u16 },
+    Return,
+}
+
+impl fmt::Display for VmCommand {
+    /// Formats the command exactly as it appears in a `.vm` file
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use VmCommand::*;
+        match self {
+            Push(seg, i) => write!(f, "push {} {i}", Into::<&str>::into(seg)),
+            Pop(seg, i) => write!(f, "pop {} {i}", Into::<&str>::into(seg)),
+            Add => write!(f, "add"),
+            Sub => write!(f, "sub"),
+            Neg => write!(f, "neg"),
+            Eq => write!(f, "eq"),
+            Gt => write!(f, "gt"),
+            Lt => write!(f, "lt"),
+            And => write!(f, "and"),
+            Or => write!(f, "or"),
+            Not => write!(f, "not"),
+            Label(l) => write!(f, "label {l}"),
+            Goto(l) => write!(f, "goto {l}"),
+            IfGoto(l) => write!(f, "if-goto {l}"),
+            Function { name, n_vars } => write!(f, "function {name} {n_vars}"),
+            Call { name, n_args } => write!(f, "call {name} {n_args}"),
+            Return => write!(f, "return"),
+        }
+    }
+}
+
+impl VmCommand {
+    /// Number of values the command pops off of the stack and pushes onto it. Calls and returns are (nArgs, 1) and
+    /// (1, 0) from the point of view of the caller and callee respectively.
+    pub fn stack_effect(&self) -> (u16, u16) {
+        use VmCommand::*;
+        match self {
+            Push(..) => (0, 1),
+            Pop(..) | IfGoto(_) => (1, 0),
+            Add | Sub | Eq | Gt | Lt | And | Or => (2, 1),
+            Neg | Not => (1, 1),
+            Label(_) | Goto(_) => (0, 0),
+            Function { n_vars, .. } => (0, *n_vars),
+            Call { n_args, .. } => (*n_args, 1),
+            Return => (1, 0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VmParseError {
+    pub module: String,
+    /// 1-indexed
+    pub line: usize,
+    /// 1-indexed, in characters
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for VmParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.vm:{}:{}: {}",
+            self.module, self.line, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for VmParseError {}
+
+/// Function metadata, see `VmModule::functions`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionInfo {
+    pub name: String,
+    pub n_vars: u16,
+    /// indices of the function's commands in `VmModule::commands`, including the `function` command itself
+    pub commands: Range<usize>,
+}
+
+/// The commands of a single `.vm` file. The module name is the file stem, which is what statics are qualified with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VmModule {
+    pub name: String,
+    pub commands: Vec<VmCommand>,
+}
+
+impl VmModule {
+    /// Accepts a Path to a `.vm` file or folder containing multiple `.vm` files
+    pub fn load(path: &Path) -> Result<Vec<VmModule>, VmParseError> {
+        let mut modules = Vec::new();
+        for (file, name) in get_file_buffers(path, "vm") {
+            let source: Vec<String> = file.lines().map(|l| l.unwrap()).collect();
+            modules.push(VmModule::parse(&name, &source.join("\n"))?);
+        }
+        Ok(modules)
+    }
+
+    pub fn parse(name: &str, source: &str) -> Result<VmModule, VmParseError> {
+        let mut commands = Vec::new();
+        for (i, line) in source.lines().enumerate() {
+            if let Some(cmd) = parse_command(line).map_err(|(column, message)| VmParseError {
+                module: name.to_string(),
+                line: i + 1,
+                column,
+                message,
+            })? {
+                commands.push(cmd);
+            }
+        }
+
+        Ok(VmModule {
+            name: name.to_string(),
+            commands,
+        })
+    }
+
+    /// The functions defined in this module, in order of definition
+    pub fn functions(&self) -> Vec<FunctionInfo> {
+        let mut result: Vec<FunctionInfo> = Vec::new();
+        for (i, cmd) in self.commands.iter().enumerate() {
+            if let VmCommand::Function { name, n_vars } = cmd {
+                if let Some(prev) = result.last_mut() {
+                    prev.commands.end = i;
+                }
+                result.push(FunctionInfo {
+                    name: name.clone(),
+                    n_vars: *n_vars,
+                    commands: i..self.commands.len(),
+                });
+            }
+        }
+        result
+    }
+}
+
+/// Splits a line into tokens, paired with their 1-indexed column. Stops at a `//` comment.
+fn tokenize(line: &str) -> Vec<(usize, &str)> {
+    let code = match line.find("//") {
+        Some(i) => &line[..i],
+        None => line,
+    };
+
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (col, (i, c)) in code.char_indices().enumerate() {
+        match (c.is_whitespace(), start) {
+            (false, None) => start = Some((col + 1, i)),
+            (true, Some((s_col, s))) => {
+                tokens.push((s_col, &code[s..i]));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    if let Some((s_col, s)) = start {
+        tokens.push((s_col, &code[s..]));
+    }
+    tokens
+}
+
+type ParseResult<T> = Result<T, (usize, String)>;
+
+fn parse_number(token: Option<&(usize, &str)>, column: usize, what: &str) -> ParseResult<u16> {
+    let &(col, text) = token.ok_or_else(|| (column, format!("expected {what}")))?;
+    text.parse()
+        .map_err(|_| (col, format!("expected {what}, got '{text}'")))
+}
+
+fn parse_symbol<'a>(
+    token: Option<&(usize, &'a str)>,
+    column: usize,
+    what: &str,
+) -> ParseResult<&'a str> {
+    let &(col, text) = token.ok_or_else(|| (column, format!("expected {what}")))?;
+    let valid_char = |c: char| c.is_ascii_alphanumeric() || "_.$:".contains(c);
+    if text.starts_with(|c: char| c.is_ascii_digit()) || !text.chars().all(valid_char) {
+        return Err((col, format!("invalid {what} '{text}'")));
+    }
+    Ok(text)
+}
+
+/// Parses a single line of VM code. Returns `None` for empty lines and comments. Errors are (column, message).
+fn parse_command(line: &str) -> ParseResult<Option<VmCommand>> {
+    let tokens = tokenize(line);
+    let Some(&(col, first)) = tokens.first() else {
+        return Ok(None);
+    };
+    // column right after the last token, for errors about missing tokens
+    let end = tokens
+        .last()
+        .map(|(c, t)| c + t.chars().count() + 1)
+        .unwrap();
+
+    let instr =
+        Instruction::from_str(first).map_err(|_| (col, format!("unknown command '{first}'")))?;
+
+    let cmd = match instr {
+        Instruction::Push | Instruction::Pop => {
+            let &(seg_col, seg_name) = tokens
+                .get(1)
+                .ok_or_else(|| (end, "expected segment".to_string()))?;
+            let segment = Segment::from_str(seg_name)
+                .map_err(|_| (seg_col, format!("unknown segment '{seg_name}'")))?;
+            let index = parse_number(tokens.get(2), end, "index")?;
+            let index_col = tokens[2].0;
+
+            let max = match segment {
+                Segment::Constant => 32767,
+                Segment::Pointer => 1,
+                Segment::Temp => 7,
+                _ => u16::MAX,
+            };
+            if index > max {
+                return Err((
+                    index_col,
+                    format!("index {index} out of range for segment '{seg_name}' (max {max})"),
+                ));
+            }
+
+            if instr == Instruction::Push {
+                VmCommand::Push(segment, index)
+            } else {
+                if segment == Segment::Constant {
+                    return Err((seg_col, "cannot pop to the constant segment".to_string()));
+                }
+                VmCommand::Pop(segment, index)
+            }
+        }
+        Instruction::Add => VmCommand::Add,
+        Instruction::Sub => VmCommand::Sub,
+        Instruction::Neg => VmCommand::Neg,
+        Instruction::Eq => VmCommand::Eq,
+        Instruction::Gt => VmCommand::Gt,
+        Instruction::Lt => VmCommand::Lt,
+        Instruction::And => VmCommand::And,
+        Instruction::Or => VmCommand::Or,
+        Instruction::Not => VmCommand::Not,
+        Instruction::Label => {
+            VmCommand::Label(parse_symbol(tokens.get(1), end, "label")?.to_string())
+        }
+        Instruction::Goto => {
+            VmCommand::Goto(parse_symbol(tokens.get(1), end, "label")?.to_string())
+        }
+        Instruction::IfGoto => {
+            VmCommand::IfGoto(parse_symbol(tokens.get(1), end, "label")?.to_string())
+        }
+        Instruction::Function => VmCommand::Function {
+            name: parse_symbol(tokens.get(1), end, "function name")?.to_string(),
+            n_vars: parse_number(tokens.get(2), end, "nVars")?,
+        },
+        Instruction::Call => VmCommand::Call {
+            name: parse_symbol(tokens.get(1), end, "function name")?.to_string(),
+            n_args: parse_number(tokens.get(2), end, "nArgs")?,
+        },
+        Instruction::Return => VmCommand::Return,
+    };
+
+    let expected_len = match cmd {
+        VmCommand::Push(..)
+        | VmCommand::Pop(..)
+        | VmCommand::Function { .. }
+        | VmCommand::Call { .. } => 3,
+        VmCommand::Label(_) | VmCommand::Goto(_) | VmCommand::IfGoto(_) => 2,
+        _ => 1,
+    };
+    if let Some(&(col, extra)) = tokens.get(expected_len) {
+        return Err((col, format!("unexpected '{extra}' after '{cmd}'")));
+    }
+
+    Ok(Some(cmd))
+}
+
+/// Parses a single line of VM code into a command, panics on invalid input. Returns `None` for empty lines and
+/// comments.
+pub fn parse_line(line: &str) -> Option<VmCommand> {
+    parse_command(line).unwrap_or_else(|(column, message)| panic!("{column}: {message}: {line}"))
+}
+
+/// Formats commands as VM code: function bodies are indented and functions are separated by a blank line. The output
+/// parses back into the same commands.
+pub fn pretty_print(commands: &[VmCommand]) -> String {
+    let mut output = String::new();
+    let mut in_function = false;
+    for cmd in commands {
+        let indent = match cmd {
+            VmCommand::Function { .. } => {
+                if !output.is_empty() {
+                    output.push('\n');
+                }
+                in_function = true;
+                ""
+            }
+            _ if !in_function => "",
+            VmCommand::Label(_) => "  ",
+            _ => "    ",
+        };
+        output.push_str(indent);
+        output.push_str(&cmd.to_string());
+        output.push('\n');
+    }
+    output
+}
@@ -0,0 +1,406 @@
+//! Optimization passes over VM code. The Jack compiler emits every expression and statement in isolation, which leaves
+//! constant arithmetic, `push x; pop x` pairs and `not; if-goto` branches behind.
+//!
+//! Every pass works on one function at a time, since labels are scoped to their function. The passes are run in a loop
+//! until none of them changes anything, so one pass can expose more work for another (e.g. folding a `while (true)`
+//! condition makes the branch after it constant).
+
+use crate::software::vm_ir::{VmCommand, VmModule};
+use crate::software::writer_impl::Segment;
+use std::collections::HashSet;
+
+/// Toggles for the individual passes, all enabled by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VmPasses {
+    /// Evaluates arithmetic on constants (including `Math.multiply` and `Math.divide`) and removes identities such as
+    /// `x + 0` and `not; not`
+    pub constant_folding: bool,
+    /// Removes stores to locals, arguments, statics and temps that are overwritten before being read, along with the
+    /// computation of the stored value
+    pub dead_stores: bool,
+    /// Removes `push x; pop x`
+    pub push_pop: bool,
+    /// Simplifies `not; if-goto` after a comparison and constant conditions, removes jumps to the next command,
+    /// unreachable code and unused labels
+    pub branches: bool,
+}
+
+impl Default for VmPasses {
+    fn default() -> Self {
+        Self {
+            constant_folding: true,
+            dead_stores: true,
+            push_pop: true,
+            branches: true,
+        }
+    }
+}
+
+/// Number of times each pass rewrote something
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VmOptStats {
+    pub constant_folding: usize,
+    pub dead_stores: usize,
+    pub push_pop: usize,
+    pub branches: usize,
+    /// Number of commands before and after optimization
+    pub before: usize,
+    pub after: usize,
+}
+
+impl std::ops::AddAssign for VmOptStats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.constant_folding += rhs.constant_folding;
+        self.dead_stores += rhs.dead_stores;
+        self.push_pop += rhs.push_pop;
+        self.branches += rhs.branches;
+        self.before += rhs.before;
+        self.after += rhs.after;
+    }
+}
+
+/// Optimizes a list of commands, returns the optimized commands and how often each pass applied
+pub fn optimize_with_stats(
+    commands: &[VmCommand],
+    passes: &VmPasses,
+) -> (Vec<VmCommand>, VmOptStats) {
+    let mut stats = VmOptStats {
+        before: commands.len(),
+        ..Default::default()
+    };
+
+    let mut result = Vec::with_capacity(commands.len());
+    // commands before the first function (e.g. the ch 7 test programs) are treated as a function of their own
+    let mut start = 0;
+    for i in 1..=commands.len() {
+        if i == commands.len() || matches!(commands[i], VmCommand::Function { .. }) {
+            result.extend(optimize_function(
+                commands[start..i].to_vec(),
+                passes,
+                &mut stats,
+            ));
+            start = i;
+        }
+    }
+
+    stats.after = result.len();
+    (result, stats)
+}
+
+/// Optimizes a list of commands
+pub fn optimize(commands: &[VmCommand], passes: &VmPasses) -> Vec<VmCommand> {
+    optimize_with_stats(commands, passes).0
+}
+
+/// Optimizes every module in place, returns the combined statistics
+pub fn optimize_modules(modules: &mut [VmModule], passes: &VmPasses) -> VmOptStats {
+    let mut total = VmOptStats::default();
+    for module in modules {
+        let (commands, stats) = optimize_with_stats(&module.commands, passes);
+        module.commands = commands;
+        total += stats;
+    }
+    total
+}
+
+fn optimize_function(
+    mut cmds: Vec<VmCommand>,
+    passes: &VmPasses,
+    stats: &mut VmOptStats,
+) -> Vec<VmCommand> {
+    loop {
+        let mut changed = false;
+        if passes.constant_folding {
+            let count = fold_constants(&mut cmds);
+            stats.constant_folding += count;
+            changed |= count > 0;
+        }
+        if passes.push_pop {
+            let count = remove_push_pop(&mut cmds);
+            stats.push_pop += count;
+            changed |= count > 0;
+        }
+        if passes.dead_stores {
+            let count = remove_dead_stores(&mut cmds);
+            stats.dead_stores += count;
+            changed |= count > 0;
+        }
+        if passes.branches {
+            let count = simplify_branches(&mut cmds);
+            stats.branches += count;
+            changed |= count > 0;
+        }
+        if !changed {
+            return cmds;
+        }
+    }
+}
+
+// ------------------------------------------------------------------------------------------ //
+//                                          Constants                                         //
+// ------------------------------------------------------------------------------------------ //
+
+/// Recognizes a constant at the start of `cmds`: `push constant n`, optionally followed by `neg` or `not`. Returns the
+/// value and the number of commands it spans.
+fn constant_at(cmds: &[VmCommand]) -> Option<(i16, usize)> {
+    let VmCommand::Push(Segment::Constant, n) = cmds.first()? else {
+        return None;
+    };
+    let n = *n as i16;
+    match cmds.get(1) {
+        Some(VmCommand::Neg) => Some((n.wrapping_neg(), 2)),
+        Some(VmCommand::Not) => Some((!n, 2)),
+        _ => Some((n, 1)),
+    }
+}
+
+/// Shortest VM code that pushes `val`. Constants are limited to 15 bits, so negative values are pushed as the
+/// complement of a positive one.
+fn push_constant(val: i16) -> Vec<VmCommand> {
+    if val >= 0 {
+        vec![VmCommand::Push(Segment::Constant, val as u16)]
+    } else {
+        vec![
+            VmCommand::Push(Segment::Constant, !val as u16),
+            VmCommand::Not,
+        ]
+    }
+}
+
+fn is_call(cmd: Option<&VmCommand>, function: &str) -> bool {
+    matches!(cmd, Some(VmCommand::Call { name, n_args: 2 }) if name == function)
+}
+
+fn bool_val(b: bool) -> i16 {
+    if b {
+        -1
+    } else {
+        0
+    }
+}
+
+/// Evaluates `op` if both operands are known
+fn eval_binary(x: i16, y: i16, op: &VmCommand) -> Option<i16> {
+    Some(match op {
+        VmCommand::Add => x.wrapping_add(y),
+        VmCommand::Sub => x.wrapping_sub(y),
+        VmCommand::And => x & y,
+        VmCommand::Or => x | y,
+        VmCommand::Eq => bool_val(x == y),
+        VmCommand::Lt => bool_val(x < y),
+        VmCommand::Gt => bool_val(x > y),
+        VmCommand::Call { name, n_args: 2 } if name == "Math.multiply" => x.wrapping_mul(y),
+        VmCommand::Call { name, n_args: 2 } if name == "Math.divide" && y != 0 => x.wrapping_div(y),
+        _ => return None,
+    })
+}
+
+/// Returns true if `y` is the identity element of `op`, i.e. `x op y == x`
+fn is_identity(y: i16, op: &VmCommand) -> bool {
+    match op {
+        VmCommand::Add | VmCommand::Sub | VmCommand::Or => y == 0,
+        VmCommand::And => y == -1,
+        VmCommand::Call { .. } => {
+            y == 1 && (is_call(Some(op), "Math.multiply") || is_call(Some(op), "Math.divide"))
+        }
+        _ => false,
+    }
+}
+
+/// Tries to fold the window starting at `cmds[0]`, returns the replacement and the length of the window it replaces
+fn fold_at(cmds: &[VmCommand]) -> Option<(Vec<VmCommand>, usize)> {
+    // double negation
+    if let [a, b, ..] = cmds {
+        if (*a == VmCommand::Not && *b == VmCommand::Not)
+            || (*a == VmCommand::Neg && *b == VmCommand::Neg)
+        {
+            return Some((Vec::new(), 2));
+        }
+    }
+
+    let (x, x_len) = constant_at(cmds)?;
+
+    // unary operation on a constant, e.g. `push constant 0; not; not`
+    match cmds.get(x_len) {
+        Some(VmCommand::Neg) => return Some((push_constant(x.wrapping_neg()), x_len + 1)),
+        Some(VmCommand::Not) => return Some((push_constant(!x), x_len + 1)),
+        Some(op) if is_identity(x, op) => return Some((Vec::new(), x_len + 1)),
+        _ => (),
+    }
+
+    let (y, y_len) = constant_at(&cmds[x_len..])?;
+    let op = cmds.get(x_len + y_len)?;
+    let result = eval_binary(x, y, op)?;
+    Some((push_constant(result), x_len + y_len + 1))
+}
+
+fn fold_constants(cmds: &mut Vec<VmCommand>) -> usize {
+    rewrite(cmds, fold_at)
+}
+
+/// Runs `rule` over every position of `cmds` once, replacing each window it matches. Returns the number of rewrites.
+fn rewrite(
+    cmds: &mut Vec<VmCommand>,
+    rule: impl Fn(&[VmCommand]) -> Option<(Vec<VmCommand>, usize)>,
+) -> usize {
+    let mut result = Vec::with_capacity(cmds.len());
+    let mut count = 0;
+    let mut i = 0;
+    while i < cmds.len() {
+        match rule(&cmds[i..]) {
+            Some((replacement, len)) => {
+                result.extend(replacement);
+                i += len;
+                count += 1;
+            }
+            None => {
+                result.push(cmds[i].clone());
+                i += 1;
+            }
+        }
+    }
+    *cmds = result;
+    count
+}
+
+// ------------------------------------------------------------------------------------------ //
+//                                       Push/pop pairs                                       //
+// ------------------------------------------------------------------------------------------ //
+
+fn remove_push_pop(cmds: &mut Vec<VmCommand>) -> usize {
+    rewrite(cmds, |window| match window {
+        [VmCommand::Push(a, i), VmCommand::Pop(b, j), ..] if a == b && i == j => {
+            Some((Vec::new(), 2))
+        }
+        _ => None,
+    })
+}
+
+// ------------------------------------------------------------------------------------------ //
+//                                         Dead stores                                        //
+// ------------------------------------------------------------------------------------------ //
+
+/// Commands without side effects besides the stack
+fn is_pure(cmd: &VmCommand) -> bool {
+    use VmCommand::*;
+    matches!(
+        cmd,
+        Push(..) | Add | Sub | Neg | Eq | Gt | Lt | And | Or | Not
+    )
+}
+
+/// Returns true if the value stored by `cmds[0]` (a pop) is overwritten before it can be read. Stores to this, that and
+/// pointer are never considered dead since they can alias other segments.
+fn is_dead_store(cmds: &[VmCommand]) -> bool {
+    let VmCommand::Pop(seg, index) = &cmds[0] else {
+        return false;
+    };
+    // locals and arguments belong to the current frame, nothing else can read them
+    let frame_local = match seg {
+        Segment::Local | Segment::Argument => true,
+        Segment::Static | Segment::Temp => false,
+        _ => return false,
+    };
+
+    for cmd in &cmds[1..] {
+        match cmd {
+            VmCommand::Push(s, i) if s == seg && i == index => return false,
+            VmCommand::Pop(s, i) if s == seg && i == index => return true,
+            VmCommand::Return => return frame_local,
+            VmCommand::Call { .. } if !frame_local => return false,
+            VmCommand::Label(_) | VmCommand::Goto(_) | VmCommand::IfGoto(_) => return false,
+            _ => (),
+        }
+    }
+    false
+}
+
+/// Start of the pure computation that produces the value on top of the stack before `cmds[end]`
+fn producer_start(cmds: &[VmCommand], end: usize) -> Option<usize> {
+    let mut needed = 1;
+    for i in (0..end).rev() {
+        if !is_pure(&cmds[i]) {
+            return None;
+        }
+        let (pops, pushes) = cmds[i].stack_effect();
+        needed = needed - pushes as i32 + pops as i32;
+        if needed == 0 {
+            return Some(i);
+        }
+    }
+    None
+}
+
+fn remove_dead_stores(cmds: &mut Vec<VmCommand>) -> usize {
+    let mut count = 0;
+    let mut i = 0;
+    while i < cmds.len() {
+        if is_dead_store(&cmds[i..]) {
+            if let Some(start) = producer_start(cmds, i) {
+                cmds.drain(start..=i);
+                count += 1;
+                i = start;
+                continue;
+            }
+        }
+        i += 1;
+    }
+    count
+}
+
+// ------------------------------------------------------------------------------------------ //
+//                                          Branches                                          //
+// ------------------------------------------------------------------------------------------ //
+
+fn simplify_branch_at(cmds: &[VmCommand]) -> Option<(Vec<VmCommand>, usize)> {
+    use VmCommand::*;
+    match cmds {
+        // if (!c) goto L1 else goto L2; L1: -> if (c) goto L2; L1:
+        // `not` is bitwise, so this only holds when c is a comparison's -1 or 0: !5 is nonzero too
+        [cmp @ (Eq | Gt | Lt), Not, IfGoto(l1), Goto(l2), Label(l3), ..] if l1 == l3 => {
+            Some((vec![cmp.clone(), IfGoto(l2.clone()), Label(l1.clone())], 5))
+        }
+        // x != y is the same as x - y != 0
+        [Eq, Not, IfGoto(l), ..] => Some((vec![Sub, IfGoto(l.clone())], 3)),
+        [Goto(l1), Label(l2), ..] if l1 == l2 => Some((vec![Label(l2.clone())], 2)),
+        _ => {
+            let (c, len) = constant_at(cmds)?;
+            match cmds.get(len)? {
+                IfGoto(l) if c != 0 => Some((vec![Goto(l.clone())], len + 1)),
+                IfGoto(_) => Some((Vec::new(), len + 1)),
+                _ => None,
+            }
+        }
+    }
+}
+
+fn simplify_branches(cmds: &mut Vec<VmCommand>) -> usize {
+    let mut count = rewrite(cmds, simplify_branch_at);
+
+    // code after an unconditional jump can only be reached through a label
+    let mut reachable = true;
+    let before = cmds.len();
+    cmds.retain(|cmd| {
+        match cmd {
+            VmCommand::Label(_) | VmCommand::Function { .. } => reachable = true,
+            _ if !reachable => return false,
+            VmCommand::Goto(_) | VmCommand::Return => reachable = false,
+            _ => (),
+        }
+        true
+    });
+    count += before - cmds.len();
+
+    let used: HashSet<String> = cmds
+        .iter()
+        .filter_map(|cmd| match cmd {
+            VmCommand::Goto(l) | VmCommand::IfGoto(l) => Some(l.clone()),
+            _ => None,
+        })
+        .collect();
+    let before = cmds.len();
+    cmds.retain(|cmd| !matches!(cmd, VmCommand::Label(l) if !used.contains(l)));
+    count += before - cmds.len();
+
+    count
+}
@@ -1,4 +1,4 @@
-use crate::software::{compiler::JackCompiler, compiler_utils::*};
+use crate::software::{compiler::JackCompiler, compiler_utils::*, jack_ast::BinaryOp};
 use concat_string::concat_string;
 use std::io::Write;
 use strum_macros::{EnumString, IntoStaticStr};
@@ -112,21 +112,43 @@ impl JackCompiler {
         writeln!(self.output, "return").unwrap();
     }
 
-    pub fn write_operators(&mut self, ops: &[Token]) {
-        use Symbol::*;
-        for op in ops.iter().rev() {
-            match *op {
-                Token::Symbol(Asterisk) => self.write_function_call("Math", "multiply", 2),
-                Token::Symbol(FwdSlash) => self.write_function_call("Math", "divide", 2),
-                Token::Symbol(Plus) => writeln!(self.output, "add").unwrap(),
-                Token::Symbol(Minus) => writeln!(self.output, "sub").unwrap(),
-                Token::Symbol(GreaterThan) => writeln!(self.output, "gt").unwrap(),
-                Token::Symbol(LessThan) => writeln!(self.output, "lt").unwrap(),
-                Token::Symbol(Equals) => writeln!(self.output, "eq").unwrap(),
-                Token::Symbol(And) => writeln!(self.output, "and").unwrap(),
-                Token::Symbol(Pipe) => writeln!(self.output, "or").unwrap(),
-                _ => panic!("invalid operator: {op:?}"),
-            }
+    /// Doubles the value on top of the stack, temp 1 holds a copy since the VM has no `dup`
+    pub fn write_double(&mut self) {
+        self.pop_seg(Segment::Temp, 1);
+        self.push_seg(Segment::Temp, 1);
+        self.push_seg(Segment::Temp, 1);
+        self.write_operator(BinaryOp::Add);
+    }
+
+    /// Divides the non-negative value on top of the stack by 2^k. Every bit from k up is tested and its value k places
+    /// lower added to the result, temp 1 holds the dividend.
+    pub fn write_shift_right(&mut self, k: u32) {
+        self.pop_seg(Segment::Temp, 1);
+        self.push_seg(Segment::Constant, 0);
+        // bit 15 is the sign, so it's always clear
+        for bit in k..15 {
+            self.push_seg(Segment::Temp, 1);
+            self.push_seg(Segment::Constant, 1 << bit);
+            self.write_operator(BinaryOp::And);
+            self.push_seg(Segment::Constant, 0);
+            self.write_operator(BinaryOp::Gt);
+            self.push_seg(Segment::Constant, 1 << (bit - k));
+            self.write_operator(BinaryOp::And);
+            self.write_operator(BinaryOp::Add);
+        }
+    }
+
+    pub fn write_operator(&mut self, op: BinaryOp) {
+        match op {
+            BinaryOp::Mul => self.write_function_call("Math", "multiply", 2),
+            BinaryOp::Div => self.write_function_call("Math", "divide", 2),
+            BinaryOp::Add => writeln!(self.output, "add").unwrap(),
+            BinaryOp::Sub => writeln!(self.output, "sub").unwrap(),
+            BinaryOp::Gt => writeln!(self.output, "gt").unwrap(),
+            BinaryOp::Lt => writeln!(self.output, "lt").unwrap(),
+            BinaryOp::Eq => writeln!(self.output, "eq").unwrap(),
+            BinaryOp::And => writeln!(self.output, "and").unwrap(),
+            BinaryOp::Or => writeln!(self.output, "or").unwrap(),
         }
         self.output.flush().unwrap();
     }
@@ -0,0 +1,559 @@
+//! .tst -> commands. Reads the nand2tetris test script language shared by the hardware simulator and the CPU and VM
+//! emulators:
+//!
+//! ```text
+//! load Xor.hdl,
+//! output-file Xor.out,
+//! compare-to Xor.cmp,
+//! output-list a%B3.1.3 b%B3.1.3 out%B3.1.3;
+//!
+//! set a 0, set b 1, eval, output;
+//! repeat 3 { tick, output; tock, output; }
+//! while out <> 0 { ticktock; }
+//! ```
+//!
+//! Commands end with `,` (part of the same simulation step), `;` (end of the step) or `!` (end of the script). The
+//! runner doesn't single step, so all three are treated the same. Parsing stops at the first error.
+
+use crate::software::diagnostics::{Diagnostic, Span};
+use std::fmt;
+
+/// How a value is written in the output file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Radix {
+    Binary,
+    Decimal,
+    Hex,
+    /// the value is already a string, like `time`
+    String,
+}
+
+/// `%D1.6.1`: radix, spaces before the value, width of the value and spaces after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Format {
+    pub radix: Radix,
+    pub left: usize,
+    pub len: usize,
+    pub right: usize,
+}
+
+impl Format {
+    pub fn new(radix: Radix, left: usize, len: usize, right: usize) -> Self {
+        Format {
+            radix,
+            left,
+            len,
+            right,
+        }
+    }
+
+    /// Width of the column in the output file, not counting the `|` separators
+    pub fn width(&self) -> usize {
+        self.left + self.len + self.right
+    }
+
+    /// Column heading, `name` centered in the column and cut off if it doesn't fit
+    pub fn header(&self, name: &str) -> String {
+        let name: String = name.chars().take(self.width()).collect();
+        let space = self.width() - name.chars().count();
+        let left = space / 2;
+        format!("{}{name}{}", " ".repeat(left), " ".repeat(space - left))
+    }
+
+    /// One cell of the output file. Binary and hex values are zero padded to `len` digits and cut to their lowest
+    /// `len` digits, decimal values are signed and right aligned, strings are left aligned.
+    pub fn cell(&self, value: &Value) -> String {
+        let len = self.len;
+        let text = match (self.radix, value) {
+            (Radix::String, value) => format!("{:<len$}", value.to_string()),
+            (_, Value::Text(text)) => format!("{text:>len$}"),
+            (Radix::Binary, Value::Word(x)) => {
+                let digits = format!("{x:016b}");
+                format!("{:0>len$}", &digits[16 - len.min(16)..])
+            }
+            (Radix::Hex, Value::Word(x)) => {
+                let digits = format!("{x:04X}");
+                format!("{:0>len$}", &digits[4 - len.min(4)..])
+            }
+            (Radix::Decimal, Value::Word(x)) => format!("{:>len$}", *x as i16),
+        };
+
+        format!("{}{text}{}", " ".repeat(self.left), " ".repeat(self.right))
+    }
+}
+
+/// A value read from the machine under test
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Word(u16),
+    Text(String),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Word(x) => write!(f, "{}", *x as i16),
+            Value::Text(x) => write!(f, "{x}"),
+        }
+    }
+}
+
+/// `name` or `name[index]`, like `a`, `RAM[256]` or `argument[0]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    pub name: String,
+    pub index: Option<usize>,
+    pub span: Span,
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.index {
+            Some(i) => write!(f, "{}[{i}]", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// One column of `output-list`, `format` is `None` when the script leaves it to the runner
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    pub variable: Variable,
+    pub format: Option<Format>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+impl Comparison {
+    /// Compares the values as signed 16 bit numbers like the official simulators do
+    pub fn test(&self, left: u16, right: u16) -> bool {
+        let (left, right) = (left as i16, right as i16);
+        match self {
+            Comparison::Eq => left == right,
+            Comparison::Ne => left != right,
+            Comparison::Lt => left < right,
+            Comparison::Gt => left > right,
+            Comparison::Le => left <= right,
+            Comparison::Ge => left >= right,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    pub variable: Variable,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandKind {
+    /// `load` with no file loads the script's directory
+    Load(Option<String>),
+    OutputFile(String),
+    CompareTo(String),
+    OutputList(Vec<Column>),
+    Set(Variable, u16),
+    Eval,
+    Tick,
+    Tock,
+    TickTock,
+    /// runs one VM command, the VM emulator's clock
+    VmStep,
+    Output,
+    Echo(String),
+    ClearEcho,
+    Repeat(usize, Vec<Command>),
+    While(Condition, Vec<Command>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Command {
+    pub kind: CommandKind,
+    pub span: Span,
+}
+
+/// A parsed .tst file. The source is kept so the runner can point errors at the command that caused them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Script {
+    pub commands: Vec<Command>,
+    pub file: String,
+    pub source: String,
+}
+
+impl Script {
+    pub fn error(&self, span: Span, message: String) -> Diagnostic {
+        Diagnostic::new(&self.file, &self.source, span, message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// anything up to whitespace or a symbol: commands, variables, file names and values
+    Word(String),
+    /// `"text"` for `echo`
+    Str(String),
+    Symbol(char),
+    None,
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word(x) => format!("'{x}'"),
+        Token::Str(x) => format!("\"{x}\""),
+        Token::Symbol(c) => format!("'{c}'"),
+        Token::None => "end of file".to_string(),
+    }
+}
+
+fn lex(file: &str, source: &str) -> Result<Vec<(Token, Span)>, Diagnostic> {
+    const SYMBOLS: &[u8] = b",;!{}";
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let start = i;
+        let token = match bytes[i] {
+            b if b.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i = source[i..].find('\n').map(|n| i + n).unwrap_or(bytes.len());
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = match source[i + 2..].find("*/") {
+                    Some(n) => i + 2 + n + 2,
+                    None => {
+                        let message = "unterminated block comment".to_string();
+                        return Err(Diagnostic::new(file, source, Span::new(i, i + 2), message));
+                    }
+                };
+                continue;
+            }
+            b'"' => match source[i + 1..].find(['"', '\n']) {
+                Some(n) if bytes[i + 1 + n] == b'"' => {
+                    i += n + 2;
+                    Token::Str(source[start + 1..i - 1].to_string())
+                }
+                _ => {
+                    let message = "unterminated string".to_string();
+                    return Err(Diagnostic::new(file, source, Span::new(i, i + 1), message));
+                }
+            },
+            b if SYMBOLS.contains(&b) => {
+                i += 1;
+                Token::Symbol(b as char)
+            }
+            _ => {
+                while i < bytes.len()
+                    && !bytes[i].is_ascii_whitespace()
+                    && !SYMBOLS.contains(&bytes[i])
+                    && bytes[i] != b'"'
+                    && !bytes[i..].starts_with(b"//")
+                    && !bytes[i..].starts_with(b"/*")
+                {
+                    i += 1;
+                }
+                Token::Word(source[start..i].to_string())
+            }
+        };
+        tokens.push((token, Span::new(start, i)));
+    }
+
+    Ok(tokens)
+}
+
+/// Parses the source of a .tst file. `file` is used to label errors.
+pub fn parse_script(file: &str, source: &str) -> Result<Script, Diagnostic> {
+    let tokens = lex(file, source)?;
+    let mut parser = ScriptParser {
+        file,
+        source,
+        tokens,
+        position: 0,
+    };
+
+    let mut commands = Vec::new();
+    while parser.peek() != &Token::None {
+        commands.push(parser.parse_command()?);
+    }
+
+    Ok(Script {
+        commands,
+        file: file.to_string(),
+        source: source.to_string(),
+    })
+}
+
+struct ScriptParser<'a> {
+    file: &'a str,
+    source: &'a str,
+    tokens: Vec<(Token, Span)>,
+    position: usize,
+}
+
+impl ScriptParser<'_> {
+    fn peek(&self) -> &Token {
+        self.tokens
+            .get(self.position)
+            .map(|(t, _)| t)
+            .unwrap_or(&Token::None)
+    }
+
+    fn peek_span(&self) -> Span {
+        match self.tokens.get(self.position) {
+            Some((_, span)) => *span,
+            None => Span::new(self.source.len(), self.source.len()),
+        }
+    }
+
+    fn prev_span(&self) -> Span {
+        self.tokens[self.position - 1].1
+    }
+
+    fn error<T>(&self, span: Span, message: String) -> Result<T, Diagnostic> {
+        Err(Diagnostic::new(self.file, self.source, span, message))
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == token {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char, context: &str) -> Result<Span, Diagnostic> {
+        if self.eat(&Token::Symbol(symbol)) {
+            return Ok(self.prev_span());
+        }
+        let got = describe(self.peek());
+        self.error(
+            self.peek_span(),
+            format!("expected '{symbol}' {context}, got {got}"),
+        )
+    }
+
+    fn expect_word(&mut self, what: &str) -> Result<(String, Span), Diagnostic> {
+        if let Token::Word(word) = self.peek() {
+            let word = word.clone();
+            self.position += 1;
+            return Ok((word, self.prev_span()));
+        }
+        let got = describe(self.peek());
+        self.error(self.peek_span(), format!("expected {what}, got {got}"))
+    }
+
+    fn is_separator(&self) -> bool {
+        matches!(self.peek(), Token::Symbol(',' | ';' | '!'))
+    }
+
+    // ------------------------------- command (',' | ';' | '!') ------------------------------- //
+    fn parse_command(&mut self) -> Result<Command, Diagnostic> {
+        let (name, start) = self.expect_word("a command")?;
+
+        let kind = match name.as_str() {
+            "repeat" => {
+                let (count, span) = self.expect_word("repeat count")?;
+                let Ok(count) = count.parse() else {
+                    return self.error(span, format!("invalid repeat count '{count}'"));
+                };
+                let body = self.parse_block("after repeat count")?;
+                return Ok(Command {
+                    kind: CommandKind::Repeat(count, body),
+                    span: start.to(self.prev_span()),
+                });
+            }
+            "while" => {
+                let condition = self.parse_condition()?;
+                let body = self.parse_block("after while condition")?;
+                return Ok(Command {
+                    kind: CommandKind::While(condition, body),
+                    span: start.to(self.prev_span()),
+                });
+            }
+            "load" if self.is_separator() => CommandKind::Load(None),
+            "load" => CommandKind::Load(Some(self.expect_word("file name")?.0)),
+            "output-file" => CommandKind::OutputFile(self.expect_word("file name")?.0),
+            "compare-to" => CommandKind::CompareTo(self.expect_word("file name")?.0),
+            "output-list" => {
+                let mut columns = Vec::new();
+                while let Token::Word(_) = self.peek() {
+                    columns.push(self.parse_column()?);
+                }
+                CommandKind::OutputList(columns)
+            }
+            "set" => {
+                let (variable, span) = self.expect_word("variable name")?;
+                let variable = self.parse_variable(&variable, span)?;
+                let value = self.parse_value()?;
+                CommandKind::Set(variable, value)
+            }
+            "eval" => CommandKind::Eval,
+            "tick" => CommandKind::Tick,
+            "tock" => CommandKind::Tock,
+            "ticktock" => CommandKind::TickTock,
+            "vmstep" => CommandKind::VmStep,
+            "output" => CommandKind::Output,
+            "echo" => match self.peek().clone() {
+                Token::Str(text) => {
+                    self.position += 1;
+                    CommandKind::Echo(text)
+                }
+                token => {
+                    let message =
+                        format!("expected a string after 'echo', got {}", describe(&token));
+                    return self.error(self.peek_span(), message);
+                }
+            },
+            "clear-echo" => CommandKind::ClearEcho,
+            _ => return self.error(start, format!("unknown command '{name}'")),
+        };
+        let span = start.to(self.prev_span());
+
+        if !self.is_separator() {
+            let got = describe(self.peek());
+            return self.error(
+                self.peek_span(),
+                format!("expected ',' or ';' after '{name}', got {got}"),
+            );
+        }
+        self.position += 1;
+
+        Ok(Command { kind, span })
+    }
+
+    // ------------------------------------ '{' command* '}' ------------------------------------ //
+    fn parse_block(&mut self, context: &str) -> Result<Vec<Command>, Diagnostic> {
+        self.expect('{', context)?;
+        let mut body = Vec::new();
+        while !self.eat(&Token::Symbol('}')) {
+            if self.peek() == &Token::None {
+                let message = "expected '}' at the end of the block, got end of file".to_string();
+                return self.error(self.peek_span(), message);
+            }
+            body.push(self.parse_command()?);
+        }
+        // a separator after the block is optional
+        if self.is_separator() {
+            self.position += 1;
+        }
+        Ok(body)
+    }
+
+    // ------------------------------ name ('[' index ']')? ('%' format)? ------------------------------ //
+    fn parse_column(&mut self) -> Result<Column, Diagnostic> {
+        let (word, span) = self.expect_word("output column")?;
+        let Some((name, format)) = word.split_once('%') else {
+            let variable = self.parse_variable(&word, span)?;
+            return Ok(Column {
+                variable,
+                format: None,
+            });
+        };
+
+        let name_span = Span::new(span.start, span.start + name.len());
+        let variable = self.parse_variable(name, name_span)?;
+        let format_span = Span::new(name_span.end, span.end);
+        let invalid = || {
+            let message = format!("invalid output format '%{format}', expected e.g. '%D1.6.1'");
+            Diagnostic::new(self.file, self.source, format_span, message)
+        };
+
+        let mut chars = format.chars();
+        let radix = match chars.next() {
+            Some('B') => Radix::Binary,
+            Some('D') => Radix::Decimal,
+            Some('X') => Radix::Hex,
+            Some('S') => Radix::String,
+            _ => return Err(invalid()),
+        };
+        let numbers: Vec<usize> = chars
+            .as_str()
+            .split('.')
+            .map(|x| x.parse().map_err(|_| invalid()))
+            .collect::<Result<_, _>>()?;
+        let [left, len, right] = numbers[..] else {
+            return Err(invalid());
+        };
+
+        Ok(Column {
+            variable,
+            format: Some(Format::new(radix, left, len, right)),
+        })
+    }
+
+    fn parse_variable(&self, word: &str, span: Span) -> Result<Variable, Diagnostic> {
+        let Some(open) = word.find('[') else {
+            return Ok(Variable {
+                name: word.to_string(),
+                index: None,
+                span,
+            });
+        };
+
+        let index = word[open + 1..]
+            .strip_suffix(']')
+            .and_then(|x| x.parse().ok());
+        match index {
+            Some(index) if open > 0 => Ok(Variable {
+                name: word[..open].to_string(),
+                index: Some(index),
+                span,
+            }),
+            _ => self.error(span, format!("invalid variable '{word}'")),
+        }
+    }
+
+    /// `123`, `-1`, `%B0101`, `%XFF` or `%D-3`, as a 16 bit pattern
+    fn parse_value(&mut self) -> Result<u16, Diagnostic> {
+        let (word, span) = self.expect_word("a value")?;
+        let value = match word.strip_prefix('%') {
+            Some(x) if x.starts_with('B') => i32::from_str_radix(&x[1..], 2).ok(),
+            Some(x) if x.starts_with('X') => i32::from_str_radix(&x[1..], 16).ok(),
+            Some(x) if x.starts_with('D') => x[1..].parse().ok(),
+            Some(_) => None,
+            None => word.parse().ok(),
+        };
+
+        match value {
+            Some(x) if (i16::MIN as i32..=u16::MAX as i32).contains(&x) => Ok(x as u16),
+            Some(_) => self.error(span, format!("value {word} doesn't fit in 16 bits")),
+            None => self.error(span, format!("invalid value '{word}'")),
+        }
+    }
+
+    fn parse_condition(&mut self) -> Result<Condition, Diagnostic> {
+        let (variable, span) = self.expect_word("variable name")?;
+        let variable = self.parse_variable(&variable, span)?;
+        let (comparison, span) = self.expect_word("a comparison")?;
+        let comparison = match comparison.as_str() {
+            "=" => Comparison::Eq,
+            "<>" => Comparison::Ne,
+            "<" => Comparison::Lt,
+            ">" => Comparison::Gt,
+            "<=" => Comparison::Le,
+            ">=" => Comparison::Ge,
+            _ => return self.error(span, format!("expected a comparison, got '{comparison}'")),
+        };
+        let value = self.parse_value()?;
+
+        Ok(Condition {
+            variable,
+            comparison,
+            value,
+        })
+    }
+}
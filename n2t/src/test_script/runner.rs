@@ -0,0 +1,447 @@
+//! Runs .tst scripts against a chip or a Hack program and checks the output against the .cmp file.
+//!
+//! `load X.hdl` elaborates the chip with `hdl::simulator` (parts without an .hdl file fall back to the builtins, which
+//! run on the `logic_gate` functions), `load X.hack` and `load X.asm` run the program on the native `Computer`. VM
+//! emulator scripts load `X.vm` or the whole directory (`load` without a file), which is translated without bootstrap
+//! and also runs on the `Computer`. Every `output` appends a line to `output` and to the `output-file`, and the line is
+//! compared with the same line of the `compare-to` file right away, so the run stops at the first difference like it
+//! does in the official simulators.
+//!
+//! The variables the script can use are the chip's pins (`name` or `name[bit]`) and `time`, or for the computer
+//! `RAM[i]`, `ROM[i]`, `A`, `D`, `PC` and `time`. The computer executes one instruction per clock cycle, on `tock`.
+//! VM programs also have `sp`, `local`, `argument`, `this`, `that`, the segments `local[i]`, `argument[i]`, `this[i]`,
+//! `that[i]` and `temp[i]`, and `vmstep` runs the instructions of one VM command.
+
+use crate::hardware::{
+    hdl::simulator::Simulator, logic_gate::memory::ROM32K, native::cpu::Computer,
+};
+use crate::software::{
+    assembler::assemble,
+    diagnostics::{Diagnostic, Span},
+    vm::{translate_listing, VmListing},
+    vm_ir::VmModule,
+};
+use crate::test_script::parser::{
+    parse_script, Column, Command, CommandKind, Format, Radix, Script, Value, Variable,
+};
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// What the script is testing
+#[derive(Debug)]
+pub enum Target {
+    Chip(Simulator),
+    Computer(Computer),
+    /// a translated VM program, stepped one VM command at a time
+    Vm(Computer, VmListing),
+}
+
+#[derive(Debug)]
+pub struct ScriptRunner {
+    /// directory of the script, file names in the script are relative to it
+    pub dir: PathBuf,
+    /// where `output-file` writes, `dir` unless it's changed
+    pub output_dir: PathBuf,
+    pub target: Option<Target>,
+    /// full clock cycles since the last `load`
+    pub time: usize,
+    /// between a `tick` and its `tock`, printed as the `+` in `time`
+    pub ticked: bool,
+    pub echo: Option<String>,
+    /// every line written so far, including the headers written by `output-list`
+    pub output: Vec<String>,
+
+    columns: Vec<Column>,
+    output_file: Option<BufWriter<File>>,
+    /// name and lines of the `compare-to` file
+    compare: Option<(String, Vec<String>)>,
+}
+
+/// Runs the .tst file at `path`, writing its `output-file` to `output_dir`. Returns the runner so the final state can
+/// be inspected.
+pub fn run_script(path: &Path, output_dir: &Path) -> Result<ScriptRunner, Diagnostic> {
+    let file = path.file_name().unwrap_or_default().to_string_lossy();
+    let source = fs::read_to_string(path).map_err(|e| Diagnostic::unreadable(path, e))?;
+    let script = parse_script(&file, &source)?;
+
+    let mut runner = ScriptRunner::new(path.parent().unwrap());
+    runner.output_dir = output_dir.to_path_buf();
+    runner.run(&script)?;
+    Ok(runner)
+}
+
+impl ScriptRunner {
+    pub fn new(dir: &Path) -> Self {
+        ScriptRunner {
+            dir: dir.to_path_buf(),
+            output_dir: dir.to_path_buf(),
+            target: None,
+            time: 0,
+            ticked: false,
+            echo: None,
+            output: Vec::new(),
+            columns: Vec::new(),
+            output_file: None,
+            compare: None,
+        }
+    }
+
+    pub fn run(&mut self, script: &Script) -> Result<(), Diagnostic> {
+        let result = self.execute(script, &script.commands);
+        let flushed = match &mut self.output_file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        };
+        result?;
+        flushed
+            .map_err(|e| script.error(Span::default(), format!("can't write the output file: {e}")))
+    }
+
+    fn execute(&mut self, script: &Script, commands: &[Command]) -> Result<(), Diagnostic> {
+        for command in commands {
+            match &command.kind {
+                CommandKind::Load(file) => self.load(script, file.as_deref(), command.span)?,
+                CommandKind::OutputFile(file) => {
+                    let file = File::create(self.output_dir.join(file)).map_err(|e| {
+                        script.error(command.span, format!("can't create {file}: {e}"))
+                    })?;
+                    self.output_file = Some(BufWriter::new(file));
+                }
+                CommandKind::CompareTo(file) => {
+                    let Ok(text) = fs::read_to_string(self.dir.join(file)) else {
+                        return Err(script.error(command.span, format!("can't read {file}")));
+                    };
+                    let lines = text.lines().map(|l| l.trim_end().to_string()).collect();
+                    self.compare = Some((file.clone(), lines));
+                }
+                CommandKind::OutputList(columns) => {
+                    self.columns = columns.clone();
+                    let mut cells = Vec::new();
+                    for column in columns {
+                        // unknown variables are reported here rather than at the first `output`
+                        self.get(script, &column.variable)?;
+                        let format = self.format(script, column)?;
+                        cells.push(format.header(&column.variable.to_string()));
+                    }
+                    self.write_line(script, command.span, &cells)?;
+                }
+                CommandKind::Set(variable, value) => self.set(script, variable, *value)?,
+                CommandKind::Eval => {
+                    if let Target::Chip(chip) = self.target(script, command.span)? {
+                        chip.eval();
+                    }
+                }
+                CommandKind::Tick => self.tick(script, command.span)?,
+                CommandKind::Tock => self.tock(script, command.span)?,
+                CommandKind::TickTock => {
+                    self.tick(script, command.span)?;
+                    self.tock(script, command.span)?;
+                }
+                CommandKind::VmStep => self.vm_step(script, command.span)?,
+                CommandKind::Output => {
+                    let mut cells = Vec::new();
+                    for column in self.columns.clone() {
+                        let format = self.format(script, &column)?;
+                        cells.push(format.cell(&self.get(script, &column.variable)?));
+                    }
+                    self.write_line(script, command.span, &cells)?;
+                }
+                CommandKind::Echo(text) => self.echo = Some(text.clone()),
+                CommandKind::ClearEcho => self.echo = None,
+                CommandKind::Repeat(count, body) => {
+                    for _ in 0..*count {
+                        self.execute(script, body)?;
+                    }
+                }
+                CommandKind::While(condition, body) => loop {
+                    let value = match self.get(script, &condition.variable)? {
+                        Value::Word(x) => x,
+                        Value::Text(_) => {
+                            let message = format!("'{}' isn't a number", condition.variable);
+                            return Err(script.error(condition.variable.span, message));
+                        }
+                    };
+                    if !condition.comparison.test(value, condition.value) {
+                        break;
+                    }
+                    self.execute(script, body)?;
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    fn load(&mut self, script: &Script, file: Option<&str>, span: Span) -> Result<(), Diagnostic> {
+        self.target = Some(match file {
+            // like the VM emulator, every .vm file in the directory
+            None => load_vm(script, &self.dir, span)?,
+            Some(file) => {
+                let path = self.dir.join(file);
+                match path.extension().and_then(|e| e.to_str()) {
+                    // a chip without an .hdl file is only builtin when it's a part
+                    Some("hdl" | "hack" | "asm" | "vm") if !path.is_file() => {
+                        return Err(script.error(span, format!("can't read {file}")));
+                    }
+                    Some("hdl") => Target::Chip(Simulator::load(&path)?),
+                    Some("hack") => Target::Computer(Computer::new(ROM32K::load_program(&path)?)),
+                    Some("asm") => {
+                        let asm = fs::read_to_string(&path)
+                            .map_err(|e| script.error(span, format!("can't read {file}: {e}")))?;
+                        Target::Computer(Computer::new(machine_code(&asm)))
+                    }
+                    Some("vm") => load_vm(script, &path, span)?,
+                    _ => {
+                        let message = format!(
+                            "can't load '{file}', expected a .hdl, .hack, .asm or .vm file"
+                        );
+                        return Err(script.error(span, message));
+                    }
+                }
+            }
+        });
+        self.time = 0;
+        self.ticked = false;
+
+        Ok(())
+    }
+
+    fn target(&mut self, script: &Script, span: Span) -> Result<&mut Target, Diagnostic> {
+        match &mut self.target {
+            Some(target) => Ok(target),
+            None => Err(script.error(span, "nothing has been loaded yet".to_string())),
+        }
+    }
+
+    fn tick(&mut self, script: &Script, span: Span) -> Result<(), Diagnostic> {
+        if let Target::Chip(chip) = self.target(script, span)? {
+            chip.tick();
+        }
+        self.ticked = true;
+        Ok(())
+    }
+
+    fn tock(&mut self, script: &Script, span: Span) -> Result<(), Diagnostic> {
+        match self.target(script, span)? {
+            Target::Chip(chip) => chip.tock(),
+            Target::Computer(computer) | Target::Vm(computer, _) => computer.step(false, false),
+        }
+        self.time += 1;
+        self.ticked = false;
+        Ok(())
+    }
+
+    /// Runs the instructions of the VM command at the PC, until the PC reaches the start of a command or jumps into
+    /// another one (like a `return` to a made up return address). Once the program has fallen into the infinite loop
+    /// after its last command it's finished and `vmstep` does nothing.
+    fn vm_step(&mut self, script: &Script, span: Span) -> Result<(), Diagnostic> {
+        let Target::Vm(computer, listing) = self.target(script, span)? else {
+            return Err(script.error(span, "'vmstep' needs a .vm program".to_string()));
+        };
+
+        let finished = |pc: u16| pc == listing.end || pc == listing.end + 1;
+        let command = |pc: u16| listing.commands.partition_point(|&start| start <= pc);
+        let current = command(computer.pc);
+        while !finished(computer.pc) {
+            computer.step(false, false);
+            let pc = computer.pc;
+            // the comparison subroutines after the program belong to whichever command called them
+            let in_program = pc < listing.end;
+            if in_program && (listing.commands.binary_search(&pc).is_ok() || command(pc) != current)
+            {
+                break;
+            }
+        }
+        self.time += 1;
+        Ok(())
+    }
+
+    /// The column's format, or the default for its variable: binary as wide as the pin for chips, 6 digit decimal
+    /// for the computer
+    fn format(&mut self, script: &Script, column: &Column) -> Result<Format, Diagnostic> {
+        if let Some(format) = column.format {
+            return Ok(format);
+        }
+
+        let variable = &column.variable;
+        if variable.name == "time" {
+            return Ok(Format::new(Radix::String, 1, 4, 1));
+        }
+        Ok(match self.target(script, variable.span)? {
+            Target::Chip(_) if variable.index.is_some() => Format::new(Radix::Binary, 1, 1, 1),
+            Target::Chip(chip) if chip.has_pin(&variable.name) => {
+                Format::new(Radix::Binary, 1, chip.width(&variable.name), 1)
+            }
+            Target::Chip(_) | Target::Computer(_) | Target::Vm(..) => {
+                Format::new(Radix::Decimal, 1, 6, 1)
+            }
+        })
+    }
+
+    pub fn get(&mut self, script: &Script, variable: &Variable) -> Result<Value, Diagnostic> {
+        if variable.name == "time" && variable.index.is_none() {
+            let plus = if self.ticked { "+" } else { "" };
+            return Ok(Value::Text(format!("{}{plus}", self.time)));
+        }
+
+        let unknown = |what: &str| {
+            let message = format!("{what} has no variable '{variable}'");
+            Err(script.error(variable.span, message))
+        };
+        let value = match (self.target(script, variable.span)?, variable.index) {
+            (Target::Chip(chip), index) if chip.has_pin(&variable.name) => {
+                let value = chip.get(&variable.name);
+                match index {
+                    Some(bit) if bit >= chip.width(&variable.name) => return unknown(&chip.chip),
+                    Some(bit) => value >> bit & 1,
+                    None => value,
+                }
+            }
+            (Target::Chip(chip), _) => return unknown(&chip.chip),
+            (Target::Computer(computer), index) => {
+                match cpu_variable(computer, &variable.name, index) {
+                    Some(value) => *value,
+                    None => return unknown("the computer"),
+                }
+            }
+            (Target::Vm(computer, _), index) => {
+                match vm_variable(computer, &variable.name, index) {
+                    Some(value) => *value,
+                    None => return unknown("the VM program"),
+                }
+            }
+        };
+
+        Ok(Value::Word(value))
+    }
+
+    fn set(&mut self, script: &Script, variable: &Variable, value: u16) -> Result<(), Diagnostic> {
+        let error = |message: String| Err(script.error(variable.span, message));
+
+        match (self.target(script, variable.span)?, variable.index) {
+            (Target::Chip(chip), index) if chip.inputs.contains(&variable.name) => {
+                let width = chip.width(&variable.name);
+                let value = match index {
+                    Some(bit) if bit < width && value <= 1 => {
+                        chip.get(&variable.name) & !(1 << bit) | value << bit
+                    }
+                    Some(bit) if bit < width => return error(format!("{value} isn't a bit")),
+                    Some(_) => return error(format!("{} has no pin '{variable}'", chip.chip)),
+                    None if width < 16 && value >> width != 0 => {
+                        let message =
+                            format!("{value} doesn't fit in the {width} bits of '{variable}'");
+                        return error(message);
+                    }
+                    None => value,
+                };
+                chip.set(&variable.name, value);
+            }
+            (Target::Chip(chip), _) => {
+                return error(format!("'{variable}' isn't an input pin of {}", chip.chip));
+            }
+            (Target::Computer(computer), index) => {
+                match cpu_variable(computer, &variable.name, index) {
+                    Some(cell) => *cell = value,
+                    None => return error(format!("the computer has no variable '{variable}'")),
+                }
+            }
+            (Target::Vm(computer, _), index) => {
+                match vm_variable(computer, &variable.name, index) {
+                    Some(cell) => *cell = value,
+                    None => return error(format!("the VM program has no variable '{variable}'")),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes `|cell|cell|...|` to the output and checks it against the compare file
+    fn write_line(
+        &mut self,
+        script: &Script,
+        span: Span,
+        cells: &[String],
+    ) -> Result<(), Diagnostic> {
+        let line = format!("|{}|", cells.join("|"));
+        if let Some(file) = &mut self.output_file {
+            writeln!(file, "{line}")
+                .map_err(|e| script.error(span, format!("can't write the output file: {e}")))?;
+        }
+        self.output.push(line);
+
+        let Some((file, lines)) = &self.compare else {
+            return Ok(());
+        };
+        let number = self.output.len();
+        let line = &self.output[number - 1];
+        match lines.get(number - 1) {
+            Some(expected) if expected == line.trim_end() => Ok(()),
+            Some(expected) => {
+                let message = format!(
+                    "comparison failure at line {number} of {file}, expected '{expected}', got '{line}'"
+                );
+                Err(script.error(span, message))
+            }
+            None => {
+                let message = format!("comparison failure, {file} only has {} lines", lines.len());
+                Err(script.error(span, message))
+            }
+        }
+    }
+}
+
+/// Assembles the program in memory
+fn machine_code(asm: &str) -> Vec<u16> {
+    assemble(asm.as_bytes())
+        .lines()
+        .map(|l| u16::from_str_radix(l, 2).unwrap())
+        .collect()
+}
+
+/// Translates the .vm file or directory and loads it into a `Computer`, ready to run `Sys.init` or the first command
+fn load_vm(script: &Script, path: &Path, span: Span) -> Result<Target, Diagnostic> {
+    let modules = VmModule::load(path).map_err(|e| script.error(span, e.to_string()))?;
+    let listing = translate_listing(&modules);
+
+    let mut computer = Computer::new(machine_code(&listing.asm));
+    computer.pc = listing.entry;
+    Ok(Target::Vm(computer, listing))
+}
+
+/// The CPU emulator's variables: `RAM[i]`, `ROM[i]`, `A`, `D` and `PC`
+fn cpu_variable<'a>(
+    computer: &'a mut Computer,
+    name: &str,
+    index: Option<usize>,
+) -> Option<&'a mut u16> {
+    match (name, index) {
+        ("RAM", Some(i)) => computer.ram.get_mut(i),
+        ("ROM", Some(i)) => computer.rom.get_mut(i),
+        ("A", None) => Some(&mut computer.a),
+        ("D", None) => Some(&mut computer.d),
+        ("PC", None) => Some(&mut computer.pc),
+        _ => None,
+    }
+}
+
+/// The VM emulator's variables on top of the CPU's: the pointers `sp`, `local`, `argument`, `this` and `that` (RAM[0]
+/// to RAM[4]), the segments they point to and `temp[i]`
+fn vm_variable<'a>(
+    computer: &'a mut Computer,
+    name: &str,
+    index: Option<usize>,
+) -> Option<&'a mut u16> {
+    const POINTERS: [&str; 5] = ["sp", "local", "argument", "this", "that"];
+
+    let address = match (POINTERS.iter().position(|&p| p == name), index) {
+        (Some(pointer), None) => pointer,
+        (Some(pointer), Some(i)) if pointer > 0 => computer.ram[pointer] as usize + i,
+        (None, Some(i)) if name == "temp" && i < 8 => 5 + i,
+        _ => return cpu_variable(computer, name, index),
+    };
+    computer.ram.get_mut(address)
+}
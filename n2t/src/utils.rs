@@ -83,7 +83,7 @@ pub fn bitvec_from_int(mut int: u16) -> Vec<u8> {
     result.into_iter().rev().collect()
 }
 
-pub fn int_from_bitvec(vec: &Vec<u8>) -> u16 {
+pub fn int_from_bitvec(vec: &[u8]) -> u16 {
     let mut result: u16 = 0;
     for (i, j) in vec.iter().enumerate() {
         result |= (*j) as u16;
@@ -94,7 +94,7 @@ pub fn int_from_bitvec(vec: &Vec<u8>) -> u16 {
     result
 }
 
-pub fn decode_bitvec_instr(instr: &Vec<u8>) {
+pub fn decode_bitvec_instr(instr: &[u8]) {
     // form: [i, i, i, a, c1, c2, c3, c4, c5, c6, d1, d2, d3, j1, j2, j3]
     let a_or_c = match instr[0] {
         0 => {
@@ -398,7 +398,7 @@ pub enum Key {
     Tilde,
 }
 
-#[derive(Debug, EnumString, FromRepr, strum_macros::Display)]
+#[derive(Debug, Clone, Copy, PartialEq, EnumString, FromRepr, strum_macros::Display)]
 #[repr(u16)]
 pub enum BuiltInFunc {
     #[strum(serialize = "Math.multiply")]
@@ -0,0 +1,512 @@
+use n2t::hardware::hdl::{
+    builtins::Builtin,
+    parser::{parse_hdl, Wire},
+    simulator::{Component, Simulator},
+};
+use n2t::hardware::logic_gate::{arithmetic::adder, gates, memory::ROM32K};
+use n2t::software::assembler::assemble;
+use n2t::utils::{bitvec_from_int, int_from_bitvec};
+
+const NOT: &str = "
+// Not gate: out = not in
+CHIP Not {
+    IN in;
+    OUT out;
+
+    PARTS:
+    Nand(a=in, b=in, out=out);
+}";
+
+const AND: &str = "
+CHIP And {
+    IN a, b;
+    OUT out;
+
+    PARTS:
+    Nand(a=a, b=b, out=nand);
+    Not(in=nand, out=out);
+}";
+
+const OR: &str = "
+CHIP Or {
+    IN a, b;
+    OUT out;
+
+    PARTS:
+    Not(in=a, out=nota);
+    Not(in=b, out=notb);
+    Nand(a=nota, b=notb, out=out);
+}";
+
+const XOR: &str = "
+/** parts are listed out of order, wires can be used before the part driving them */
+CHIP Xor {
+    IN a, b;
+    OUT out;
+
+    PARTS:
+    Nand(a=a, b=nand, out=x);
+    Nand(a=nand, b=b, out=y);
+    Nand(a=x, b=y, out=out);
+    Nand(a=a, b=b, out=nand);
+}";
+
+const MUX: &str = "
+CHIP Mux {
+    IN a, b, sel;
+    OUT out;
+
+    PARTS:
+    Not(in=sel, out=notsel);
+    Nand(a=a, b=notsel, out=x);
+    Nand(a=b, b=sel, out=y);
+    Nand(a=x, b=y, out=out);
+}";
+
+const HALF_ADDER: &str = "
+CHIP HalfAdder {
+    IN a, b;
+    OUT sum, carry;
+
+    PARTS:
+    Xor(a=a, b=b, out=sum);
+    And(a=a, b=b, out=carry);
+}";
+
+const FULL_ADDER: &str = "
+CHIP FullAdder {
+    IN a, b, c;
+    OUT sum, carry;
+
+    PARTS:
+    HalfAdder(a=a, b=b, sum=ab, carry=c1);
+    HalfAdder(a=ab, b=c, sum=sum, carry=c2);
+    Or(a=c1, b=c2, out=carry);
+}";
+
+const ADD16: &str = "
+CHIP Add16 {
+    IN a[16], b[16];
+    OUT out[16];
+
+    PARTS:
+    HalfAdder(a=a[0], b=b[0], sum=out[0], carry=c0);
+    FullAdder(a=a[1], b=b[1], c=c0, sum=out[1], carry=c1);
+    FullAdder(a=a[2], b=b[2], c=c1, sum=out[2], carry=c2);
+    FullAdder(a=a[3], b=b[3], c=c2, sum=out[3], carry=c3);
+    FullAdder(a=a[4], b=b[4], c=c3, sum=out[4], carry=c4);
+    FullAdder(a=a[5], b=b[5], c=c4, sum=out[5], carry=c5);
+    FullAdder(a=a[6], b=b[6], c=c5, sum=out[6], carry=c6);
+    FullAdder(a=a[7], b=b[7], c=c6, sum=out[7], carry=c7);
+    FullAdder(a=a[8], b=b[8], c=c7, sum=out[8], carry=c8);
+    FullAdder(a=a[9], b=b[9], c=c8, sum=out[9], carry=c9);
+    FullAdder(a=a[10], b=b[10], c=c9, sum=out[10], carry=c10);
+    FullAdder(a=a[11], b=b[11], c=c10, sum=out[11], carry=c11);
+    FullAdder(a=a[12], b=b[12], c=c11, sum=out[12], carry=c12);
+    FullAdder(a=a[13], b=b[13], c=c12, sum=out[13], carry=c13);
+    FullAdder(a=a[14], b=b[14], c=c13, sum=out[14], carry=c14);
+    FullAdder(a=a[15], b=b[15], c=c14, sum=out[15]);
+}";
+
+const BIT: &str = "
+CHIP Bit {
+    IN in, load;
+    OUT out;
+
+    PARTS:
+    Mux(a=dffout, b=in, sel=load, out=muxout);
+    DFF(in=muxout, out=out, out=dffout);
+}";
+
+fn sources() -> Vec<(&'static str, &'static str)> {
+    vec![
+        ("Not.hdl", NOT),
+        ("And.hdl", AND),
+        ("Or.hdl", OR),
+        ("Xor.hdl", XOR),
+        ("Mux.hdl", MUX),
+        ("HalfAdder.hdl", HALF_ADDER),
+        ("FullAdder.hdl", FULL_ADDER),
+        ("Add16.hdl", ADD16),
+        ("Bit.hdl", BIT),
+    ]
+}
+
+fn error(chip: &str, sources: &[(&str, &str)]) -> String {
+    Simulator::from_sources(chip, sources)
+        .unwrap_err()
+        .summary()
+}
+
+#[test]
+fn test_parse() {
+    let chip = parse_hdl("Add16.hdl", ADD16).unwrap();
+    assert_eq!(chip.name, "Add16");
+    assert_eq!(
+        chip.inputs
+            .iter()
+            .map(|p| (p.name.as_str(), p.width))
+            .collect::<Vec<_>>(),
+        [("a", 16), ("b", 16)]
+    );
+    assert_eq!(chip.outputs[0].width, 16);
+    assert_eq!(chip.parts.len(), 16);
+
+    let first = &chip.parts[1].connections[0];
+    assert_eq!(first.pin.name, "a");
+    let Wire::Pin(wire) = &first.wire else {
+        panic!("expected a pin, got {:?}", first.wire)
+    };
+    assert_eq!((wire.name.as_str(), wire.range), ("a", Some((1, 1))));
+
+    let chip = parse_hdl(
+        "Foo.hdl",
+        "CHIP Foo { IN a[8]; OUT out[16]; PARTS: Bar(x[0..7]=a, x[8..15]=true, y=false, out=out); }",
+    )
+    .unwrap();
+    let connections = &chip.parts[0].connections;
+    assert_eq!(connections[0].pin.range, Some((0, 7)));
+    assert_eq!(connections[0].pin.width(), Some(8));
+    assert_eq!(connections[1].wire, Wire::Const(true));
+    assert_eq!(connections[2].wire, Wire::Const(false));
+
+    let chip = parse_hdl(
+        "DFF.hdl",
+        "CHIP DFF { IN in; OUT out; BUILTIN DFF; CLOCKED in; }",
+    )
+    .unwrap();
+    assert_eq!(chip.builtin.as_deref(), Some("DFF"));
+    assert_eq!(chip.clocked, ["in"]);
+    assert!(chip.parts.is_empty());
+}
+
+#[test]
+fn test_syntax_errors() {
+    let summary = |source: &str| parse_hdl("Foo.hdl", source).unwrap_err().summary();
+
+    assert_eq!(
+        summary("CHIP Foo { IN a[17]; OUT out; PARTS: }"),
+        "Foo.hdl:1:17: bus width must be between 1 and 16, got 17"
+    );
+    assert_eq!(
+        summary("CHIP Foo { IN a; OUT out; PARTS: Not(in=a[3..1], out=out); }"),
+        "Foo.hdl:1:41: invalid sub-bus 3..1"
+    );
+    assert_eq!(
+        summary("CHIP Foo { IN a; OUT out; PARTS: Not(in=a out=out); }"),
+        "Foo.hdl:1:43: expected ')' after part connections, got 'out'"
+    );
+    assert_eq!(
+        summary("CHIP Foo { IN a; /* unterminated"),
+        "Foo.hdl:1:18: unterminated block comment"
+    );
+}
+
+#[test]
+fn test_elaborated_gates() {
+    let sources = sources();
+    let mut not = Simulator::from_sources("Not", &sources).unwrap();
+    let mut xor = Simulator::from_sources("Xor", &sources).unwrap();
+    let mut mux = Simulator::from_sources("Mux", &sources).unwrap();
+    assert_eq!(not.inputs, ["in"]);
+    assert_eq!(mux.inputs, ["a", "b", "sel"]);
+
+    for a in 0..2 {
+        not.set("in", a);
+        not.eval();
+        assert_eq!(not.get("out"), 1 - a);
+
+        for b in 0..2 {
+            xor.set("a", a);
+            xor.set("b", b);
+            xor.eval();
+            assert_eq!(xor.get("out"), gates::XOR(a as u8, b as u8) as u16);
+
+            for sel in 0..2 {
+                mux.set("a", a);
+                mux.set("b", b);
+                mux.set("sel", sel);
+                mux.eval();
+                assert_eq!(mux.get("out"), if sel == 1 { b } else { a });
+            }
+        }
+    }
+}
+
+#[test]
+fn test_elaborated_adder() {
+    let mut add = Simulator::from_sources("Add16", &sources()).unwrap();
+    // everything is elaborated down to NANDs, none of it is builtin
+    assert!(add
+        .components
+        .iter()
+        .all(|c| !matches!(c, Component::Builtin(_))));
+
+    for (a, b) in [
+        (0, 0),
+        (1, 1),
+        (12345, 54321),
+        (0xFFFF, 1),
+        (0x7FFF, 0x7FFF),
+        (0xAAAA, 0x5555),
+    ] {
+        add.set("a", a);
+        add.set("b", b);
+        add.eval();
+        let expected = int_from_bitvec(&adder(&bitvec_from_int(a), &bitvec_from_int(b)));
+        assert_eq!(add.get("out"), expected);
+        assert_eq!(add.get("out"), a.wrapping_add(b));
+        // internal wires are visible too
+        assert_eq!(add.get("c0"), a & b & 1);
+    }
+}
+
+#[test]
+fn test_clocked() {
+    let mut bit = Simulator::from_sources("Bit", &sources()).unwrap();
+    let mut expected = 0;
+    for (input, load) in [(1, 0), (1, 1), (0, 0), (0, 1), (1, 1), (0, 0)] {
+        bit.set("in", input);
+        bit.set("load", load);
+        bit.tick();
+        // the output only changes on the falling edge
+        assert_eq!(bit.get("out"), expected);
+        bit.tock();
+        if load == 1 {
+            expected = input;
+        }
+        assert_eq!(bit.get("out"), expected);
+    }
+
+    // a register out of the Bit above, one bit per part
+    let parts: String = (0..16)
+        .map(|i| format!("    Bit(in=in[{i}], load=load, out=out[{i}]);\n"))
+        .collect();
+    let register = format!("CHIP Register {{ IN in[16], load; OUT out[16]; PARTS:\n{parts}}}");
+    let mut sources = sources();
+    sources.push(("Register.hdl", &register));
+    let mut register = Simulator::from_sources("Register", &sources).unwrap();
+    for (input, load, out) in [
+        (-32123i16, 0, 0),
+        (-32123, 1, -32123),
+        (11111, 0, -32123),
+        (200, 1, 200),
+    ] {
+        register.set("in", input as u16);
+        register.set("load", load);
+        register.tick();
+        register.tock();
+        assert_eq!(register.get("out") as i16, out);
+    }
+}
+
+#[test]
+fn test_constants_and_sub_buses() {
+    let source = "
+        CHIP Swap {
+            IN in[16];
+            OUT out[16], low[8], one, zero;
+
+            PARTS:
+            Or16(a[0..7]=in[8..15], a[8..15]=in[0..7], b=false, out=out, out[0..7]=low);
+            And(a=true, b=true, out=one);
+            And(a=true, out=zero);
+        }";
+    let mut swap = Simulator::from_sources("Swap", &[("Swap.hdl", source)]).unwrap();
+    swap.set("in", 0x12AB);
+    swap.eval();
+    assert_eq!(swap.get("out"), 0xAB12);
+    assert_eq!(swap.get("low"), 0x12);
+    assert_eq!(swap.get("one"), 1);
+    assert_eq!(swap.get("zero"), 0);
+}
+
+#[test]
+fn test_builtin_fallback() {
+    // no .hdl files at all, Add16 and the ALU are builtin
+    let source = "
+        CHIP Sub {
+            IN x[16], y[16];
+            OUT out[16], zr, ng;
+
+            PARTS:
+            ALU(x=x, y=y, zx=false, nx=true, zy=false, ny=false, f=true, no=true, out=out, zr=zr, ng=ng);
+        }";
+    let mut sub = Simulator::from_sources("Sub", &[("Sub.hdl", source)]).unwrap();
+    for (x, y) in [(5i16, 3i16), (3, 5), (7, 7), (-100, 200)] {
+        sub.set("x", x as u16);
+        sub.set("y", y as u16);
+        sub.eval();
+        assert_eq!(sub.get("out") as i16, x.wrapping_sub(y));
+        assert_eq!(sub.get("zr"), (x == y) as u16);
+        assert_eq!(sub.get("ng"), (x < y) as u16);
+    }
+
+    // the top level chip can be builtin too, and a BUILTIN .hdl file picks the builtin over its parts
+    let mut ram = Simulator::from_sources("RAM8", &[]).unwrap();
+    ram.set("in", 42);
+    ram.set("load", 1);
+    ram.set("address", 5);
+    ram.tick();
+    ram.tock();
+    ram.set("load", 0);
+    ram.set("address", 4);
+    ram.eval();
+    assert_eq!(ram.get("out"), 0);
+    ram.set("address", 5);
+    ram.eval();
+    assert_eq!(ram.get("out"), 42);
+
+    let builtin = "CHIP Xor { IN a, b; OUT out; BUILTIN Xor; }";
+    let xor = Simulator::from_sources("Xor", &[("Xor.hdl", builtin)]).unwrap();
+    assert_eq!(xor.components.len(), 1);
+    assert_eq!(Builtin::Xor.interface().inputs.len(), 2);
+}
+
+#[test]
+fn test_computer_builtins() {
+    // the RAMs are the memory chips, the high addresses of a RAM16K work
+    let mut ram = Simulator::from_sources("RAM16K", &[]).unwrap();
+    for (address, value) in [(16000, 7), (0, 9), (16383, 11)] {
+        ram.set("in", value);
+        ram.set("load", 1);
+        ram.set("address", address);
+        ram.tick();
+        ram.tock();
+    }
+    ram.set("load", 0);
+    for (address, value) in [(16000, 7), (0, 9), (16383, 11), (1, 0)] {
+        ram.set("address", address);
+        ram.eval();
+        assert_eq!(ram.get("out"), value);
+    }
+
+    let mut keyboard = Simulator::from_sources("Keyboard", &[]).unwrap();
+    assert_eq!(keyboard.get("out"), 0);
+    keyboard.set_keyboard(75);
+    keyboard.eval();
+    assert_eq!(keyboard.get("out"), 75);
+
+    // a project 5 computer out of the builtin CPU, Memory and ROM32K
+    let computer = "
+        CHIP Computer {
+            IN reset;
+            OUT pc[15];
+
+            PARTS:
+            ROM32K(address=pc, out=instruction);
+            CPU(inM=inM, instruction=instruction, reset=reset, outM=outM, writeM=writeM, addressM=addressM, pc=pc);
+            Memory(in=outM, load=writeM, address=addressM, out=inM);
+        }";
+    let program = "
+        @KBD
+        D=M
+        @SCREEN
+        M=D+1
+        @SCREEN
+        D=M
+        @100
+        M=D+1
+    (END)
+        @100
+        D=M
+        @END
+        0;JMP";
+    let hack = assemble(program.as_bytes());
+    let mut computer = Simulator::from_sources("Computer", &[("Computer.hdl", computer)]).unwrap();
+    computer.set_rom(&ROM32K::parse("Prog.hack", &hack).unwrap());
+    computer.set_keyboard(40);
+    computer.eval();
+    for _ in 0..20 {
+        computer.tick();
+        computer.tock();
+    }
+    // the D=M of the loop reads RAM[100], which went through the screen
+    while computer.get("pc") != 9 {
+        computer.tick();
+        computer.tock();
+    }
+    assert_eq!(computer.get("addressM"), 100);
+    assert_eq!(computer.get("inM"), 42);
+    assert_eq!(computer.get("writeM"), 0);
+
+    computer.set("reset", 1);
+    computer.tick();
+    computer.tock();
+    assert_eq!(computer.get("pc"), 0);
+}
+
+#[test]
+fn test_elaboration_errors() {
+    let chip = |parts: &str| format!("CHIP Foo {{ IN a, b[4]; OUT out; PARTS: {parts} }}");
+
+    assert_eq!(
+        error("Foo", &[("Foo.hdl", &chip("Frob(in=a, out=out);"))]),
+        "Foo.hdl:1:40: unknown chip 'Frob'"
+    );
+    assert_eq!(
+        error("Foo", &[("Foo.hdl", &chip("Not(x=a, out=out);"))]),
+        "Foo.hdl:1:44: chip Not has no pin 'x'"
+    );
+    assert_eq!(
+        error("Foo", &[("Foo.hdl", &chip("Not(in=b, out=out);"))]),
+        "Foo.hdl:1:44: width mismatch, 4 bits connected to 1 bits"
+    );
+    assert_eq!(
+        error("Foo", &[("Foo.hdl", &chip("Not(in=c, out=out);"))]),
+        "Foo.hdl:1:47: 'c' is neither a pin of Foo nor the output of one of its parts"
+    );
+    assert_eq!(
+        error(
+            "Foo",
+            &[(
+                "Foo.hdl",
+                &chip("Not(in=a, out=x); Not(in=a, out=x); Not(in=x, out=out);")
+            )]
+        ),
+        "Foo.hdl:1:72: internal pin 'x' has more than one source"
+    );
+    assert_eq!(
+        error(
+            "Foo",
+            &[("Foo.hdl", &chip("Not(in=a, out=out); Not(in=a, out=out);"))]
+        ),
+        "Foo.hdl:1:74: output pin 'out' has more than one source"
+    );
+    assert_eq!(
+        error("Foo", &[("Foo.hdl", &chip("Not(in=out, out=a);"))]),
+        "Foo.hdl:1:56: input pin 'a' can't be set by a part"
+    );
+    assert_eq!(
+        error(
+            "Foo",
+            &[(
+                "Foo.hdl",
+                &chip("Not(in=x, out=y); Not(in=y, out=x); Not(in=a, out=out);")
+            )]
+        ),
+        "Foo.hdl:1:40: combinational loop in Foo, every loop must go through a DFF or clocked chip"
+    );
+    assert_eq!(
+        error("Foo", &[("Foo.hdl", &chip("Foo(a=a, b=b, out=out);"))]),
+        "Foo.hdl:1:40: chip Foo contains itself"
+    );
+
+    // errors in parts point into the part's file
+    let not = "CHIP Not { IN in; OUT out; PARTS: Nand(a=in, b=x, out=out); }";
+    assert_eq!(
+        error(
+            "Foo",
+            &[("Foo.hdl", &chip("Not(in=a, out=out);")), ("Not.hdl", not)]
+        ),
+        "Not.hdl:1:48: 'x' is neither a pin of Not nor the output of one of its parts"
+    );
+
+    // loading a file that isn't there doesn't fall back to the builtin
+    let path = std::env::temp_dir().join("n2t_missing").join("And.hdl");
+    let error = Simulator::load(&path).unwrap_err();
+    assert_eq!(
+        error.summary(),
+        format!("And.hdl:1:1: can't read {}: no such file", path.display())
+    );
+}
@@ -0,0 +1,217 @@
+use n2t::test_script::{
+    parser::{parse_script, Format, Radix, Value},
+    runner::{run_script, ScriptRunner, Target},
+};
+use std::{fs, path::PathBuf};
+
+pub fn test_data_path(file_path: &str) -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.pop();
+    path.push("test_files");
+    path.push(file_path);
+    path
+}
+
+/// Runs the script and checks that the .out file it wrote matches the .cmp file. The .out file goes to a temporary
+/// directory so the test files stay untouched.
+fn check(script: &str) {
+    let path = test_data_path(script);
+    let output_dir = std::env::temp_dir().join(format!("n2t_scripts_{}", std::process::id()));
+    fs::create_dir_all(&output_dir).unwrap();
+    if let Err(e) = run_script(&path, &output_dir) {
+        panic!("{e}");
+    }
+    let out_file = output_dir.join(path.with_extension("out").file_name().unwrap());
+    let out = fs::read_to_string(out_file).unwrap();
+    let cmp = fs::read_to_string(path.with_extension("cmp")).unwrap();
+    assert_eq!(out, cmp);
+}
+
+#[test]
+fn test_gate_scripts() {
+    // Xor.hdl is built from the builtin Not, And and Or, Mux4Way16.hdl just says it's builtin
+    check("ch 1/Xor.tst");
+    check("ch 1/Mux4Way16.tst");
+    check("ch 2/ALU.tst");
+}
+
+#[test]
+fn test_clocked_scripts() {
+    check("ch 3/Bit.tst");
+    check("ch 3/PC.tst");
+}
+
+#[test]
+fn test_computer_script() {
+    check("ch 4/Mult.tst");
+
+    // a loop until the program reaches its final infinite loop
+    let source = "
+        load Mult.hack,
+        output-list time RAM[2]%X1.4.1;
+        set RAM[0] 6, set RAM[1] 7;
+        while PC <> 18 {
+            ticktock;
+        }
+        output;
+        echo \"done\";
+    ";
+    let script = parse_script("Loop.tst", source).unwrap();
+    let mut runner = ScriptRunner::new(&test_data_path("ch 4"));
+    runner.run(&script).unwrap();
+    assert_eq!(runner.output, ["| time |RAM[2]|", "| 94   | 002A |"]);
+    assert_eq!(runner.echo.as_deref(), Some("done"));
+    assert!(matches!(runner.target, Some(Target::Computer(_))));
+}
+
+#[test]
+fn test_vm_script() {
+    // one `vmstep` per VM command, nothing happens once the program is done
+    let source = "
+        load SimpleAdd.vm,
+        output-list sp local[0] temp[1] RAM[256];
+        set sp 256, set local 300, set local[0] 5, set temp[1] 9;
+        vmstep, vmstep, output;
+        repeat 2 { vmstep; }
+        output;
+    ";
+    let script = parse_script("SimpleAdd.tst", source).unwrap();
+    let mut runner = ScriptRunner::new(&test_data_path("ch 7"));
+    runner.run(&script).unwrap();
+    assert_eq!(
+        runner.output,
+        [
+            "|   sp   |local[0]|temp[1] |RAM[256]|",
+            "|    258 |      5 |      9 |      7 |",
+            "|    257 |      5 |      9 |     15 |",
+        ]
+    );
+    assert_eq!(runner.time, 4);
+    assert!(matches!(runner.target, Some(Target::Vm(..))));
+}
+
+#[test]
+fn test_formats() {
+    let word = Value::Word;
+    assert_eq!(
+        Format::new(Radix::Decimal, 1, 6, 1).cell(&word(u16::MAX)),
+        "     -1 "
+    );
+    assert_eq!(
+        Format::new(Radix::Decimal, 0, 2, 0).cell(&word(12345)),
+        "12345"
+    );
+    assert_eq!(
+        Format::new(Radix::Binary, 3, 1, 3).cell(&word(1)),
+        "   1   "
+    );
+    assert_eq!(
+        Format::new(Radix::Binary, 0, 4, 0).cell(&word(0b1_0110)),
+        "0110"
+    );
+    assert_eq!(
+        Format::new(Radix::Hex, 1, 4, 1).cell(&word(0xBEEF)),
+        " BEEF "
+    );
+    assert_eq!(Format::new(Radix::Hex, 0, 2, 0).cell(&word(0xA)), "0A");
+    let time = Value::Text("12+".to_string());
+    assert_eq!(Format::new(Radix::String, 1, 4, 1).cell(&time), " 12+  ");
+
+    assert_eq!(
+        Format::new(Radix::Binary, 1, 16, 1).header("a"),
+        "        a         "
+    );
+    assert_eq!(
+        Format::new(Radix::Decimal, 1, 6, 1).header("RAM[0]"),
+        " RAM[0] "
+    );
+    assert_eq!(
+        Format::new(Radix::Decimal, 0, 4, 0).header("RAM[256]"),
+        "RAM["
+    );
+}
+
+#[test]
+fn test_script_errors() {
+    let summary = |source: &str| parse_script("Foo.tst", source).unwrap_err().summary();
+
+    assert_eq!(
+        summary("load Foo.hdl\noutput;"),
+        "Foo.tst:2:1: expected ',' or ';' after 'load', got 'output'"
+    );
+    assert_eq!(
+        summary("output-list a%Q1.1.1;"),
+        "Foo.tst:1:14: invalid output format '%Q1.1.1', expected e.g. '%D1.6.1'"
+    );
+    assert_eq!(
+        summary("set a 70000;"),
+        "Foo.tst:1:7: value 70000 doesn't fit in 16 bits"
+    );
+    assert_eq!(
+        summary("set RAM[x] 1;"),
+        "Foo.tst:1:5: invalid variable 'RAM[x]'"
+    );
+    assert_eq!(
+        summary("frobnicate;"),
+        "Foo.tst:1:1: unknown command 'frobnicate'"
+    );
+    assert_eq!(
+        summary("repeat 3 { tick; "),
+        "Foo.tst:1:18: expected '}' at the end of the block, got end of file"
+    );
+    assert_eq!(
+        summary("while out ! 3 { tick; }"),
+        "Foo.tst:1:11: expected a comparison, got '!'"
+    );
+
+    let run = |source: &str| {
+        let script = parse_script("Foo.tst", source).unwrap();
+        let mut runner = ScriptRunner::new(&test_data_path("ch 1"));
+        runner.run(&script).unwrap_err().summary()
+    };
+    assert_eq!(run("set a 1;"), "Foo.tst:1:5: nothing has been loaded yet");
+    assert_eq!(
+        run("load Xor.hdl, set out 1;"),
+        "Foo.tst:1:19: 'out' isn't an input pin of Xor"
+    );
+    assert_eq!(
+        run("load Xor.hdl, output-list a c;"),
+        "Foo.tst:1:29: Xor has no variable 'c'"
+    );
+    assert_eq!(
+        run("load Xor.hdl, set a 2;"),
+        "Foo.tst:1:19: 2 doesn't fit in the 1 bits of 'a'"
+    );
+    assert_eq!(
+        run("load Xor.tst;"),
+        "Foo.tst:1:1: can't load 'Xor.tst', expected a .hdl, .hack, .asm or .vm file"
+    );
+    // And has no .hdl file, it's only builtin as a part of another chip
+    assert_eq!(run("load And.hdl;"), "Foo.tst:1:1: can't read And.hdl");
+    assert_eq!(
+        run("load Xor.hdl, vmstep;"),
+        "Foo.tst:1:15: 'vmstep' needs a .vm program"
+    );
+    // a .hack file is checked like the emulator checks it, the error points into the .hack file
+    let dir = std::env::temp_dir().join(format!("n2t_bad_hack_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("Bad.hack"), "0000000000000001\n111000\n").unwrap();
+    fs::write(dir.join("Big.hack"), "0000000000000000\n".repeat(32769)).unwrap();
+    let load = |file: &str| {
+        let script = parse_script("Foo.tst", &format!("load {file};")).unwrap();
+        ScriptRunner::new(&dir).run(&script).unwrap_err().summary()
+    };
+    assert_eq!(
+        load("Bad.hack"),
+        "Bad.hack:2:1: expected a 16 bit binary instruction, found `111000`"
+    );
+    assert_eq!(
+        load("Big.hack"),
+        "Big.hack:32769:1: the program is 32769 instructions long, ROM32K only holds 32768"
+    );
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(
+        run("load Xor.hdl, compare-to Xor.cmp, output-list a%B3.1.3 b%B3.1.3 out%B3.1.3;\nset a 1, eval, output;"),
+        "Foo.tst:2:16: comparison failure at line 2 of Xor.cmp, expected '|   0   |   0   |   0   |', got '|   1   |   0   |   1   |'"
+    );
+}
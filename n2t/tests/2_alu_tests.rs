@@ -615,3 +615,73 @@ fn test_alu() {
         [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 1]
     );
 }
+
+fn control_bits(bits: u8) -> ControlBits {
+    ControlBits {
+        zx: bits >> 5 & 1,
+        nx: bits >> 4 & 1,
+        zy: bits >> 3 & 1,
+        ny: bits >> 2 & 1,
+        f: bits >> 1 & 1,
+        no: bits & 1,
+        ..ControlBits::new()
+    }
+}
+
+#[test]
+fn test_alu16() {
+    use n2t::hardware::cpu_fuzz::Rng;
+    use n2t::utils::{bitvec_from_int, int_from_bitvec};
+
+    let mut rng = Rng::new(3);
+    for _ in 0..50 {
+        let (x, y) = (rng.next_u32() as u16, rng.next_u32() as u16);
+        for bits in 0..64 {
+            let (mut packed, mut bitvec) = (control_bits(bits), control_bits(bits));
+            let out = ALU16(x, y, &mut packed);
+            let expected = ALU(&bitvec_from_int(x), &bitvec_from_int(y), &mut bitvec);
+            assert_eq!(out, int_from_bitvec(&expected), "{x} {y} {bits:06b}");
+            assert_eq!((packed.zr, packed.ng), (bitvec.zr, bitvec.ng));
+        }
+    }
+}
+
+// wall-clock timing depends on the machine and the build, run it with `cargo test -- --ignored`
+#[test]
+#[ignore = "timing, not correctness: test_alu16 checks ALU16 against ALU"]
+fn test_alu16_speedup() {
+    use n2t::utils::bitvec_from_int;
+    use std::hint::black_box;
+    use std::time::Instant;
+
+    // the fastest of a few runs, so another test hogging the cpu doesn't count
+    let fastest = |run: &dyn Fn()| {
+        (0..5)
+            .map(|_| {
+                let now = Instant::now();
+                run();
+                now.elapsed()
+            })
+            .min()
+            .unwrap()
+    };
+    // converted up front, so only `ALU` is timed and not the allocations
+    let inputs: Vec<(Vec<u8>, Vec<u8>)> = (0..2000u16)
+        .map(|i| (bitvec_from_int(i), bitvec_from_int(3)))
+        .collect();
+    let bitvec = fastest(&|| {
+        for (x, y) in &inputs {
+            black_box(ALU(black_box(x), black_box(y), &mut control_bits(0b000010)));
+        }
+    });
+    let packed = fastest(&|| {
+        for i in 0..2000u16 {
+            black_box(ALU16(
+                black_box(i),
+                black_box(3),
+                &mut control_bits(0b000010),
+            ));
+        }
+    });
+    assert!(packed * 10 < bitvec, "{packed:?} vs {bitvec:?}");
+}
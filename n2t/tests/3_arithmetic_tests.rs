@@ -100,3 +100,19 @@ fn test_is_equal() {
         0
     );
 }
+
+#[test]
+fn test_packed_arithmetic() {
+    use n2t::hardware::cpu_fuzz::Rng;
+    use n2t::utils::{bitvec_from_int, int_from_bitvec};
+
+    let mut rng = Rng::new(5);
+    let mut values = vec![0, 1, 0x7FFF, 0x8000, 0xFFFF];
+    values.extend((0..200).map(|_| rng.next_u32() as u16));
+    for (&a, &b) in values.iter().zip(values.iter().rev()) {
+        let (bits_a, bits_b) = (bitvec_from_int(a), bitvec_from_int(b));
+        assert_eq!(adder16(a, b), int_from_bitvec(&adder(&bits_a, &bits_b)));
+        assert_eq!(incrementer16(a), int_from_bitvec(&incrementer(&bits_a)));
+        assert_eq!(is_zero16(a), is_zero(&bits_a));
+    }
+}
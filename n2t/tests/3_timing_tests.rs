@@ -0,0 +1,183 @@
+use n2t::hardware::cpu_fuzz::Rng;
+use n2t::hardware::hdl::library::elaborate;
+use n2t::hardware::hdl::simulator::{Node, Simulator};
+use n2t::hardware::logic_gate::alu::{ControlBits, ALU};
+use n2t::hardware::logic_gate::timing::*;
+use n2t::hardware::vcd::dump_trace;
+use n2t::utils::{bitvec_from_int, int_from_bitvec};
+
+fn control_bits(bits: u8) -> ControlBits {
+    ControlBits {
+        zx: bits >> 5 & 1,
+        nx: bits >> 4 & 1,
+        zy: bits >> 3 & 1,
+        ny: bits >> 2 & 1,
+        f: bits >> 1 & 1,
+        no: bits & 1,
+        ..ControlBits::new()
+    }
+}
+
+/// The ALU's outputs, `out` then `zr` and `ng`
+fn alu_outputs(alu: &Simulator) -> Vec<Node> {
+    let mut outputs = alu.nodes("out").to_vec();
+    outputs.extend([alu.nodes("zr")[0], alu.nodes("ng")[0]]);
+    outputs
+}
+
+/// A MUX with both inputs at 1
+fn mux_sim(mux: &Simulator) -> EventSim<'_> {
+    let mut sim = EventSim::new(mux);
+    sim.set(mux.nodes("a")[0], 1);
+    sim.set(mux.nodes("b")[0], 1);
+    sim.run();
+    sim
+}
+
+#[test]
+fn test_adders() {
+    for adder in ["Add16", "LookaheadAdd16"] {
+        let chip = elaborate(adder);
+        let mut sim = EventSim::new(&chip);
+        let mut rng = Rng::new(7);
+        for _ in 0..200 {
+            let (x, y) = (rng.next_u32() as u16, rng.next_u32() as u16);
+            sim.set_bus(chip.nodes("a"), x);
+            sim.set_bus(chip.nodes("b"), y);
+            sim.run();
+            assert_eq!(sim.get_bus(chip.nodes("out")), x.wrapping_add(y), "{adder}");
+        }
+    }
+}
+
+#[test]
+fn test_alu_matches_logic_gate() {
+    let alu = elaborate("ALU");
+    let mut sim = EventSim::new(&alu);
+    let mut rng = Rng::new(11);
+    for _ in 0..200 {
+        let (a, b) = (rng.next_u32() as u16, rng.next_u32() as u16);
+        let mut bits = control_bits(rng.below(64) as u8);
+        let expected = ALU(&bitvec_from_int(a), &bitvec_from_int(b), &mut bits);
+
+        sim.set_bus(alu.nodes("x"), a);
+        sim.set_bus(alu.nodes("y"), b);
+        let control = [bits.zx, bits.nx, bits.zy, bits.ny, bits.f, bits.no];
+        for (pin, value) in ["zx", "nx", "zy", "ny", "f", "no"].into_iter().zip(control) {
+            sim.set(alu.nodes(pin)[0], value);
+        }
+        sim.run();
+        assert_eq!(sim.get_bus(alu.nodes("out")), int_from_bitvec(&expected));
+        let flags = (sim.get(alu.nodes("zr")[0]), sim.get(alu.nodes("ng")[0]));
+        assert_eq!(flags, (bits.zr, bits.ng));
+    }
+}
+
+#[test]
+fn test_critical_paths() {
+    let (ripple, lookahead) = (elaborate("Add16"), elaborate("LookaheadAdd16"));
+    let ripple_path = critical_path(&ripple, ripple.nodes("out"), 1);
+    let lookahead_path = critical_path(&lookahead, lookahead.nodes("out"), 1);
+
+    // the carry goes through every full adder, the lookahead adder needs 4 combining steps
+    assert_eq!(ripple_path.delay, 68);
+    assert_eq!(lookahead_path.delay, 22);
+    assert_eq!(ripple_path.gates.len() as u32, ripple_path.delay);
+    assert!(ripple.components.len() < lookahead.components.len());
+
+    let alus: Vec<u32> = ["ALU", "LookaheadALU"]
+        .into_iter()
+        .map(|chip| {
+            let alu = elaborate(chip);
+            critical_path(&alu, &alu_outputs(&alu), 1).delay
+        })
+        .collect();
+    assert_eq!(alus, [99, 53]);
+
+    // slower gates make the path longer
+    let slow = critical_path(&ripple, ripple.nodes("out"), 3);
+    assert_eq!(slow.delay, 3 * ripple_path.delay);
+    assert_eq!(slow.gates, ripple_path.gates);
+}
+
+#[test]
+fn test_settle_time() {
+    // 0xFFFF + 1 carries through all 16 bits
+    let settle = |adder: &str| {
+        let chip = elaborate(adder);
+        let mut sim = EventSim::new(&chip);
+        sim.set_bus(chip.nodes("a"), 0xFFFF);
+        sim.run();
+        sim.set_bus(chip.nodes("b"), 1);
+        let time = sim.run();
+        assert_eq!(sim.get_bus(chip.nodes("out")), 0);
+        time
+    };
+    assert_eq!(settle("Add16"), 64);
+    assert_eq!(settle("LookaheadAdd16"), 18);
+}
+
+#[test]
+fn test_adder_glitches() {
+    // 0x7FFF + 1 -> 0x8000 + 1: every bit of `a` flips at once, each sum bit follows right away and flips back when
+    // the carry arrives
+    let chip = elaborate("Add16");
+    let sum = chip.nodes("out");
+    let mut sim = EventSim::new(&chip);
+    sim.set_bus(chip.nodes("a"), 0x7FFF);
+    sim.set_bus(chip.nodes("b"), 1);
+    sim.run();
+    assert_eq!(sim.get_bus(sum), 0x8000);
+
+    sim.set_bus(chip.nodes("a"), 0x8000);
+    sim.run();
+    assert_eq!(sim.get_bus(sum), 0x8001);
+    assert_eq!(sim.glitches(sum), sum[1..]);
+    // the final value of the top bit doesn't change, it only glitches
+    assert_eq!(sim.transitions(sum[15]).len(), 2);
+}
+
+#[test]
+fn test_glitch() {
+    // both inputs of the MUX are 1, but when `sel` falls the NOT makes one AND turn on a gate delay after the other
+    // turned off
+    let mux = elaborate("Mux");
+    let (sel, out) = (mux.nodes("sel")[0], mux.nodes("out")[0]);
+
+    let mut sim = mux_sim(&mux);
+    sim.set(sel, 1);
+    sim.run();
+    assert_eq!(sim.glitches(&[out]), []);
+
+    sim.set(sel, 0);
+    assert_eq!(sim.run(), 5);
+    assert_eq!(sim.get(out), 1);
+    assert_eq!(sim.glitches(&[out]), [out]);
+    assert_eq!(
+        sim.waveform(&[("sel", sel), ("out", out)]),
+        "sel ______\nout ####_#\n"
+    );
+}
+
+#[test]
+fn test_vcd_trace() {
+    let mux = elaborate("Mux");
+    let (sel, out) = (mux.nodes("sel")[0], mux.nodes("out")[0]);
+
+    let mut sim = mux_sim(&mux);
+    sim.set(sel, 1);
+    sim.run();
+    sim.set(sel, 0);
+    sim.run();
+
+    let vcd = dump_trace(&sim, &[("sel", sel), ("out", out)], Vec::new()).unwrap();
+    let vcd = String::from_utf8(vcd).unwrap();
+    assert!(vcd.contains("$var wire 1 ! sel $end\n$var wire 1 \" out $end\n"));
+    // shifted by one: `out` rises at 5 once `a` and `b` are 1, `sel` rises then, falls at 10 and `out` glitches 4 gate
+    // delays later
+    let changes = &vcd[vcd.find("#0").unwrap()..];
+    assert_eq!(
+        changes,
+        "#0\n0!\n0\"\n#5\n1\"\n1!\n#10\n0!\n#14\n0\"\n#15\n1\"\n"
+    );
+}
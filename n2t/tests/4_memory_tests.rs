@@ -1,4 +1,5 @@
-use n2t::hardware::logic_gate::memory::*;
+use n2t::hardware::logic_gate::{gates::*, memory::*};
+use std::fmt::Debug;
 
 #[test]
 fn test_bit() {
@@ -1289,4 +1290,204 @@ fn test_bit() {
         let address = 10923;
         assert_eq!(ram.data[address as usize], 21845);
         ram.cycle(input, address, load);
-    }
\ No newline at end of file
+    }
+
+#[test]
+fn test_gate_rams() {
+    let mut ram8 = RAM8::new();
+    ram8.cycle(1234, 5, 1);
+    ram8.cycle(99, 2, 1);
+    assert_eq!(ram8.cycle(7, 5, 0), 1234);
+    assert_eq!(ram8.cycle(7, 2, 0), 99);
+
+    let mut ram64 = RAM64::new();
+    ram64.cycle(4321, 45, 1);
+    assert_eq!(ram64.cycle(0, 45, 0), 4321);
+    assert_eq!(ram64.cycle(0, 44, 0), 0);
+
+    let mut ram4k = RAM4K::new();
+    for a in [0, 511, 512, 4095] {
+        ram4k.cycle(a + 1, a, 1);
+    }
+    for a in [0, 511, 512, 4095] {
+        assert_eq!(ram4k.cycle(0, a, 0), a + 1);
+    }
+
+    let mut ram32k = RAM32K::new();
+    for a in [0, 4096, 16383, 16384, 32767] {
+        ram32k.cycle(a ^ 0xABCD, a, 1);
+    }
+    for a in [0, 4096, 16383, 16384, 32767] {
+        assert_eq!(ram32k.cycle(0, a, 0), a ^ 0xABCD);
+    }
+    assert_eq!(ram32k.cycle(0, 1, 0), 0);
+}
+
+/// One level of a RAM the way the book builds it: the load goes through a DMux to every part, every part is clocked
+/// and a Mux picks the output. The low address bits pick the part, the rest are passed down.
+fn every_part<P, const N: usize>(
+    parts: &mut [P; N],
+    input: u16,
+    address: u16,
+    load: u8,
+    cycle: fn(&mut P, u16, u16, u8) -> u16,
+) -> u16 {
+    let k = N.trailing_zeros();
+    let s: Vec<u8> = (0..k).map(|i| (address >> (k - 1 - i) & 1) as u8).collect();
+    let loads = match k {
+        1 => DEMUX(load, s[0]).to_vec(),
+        2 => DEMUX_4(load, s[0], s[1]).to_vec(),
+        _ => DEMUX_8(load, s[0], s[1], s[2]).to_vec(),
+    };
+    let out: Vec<u16> = parts
+        .iter_mut()
+        .zip(loads)
+        .map(|(part, load)| cycle(part, input, address >> k, load))
+        .collect();
+    match k {
+        1 => MUX16(out[0], out[1], s[0]),
+        2 => MUX4WAY16(out[0], out[1], out[2], out[3], s[0], s[1]),
+        _ => MUX8WAY16(
+            out[0], out[1], out[2], out[3], out[4], out[5], out[6], out[7], s[0], s[1], s[2],
+        ),
+    }
+}
+
+fn ram64(ram: &mut RAM64, input: u16, address: u16, load: u8) -> u16 {
+    every_part(&mut ram.ram8, input, address, load, RAM8::cycle)
+}
+
+fn ram512(ram: &mut RAM512, input: u16, address: u16, load: u8) -> u16 {
+    every_part(&mut ram.ram64, input, address, load, ram64)
+}
+
+fn ram4k(ram: &mut RAM4K, input: u16, address: u16, load: u8) -> u16 {
+    every_part(&mut ram.ram512, input, address, load, ram512)
+}
+
+fn ram16k(ram: &mut RAM16K, input: u16, address: u16, load: u8) -> u16 {
+    every_part(&mut ram.ram4k, input, address, load, ram4k)
+}
+
+fn ram32k(ram: &mut RAM32K, input: u16, address: u16, load: u8) -> u16 {
+    every_part(&mut ram.ram16k, input, address, load, ram16k)
+}
+
+fn screen(screen: &mut Screen, input: u16, address: u16, load: u8) -> u16 {
+    every_part(&mut screen.ram4k, input, address, load, ram4k)
+}
+
+/// Runs the same random reads and writes on two copies of `chip`, one clocked by `fast`, the other by `reference`,
+/// and checks that every output and the final state are the same
+fn same_as_every_part<C: Clone + PartialEq + Debug>(
+    chip: C,
+    bits: u32,
+    fast: fn(&mut C, u16, u16, u8) -> u16,
+    reference: fn(&mut C, u16, u16, u8) -> u16,
+) {
+    let mut seed = 0x2545_F491u32;
+    let mut next = || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed
+    };
+    // a handful of addresses so the reads hit earlier writes
+    let addresses: Vec<u16> = (0..16).map(|_| (next() % (1 << bits)) as u16).collect();
+    let (mut a, mut b) = (chip.clone(), chip);
+    for _ in 0..200 {
+        let address = addresses[next() as usize % addresses.len()];
+        let input = next() as u16;
+        let load = (next() % 2) as u8;
+        assert_eq!(
+            fast(&mut a, input, address, load),
+            reference(&mut b, input, address, load)
+        );
+    }
+    assert!(a == b);
+}
+
+#[test]
+fn test_rams_clock_one_part() {
+    same_as_every_part(RAM64::new(), 6, RAM64::cycle, ram64);
+    same_as_every_part(RAM512::new(), 9, RAM512::cycle, ram512);
+    same_as_every_part(RAM4K::new(), 12, RAM4K::cycle, ram4k);
+    same_as_every_part(
+        Box::new(RAM16K::new()),
+        14,
+        |r, i, a, l| r.cycle(i, a, l),
+        |r, i, a, l| ram16k(r, i, a, l),
+    );
+    same_as_every_part(
+        Box::new(RAM32K::new()),
+        15,
+        |r, i, a, l| r.cycle(i, a, l),
+        |r, i, a, l| ram32k(r, i, a, l),
+    );
+    same_as_every_part(
+        Box::new(Screen::new()),
+        13,
+        |r, i, a, l| r.cycle(i, a, l),
+        |r, i, a, l| screen(r, i, a, l),
+    );
+}
+
+#[test]
+fn test_gate_memory_map() {
+    let mut memory = Memory::new();
+    memory.cycle(11, 0, 1);
+    memory.cycle(22, 16383, 1);
+    memory.cycle(33, 16384, 1);
+    memory.cycle(44, 24575, 1);
+    assert_eq!(memory.ram.peek(16383), 22);
+    assert_eq!(memory.screen.peek(0), 33);
+    assert_eq!(memory.screen.peek(8191), 44);
+    for (address, value) in [(0, 11), (16383, 22), (16384, 33), (24575, 44)] {
+        assert_eq!(memory.cycle(0, address, 0), value);
+        assert_eq!(memory.peek(address), value);
+    }
+
+    // the keyboard can't be written by the cpu, only pressed
+    memory.cycle(55, 24576, 1);
+    assert_eq!(memory.cycle(0, 24576, 0), 0);
+    memory.keyboard.press(75);
+    assert_eq!(memory.cycle(0, 24576, 0), 75);
+    memory.poke(24576, 0);
+    assert_eq!(memory.peek(24576), 0);
+    assert_eq!(memory.peek(0), 11);
+}
+
+#[test]
+fn test_gate_rom() {
+    let mut rom = ROM32K::with_program(&[7, 8, 9]);
+    assert_eq!((rom.cycle(0), rom.cycle(2), rom.cycle(3)), (7, 9, 0));
+    // 15 address pins: the top bit of the PC is dropped
+    assert_eq!(rom.cycle(0x8001), 8);
+    assert_eq!(rom.peek(32767), 0);
+
+    let mut rom = ROM32K::parse("Prog.hack", "0000000000000101\r\n\n1110110000010000\n").unwrap();
+    assert_eq!((rom.cycle(0), rom.cycle(1)), (5, 0b1110_1100_0001_0000));
+
+    let error = ROM32K::parse("Prog.hack", "0000000000000101\n  @5\n").unwrap_err();
+    assert_eq!(
+        error.summary(),
+        "Prog.hack:2:3: expected a 16 bit binary instruction, found `@5`"
+    );
+    let error = ROM32K::parse("Prog.hack", "000000000000010\n").unwrap_err();
+    assert_eq!((error.line, error.column), (1, 1));
+
+    // the whole ROM fits, one more instruction doesn't
+    let full = "0000000000000001\n".repeat(ROM32K::SIZE);
+    assert_eq!(ROM32K::parse("Full.hack", &full).unwrap().peek(32767), 1);
+    let error = ROM32K::parse("Big.hack", &(full + "0000000000000010\n")).unwrap_err();
+    assert_eq!(
+        error.summary(),
+        "Big.hack:32769:1: the program is 32769 instructions long, ROM32K only holds 32768"
+    );
+}
+
+#[test]
+#[should_panic(expected = "the program is 32769 instructions long, ROM32K only holds 32768")]
+fn test_gate_rom_too_long() {
+    ROM32K::with_program(&[0; 32769]);
+}
@@ -0,0 +1,163 @@
+use n2t::hardware::cpu_fuzz::Rng;
+use n2t::hardware::hdl::library::elaborate;
+use n2t::hardware::logic_gate::alu::*;
+use n2t::hardware::logic_gate::cost::*;
+use n2t::hardware::logic_gate::memory::{InstPtr, RAM64};
+use n2t::utils::{bitvec_from_int, int_from_bitvec};
+
+#[test]
+fn test_alu_designs() {
+    let (alu, mux_alu) = (chip_cost("ALU"), chip_cost("MuxALU"));
+
+    // the MUX_4 ALU also computes `zr` and `ng`, and runs x and y through a 4-way MUX instead of two MUXes
+    assert_eq!((alu.nands, alu.depth), (1710, 99));
+    assert_eq!((mux_alu.nands, mux_alu.depth), (1344, 90));
+
+    // the ripple-carry adder is most of the ALU's depth
+    let adder = chip_cost("Add16");
+    let lookahead = chip_cost("LookaheadAdd16");
+    assert_eq!((adder.nands, adder.depth), (304, 68));
+    assert_eq!((lookahead.nands, lookahead.depth), (545, 22));
+    let lookahead_alu = chip_cost("LookaheadALU");
+    assert_eq!(
+        lookahead_alu.nands,
+        alu.nands - adder.nands + lookahead.nands
+    );
+    assert_eq!(lookahead_alu.depth, 53);
+}
+
+/// The library chips compute what the `logic_gate` functions they're costed for compute
+#[test]
+fn test_library_matches_logic_gate() {
+    let mut rng = Rng::new(5);
+    let (mut alu, mut lookahead_alu, mut mux_alu) = (
+        elaborate("ALU"),
+        elaborate("LookaheadALU"),
+        elaborate("MuxALU"),
+    );
+    for _ in 0..20 {
+        let (x, y) = (rng.next_u32() as u16, rng.next_u32() as u16);
+        for bits in 0..64u16 {
+            let mut control = ControlBits {
+                zx: (bits >> 5 & 1) as u8,
+                nx: (bits >> 4 & 1) as u8,
+                zy: (bits >> 3 & 1) as u8,
+                ny: (bits >> 2 & 1) as u8,
+                f: (bits >> 1 & 1) as u8,
+                no: (bits & 1) as u8,
+                ..ControlBits::new()
+            };
+            let expected = ALU16(x, y, &mut control);
+            let mux_expected =
+                mux_ALU(bitvec_from_int(x), bitvec_from_int(y), &mut control.clone());
+            for chip in [&mut alu, &mut lookahead_alu, &mut mux_alu] {
+                chip.set("x", x);
+                chip.set("y", y);
+                for (i, pin) in ["zx", "nx", "zy", "ny", "f", "no"].into_iter().enumerate() {
+                    chip.set(pin, bits >> (5 - i) & 1);
+                }
+                chip.eval();
+            }
+            for chip in [&alu, &lookahead_alu] {
+                assert_eq!(
+                    chip.get("out"),
+                    expected,
+                    "{} {x} {y} {bits:06b}",
+                    chip.chip
+                );
+                assert_eq!(chip.get("zr"), control.zr as u16);
+                assert_eq!(chip.get("ng"), control.ng as u16);
+            }
+            assert_eq!(mux_alu.get("out"), int_from_bitvec(&mux_expected));
+        }
+    }
+
+    let (mut pc, mut inst_ptr) = (elaborate("PC"), InstPtr::new());
+    let (mut ram, mut ram64) = (elaborate("RAM64"), RAM64::new());
+    for _ in 0..500 {
+        let (input, r) = (rng.next_u32() as u16, rng.next_u32());
+        let (load, inc, reset) = ((r & 1) as u8, (r >> 1 & 1) as u8, (r >> 2 & 15 == 0) as u8);
+        for (pin, value) in [
+            ("in", input),
+            ("load", load as u16),
+            ("inc", inc as u16),
+            ("reset", reset as u16),
+        ] {
+            pc.set(pin, value);
+        }
+        pc.tick();
+        pc.tock();
+        assert_eq!(pc.get("out"), inst_ptr.cycle(input, load, inc, reset));
+
+        let address = (r >> 8) as u16 & 0b111111;
+        ram.set("in", input);
+        ram.set("address", address);
+        ram.set("load", load as u16);
+        ram.tick();
+        ram.tock();
+        assert_eq!(
+            ram.get("out"),
+            ram64.cycle(input, address, load),
+            "{address}"
+        );
+    }
+}
+
+#[test]
+fn test_memory_costs() {
+    // a register bit is a DFF and the MUX that loads it
+    let register = chip_cost("Register");
+    assert_eq!(
+        (register.nands, register.dffs, register.depth),
+        (128, 16, 5)
+    );
+
+    // every RAM is 8 (4 for RAM16K, 2 for RAM32K) of the one below it plus the address decoding and output MUXes
+    let rams = ["RAM8", "RAM64", "RAM512", "RAM4K", "RAM16K"];
+    let sizes = [8, 64, 512, 4096, 16384];
+    let costs: Vec<ChipCost> = rams.into_iter().map(chip_cost).collect();
+    for (cost, size) in costs.iter().zip(sizes) {
+        assert_eq!(cost.dffs, size * 16, "{}", cost.chip);
+    }
+    for pair in costs.windows(2) {
+        let (small, big) = (&pair[0], &pair[1]);
+        let parts = big.dffs / small.dffs;
+        assert!(big.nands > parts * small.nands, "{}", big.chip);
+        assert!(big.depth > small.depth, "{}", big.chip);
+    }
+    let (ram8, ram4k, ram16k) = (&costs[0], &costs[3], &costs[4]);
+    assert_eq!(ram8.nands, 1955);
+    // the RAM32K is two RAM16Ks, a DMux for the load and a Mux16 for the output
+    let ram32k = chip_cost("RAM32K");
+    assert_eq!(ram32k.dffs, 32768 * 16);
+    assert_eq!(ram32k.nands, 2 * ram16k.nands + 5 + 16 * 8);
+    assert_eq!(ram32k.nands, 8_552_315);
+
+    // the screen is two RAM4Ks, a DMux for the load and a Mux16 for the output
+    let screen = chip_cost("Screen");
+    assert_eq!(screen.dffs, 8192 * 16);
+    assert_eq!(screen.nands, 2 * ram4k.nands + 5 + 16 * 8);
+
+    // the computer is its memory plus the CPU: the RAM16K, the screen and the keyboard register decoded by a DMux4Way
+    // and a Mux4Way16, then 3 registers, the ALU, the PC and the jump logic
+    let computer = chip_cost("Computer");
+    assert_eq!(computer.dffs, (16384 + 8192 + 1) * 16 + 3 * 16);
+    assert_eq!(computer.nands - ram16k.nands - screen.nands, 3520);
+    assert_eq!(computer.depth, 194);
+}
+
+#[test]
+fn test_cost_table() {
+    let table = cost_table(&[chip_cost("Add16"), chip_cost("LookaheadAdd16")]);
+    assert_eq!(
+        table,
+        "\
++----------------+-------+------+-------+
+| chip           | NANDs | DFFs | depth |
++----------------+-------+------+-------+
+| Add16          |   304 |    0 |    68 |
+| LookaheadAdd16 |   545 |    0 |    22 |
++----------------+-------+------+-------+
+"
+    );
+}
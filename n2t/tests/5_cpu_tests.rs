@@ -1,5 +1,19 @@
+use n2t::hardware::cpu_fuzz::*;
+use n2t::hardware::hack_cpu::{CpuSignals, HackCpu};
 use n2t::hardware::logic_gate::cpu::*;
-use n2t::utils::*;
+use n2t::hardware::native;
+use n2t::hardware::vcd::*;
+use n2t::HackEmulator;
+use std::path::{Path, PathBuf};
+
+pub fn test_data_path(file_path: &str) -> PathBuf {
+    match std::env::var("ENV_ROOT_DIR") {
+        Ok(path) => Path::new(&path).join(file_path),
+        Err(_) => Path::new(&std::env::current_dir().unwrap())
+            .join("../")
+            .join(file_path),
+    }
+}
 
 #[test]
 fn test_cpu() {
@@ -21,271 +35,271 @@ fn test_cpu() {
         0b0000000000001110,
         0b1110101010000111,
     ]);
-    comp.ram.data[0] = 3;
-    comp.ram.data[1] = 5;
+    comp.set_ram(0, 3);
+    comp.set_ram(1, 5);
 
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (0, 0, 1, 3, 5, 0)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (0, 3, 2, 3, 5, 0)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (1, 3, 3, 3, 5, 0)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (1, -2, 4, 3, 5, 0)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (10, -2, 5, 3, 5, 0)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (10, -2, 6, 3, 5, 0)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (1, -2, 7, 3, 5, 0,)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (1, 5, 8, 3, 5, 0)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (12, 5, 9, 3, 5, 0)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (12, 5, 12, 3, 5, 0)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (2, 5, 13, 3, 5, 0)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (2, 5, 14, 3, 5, 5,)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (14, 5, 15, 3, 5, 5)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (14, 5, 14, 3, 5, 5)
     );
     comp.execute(true, true);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (14, 5, 0, 3, 5, 5)
     );
-    comp.ram.data[0] = 23456;
-    comp.ram.data[1] = 12345;
+    comp.set_ram(0, 23456);
+    comp.set_ram(1, 12345);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (14, 5, 0, 23456, 12345, 5)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (0, 5, 1, 23456, 12345, 5)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (0, 23456, 2, 23456, 12345, 5)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (1, 23456, 3, 23456, 12345, 5)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (1, 11111, 4, 23456, 12345, 5)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (10, 11111, 5, 23456, 12345, 5)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (10, 11111, 10, 23456, 12345, 5,)
     );
@@ -293,49 +307,213 @@ fn test_cpu() {
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (0, 11111, 11, 23456, 12345, 5)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (0, 23456, 12, 23456, 12345, 5)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (2, 23456, 13, 23456, 12345, 5,)
     );
     comp.execute(true, false);
     assert_eq!(
         (
-            int_from_bitvec(&comp.a.data),
-            i16::from_ne_bytes(int_from_bitvec(&comp.d.data).to_ne_bytes()),
-            int_from_bitvec(&comp.pc.val.data),
-            comp.ram.data[0],
-            comp.ram.data[1],
-            comp.ram.data[2]
+            comp.a.data,
+            comp.d.data as i16,
+            comp.pc.val.data,
+            comp.ram(0),
+            comp.ram(1),
+            comp.ram(2)
         ),
         (2, 23456, 14, 23456, 12345, 23456)
     );
 }
+
+#[test]
+fn test_differential_fuzz() {
+    if let Err(report) = fuzz::<Computer, native::cpu::Computer>(0x2545_F491, 300, 24, 64) {
+        panic!("{report}");
+    }
+}
+
+/// The native CPU with bit 0 of D stuck at 0
+struct StuckBit(native::cpu::Computer);
+
+impl HackCpu for StuckBit {
+    fn with_program(program: Vec<u16>) -> Self {
+        StuckBit(native::cpu::Computer::new(program))
+    }
+
+    fn step(&mut self) {
+        HackCpu::step(&mut self.0);
+        self.0.d &= !1;
+    }
+
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+
+    fn a(&self) -> u16 {
+        self.0.a
+    }
+
+    fn d(&self) -> u16 {
+        self.0.d
+    }
+
+    fn pc(&self) -> u16 {
+        self.0.pc
+    }
+
+    fn ram(&self, address: u16) -> u16 {
+        HackCpu::ram(&self.0, address)
+    }
+
+    fn set_ram(&mut self, address: u16, value: u16) {
+        self.0.set_ram(address, value);
+    }
+
+    fn signals(&self) -> CpuSignals {
+        self.0.signals()
+    }
+}
+
+#[test]
+fn test_fuzz_report() {
+    // @3, D=A, D=D+1: the first cycle that leaves D odd is reported
+    let program = [3, 0b1110_1100_0001_0000, 0b1110_0111_1101_0000];
+    let divergence = run_lockstep::<native::cpu::Computer, StuckBit>(&program, 10).unwrap();
+    assert_eq!((divergence.cycle, divergence.pc), (2, 1));
+    assert_eq!(disassemble(divergence.instruction), "D=A");
+    assert_eq!((divergence.left.d, divergence.right.d), (3, 2));
+
+    let report = fuzz::<native::cpu::Computer, StuckBit>(1, 100, 24, 64).unwrap_err();
+    assert_eq!(report.program.len(), 24);
+    assert!(report.reproducer.len() < report.program.len(), "{report}");
+    assert_eq!(
+        run_lockstep::<native::cpu::Computer, StuckBit>(&report.reproducer, 64),
+        Some(report.reproducer_divergence.clone())
+    );
+    assert!(report.to_string().contains("minimized to"));
+}
+
+#[test]
+fn test_disassemble() {
+    assert_eq!(disassemble(21), "@21");
+    assert_eq!(disassemble(0b1111_1101_1101_1000), "MD=M+1");
+    assert_eq!(disassemble(0b1110_0011_0000_0101), "D;JNE");
+    assert_eq!(disassemble(0b1110_1010_1000_0111), "0;JMP");
+}
+
+/// The value changes at `#time` in a VCD file
+fn vcd_changes<'a>(vcd: &'a str, time: &str) -> Vec<&'a str> {
+    let start = vcd.find(&format!("#{time}\n")).unwrap();
+    vcd[start..]
+        .lines()
+        .skip(1)
+        .take_while(|l| !l.starts_with('#'))
+        .collect()
+}
+
+#[test]
+fn test_vcd_dump() {
+    // @3, D=A, @2, M=D;JGT
+    let program = vec![3, 0b1110_1100_0001_0000, 2, 0b1110_0011_0000_1001];
+    let dump = |vcd: Vec<u8>| String::from_utf8(vcd).unwrap();
+    let gates = dump(dump_cpu(&mut Computer::new(program.clone()), 4, Vec::new()).unwrap());
+    let native = dump(dump_cpu(&mut native::cpu::Computer::new(program), 4, Vec::new()).unwrap());
+
+    assert!(gates.starts_with("$version n2t $end\n$timescale 1 us $end\n$scope module cpu $end\n"));
+    assert!(gates.contains("$var wire 15 - addressM [14:0] $end\n$var wire 1 . writeM $end\n"));
+
+    // D=A: only what changed is written
+    assert_eq!(
+        vcd_changes(&gates, "2"),
+        ["b11 \"", "b10 #", "1$", "1%", "0*", "b11 ,", "b11 -"]
+    );
+    // M=D;JGT writes RAM[2] and jumps back to 2
+    assert_eq!(
+        vcd_changes(&gates, "4"),
+        ["b10 #", "1&", "1'", "b10 -", "1."]
+    );
+    // the native CPU doesn't run the ALU for A-instructions, but the pins agree on C-instructions
+    for change in ["b10 #", "b10 -", "1."] {
+        assert!(vcd_changes(&native, "4").contains(&change));
+    }
+}
+
+#[test]
+fn test_pong_on_gates() {
+    // Pong from chapter 6 brings its own OS, it takes about 5M cycles to clear the screen and draw the bat and ball
+    let path = test_data_path("test_files/ch 6/test/Pong.hack");
+    let mut gates = HackEmulator::<Computer>::from_hack(path.clone()).unwrap();
+    let mut native = HackEmulator::<native::cpu::Computer>::from_hack(path).unwrap();
+    gates.run(5_500_000);
+    native.run(5_500_000);
+    assert!(gates.get_screen().iter().any(|&word| word != 0));
+    assert_eq!(gates.get_screen(), native.get_screen());
+
+    // holding the left arrow
+    gates.set_keyboard(130);
+    native.set_keyboard(130);
+    assert_eq!(gates.get_keyboard(), 130);
+    gates.run(500_000);
+    native.run(500_000);
+    assert_eq!(gates.get_screen(), native.get_screen());
+}
+
+#[test]
+fn test_load_hack() {
+    // RAM[2] = max(RAM[0], RAM[1])
+    let mut comp = Computer::load(&test_data_path("test_files/ch 6/test/Max.hack")).unwrap();
+    comp.set_ram(0, 17);
+    comp.set_ram(1, 42);
+    for _ in 0..20 {
+        comp.step();
+    }
+    assert_eq!(comp.ram(2), 42);
+
+    // a file that isn't there is an error, not a panic
+    let error = Computer::load(&test_data_path("test_files/ch 6/test/Missing.hack")).unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .starts_with("Missing.hack:1:1: can't read "),
+        "{error}"
+    );
+
+    // a program that doesn't fit in the ROM is an error on either computer, rather than a panic
+    let path = std::env::temp_dir().join(format!("n2t_big_{}.hack", std::process::id()));
+    std::fs::write(&path, "0000000000000000\n".repeat(32769)).unwrap();
+    let error = HackEmulator::<native::cpu::Computer>::from_hack(path.clone()).unwrap_err();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        error.message,
+        "the program is 32769 instructions long, ROM32K only holds 32768"
+    );
+}
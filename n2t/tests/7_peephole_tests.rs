@@ -0,0 +1,274 @@
+use std::path::{Path, PathBuf};
+
+use n2t::{
+    hardware::native::cpu::Computer,
+    software::{
+        assembler::{assemble, builtin_symbols, translate_instruction},
+        peephole::{optimize_with_stats, RULES},
+        vm::translate,
+    },
+    utils::u16_from_i16,
+};
+
+/// Everything below the screen is compared when checking program results
+const STATE_END: usize = 0x4000;
+
+pub fn test_data_path(file_path: &str) -> PathBuf {
+    match std::env::var("ENV_ROOT_DIR") {
+        Ok(path) => Path::new(&path).join(file_path),
+        Err(_) => Path::new(&std::env::current_dir().unwrap())
+            .join("../")
+            .join(file_path),
+    }
+}
+
+fn to_machine_code(hack: &str) -> Vec<u16> {
+    hack.lines()
+        .map(|l| u16::from_str_radix(l, 2).unwrap())
+        .collect()
+}
+
+/// xorshift, good enough to scramble the machine state
+fn next_rand(state: &mut u32) -> u32 {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+    *state
+}
+
+/// Instantiates the `@?` wildcards with concrete A instructions
+fn instantiate(lines: &[&str]) -> Vec<String> {
+    let mut wildcard = ["@LCL", "@1234"].iter().cycle();
+    lines
+        .iter()
+        .map(|l| match *l {
+            "@?" => wildcard.next().unwrap().to_string(),
+            _ => l.to_string(),
+        })
+        .collect()
+}
+
+#[test]
+fn test_rules_equivalent() {
+    let symbols = builtin_symbols();
+    let mut seed = 0x2545_F491;
+
+    for rule in RULES {
+        let pattern = instantiate(rule.pattern);
+        let replacement: Vec<String> = rule
+            .replacement
+            .iter()
+            .map(|r| match r.strip_prefix('$') {
+                Some(i) => pattern[i.parse::<usize>().unwrap()].clone(),
+                None => r.to_string(),
+            })
+            .collect();
+
+        assert!(
+            replacement.len() < pattern.len(),
+            "rule '{}' does not shrink the program",
+            rule.name
+        );
+
+        let assemble_window = |lines: &[String]| -> Vec<u16> {
+            let mut hack = String::new();
+            for line in lines {
+                hack.push_str(&translate_instruction(line.clone(), &symbols));
+            }
+            to_machine_code(&hack)
+        };
+
+        let before = assemble_window(&pattern);
+        let after = assemble_window(&replacement);
+
+        for _ in 0..200 {
+            let mut ram = vec![0u16; 4096];
+            for val in ram.iter_mut() {
+                *val = (next_rand(&mut seed) % 4096) as u16;
+            }
+            // stack pointer has to point somewhere inside the stack
+            ram[0] = 256 + (next_rand(&mut seed) % 1024) as u16;
+            let a = (next_rand(&mut seed) % 4096) as u16;
+            let d = next_rand(&mut seed) as u16;
+
+            let run = |program: &Vec<u16>| {
+                let mut cpu = Computer::new(program.clone());
+                cpu.ram[..4096].copy_from_slice(&ram);
+                cpu.a = a;
+                cpu.d = d;
+                cpu.run_exact(program.len(), false, false);
+                cpu
+            };
+
+            let expected = run(&before);
+            let got = run(&after);
+
+            assert_eq!(expected.ram, got.ram, "rule '{}' modified RAM", rule.name);
+            assert_eq!(expected.d, got.d, "rule '{}' modified D", rule.name);
+            if !rule.clobbers_a {
+                assert_eq!(expected.a, got.a, "rule '{}' modified A", rule.name);
+            }
+        }
+    }
+}
+
+/// (path, initial RAM contents, cells to compare)
+type Program<'a> = (&'a str, &'a [(usize, i16)], Option<&'a [usize]>);
+
+/// Runs the translated program until it falls off the end of the program or `cycles` is reached,
+/// returns the final RAM
+fn run_translated(
+    file_path: &str,
+    optimize: bool,
+    setup: &[(usize, i16)],
+    cycles: usize,
+) -> (Vec<u16>, usize) {
+    let asm = translate(&test_data_path(file_path), optimize);
+    let program = to_machine_code(&assemble(asm.as_bytes()));
+    let len = program.len();
+
+    let mut cpu = Computer::new(program);
+    for &(addr, val) in setup {
+        cpu.ram[addr] = u16_from_i16(val);
+    }
+    while (cpu.pc as usize) < len && cpu.time < cycles {
+        cpu.step(false, false);
+    }
+
+    (cpu.ram, len)
+}
+
+#[test]
+fn test_optimized_programs_equivalent() {
+    let ch_7_setup = [(0, 256), (1, 300), (2, 400), (3, 3000), (4, 3010)];
+    let mut nested_call_setup = vec![
+        (0, 261),
+        (1, 261),
+        (2, 256),
+        (3, -3),
+        (4, -4),
+        (5, -1),
+        (6, -1),
+        (256, 1234),
+        (257, -1),
+        (258, -2),
+        (259, -3),
+        (260, -4),
+    ];
+    nested_call_setup.extend((261..300).map(|i| (i, -1)));
+    // the frames NestedCall leaves on the stack: the saved LCL, ARG, THIS and THAT of every call,
+    // its locals and arguments. The return addresses in them have been overwritten by the values
+    // returned, except Sys.init's, which comes from the bootstrap code the two versions share.
+    let nested_call_frames: Vec<usize> = (256..300).collect();
+
+    // programs that call functions leave return addresses on the stack, which naturally differ
+    // between the two versions. For those only the registers, statics and the cells checked by the
+    // official test scripts are compared.
+    let programs: [Program; 11] = [
+        (
+            "./test_files/ch 7/SimpleAdd.vm",
+            &ch_7_setup,
+            None,
+        ),
+        (
+            "./test_files/ch 7/StackTest.vm",
+            &ch_7_setup,
+            None,
+        ),
+        (
+            "./test_files/ch 7/BasicTest.vm",
+            &ch_7_setup,
+            None,
+        ),
+        (
+            "./test_files/ch 7/PointerTest.vm",
+            &ch_7_setup,
+            None,
+        ),
+        (
+            "./test_files/ch 7/StaticTest.vm",
+            &ch_7_setup,
+            None,
+        ),
+        (
+            "./test_files/ch 8/ProgramFlow/BasicLoop/BasicLoop.vm",
+            &[(0, 256), (1, 300), (2, 400), (400, 3)],
+            None,
+        ),
+        (
+            "./test_files/ch 8/ProgramFlow/FibonacciSeries/FibonacciSeries.vm",
+            &[(0, 256), (1, 300), (2, 400), (400, 6), (401, 3000)],
+            None,
+        ),
+        (
+            "./test_files/ch 8/FunctionCalls/SimpleFunction/SimpleFunction.vm",
+            &[
+                (0, 317),
+                (1, 317),
+                (2, 310),
+                (3, 3000),
+                (4, 4000),
+                (310, 1234),
+                (311, 37),
+                (312, 1000),
+                (313, 305),
+                (314, 300),
+                (315, 3010),
+                (316, 4010),
+            ],
+            None,
+        ),
+        (
+            "./test_files/ch 8/FunctionCalls/NestedCall/",
+            &nested_call_setup,
+            Some(&nested_call_frames),
+        ),
+        (
+            "./test_files/ch 8/FunctionCalls/FibonacciElement/",
+            &[],
+            Some(&[261]),
+        ),
+        (
+            "./test_files/ch 8/FunctionCalls/StaticsTest/",
+            &[],
+            Some(&[261, 262]),
+        ),
+    ];
+
+    for (path, setup, results) in programs {
+        let (expected, unoptimized_len) = run_translated(path, false, setup, 20_000);
+        let (got, optimized_len) = run_translated(path, true, setup, 20_000);
+
+        assert!(
+            optimized_len < unoptimized_len,
+            "{path}: optimized program is not shorter ({optimized_len} >= {unoptimized_len})"
+        );
+
+        // R13-R15 are scratch registers for the translator, R15 holds return addresses of comparisons
+        assert_eq!(expected[..13], got[..13], "{path}: registers differ");
+        match results {
+            None => assert_eq!(
+                expected[16..STATE_END],
+                got[16..STATE_END],
+                "{path}: optimized program produced different results"
+            ),
+            Some(cells) => {
+                assert_eq!(expected[16..256], got[16..256], "{path}: statics differ");
+                for &cell in cells {
+                    assert_eq!(expected[cell], got[cell], "{path}: RAM[{cell}] differs");
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_optimizer_stats() {
+    let asm = "@SP\nA=M\nM=D\n@SP\nAM=M+1\n@SP\nAM=M-1\nD=M\n@R5\nM=D\n";
+    let (optimized, stats) = optimize_with_stats(asm);
+
+    assert_eq!(optimized, "@SP\nA=M\nM=D\n@R5\nM=D\n");
+    assert_eq!(stats.before, 10);
+    assert_eq!(stats.after, 5);
+    assert_eq!(stats.applied.iter().sum::<usize>(), 3);
+}
@@ -0,0 +1,393 @@
+use std::path::{Path, PathBuf};
+
+use n2t::{
+    hardware::native::cpu::Computer,
+    software::{
+        assembler::assemble,
+        vm::translate,
+        vm_interpreter::{VmInterpreter, VmProgram},
+    },
+    utils::u16_from_i16,
+    SCREEN_END, SCREEN_START, STACK_START, STATIC_START,
+};
+
+pub fn test_data_path(file_path: &str) -> PathBuf {
+    match std::env::var("ENV_ROOT_DIR") {
+        Ok(path) => Path::new(&path).join(file_path),
+        Err(_) => Path::new(&std::env::current_dir().unwrap())
+            .join("../")
+            .join(file_path),
+    }
+}
+
+/// xorshift, good enough to generate random programs
+fn next_rand(state: &mut u32) -> u32 {
+    *state ^= *state << 13;
+    *state ^= *state >> 17;
+    *state ^= *state << 5;
+    *state
+}
+
+/// Translates and assembles the program, then loads it into a native `Computer`
+fn get_computer(path: &Path) -> Computer {
+    let asm = translate(path, true);
+    let program = assemble(asm.as_bytes())
+        .lines()
+        .map(|l| u16::from_str_radix(l, 2).unwrap())
+        .collect();
+    Computer::new(program)
+}
+
+/// Runs a program without `Sys.init` (and so without bootstrap code) on both the interpreter and the native `Computer`, starting
+/// from the same RAM contents
+fn run_both(path: &Path, setup: &[(usize, i16)], ops: usize) -> (VmInterpreter, Computer) {
+    let mut vm = VmInterpreter::new(VmProgram::load(path));
+    let mut cpu = get_computer(path);
+    for &(addr, val) in setup {
+        vm.ram[addr] = u16_from_i16(val);
+        cpu.ram[addr] = u16_from_i16(val);
+    }
+
+    vm.run_until(ops);
+    assert!(vm.halted, "{path:?}: interpreter did not finish");
+
+    cpu.run_until(ops * 40, false, false);
+
+    (vm, cpu)
+}
+
+#[test]
+fn test_ch_7_programs() {
+    let setup = [(0, 256), (1, 300), (2, 400), (3, 3000), (4, 3010)];
+    for file in [
+        "SimpleAdd",
+        "StackTest",
+        "BasicTest",
+        "PointerTest",
+        "StaticTest",
+    ] {
+        let path = test_data_path(&format!("./test_files/ch 7/{file}.vm"));
+        let (vm, cpu) = run_both(&path, &setup, 1000);
+
+        // R13-R15 are scratch registers for the translator
+        assert_eq!(vm.ram[..13], cpu.ram[..13], "{file}: registers differ");
+        assert_eq!(
+            vm.ram[16..SCREEN_START],
+            cpu.ram[16..SCREEN_START],
+            "{file}: RAM differs"
+        );
+    }
+
+    let path = test_data_path("./test_files/ch 7/StackTest.vm");
+    let vm = run_both(&path, &setup, 1000).0;
+    assert_eq!(vm.ram[0], 266);
+    assert_eq!(vm.ram[265] as i16, !((28 & 57) | 82));
+}
+
+#[test]
+fn test_program_flow() {
+    let (vm, cpu) = run_both(
+        &test_data_path("./test_files/ch 8/ProgramFlow/FibonacciSeries/FibonacciSeries.vm"),
+        &[(0, 256), (1, 300), (2, 400), (400, 6), (401, 3000)],
+        1000,
+    );
+    assert_eq!(vm.ram[3000..=3005], [0, 1, 1, 2, 3, 5]);
+    assert_eq!(vm.ram[..13], cpu.ram[..13]);
+    assert_eq!(vm.ram[16..SCREEN_START], cpu.ram[16..SCREEN_START]);
+
+    let (vm, cpu) = run_both(
+        &test_data_path("./test_files/ch 8/FunctionCalls/SimpleFunction/SimpleFunction.vm"),
+        &[
+            (0, 317),
+            (1, 317),
+            (2, 310),
+            (3, 3000),
+            (4, 4000),
+            (310, 1234),
+            (311, 37),
+            (312, 1000),
+            (313, 305),
+            (314, 300),
+            (315, 3010),
+            (316, 4010),
+        ],
+        1000,
+    );
+    assert_eq!(vm.ram[0..=4], [311, 305, 300, 3010, 4010]);
+    assert_eq!(vm.ram[310], 1196);
+    assert_eq!(vm.ram[..13], cpu.ram[..13]);
+}
+
+#[test]
+fn test_function_calls() {
+    // (program, cells to compare). The stack contains return addresses, which differ between the two.
+    let programs: [(&str, &[usize]); 2] = [
+        ("./test_files/ch 8/FunctionCalls/FibonacciElement/", &[261]),
+        ("./test_files/ch 8/FunctionCalls/StaticsTest/", &[261, 262]),
+    ];
+
+    for (path, cells) in programs {
+        let path = test_data_path(path);
+
+        let mut vm = VmInterpreter::new(VmProgram::load(&path));
+        vm.bootstrap();
+        vm.run_until(20_000);
+
+        let mut cpu = get_computer(&path);
+        cpu.run_until(20_000, false, false);
+
+        assert_eq!(vm.ram[..5], cpu.ram[..5], "{path:?}: registers differ");
+        assert_eq!(
+            vm.ram[16..256],
+            cpu.ram[16..256],
+            "{path:?}: statics differ"
+        );
+        for &cell in cells {
+            assert_eq!(vm.ram[cell], cpu.ram[cell], "{path:?}: RAM[{cell}] differs");
+        }
+    }
+}
+
+#[test]
+fn test_os_program() {
+    // full Jack OS in VM code, Main prints "7". The folder also contains the reference output `MainTarget.vm`, which
+    // defines `Main.main` a second time.
+    let path = std::env::temp_dir().join(format!("Seven_{}", std::process::id()));
+    std::fs::create_dir_all(&path).unwrap();
+    for entry in test_data_path("./test_files/ch 11/Seven/")
+        .read_dir()
+        .unwrap()
+    {
+        let file = entry.unwrap().path();
+        let name = file.file_name().unwrap().to_str().unwrap();
+        if name.ends_with(".vm") && !name.ends_with("Target.vm") {
+            std::fs::copy(&file, path.join(name)).unwrap();
+        }
+    }
+
+    let program = VmProgram::load(&path);
+    std::fs::remove_dir_all(&path).unwrap();
+
+    let mut vm = VmInterpreter::new(program.clone());
+    vm.bootstrap();
+    vm.run_until(1_000_000);
+    assert!(vm.screen_drawn(), "nothing was drawn");
+
+    // Math.multiply and Sys.halt are replaced by the native OS, the output has to stay the same
+    let mut native = VmInterpreter::new(program);
+    native.native_os = true;
+    native.bootstrap();
+    native.run_until(1_000_000);
+
+    assert!(native.halted);
+    assert!(native.time < vm.time);
+    assert_eq!(
+        vm.ram[SCREEN_START..SCREEN_END],
+        native.ram[SCREEN_START..SCREEN_END],
+        "screens differ"
+    );
+}
+
+#[test]
+fn test_random_programs() {
+    let binary = ["add", "sub", "and", "or", "eq", "lt", "gt"];
+    let unary = ["neg", "not"];
+    let segments = ["local", "argument", "this", "that", "temp", "static"];
+    let mut seed = 0x9E37_79B9;
+
+    for n in 0..20 {
+        let mut vm_code = String::new();
+        let mut depth = 0;
+        for _ in 0..300 {
+            let r = next_rand(&mut seed);
+            let line = match r % 8 {
+                0..=2 => {
+                    depth += 1;
+                    format!("push constant {}", next_rand(&mut seed) % 32768)
+                }
+                3 if depth > 0 => {
+                    depth -= 1;
+                    let seg = segments[(r >> 8) as usize % segments.len()];
+                    format!("pop {seg} {}", next_rand(&mut seed) % 8)
+                }
+                4 => {
+                    depth += 1;
+                    let seg = segments[(r >> 8) as usize % segments.len()];
+                    format!("push {seg} {}", next_rand(&mut seed) % 8)
+                }
+                5 if depth > 0 => unary[(r >> 8) as usize % unary.len()].to_string(),
+                _ if depth > 1 => {
+                    depth -= 1;
+                    binary[(r >> 8) as usize % binary.len()].to_string()
+                }
+                _ => continue,
+            };
+            vm_code.push_str(&line);
+            vm_code.push('\n');
+        }
+
+        let path = std::env::temp_dir().join(format!("Random{n}_{}.vm", std::process::id()));
+        std::fs::write(&path, &vm_code).unwrap();
+
+        let setup = [(0, 256), (1, 300), (2, 400), (3, 3000), (4, 3010)];
+        let (vm, cpu) = run_both(&path, &setup, 1000);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            vm.ram[..13],
+            cpu.ram[..13],
+            "registers differ for:\n{vm_code}"
+        );
+        assert_eq!(
+            vm.ram[STATIC_START..SCREEN_START],
+            cpu.ram[STATIC_START..SCREEN_START],
+            "RAM differs for:\n{vm_code}"
+        );
+    }
+}
+
+#[test]
+fn test_native_os() {
+    let program = VmProgram::parse([(
+        "Main",
+        "function Main.main 0
+        push constant 7
+        neg
+        push constant 3
+        call Math.multiply 2
+        push constant 7
+        neg
+        push constant 2
+        call Math.divide 2
+        push constant 3
+        call Array.new 1
+        pop pointer 1
+        push constant 42
+        pop that 2
+        push pointer 1
+        push constant 2
+        add
+        call Memory.peek 1
+        call Sys.halt 0
+        push constant 1
+        return",
+    )]);
+
+    let mut vm = VmInterpreter::new(program);
+    vm.native_os = true;
+    vm.ram[0] = STACK_START as u16;
+    vm.run_until(1000);
+
+    assert!(vm.halted);
+    assert_eq!(vm.current_function(), "Main.main");
+    assert_eq!(vm.stack(), [u16_from_i16(-21), u16_from_i16(-3), 42, 0]);
+}
+
+#[test]
+fn test_missing_os() {
+    let program = VmProgram::parse([(
+        "Main",
+        "function Main.main 0\npush constant 2\npush constant 3\ncall Math.multiply 2\nreturn",
+    )]);
+
+    let mut vm = VmInterpreter::new(program);
+    vm.ram[0] = STACK_START as u16;
+    vm.run_until(1000);
+
+    assert!(vm.halted);
+    assert_eq!(
+        vm.error.as_deref(),
+        Some("call to undefined function 'Math.multiply' in 'Main.main'")
+    );
+    // halted on the call
+    assert_eq!(vm.pc, 3);
+}
+
+#[test]
+fn test_return_without_frame() {
+    let program = VmProgram::parse([("Main", "function Main.main 0\npush constant 1\nreturn")]);
+
+    let mut vm = VmInterpreter::new(program);
+    vm.ram[0] = STACK_START as u16;
+    vm.run_until(1000);
+
+    assert!(vm.halted);
+    assert_eq!(
+        vm.error.as_deref(),
+        Some("return with LCL = 0, there's no call frame below it in 'Main.main'")
+    );
+}
+
+#[test]
+fn test_native_memory_out_of_range() {
+    for (call, error) in [
+        (
+            "call Memory.peek 1",
+            "Memory.peek: address 65535 is outside of RAM in 'Main.main'",
+        ),
+        (
+            "push constant 7\ncall Memory.poke 2",
+            "Memory.poke: address 65535 is outside of RAM in 'Main.main'",
+        ),
+    ] {
+        let program = VmProgram::parse([(
+            "Main",
+            format!("function Main.main 0\npush constant 0\nnot\n{call}\nreturn").as_str(),
+        )]);
+
+        let mut vm = VmInterpreter::new(program);
+        vm.native_os = true;
+        vm.ram[0] = STACK_START as u16;
+        vm.run_until(1000);
+
+        assert!(vm.halted);
+        assert_eq!(vm.error.as_deref(), Some(error));
+    }
+}
+
+#[test]
+fn test_native_os_errors() {
+    for (call, error) in [
+        (
+            "push constant 1\npush constant 0\ncall Math.divide 2",
+            "Math.divide: division by zero in 'Main.main'",
+        ),
+        (
+            "push constant 4\nneg\ncall Math.sqrt 1",
+            "Math.sqrt: negative argument -4 in 'Main.main'",
+        ),
+        (
+            "push constant 0\ncall Memory.alloc 1",
+            "Memory.alloc: size must be positive, got 0 in 'Main.main'",
+        ),
+        (
+            "push constant 1\nneg\ncall Array.new 1",
+            "Array.new: size must be positive, got -1 in 'Main.main'",
+        ),
+        (
+            "push constant 30000\ncall Memory.alloc 1",
+            "Memory.alloc: heap overflow, unable to allocate 30000 words in 'Main.main'",
+        ),
+        (
+            "push constant 0\ncall Memory.deAlloc 1",
+            "Memory.deAlloc: there's no block at address 0 in 'Main.main'",
+        ),
+        (
+            "push constant 3\ncall Sys.error 1",
+            "Sys.error: 3 in 'Main.main'",
+        ),
+    ] {
+        let program = VmProgram::parse([(
+            "Main",
+            format!("function Main.main 0\n{call}\nreturn").as_str(),
+        )]);
+
+        let mut vm = VmInterpreter::new(program);
+        vm.native_os = true;
+        vm.ram[0] = STACK_START as u16;
+        vm.run_until(1000);
+
+        assert!(vm.halted);
+        assert_eq!(vm.error.as_deref(), Some(error), "{call}");
+    }
+}
@@ -0,0 +1,137 @@
+use std::path::{Path, PathBuf};
+
+use n2t::software::{
+    vm_ir::{pretty_print, VmCommand, VmModule, VmParseError},
+    writer_impl::Segment,
+};
+
+pub fn test_data_path(file_path: &str) -> PathBuf {
+    match std::env::var("ENV_ROOT_DIR") {
+        Ok(path) => Path::new(&path).join(file_path),
+        Err(_) => Path::new(&std::env::current_dir().unwrap())
+            .join("../")
+            .join(file_path),
+    }
+}
+
+fn parse_err(source: &str) -> VmParseError {
+    VmModule::parse("Main", source).unwrap_err()
+}
+
+#[test]
+fn test_parse() {
+    let module = VmModule::parse(
+        "Main",
+        "// comment\n\
+        function Main.main 2\n\
+        \x20   push constant 7 // trailing comment\n\
+        \x20   pop static 3\n\
+        label LOOP\n\
+        \x20   if-goto LOOP\n\
+        \x20   call Math.multiply 2\n\
+        \x20   return\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        module.commands,
+        [
+            VmCommand::Function {
+                name: "Main.main".to_string(),
+                n_vars: 2
+            },
+            VmCommand::Push(Segment::Constant, 7),
+            VmCommand::Pop(Segment::Static, 3),
+            VmCommand::Label("LOOP".to_string()),
+            VmCommand::IfGoto("LOOP".to_string()),
+            VmCommand::Call {
+                name: "Math.multiply".to_string(),
+                n_args: 2
+            },
+            VmCommand::Return,
+        ]
+    );
+
+    let functions = module.functions();
+    assert_eq!(functions.len(), 1);
+    assert_eq!(functions[0].name, "Main.main");
+    assert_eq!(functions[0].n_vars, 2);
+    assert_eq!(functions[0].commands, 0..7);
+}
+
+#[test]
+fn test_parse_errors() {
+    let err = parse_err("push constant 1\n  psh constant 2");
+    assert_eq!((err.line, err.column), (2, 3));
+    assert_eq!(err.message, "unknown command 'psh'");
+    assert_eq!(err.to_string(), "Main.vm:2:3: unknown command 'psh'");
+
+    let err = parse_err("push stack 1");
+    assert_eq!((err.line, err.column), (1, 6));
+    assert_eq!(err.message, "unknown segment 'stack'");
+
+    let err = parse_err("push local");
+    assert_eq!((err.line, err.column), (1, 12));
+    assert_eq!(err.message, "expected index");
+
+    let err = parse_err("push local x");
+    assert_eq!(err.column, 12);
+    assert_eq!(err.message, "expected index, got 'x'");
+
+    let err = parse_err("push constant 32768");
+    assert_eq!(err.column, 15);
+
+    let err = parse_err("pop pointer 2");
+    assert_eq!(err.column, 13);
+
+    let err = parse_err("pop constant 2");
+    assert_eq!(
+        (err.column, err.message.as_str()),
+        (5, "cannot pop to the constant segment")
+    );
+
+    let err = parse_err("label 1ABC");
+    assert_eq!(
+        (err.column, err.message.as_str()),
+        (7, "invalid label '1ABC'")
+    );
+
+    let err = parse_err("add 1");
+    assert_eq!(
+        (err.column, err.message.as_str()),
+        (5, "unexpected '1' after 'add'")
+    );
+}
+
+#[test]
+fn test_print_roundtrip() {
+    let programs = [
+        "./test_files/ch 7/StackTest.vm",
+        "./test_files/ch 7/BasicTest.vm",
+        "./test_files/ch 8/ProgramFlow/FibonacciSeries/FibonacciSeries.vm",
+        "./test_files/ch 8/FunctionCalls/StaticsTest/",
+        "./test_files/ch 11/os/",
+        "./test_files/ch 11/Pong/",
+    ];
+
+    for path in programs {
+        let path = test_data_path(path);
+        for module in VmModule::load(&path).unwrap() {
+            // the display form is exactly what the official tools and the compiler emit
+            let source = std::fs::read_to_string(path.join(format!("{}.vm", module.name)))
+                .or_else(|_| std::fs::read_to_string(&path))
+                .unwrap();
+            let expected: Vec<&str> = source
+                .lines()
+                .map(|l| l.split("//").next().unwrap().trim())
+                .filter(|l| !l.is_empty())
+                .collect();
+            let printed: Vec<String> = module.commands.iter().map(|c| c.to_string()).collect();
+            assert_eq!(printed, expected, "{}", module.name);
+
+            let pretty = pretty_print(&module.commands);
+            let reparsed = VmModule::parse(&module.name, &pretty).unwrap();
+            assert_eq!(reparsed, module, "{}", module.name);
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use std::path::{Path, PathBuf};
+
+use n2t::{
+    software::{
+        vm_interpreter::{VmInterpreter, VmProgram},
+        vm_ir::{VmCommand, VmModule},
+        vm_optimizer::{optimize_modules, optimize_with_stats, VmPasses},
+    },
+    SCREEN_END, SCREEN_START,
+};
+
+pub fn test_data_path(file_path: &str) -> PathBuf {
+    match std::env::var("ENV_ROOT_DIR") {
+        Ok(path) => Path::new(&path).join(file_path),
+        Err(_) => Path::new(&std::env::current_dir().unwrap())
+            .join("../")
+            .join(file_path),
+    }
+}
+
+fn commands(source: &str) -> Vec<VmCommand> {
+    VmModule::parse("Main", source).unwrap().commands
+}
+
+/// Optimizes `source` with a single pass enabled
+fn run_pass(source: &str, pass: fn(&mut VmPasses)) -> (String, usize) {
+    let mut passes = VmPasses {
+        constant_folding: false,
+        dead_stores: false,
+        push_pop: false,
+        branches: false,
+    };
+    pass(&mut passes);
+
+    let (result, stats) = optimize_with_stats(&commands(source), &passes);
+    let text: Vec<String> = result.iter().map(|c| c.to_string()).collect();
+    let count = stats.constant_folding + stats.dead_stores + stats.push_pop + stats.branches;
+    (text.join("\n"), count)
+}
+
+/// Loads a ch 11 program along with the OS if the folder doesn't contain it. The reference compiler output
+/// (`*Target.vm`) is skipped.
+fn load_program(path: &str) -> Vec<VmModule> {
+    let mut modules: Vec<VmModule> = VmModule::load(&test_data_path(path))
+        .unwrap()
+        .into_iter()
+        .filter(|m| !m.name.ends_with("Target"))
+        .collect();
+    if !modules.iter().any(|m| m.name == "Sys") {
+        modules.extend(VmModule::load(&test_data_path("./test_files/ch 11/os/")).unwrap());
+    }
+    modules
+}
+
+#[test]
+fn test_constant_folding() {
+    let fold = |passes: &mut VmPasses| passes.constant_folding = true;
+
+    assert_eq!(
+        run_pass("push constant 2\npush constant 3\nadd", fold).0,
+        "push constant 5"
+    );
+    // negative results are pushed as the complement of a constant
+    assert_eq!(
+        run_pass("push constant 2\npush constant 3\nsub", fold).0,
+        "push constant 0\nnot"
+    );
+    assert_eq!(
+        run_pass(
+            "push constant 6\npush constant 7\ncall Math.multiply 2\npush constant 4\nneg\nadd",
+            fold
+        )
+        .0,
+        "push constant 38"
+    );
+    assert_eq!(
+        run_pass(
+            "push constant 7\nneg\npush constant 2\ncall Math.divide 2",
+            fold
+        )
+        .0,
+        "push constant 2\nnot"
+    );
+    // overflow wraps just like on the hardware
+    assert_eq!(
+        run_pass("push constant 32767\npush constant 1\nadd", fold).0,
+        "push constant 32767\nnot"
+    );
+    // division by zero is left for the OS to report
+    assert_eq!(
+        run_pass("push constant 1\npush constant 0\ncall Math.divide 2", fold).0,
+        "push constant 1\npush constant 0\ncall Math.divide 2"
+    );
+    assert_eq!(
+        run_pass(
+            "push constant 0\nnot\nnot\npush constant 1\npush constant 1\neq",
+            fold
+        )
+        .0,
+        "push constant 0\npush constant 0\nnot"
+    );
+    assert_eq!(
+        run_pass(
+            "push local 0\npush constant 0\nadd\npush constant 1\ncall Math.multiply 2\nnot\nnot",
+            fold
+        ),
+        ("push local 0".to_string(), 3)
+    );
+}
+
+#[test]
+fn test_push_pop() {
+    let pass = |passes: &mut VmPasses| passes.push_pop = true;
+
+    assert_eq!(
+        run_pass("push local 0\npop local 0\npush local 1\npop local 2", pass),
+        ("push local 1\npop local 2".to_string(), 1)
+    );
+}
+
+#[test]
+fn test_dead_stores() {
+    let pass = |passes: &mut VmPasses| passes.dead_stores = true;
+
+    // overwritten before being read
+    assert_eq!(
+        run_pass(
+            "push local 1\npush constant 1\nadd\npop local 0\npush constant 2\npop local 0",
+            pass
+        )
+        .0,
+        "push constant 2\npop local 0"
+    );
+    // locals die when the function returns, statics don't
+    assert_eq!(
+        run_pass(
+            "function Main.f 1\npush argument 0\npop local 0\npush argument 0\npop static 0\npush constant 0\nreturn",
+            pass
+        )
+        .0,
+        "function Main.f 1\npush argument 0\npop static 0\npush constant 0\nreturn"
+    );
+    // the value is read first
+    let source = "push constant 1\npop local 0\npush local 0\npush constant 2\npop local 0";
+    assert_eq!(run_pass(source, pass).0, source);
+    // a call result has side effects, the callee may read statics and temps
+    let source = "call Main.g 0\npop local 0\npush constant 1\npop local 0";
+    assert_eq!(run_pass(source, pass).0, source);
+    let source = "push constant 1\npop static 0\ncall Main.g 0\npush constant 2\npop static 0";
+    assert_eq!(run_pass(source, pass).0, source);
+    // jumps end the search
+    let source = "push constant 1\npop local 0\nlabel L\npush constant 2\npop local 0\ngoto L";
+    assert_eq!(run_pass(source, pass).0, source);
+}
+
+#[test]
+fn test_branches() {
+    let pass = |passes: &mut VmPasses| passes.branches = true;
+    let all = |passes: &mut VmPasses| *passes = VmPasses::default();
+
+    assert_eq!(
+        run_pass(
+            "label A\npush local 0\npush local 1\neq\nnot\nif-goto A",
+            pass
+        )
+        .0,
+        "label A\npush local 0\npush local 1\nsub\nif-goto A"
+    );
+    // if (!(x < y)) { ... } else { ... } where the then branch is empty
+    assert_eq!(
+        run_pass(
+            "push local 0\npush local 1\nlt\nnot\nif-goto L0\ngoto L1\nlabel L0\npush constant 1\npop local 0\nlabel L1",
+            pass
+        )
+        .0,
+        "push local 0\npush local 1\nlt\nif-goto L1\npush constant 1\npop local 0\nlabel L1"
+    );
+    // the same with a condition that isn't a comparison: `not` is bitwise, so it stays
+    let source =
+        "push local 0\nnot\nif-goto L0\ngoto L1\nlabel L0\npush constant 1\npop local 0\nlabel L1";
+    assert_eq!(run_pass(source, pass), (source.to_string(), 0));
+    // while (true) { ... }, as emitted by the compiler
+    assert_eq!(
+        run_pass(
+            "label L1\npush constant 0\nnot\nnot\nif-goto L0\ncall Main.f 0\npop temp 0\ngoto L1\nlabel L0\npush constant 0\nreturn",
+            all
+        )
+        .0,
+        "label L1\ncall Main.f 0\npop temp 0\ngoto L1"
+    );
+    // if (false) { ... }
+    assert_eq!(
+        run_pass(
+            "push constant 0\nnot\nif-goto L0\npush constant 1\npop static 0\nlabel L0\npush constant 0\nreturn",
+            pass
+        )
+        .0,
+        "push constant 0\nreturn"
+    );
+}
+
+#[test]
+fn test_non_boolean_condition() {
+    // if (~c) { static 0 = 1 } with c = 5: ~5 is -6, which is true as well
+    let source = "push constant 5\npop temp 0\npush temp 0\nnot\nif-goto L0\ngoto L1\nlabel L0\npush constant 1\npop static 0\nlabel L1";
+    let module = VmModule::parse("Main", source).unwrap();
+    let mut optimized = module.clone();
+    optimize_modules(std::slice::from_mut(&mut optimized), &VmPasses::default());
+
+    for module in [module, optimized] {
+        let mut vm = VmInterpreter::new(VmProgram::link(&[module]));
+        vm.bootstrap();
+        vm.run_until(100);
+        assert!(vm.halted);
+        assert_eq!(vm.ram[16], 1, "{:?}", vm.program);
+    }
+}
+
+#[test]
+fn test_ch_11_programs() {
+    let programs = [
+        "./test_files/ch 11/Seven/",
+        "./test_files/ch 11/ConvertToBin/",
+        "./test_files/ch 11/Square/",
+        "./test_files/ch 11/Average/",
+        "./test_files/ch 11/Pong/",
+        "./test_files/ch 11/ComplexArrays/",
+    ];
+
+    for path in programs {
+        let mut modules = load_program(path);
+        let stats = optimize_modules(&mut modules, &VmPasses::default());
+
+        assert!(stats.after < stats.before, "{path}: {stats:?}");
+        assert!(
+            stats.constant_folding > 0 && stats.branches > 0,
+            "{path}: {stats:?}"
+        );
+
+        // toggling every pass off leaves the program untouched
+        let mut unchanged = load_program(path);
+        let none = VmPasses {
+            constant_folding: false,
+            dead_stores: false,
+            push_pop: false,
+            branches: false,
+        };
+        let stats = optimize_modules(&mut unchanged, &none);
+        assert_eq!(stats.before, stats.after);
+        assert_eq!(unchanged, load_program(path));
+    }
+}
+
+/// Runs until `Sys.halt` is reached
+fn run_to_halt(modules: &[VmModule]) -> VmInterpreter {
+    let mut vm = VmInterpreter::new(VmProgram::link(modules));
+    vm.bootstrap();
+    while vm.current_function() != "Sys.halt" {
+        vm.run_until(vm.time + 10_000);
+        assert!(vm.time < 50_000_000, "program did not halt");
+    }
+    vm
+}
+
+#[test]
+fn test_optimized_programs_equivalent() {
+    for path in [
+        "./test_files/ch 11/Seven/",
+        "./test_files/ch 11/ComplexArrays/",
+    ] {
+        let modules = load_program(path);
+        let mut optimized = modules.clone();
+        optimize_modules(&mut optimized, &VmPasses::default());
+
+        let expected = run_to_halt(&modules);
+        let got = run_to_halt(&optimized);
+
+        assert!(
+            got.time < expected.time,
+            "{path}: optimized program is not faster"
+        );
+        assert_eq!(
+            expected.ram[SCREEN_START..SCREEN_END],
+            got.ram[SCREEN_START..SCREEN_END],
+            "{path}: output differs"
+        );
+        assert_eq!(
+            expected.ram[16..256],
+            got.ram[16..256],
+            "{path}: statics differ"
+        );
+    }
+}
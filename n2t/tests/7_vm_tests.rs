@@ -1,10 +1,16 @@
-use std::path::{Path, PathBuf};
-
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 use n2t::{
     hardware::native::cpu::Computer,
-    software::{assembler::asm_to_hack, vm::vm_to_asm},
-    utils::{hack_to_vec, u16_from_i16},
+    software::{
+        assembler::assemble,
+        vm::{translate, vm_to_asm},
+    },
+    test_script::runner::run_script,
+    utils::u16_from_i16,
 };
 
 pub fn test_data_path(file_path: &str) -> PathBuf {
@@ -16,13 +22,50 @@ pub fn test_data_path(file_path: &str) -> PathBuf {
     }
 }
 
-fn get_computer(file_path: &str) -> Computer {
+/// Copies the program with its scripts and .cmp file to a temporary directory and translates it there, then runs the
+/// official CPU emulator script on the .asm and the VM emulator script on the .vm code, both checked against the same
+/// .cmp file
+fn run_scripts(file_path: &str) {
     let path = test_data_path(file_path);
-    let asm = vm_to_asm(&path);
-    let machine = asm_to_hack(&asm);
-    let program = hack_to_vec(&machine);
+    let (dir, name) = if path.is_file() {
+        (path.parent().unwrap(), path.file_stem().unwrap())
+    } else {
+        (path.as_path(), path.file_name().unwrap())
+    };
+    let name = name.to_str().unwrap();
+
+    // the .asm is named after the directory for multi-file programs, so the copy keeps the name
+    let work_dir = std::env::temp_dir()
+        .join(format!("n2t_vm_{}", std::process::id()))
+        .join(name);
+    fs::create_dir_all(&work_dir).unwrap();
+    let mut files = vec![
+        format!("{name}.tst"),
+        format!("{name}VME.tst"),
+        format!("{name}.cmp"),
+    ];
+    let program = if path.is_file() {
+        files.push(format!("{name}.vm"));
+        work_dir.join(format!("{name}.vm"))
+    } else {
+        for entry in fs::read_dir(dir).unwrap() {
+            let entry = entry.unwrap().path();
+            if entry.extension().is_some_and(|e| e == "vm") {
+                files.push(entry.file_name().unwrap().to_str().unwrap().to_string());
+            }
+        }
+        work_dir.clone()
+    };
+    for file in &files {
+        fs::copy(dir.join(file), work_dir.join(file)).unwrap();
+    }
 
-    Computer::new(program)
+    vm_to_asm(&program);
+    for script in [format!("{name}.tst"), format!("{name}VME.tst")] {
+        if let Err(e) = run_script(&work_dir.join(&script), &work_dir) {
+            panic!("{script}: {e}");
+        }
+    }
 }
 
 // ------------------------------------------------------------------------------------------------------------------ //
@@ -31,110 +74,78 @@ fn get_computer(file_path: &str) -> Computer {
 
 #[test]
 fn test_simpleadd() {
-    let mut cpu = get_computer(r"./test_files/ch 7/SimpleAdd.vm");
-
-    // forcing initialized pointers to match official software test conditions
-    cpu.ram[0] = 256; // stack pointer
-    cpu.ram[1] = 300; // "local" pointer
-    cpu.ram[2] = 400; // "argument" pointer
-    cpu.ram[3] = 3000; // "this" pointer
-    cpu.ram[4] = 3010; // "that" pointer
-    cpu.ram[16] = 3; // "pointer" pointer
-
-    cpu.pc = 53;
-    cpu.run_until(60, false, false);
-
-
-    assert_eq!(
-        cpu.ram[0], 257,
-        "Stack pointer pointing to incorrect memory location"
-    );
-    assert_eq!(cpu.ram[256], 15, "Incorrect calcultion of '7 + 8'");
+    run_scripts("./test_files/ch 7/SimpleAdd.vm");
 }
 
 #[test]
 fn test_stacktest() {
-    let mut cpu = get_computer(r#"./test_files/ch 7/StackTest.vm"#);
-    // forcing initialized pointers to match official software test conditions
-    cpu.ram[0] = 256; // stack pointer
-    cpu.ram[1] = 300; // "local" pointer
-    cpu.ram[2] = 400; // "argument" pointer
-    cpu.ram[3] = 3000; // "this" pointer
-    cpu.ram[4] = 3010; // "that" pointer
-    cpu.ram[16] = 3; // "pointer" pointer
-
-    cpu.pc = 53; // skip over bootstrapping code
-    cpu.run_until(1000, false, false);
-
-    assert_eq!(
-        cpu.ram[0], 266,
-        "Stack pointer pointing to incorrect memory location"
-    );
-    assert_eq!(
-        i16::from_ne_bytes(cpu.ram[265].to_ne_bytes()),
-        !((28 & 57) | 82)
-    );
+    run_scripts("./test_files/ch 7/StackTest.vm");
 }
 
 #[test]
 fn test_basictest() {
-    let mut cpu = get_computer("./test_files/ch 7/BasicTest.vm");
-    // forcing initialized pointers to match official software test conditions
-    cpu.ram[0] = 256; // stack pointer
-    cpu.ram[1] = 300; // "local" pointer
-    cpu.ram[2] = 400; // "argument" pointer
-    cpu.ram[3] = 3000; // "this" pointer
-    cpu.ram[4] = 3010; // "that" pointer
-    cpu.ram[16] = 3; // "pointer" pointer
-
-    cpu.pc = 53; // skip over bootstrapping code
-    cpu.run_until(600, false, false);
-
-    assert_eq!(cpu.ram[(cpu.ram[0] - 1) as usize], 472);
-    assert_eq!(cpu.ram[300], 10);
-    assert_eq!(cpu.ram[401], 21);
-    assert_eq!(cpu.ram[402], 22);
-    assert_eq!(cpu.ram[3006], 36);
-    assert_eq!(cpu.ram[3012], 42);
-    assert_eq!(cpu.ram[3015], 45);
-    assert_eq!(cpu.ram[11], 510);
+    run_scripts("./test_files/ch 7/BasicTest.vm");
 }
 
 #[test]
 fn test_pointertest() {
-    let mut cpu = get_computer("./test_files/ch 7/PointerTest.vm");
-    // forcing initialized pointers to match official software test conditions
-    cpu.ram[0] = 256; // stack pointer
-    cpu.ram[1] = 300; // "local" pointer
-    cpu.ram[2] = 400; // "argument" pointer
-    cpu.ram[3] = 3000; // "this" pointer
-    cpu.ram[4] = 3010; // "that" pointer
-
-    cpu.pc = 53; // skip over bootstrapping code
-    cpu.run_until(450, false, false);
-
-    assert_eq!(cpu.ram[(cpu.ram[0] - 1) as usize], 6084);
-    assert_eq!(cpu.ram[3], 3030);
-    assert_eq!(cpu.ram[4], 3040);
-    assert_eq!(cpu.ram[3032], 32);
-    assert_eq!(cpu.ram[3046], 46);
+    run_scripts("./test_files/ch 7/PointerTest.vm");
 }
 
 #[test]
 fn test_statictest() {
-    let mut cpu = get_computer("./test_files/ch 7/StaticTest.vm");
-    // forcing initialized pointers to match official software test conditions
-    cpu.ram[0] = 256; // stack pointer
-    cpu.ram[1] = 300; // "local" pointer
-    cpu.ram[2] = 400; // "argument" pointer
-    cpu.ram[3] = 3000; // "this" pointer
-    cpu.ram[4] = 3010; // "that" pointer
-    cpu.ram[16] = 3; // "pointer" pointer
+    run_scripts("./test_files/ch 7/StaticTest.vm");
+}
 
-    cpu.pc = 53; // skip over bootstrapping code
-    cpu.run_until(200, false, false);
+/// Pushes `val` using only non-negative constants
+fn push_i16(vm: &mut String, val: i16) {
+    match val {
+        i16::MIN => vm.push_str("push constant 32767\nneg\npush constant 1\nsub\n"),
+        v if v < 0 => vm.push_str(&format!("push constant {}\nneg\n", -v)),
+        v => vm.push_str(&format!("push constant {v}\n")),
+    }
+}
+
+#[test]
+fn test_comparison_overflow() {
+    let values = [i16::MIN, -32767, -1, 0, 1, 32766, i16::MAX];
+
+    let mut vm = String::new();
+    let mut expected = Vec::new();
+    for &x in &values {
+        for &y in &values {
+            for (op, result) in [("eq", x == y), ("lt", x < y), ("gt", x > y)] {
+                push_i16(&mut vm, x);
+                push_i16(&mut vm, y);
+                vm.push_str(op);
+                vm.push('\n');
+                expected.push((x, y, op, if result { -1 } else { 0 }));
+            }
+        }
+    }
 
-    assert_eq!(cpu.ram[256], 1110);
+    // the process id keeps parallel runs of the tests from writing the same file
+    let path = std::env::temp_dir().join(format!("ComparisonOverflow_{}.vm", std::process::id()));
+    std::fs::write(&path, vm).unwrap();
+    let asm = translate(&path, false);
+    std::fs::remove_file(&path).unwrap();
+
+    let program = assemble(asm.as_bytes())
+        .lines()
+        .map(|l| u16::from_str_radix(l, 2).unwrap())
+        .collect();
+    let mut cpu = Computer::new(program);
+    cpu.ram[0] = 256;
+    cpu.run_until(100_000, false, false);
+
+    assert_eq!(cpu.ram[0] as usize, 256 + expected.len());
+    for (i, (x, y, op, result)) in expected.into_iter().enumerate() {
+        assert_eq!(
+            cpu.ram[256 + i],
+            u16_from_i16(result),
+            "'{op}' of {x} and {y} produced the wrong result"
+        );
+    }
 }
 
 // ------------------------------------------------------------------------------------------------------------------ //
@@ -143,133 +154,30 @@ fn test_statictest() {
 
 #[test]
 fn test_basicloop() {
-    let mut cpu = get_computer("./test_files/ch 8/ProgramFlow/BasicLoop/BasicLoop.vm");
-    // forcing initialized pointers to match official software test conditions
-    cpu.ram[0] = 256; // stack pointer
-    cpu.ram[1] = 300; // "local" pointer
-    cpu.ram[2] = 400; // "argument" pointer
-    cpu.ram[400] = 3; // argument initial val
-
-    cpu.pc = 53; // skip over bootstrapping code
-    cpu.run_until(600, false, false);
-
-    assert_eq!(cpu.ram[0], 257);
-    assert_eq!(cpu.ram[256], 6);
+    run_scripts("./test_files/ch 8/ProgramFlow/BasicLoop/BasicLoop.vm");
 }
 
 #[test]
 fn test_fibseries() {
-    let mut cpu = get_computer("./test_files/ch 8/ProgramFlow/FibonacciSeries/FibonacciSeries.vm");
-
-    // force initialized poitners to match official software test conditions
-    cpu.ram[0] = 256; // stack pointer
-    cpu.ram[1] = 300;
-    cpu.ram[2] = 400;
-    cpu.ram[400] = 6;
-    cpu.ram[401] = 3000;
-
-    cpu.pc = 53; // skip over bootstrapping code
-    cpu.run_until(1100, false, false);
-
-    assert_eq!(cpu.ram[3000..=3005], [0, 1, 1, 2, 3, 5])
+    run_scripts("./test_files/ch 8/ProgramFlow/FibonacciSeries/FibonacciSeries.vm");
 }
 
 #[test]
 fn test_simplefunction() {
-    let mut cpu = get_computer("./test_files/ch 8/FunctionCalls/SimpleFunction/SimpleFunction.vm");
-
-    // let mut cpu = Computer::new(program);
-
-    cpu.ram[0] = 317;
-    cpu.ram[1] = 317;
-    cpu.ram[2] = 310;
-    cpu.ram[3] = 3000;
-    cpu.ram[4] = 4000;
-    cpu.ram[310] = 1234;
-    cpu.ram[311] = 37;
-    cpu.ram[312] = 1000;
-    cpu.ram[313] = 305;
-    cpu.ram[314] = 300;
-    cpu.ram[315] = 3010;
-    cpu.ram[316] = 4010;
-
-    cpu.pc = 53; // skip over bootstrapping code
-    cpu.run_until(300, false, false);
-
-    assert_eq!(cpu.ram[0..=4], [311, 305, 300, 3010, 4010]);
-    assert_eq!(cpu.ram[310], 1196);
+    run_scripts("./test_files/ch 8/FunctionCalls/SimpleFunction/SimpleFunction.vm");
 }
 
 #[test]
 fn test_nestedcall() {
-    let mut cpu = get_computer("./test_files/ch 8/FunctionCalls/NestedCall/");
-
-    cpu.ram[0] = 261;
-    cpu.ram[1] = 261;
-    cpu.ram[2] = 256;
-    cpu.ram[3] = u16_from_i16(-3);
-    cpu.ram[4] = u16_from_i16(-4);
-    cpu.ram[5] = u16_from_i16(-1); // test results
-    cpu.ram[6] = u16_from_i16(-1);
-    cpu.ram[256] = 1234; // fake stack frame from call Sys.init
-    cpu.ram[257] = u16_from_i16(-1);
-    cpu.ram[258] = u16_from_i16(-2);
-    cpu.ram[259] = u16_from_i16(-3);
-    cpu.ram[260] = u16_from_i16(-4);
-
-    cpu.ram[261] = u16_from_i16(-1); // Initialize stack to check for local segment
-    cpu.ram[262] = u16_from_i16(-1); // being cleared to zero.
-    cpu.ram[263] = u16_from_i16(-1);
-    cpu.ram[264] = u16_from_i16(-1);
-    cpu.ram[265] = u16_from_i16(-1);
-    cpu.ram[266] = u16_from_i16(-1);
-    cpu.ram[267] = u16_from_i16(-1);
-    cpu.ram[268] = u16_from_i16(-1);
-    cpu.ram[269] = u16_from_i16(-1);
-    cpu.ram[270] = u16_from_i16(-1);
-    cpu.ram[271] = u16_from_i16(-1);
-    cpu.ram[272] = u16_from_i16(-1);
-    cpu.ram[273] = u16_from_i16(-1);
-    cpu.ram[274] = u16_from_i16(-1);
-    cpu.ram[275] = u16_from_i16(-1);
-    cpu.ram[276] = u16_from_i16(-1);
-    cpu.ram[277] = u16_from_i16(-1);
-    cpu.ram[278] = u16_from_i16(-1);
-    cpu.ram[279] = u16_from_i16(-1);
-    cpu.ram[280] = u16_from_i16(-1);
-    cpu.ram[281] = u16_from_i16(-1);
-    cpu.ram[282] = u16_from_i16(-1);
-    cpu.ram[283] = u16_from_i16(-1);
-    cpu.ram[284] = u16_from_i16(-1);
-    cpu.ram[285] = u16_from_i16(-1);
-    cpu.ram[286] = u16_from_i16(-1);
-    cpu.ram[287] = u16_from_i16(-1);
-    cpu.ram[288] = u16_from_i16(-1);
-    cpu.ram[289] = u16_from_i16(-1);
-    cpu.ram[290] = u16_from_i16(-1);
-    cpu.ram[291] = u16_from_i16(-1);
-    cpu.ram[292] = u16_from_i16(-1);
-    cpu.ram[293] = u16_from_i16(-1);
-    cpu.ram[294] = u16_from_i16(-1);
-    cpu.ram[295] = u16_from_i16(-1);
-    cpu.ram[296] = u16_from_i16(-1);
-    cpu.ram[297] = u16_from_i16(-1);
-    cpu.ram[298] = u16_from_i16(-1);
-    cpu.ram[299] = u16_from_i16(-1);
-
-    // cpu.pc = 53; // skip over bootstrapping code
-    cpu.run_until(4000, false, false);
-
-
-    assert_eq!(cpu.ram[0..=6], [261, 261, 256, 4000, 5000, 135, 246])
+    run_scripts("./test_files/ch 8/FunctionCalls/NestedCall/");
 }
 
 #[test]
 fn test_fibelement() {
-    let mut cpu = get_computer("./test_files/ch 8/FunctionCalls/FibonacciElement/");
-
-    cpu.run_until(6000, false, false);
+    run_scripts("./test_files/ch 8/FunctionCalls/FibonacciElement/");
+}
 
-    assert_eq!(cpu.ram[0], 262);
-    assert_eq!(cpu.ram[(cpu.ram[0] - 1) as usize], 3);
+#[test]
+fn test_staticstest() {
+    run_scripts("./test_files/ch 8/FunctionCalls/StaticsTest/");
 }
@@ -0,0 +1,386 @@
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use n2t::software::{
+    compiler_utils::{Dialect, Keyword, Token},
+    diagnostics::Span,
+    jack_ast::{BinaryOp, Statement, TermKind, UnaryOp},
+    jack_optimizer::inline_consts,
+    jack_parser::{parse, parse_dialect},
+    tokenizer::JackTokenizer,
+};
+
+pub fn test_data_path(file_path: &str) -> PathBuf {
+    match std::env::var("ENV_ROOT_DIR") {
+        Ok(path) => Path::new(&path).join(file_path),
+        Err(_) => Path::new(&std::env::current_dir().unwrap())
+            .join("../")
+            .join(file_path),
+    }
+}
+
+/// Copies the .jack files in `dir` to a temporary directory, so the .xml files written next to them don't end up in
+/// the test files
+fn temp_copy(dir: &str) -> PathBuf {
+    let source = test_data_path(dir);
+    let name = source.file_name().unwrap().to_str().unwrap();
+    let copy = std::env::temp_dir().join(format!("n2t_{name}_{}", std::process::id()));
+    fs::create_dir_all(&copy).unwrap();
+    for entry in fs::read_dir(&source).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|e| e == "jack") {
+            fs::copy(&path, copy.join(path.file_name().unwrap())).unwrap();
+        }
+    }
+    copy
+}
+
+const SOURCE: &str = "// header
+class Main {
+    static int count;
+
+    /** entry point */
+    function void main(int a, Array b) {
+        var int x, y;
+        let x = -a + (b[1] * 2);
+        if (~(x < 3)) { do Output.printInt(x, \"hi\"); } else { let y = x; }
+        return;
+    }
+}
+";
+
+#[test]
+fn test_parse() {
+    let class = parse("Main.jack", SOURCE).unwrap();
+
+    assert_eq!(class.name.name, "Main");
+    assert_eq!(
+        &SOURCE[class.span.start..class.span.end],
+        &SOURCE[10..SOURCE.len() - 1]
+    );
+
+    assert_eq!(class.vars.len(), 1);
+    assert_eq!(class.vars[0].kind, Keyword::Static);
+    assert_eq!(class.vars[0].dtype, Token::Keyword(Keyword::Int));
+
+    let main = &class.subroutines[0];
+    assert_eq!(main.kind, Keyword::Function);
+    assert_eq!(main.return_type, Token::Keyword(Keyword::Void));
+    assert_eq!(main.params.len(), 2);
+    assert_eq!(main.params[1].dtype, Token::Identifier("Array".to_string()));
+    assert_eq!(main.locals[0].names.len(), 2);
+    assert_eq!(main.statements.len(), 3);
+
+    let Statement::Let { target, value, .. } = &main.statements[0] else {
+        panic!("expected let, got {:?}", main.statements[0]);
+    };
+    assert_eq!(target.name, "x");
+    let span = |s: Span| &SOURCE[s.start..s.end];
+    assert_eq!(span(main.statements[0].span()), "let x = -a + (b[1] * 2);");
+    assert_eq!(span(value.span), "-a + (b[1] * 2)");
+    assert!(matches!(value.first.kind, TermKind::Unary(UnaryOp::Neg, _)));
+    assert_eq!(value.rest.len(), 1);
+    assert_eq!(value.rest[0].0, BinaryOp::Add);
+    assert_eq!(span(value.rest[0].1.span), "(b[1] * 2)");
+
+    let Statement::If {
+        cond,
+        then_branch,
+        else_branch,
+        ..
+    } = &main.statements[1]
+    else {
+        panic!("expected if, got {:?}", main.statements[1]);
+    };
+    assert_eq!(span(cond.span), "~(x < 3)");
+    let Statement::Do { call, .. } = &then_branch[0] else {
+        panic!("expected do");
+    };
+    assert_eq!(call.receiver.as_ref().unwrap().name, "Output");
+    assert_eq!(call.args.len(), 2);
+    assert_eq!(span(call.span), "Output.printInt(x, \"hi\")");
+    assert!(matches!(&call.args[1].first.kind, TermKind::StringConst(s) if s == "hi"));
+    assert_eq!(else_branch.as_ref().unwrap().len(), 1);
+
+    assert!(matches!(
+        main.statements[2],
+        Statement::Return { value: None, .. }
+    ));
+}
+
+#[test]
+fn test_syntax_errors() {
+    let source = "class Main {
+    function void main() {
+        var int x
+        let x = 3
+        let y = (x + 2;
+        do Output.printInt(x);
+        else { }
+        return;
+    }
+}
+";
+    let errors = parse("Main.jack", source).unwrap_err();
+
+    assert_eq!(
+        errors.iter().map(|e| e.summary()).collect::<Vec<_>>(),
+        [
+            "Main.jack:3:18: expected ';' after variable declaration",
+            "Main.jack:4:18: expected ';' after let statement",
+            "Main.jack:5:23: expected ')' to close parenthesized expression",
+            "Main.jack:7:9: 'else' without a matching 'if'",
+        ]
+    );
+    assert_eq!(
+        errors[1].to_string(),
+        "Main.jack:4:18: expected ';' after let statement\n            let x = 3\n                     ^"
+    );
+}
+
+#[test]
+fn test_error_recovery() {
+    let source = "class Main {
+    field int x;
+    method void a() {
+        let x = 1 +;
+        let x = x * 2
+        return;
+    }
+    junk
+    function int b(int n {
+        while (n > 0) { let n = n - ; }
+        return n;
+    }
+    static int late;
+    function void c() {
+        do Output.printInt(1)
+    }
+}
+";
+    let errors = parse("Main.jack", source).unwrap_err();
+
+    assert_eq!(
+        errors.iter().map(|e| e.summary()).collect::<Vec<_>>(),
+        [
+            "Main.jack:4:20: expected expression, got ';'",
+            "Main.jack:5:22: expected ';' after let statement",
+            "Main.jack:8:5: expected class variable or subroutine declaration, got 'junk'",
+            "Main.jack:9:25: expected ')' after parameter list",
+            "Main.jack:10:37: expected expression, got ';'",
+            "Main.jack:13:5: class variables must be declared before subroutines",
+            "Main.jack:15:30: expected ';' after do statement",
+        ]
+    );
+}
+
+#[test]
+fn test_lexer_errors_are_reported() {
+    let source = "class Main {\n    function int f() {\n        return 40000;\n    }\n}\n";
+    let errors = parse("Main.jack", source).unwrap_err();
+
+    assert_eq!(
+        errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+        ["Main.jack:3:16: integer constant 40000 is out of range 0..32767\n            return 40000;\n                   ^"]
+    );
+}
+
+#[test]
+fn test_unexpected_end_of_file() {
+    let errors = parse(
+        "Main.jack",
+        "class Main {\n    function void main() {\n        return",
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        errors.iter().map(|e| e.summary()).collect::<Vec<_>>(),
+        [
+            "Main.jack:3:15: expected expression, got end of file",
+            "Main.jack:3:15: expected '}' to close subroutine body",
+            "Main.jack:3:15: expected '}' to close class, got end of file",
+        ]
+    );
+}
+
+#[test]
+fn test_extended_dialect() {
+    // the extended keywords are ordinary identifiers in standard Jack
+    let source = "class Main {
+    function void main() {
+        var int for, break;
+        let for = 1;
+        let break = for;
+        return;
+    }
+}
+";
+    assert!(parse("Main.jack", source).is_ok());
+    assert_eq!(
+        parse_dialect("Main.jack", source, Dialect::Extended).unwrap_err()[0].summary(),
+        "Main.jack:3:17: expected variable name, got 'for'"
+    );
+
+    let source = "class Main {
+    function void main() {
+        var int i;
+        for (let i = 0; i < 3; let i = i + 1) {
+            while (true) { continue; }
+            break;
+        }
+        for (;;) { }
+        break;
+        for (let i = 0; i < 3 ) { }
+        return;
+    }
+}
+";
+    assert_eq!(
+        parse("Main.jack", source).unwrap_err()[0].summary(),
+        "Main.jack:4:9: expected statement, got 'for'"
+    );
+
+    let errors = parse_dialect("Main.jack", source, Dialect::Extended).unwrap_err();
+    assert_eq!(
+        errors.iter().map(|e| e.summary()).collect::<Vec<_>>(),
+        [
+            "Main.jack:9:9: 'break' outside of a loop",
+            "Main.jack:10:30: expected ';' after for loop condition",
+        ]
+    );
+
+    let source = source.replace("{ }\n        break;\n", "{ }\n");
+    let source = source.replace("i < 3 )", "; do Main.f())");
+    let class = parse_dialect("Main.jack", &source, Dialect::Extended).unwrap();
+    let Statement::For {
+        init: Some(init),
+        cond: Some(_),
+        step: Some(step),
+        body,
+        span,
+    } = &class.subroutines[0].statements[0]
+    else {
+        panic!("expected a for loop");
+    };
+    assert!(matches!(**init, Statement::Let { .. }));
+    assert!(matches!(**step, Statement::Let { .. }));
+    assert!(matches!(
+        body[..],
+        [Statement::While { .. }, Statement::Break { .. }]
+    ));
+    assert!(source[span.start..span.end].starts_with("for (let i = 0;"));
+    assert!(matches!(
+        &class.subroutines[0].statements[2],
+        Statement::For {
+            init: Some(_),
+            cond: None,
+            step: Some(step),
+            ..
+        } if matches!(**step, Statement::Do { .. })
+    ));
+}
+
+#[test]
+fn test_consts() {
+    let source = "class Main {
+    const int LEFT = 130, RIGHT = LEFT + 2;
+    const char SPACE = ' ';
+    const boolean DEBUG = false;
+    const int MASK = -(1 + 1) * 8 | ~DEBUG, LAST = 'z' - 'a';
+
+    function void main() {
+        var int SPACE;
+        let SPACE = LEFT[RIGHT];
+        let LEFT = MASK;
+        return;
+    }
+}
+";
+    let mut class = parse_dialect("Main.jack", source, Dialect::Extended).unwrap();
+    assert_eq!(
+        class
+            .consts
+            .iter()
+            .map(|c| (c.name.name.as_str(), c.value))
+            .collect::<Vec<_>>(),
+        [
+            ("LEFT", 130),
+            ("RIGHT", 132),
+            ("SPACE", 32),
+            ("DEBUG", 0),
+            ("MASK", -1),
+            ("LAST", 25),
+        ]
+    );
+    assert_eq!(
+        &source[class.consts[1].span.start..class.consts[1].span.end],
+        "const int LEFT = 130, RIGHT = LEFT + 2;"
+    );
+
+    // the local SPACE shadows the constant
+    let errors: Vec<_> = inline_consts(&mut class)
+        .into_iter()
+        .map(|(span, message)| (&source[span.start..span.end], message))
+        .collect();
+    assert_eq!(
+        errors,
+        [
+            ("LEFT", "constant 'LEFT' cannot be indexed".to_string()),
+            ("LEFT", "cannot assign to constant 'LEFT'".to_string()),
+        ]
+    );
+    let Statement::Let { value, .. } = &class.subroutines[0].statements[1] else {
+        panic!("expected a let statement");
+    };
+    assert_eq!(value.first.kind, TermKind::IntConst(-1));
+
+    let source = "class Main {
+    static int x;
+    const int A = x + 1, B = 1 / 0, A = 2;
+    function void main() { return; }
+    const int C = 3;
+}
+";
+    assert_eq!(
+        parse_dialect("Main.jack", source, Dialect::Extended)
+            .unwrap_err()
+            .iter()
+            .map(|e| e.summary())
+            .collect::<Vec<_>>(),
+        [
+            "Main.jack:3:19: constant 'A' must have a constant value",
+            "Main.jack:3:30: constant 'B' must have a constant value",
+            "Main.jack:3:37: constant 'A' is already declared",
+            "Main.jack:5:5: class variables must be declared before subroutines",
+        ]
+    );
+}
+
+#[test]
+fn test_expressionless_square() {
+    let dir = "./test_files/ch 10/ExpressionLessSquare/";
+    let out_dir = temp_copy(dir);
+    for name in ["Main", "Square", "SquareGame"] {
+        JackTokenizer::compile(&out_dir.join(format!("{name}.jack")));
+
+        let mut output_text = String::new();
+        File::open(out_dir.join(format!("{name}.xml")))
+            .unwrap()
+            .read_to_string(&mut output_text)
+            .unwrap();
+        let mut example_text = String::new();
+        File::open(test_data_path(&format!("{dir}{name}Example.xml")))
+            .unwrap()
+            .read_to_string(&mut example_text)
+            .unwrap();
+
+        assert_eq!(
+            output_text.lines().collect::<Vec<_>>(),
+            example_text.lines().collect::<Vec<_>>(),
+            "{name}"
+        );
+    }
+}
@@ -1,13 +1,17 @@
 //! tests for chapter 10
 
 use std::{
-    fs::File,
+    fs::{self, File},
     io::Read,
     iter::zip,
     path::{Path, PathBuf},
 };
 
-use n2t::software::tokenizer::*;
+use n2t::software::{
+    compiler_utils::{Dialect, Keyword, Symbol, Token},
+    jack_lexer::Lexer,
+    tokenizer::*,
+};
 
 pub fn test_data_path(file_path: &str) -> PathBuf {
     match std::env::var("ENV_ROOT_DIR") {
@@ -18,6 +22,22 @@ pub fn test_data_path(file_path: &str) -> PathBuf {
     }
 }
 
+/// Copies the .jack files in `dir` to a temporary directory, so the .xml files written next to them don't end up in
+/// the test files
+fn temp_copy(dir: &str) -> PathBuf {
+    let source = test_data_path(dir);
+    let name = source.file_name().unwrap().to_str().unwrap();
+    let copy = std::env::temp_dir().join(format!("n2t_{name}_{}", std::process::id()));
+    fs::create_dir_all(&copy).unwrap();
+    for entry in fs::read_dir(&source).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|e| e == "jack") {
+            fs::copy(&path, copy.join(path.file_name().unwrap())).unwrap();
+        }
+    }
+    copy
+}
+
 #[test]
 fn test_square() {
     let paths = [
@@ -96,3 +116,171 @@ fn test_array() {
         }
     }
 }
+
+#[test]
+fn test_tokens() {
+    for (dir, names) in [
+        ("./test_files/ch 10/ArrayTest/", &["Main"][..]),
+        (
+            "./test_files/ch 10/Square/",
+            &["Main", "Square", "SquareGame"],
+        ),
+        (
+            "./test_files/ch 10/ExpressionLessSquare/",
+            &["Main", "Square", "SquareGame"],
+        ),
+    ] {
+        let out_dir = temp_copy(dir);
+        JackTokenizer::tokenize(&out_dir);
+
+        for name in names {
+            let mut output_text = String::new();
+            File::open(out_dir.join(format!("{name}T.xml")))
+                .unwrap()
+                .read_to_string(&mut output_text)
+                .unwrap();
+            let mut example_text = String::new();
+            File::open(test_data_path(&format!("{dir}{name}TExample.xml")))
+                .unwrap()
+                .read_to_string(&mut example_text)
+                .unwrap();
+
+            assert_eq!(
+                output_text.lines().collect::<Vec<_>>(),
+                example_text.lines().collect::<Vec<_>>(),
+                "{dir}{name}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_lexer() {
+    let source = "/** api */ let s = \"a // b\";// trailing\n/* block\n */x[10]-arg";
+    let tokens: Vec<_> = Lexer::new(source)
+        .map(|t| (t.token, &source[t.span.start..t.span.end]))
+        .collect();
+
+    assert_eq!(
+        tokens,
+        [
+            (Token::Keyword(Keyword::Let), "let"),
+            (Token::Identifier("s".to_string()), "s"),
+            (Token::Symbol(Symbol::Equals), "="),
+            (Token::ConstString("a // b".to_string()), "\"a // b\""),
+            (Token::Symbol(Symbol::SemiColon), ";"),
+            (Token::Identifier("x".to_string()), "x"),
+            (Token::Symbol(Symbol::BraceOp), "["),
+            (Token::ConstInt(10), "10"),
+            (Token::Symbol(Symbol::BraceCl), "]"),
+            (Token::Symbol(Symbol::Minus), "-"),
+            (Token::Identifier("arg".to_string()), "arg"),
+        ]
+    );
+}
+
+#[test]
+fn test_lexer_errors() {
+    let source = "let x = 32767 + 32768 + 12ab;\nlet s = \"no end\nlet t = 1 # 2;\n/* open";
+    let mut lexer = Lexer::new(source);
+    let tokens: Vec<_> = lexer.by_ref().map(|t| t.token).collect();
+    let errors: Vec<_> = lexer
+        .errors
+        .iter()
+        .map(|e| (&source[e.span.start..e.span.end], e.message.as_str()))
+        .collect();
+
+    assert_eq!(
+        errors,
+        [
+            ("32768", "integer constant 32768 is out of range 0..32767"),
+            (
+                "12ab",
+                "invalid integer constant '12ab', identifiers cannot start with a digit"
+            ),
+            (
+                "",
+                "newline in string constant, strings must close on the line they start"
+            ),
+            ("#", "unexpected character '#'"),
+            ("/*", "unterminated block comment"),
+        ]
+    );
+    assert_eq!(tokens[3], Token::ConstInt(32767));
+    // lexing resumes on the line after the broken string
+    assert_eq!(tokens[12], Token::ConstString("no end".to_string()));
+    assert_eq!(tokens[13], Token::Keyword(Keyword::Let));
+    assert_eq!(tokens.len(), 19);
+
+    let mut lexer = Lexer::new("do f(\"oops);");
+    assert_eq!(
+        lexer.by_ref().last().unwrap().token,
+        Token::ConstString("oops);".to_string())
+    );
+    assert_eq!(lexer.errors[0].message, "unterminated string constant");
+    assert_eq!(lexer.errors[0].span.start, 5);
+}
+
+#[test]
+fn test_extended_lexer() {
+    let source = r#"const char A = 'a', Q = '\'', N = '\n'; let s = "say \"hi\"\n\\"; for"#;
+    let tokens = |dialect| {
+        Lexer::with_dialect(source, dialect)
+            .map(|t| t.token)
+            .collect::<Vec<_>>()
+    };
+
+    let extended = tokens(Dialect::Extended);
+    assert_eq!(extended[0], Token::Keyword(Keyword::Const));
+    assert_eq!(extended[4], Token::ConstInt(97));
+    assert_eq!(extended[8], Token::ConstInt(39));
+    assert_eq!(extended[12], Token::ConstInt(128));
+    assert_eq!(
+        extended[17],
+        Token::ConstString("say \"hi\"\u{80}\\".to_string())
+    );
+    assert_eq!(extended[19], Token::Keyword(Keyword::For));
+
+    // standard Jack has none of it: the quotes are stray characters and backslashes are just characters
+    let mut lexer = Lexer::new(source);
+    let standard: Vec<_> = lexer.by_ref().map(|t| t.token).collect();
+    assert_eq!(standard[0], Token::Identifier("const".to_string()));
+    assert_eq!(standard[4], Token::Identifier("a".to_string()));
+    assert!(lexer
+        .errors
+        .iter()
+        .all(|e| e.message.starts_with("unexpected character")));
+    assert_eq!(standard[16], Token::ConstString("say \\".to_string()));
+    assert_eq!(standard.last(), Some(&Token::Identifier("for".to_string())));
+
+    let source = "'' 'ab' '\\q' 'é' \"\\z\" '";
+    let mut lexer = Lexer::with_dialect(source, Dialect::Extended);
+    let tokens: Vec<_> = lexer.by_ref().map(|t| t.token).collect();
+    let errors: Vec<_> = lexer
+        .errors
+        .iter()
+        .map(|e| (&source[e.span.start..e.span.end], e.message.as_str()))
+        .collect();
+
+    assert_eq!(
+        errors,
+        [
+            ("''", "empty character literal"),
+            ("'ab'", "character literals hold a single character"),
+            ("\\q", "unknown escape sequence '\\q'"),
+            ("é", "character 'é' is not in the Jack character set"),
+            ("\\z", "unknown escape sequence '\\z'"),
+            ("'", "unterminated character literal"),
+        ]
+    );
+    assert_eq!(tokens[1], Token::ConstInt(97));
+    assert_eq!(
+        tokens[..3],
+        [
+            Token::ConstInt(0),
+            Token::ConstInt(97),
+            Token::ConstInt(113)
+        ]
+    );
+    assert_eq!(tokens[4], Token::ConstString("z".to_string()));
+}
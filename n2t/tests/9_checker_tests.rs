@@ -0,0 +1,129 @@
+use std::path::{Path, PathBuf};
+
+use n2t::software::jack_checker::{check, check_sources};
+
+pub fn test_data_path(file_path: &str) -> PathBuf {
+    match std::env::var("ENV_ROOT_DIR") {
+        Ok(path) => Path::new(&path).join(file_path),
+        Err(_) => Path::new(&std::env::current_dir().unwrap())
+            .join("../")
+            .join(file_path),
+    }
+}
+
+#[test]
+fn test_valid_programs() {
+    for path in [
+        "./test_files/ch 9/Average/",
+        "./test_files/ch 9/Fraction/",
+        "./test_files/ch 9/HelloWorld/",
+        "./test_files/ch 9/List/",
+        "./test_files/ch 9/Square/",
+        "./test_files/ch 10/ArrayTest/",
+        "./test_files/ch 10/Square/",
+        "./test_files/ch 11/Seven/",
+        "./test_files/ch 11/ConvertToBin/",
+        "./test_files/ch 11/Square/",
+        "./test_files/ch 11/Average/",
+        "./test_files/ch 11/Pong/",
+        "./test_files/ch 11/ComplexArrays/",
+        "./test_files/ch 12/",
+    ] {
+        let errors = check(&test_data_path(path));
+        assert!(
+            errors.is_empty(),
+            "{path}:\n{}",
+            errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+    }
+}
+
+#[test]
+fn test_errors() {
+    let main = "class Main {
+    field int size;
+
+    function void main() {
+        var Point p;
+        let p = Point.new(1);
+        let q = 3;
+        do p.move(1, 2, 3);
+        do Point.move(1, 2);
+        do p.origin();
+        do draw();
+        do Output.printInt(Main.half(4));
+        return 1;
+    }
+
+    function int half(int x) {
+        do Screen.fill();
+        if (x) { return; }
+        return x / 2 + size + this;
+    }
+
+    method void draw() {
+        var int n;
+        do n.print();
+        do Foo.bar();
+        let n = Output.println();
+        return;
+    }
+}
+";
+    let point = "class Point {
+    constructor Point new(int x, int y) { return this; }
+    function Point origin() { return Point.new(0, 0); }
+    method void move(int dx, int dy) { return; }
+}
+";
+    let errors: Vec<String> = check_sources(&[("Main", main), ("Point", point)])
+        .iter()
+        .map(|e| e.summary())
+        .collect();
+
+    assert_eq!(
+        errors,
+        [
+            "Main.jack:6:17: 'Point.new' expects 2 arguments, got 1",
+            "Main.jack:7:13: undeclared variable 'q'",
+            "Main.jack:8:12: 'Point.move' expects 2 arguments, got 3",
+            "Main.jack:9:18: method 'Point.move' called as a function",
+            "Main.jack:10:14: function 'Point.origin' called as a method, use 'Point.origin(...)'",
+            "Main.jack:11:12: method 'Main.draw' called from a function without an object",
+            "Main.jack:13:16: void subroutine 'Main.main' cannot return a value",
+            "Main.jack:17:19: class 'Screen' has no subroutine 'fill'",
+            "Main.jack:18:18: subroutine 'Main.half' must return a value",
+            "Main.jack:19:24: field 'size' cannot be used in a function",
+            "Main.jack:19:31: 'this' cannot be used in a function",
+            "Main.jack:24:12: cannot call 'print' on 'n' of type 'int'",
+            "Main.jack:25:12: undeclared class or variable 'Foo'",
+            "Main.jack:26:17: 'Output.println' returns void and cannot be used in an expression",
+        ]
+    );
+
+    let dup = "class Dup {\n    static int a, a;\n    function void f() { return; }\n    function void f() { return; }\n}";
+    let errors: Vec<String> = check_sources(&[("Other", dup)])
+        .iter()
+        .map(|e| e.summary())
+        .collect();
+    assert_eq!(
+        errors,
+        [
+            "Other.jack:1:7: class 'Dup' must be declared in 'Dup.jack'",
+            "Other.jack:2:19: 'a' is already declared",
+            "Other.jack:4:19: subroutine 'f' is already declared",
+        ]
+    );
+
+    // syntax errors are reported and the class is skipped
+    let broken = "class Broken {\n    function void f() {\n        let x = ;\n    }\n}";
+    let errors: Vec<String> = check_sources(&[("Broken", broken), ("Point", point)])
+        .iter()
+        .map(|e| e.summary())
+        .collect();
+    assert_eq!(errors, ["Broken.jack:3:17: expected expression, got ';'"]);
+}
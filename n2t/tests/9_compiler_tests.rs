@@ -7,7 +7,17 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use n2t::software::compiler::JackCompiler;
+use n2t::{
+    hardware::native::cpu::Computer,
+    software::{
+        assembler::assemble,
+        compiler::JackCompiler,
+        compiler_utils::Dialect,
+        vm::translate,
+        vm_interpreter::{VmInterpreter, VmProgram},
+    },
+    LCL, STACK_START, STATIC_START,
+};
 
 pub fn test_data_path(file_path: &str) -> PathBuf {
     match std::env::var("ENV_ROOT_DIR") {
@@ -67,7 +77,7 @@ fn test_convertbin() {
 
     for (jack, vm, target) in paths {
         let path = test_data_path(jack);
-        let _vm = JackCompiler::compile(&path);
+        JackCompiler::compile(&path).unwrap();
 
         let vm_path = test_data_path(vm);
         let mut vm_out = File::open(vm_path).unwrap();
@@ -109,23 +119,23 @@ fn test_square() {
         (
             "./test_files/ch 11/Square/Main.jack",
             "./test_files/ch 11/Square/Main.vm",
-            "./test_files/ch 11/Square/MainTarget.vm",
+            "./test_files/ch 11/SquareTarget/MainTarget.vm",
         ),
         (
             "./test_files/ch 11/Square/Square.jack",
             "./test_files/ch 11/Square/Square.vm",
-            "./test_files/ch 11/Square/SquareTarget.vm",
+            "./test_files/ch 11/SquareTarget/SquareTarget.vm",
         ),
         (
             "./test_files/ch 11/Square/SquareGame.jack",
             "./test_files/ch 11/Square/SquareGame.vm",
-            "./test_files/ch 11/Square/SquareGameTarget.vm",
+            "./test_files/ch 11/SquareTarget/SquareGameTarget.vm",
         ),
     ];
 
     for (jack, vm, target) in paths {
         let path = test_data_path(jack);
-        let _vm = JackCompiler::compile(&path);
+        JackCompiler::compile(&path).unwrap();
 
         let vm_path = test_data_path(vm);
         let mut vm_out = File::open(vm_path).unwrap();
@@ -167,7 +177,7 @@ fn test_average() {
 
     for (jack, vm, target) in paths {
         let path = test_data_path(jack);
-        let _vm = JackCompiler::compile(&path);
+        JackCompiler::compile(&path).unwrap();
 
         let vm_path = test_data_path(vm);
         let mut vm_out = File::open(vm_path).unwrap();
@@ -208,28 +218,28 @@ fn test_pong() {
         (
             "./test_files/ch 11/Pong/Main.jack",
             "./test_files/ch 11/Pong/Main.vm",
-            "./test_files/ch 11/Pong/MainTarget.vm",
+            "./test_files/ch 11/PongTarget/MainTarget.vm",
         ),
         (
             "./test_files/ch 11/Pong/Ball.jack",
             "./test_files/ch 11/Pong/Ball.vm",
-            "./test_files/ch 11/Pong/BallTarget.vm",
+            "./test_files/ch 11/PongTarget/BallTarget.vm",
         ),
         (
             "./test_files/ch 11/Pong/Bat.jack",
             "./test_files/ch 11/Pong/Bat.vm",
-            "./test_files/ch 11/Pong/BatTarget.vm",
+            "./test_files/ch 11/PongTarget/BatTarget.vm",
         ),
         (
             "./test_files/ch 11/Pong/PongGame.jack",
             "./test_files/ch 11/Pong/PongGame.vm",
-            "./test_files/ch 11/Pong/PongGameTarget.vm",
+            "./test_files/ch 11/PongTarget/PongGameTarget.vm",
         ),
     ];
 
     for (jack, vm, target) in paths {
         let path = test_data_path(jack);
-        let _vm = JackCompiler::compile(&path);
+        JackCompiler::compile(&path).unwrap();
 
         let vm_path = test_data_path(vm);
         let mut vm_out = File::open(vm_path).unwrap();
@@ -271,7 +281,7 @@ fn test_complexarrays() {
 
     for (jack, vm, target) in paths {
         let path = test_data_path(jack);
-        let _vm = JackCompiler::compile(&path);
+        JackCompiler::compile(&path).unwrap();
 
         let vm_path = test_data_path(vm);
         let mut vm_out = File::open(vm_path).unwrap();
@@ -302,3 +312,337 @@ fn test_complexarrays() {
         }
     }
 }
+
+#[test]
+/// jack has no operator precedence, chained operators are applied strictly left to right like the
+/// reference compiler does (see the ch 11 compare files)
+fn test_left_to_right() {
+    let dir = std::env::temp_dir().join("LeftToRight");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("Main.jack"),
+        "class Main {
+            static int a, b, c, d;
+
+            function void main() {
+                let a = 10 - 4 - 3;
+                let b = 20 / 5 * 2;
+                let c = 2 + 3 * 4;
+                let d = 1 - 2 + 3 - 4;
+                do Sys.halt();
+                return;
+            }
+        }",
+    )
+    .unwrap();
+
+    JackCompiler::compile(&dir).unwrap();
+    let vm_text = std::fs::read_to_string(dir.join("Main.vm")).unwrap();
+    let program = VmProgram::load(&dir);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let expected = [
+        "push constant 10",
+        "push constant 4",
+        "sub",
+        "push constant 3",
+        "sub",
+        "pop static 0",
+        "push constant 20",
+        "push constant 5",
+        "call Math.divide 2",
+        "push constant 2",
+        "call Math.multiply 2",
+        "pop static 1",
+    ];
+    assert_eq!(vm_text.lines().skip(1).take(12).collect::<Vec<_>>(), expected);
+
+    let mut vm = VmInterpreter::new(program);
+    vm.native_os = true;
+    vm.ram[0] = STACK_START as u16;
+    vm.run_until(1000);
+
+    assert!(vm.halted);
+    let statics: Vec<i16> = vm.ram[STATIC_START..STATIC_START + 4]
+        .iter()
+        .map(|&x| x as i16)
+        .collect();
+    assert_eq!(statics, [3, 8, 20, -2]);
+}
+
+#[test]
+/// extended Jack's loops only use labels and gotos, so they run on the VM like any other program
+fn test_extended_loops() {
+    let dir = std::env::temp_dir().join("ExtendedLoops");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("Main.jack"),
+        "class Main {
+            static int sum, evens, count, last, skipped;
+
+            function void main() {
+                var int i, j;
+
+                for (let i = 0; i < 10; let i = i + 1) {
+                    if (i = 7) { break; }
+                    let sum = sum + i;
+                    if ((i & 1) = 1) { continue; }
+                    let evens = evens + i;
+                }
+
+                let i = 0;
+                while (true) {
+                    let i = i + 1;
+                    if (i > 3) { break; }
+                    for (let j = 0; ; let j = j + 1) {
+                        if (j = i) { break; }
+                        let count = count + 1;
+                    }
+                }
+
+                for (;;) {
+                    let last = i;
+                    break;
+                }
+
+                let j = 0;
+                while (j < 5) {
+                    let j = j + 1;
+                    if (j = 2) { continue; }
+                    let skipped = skipped + j;
+                }
+
+                do Sys.halt();
+                return;
+            }
+        }",
+    )
+    .unwrap();
+
+    JackCompiler::compile_with(&dir, false, Dialect::Extended).unwrap();
+    let program = VmProgram::load(&dir);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let mut vm = VmInterpreter::new(program);
+    vm.native_os = true;
+    vm.ram[0] = STACK_START as u16;
+    // main is entered without a call, its locals go where the frame would have been
+    vm.ram[LCL] = STACK_START as u16;
+    vm.run_until(10_000);
+
+    assert!(vm.halted);
+    let statics: Vec<i16> = vm.ram[STATIC_START..STATIC_START + 5]
+        .iter()
+        .map(|&x| x as i16)
+        .collect();
+    assert_eq!(statics, [21, 12, 6, 4, 13]);
+}
+
+#[test]
+/// constants and character literals compile to plain `push constant`s, and take no static slots
+fn test_extended_constants() {
+    let dir = std::env::temp_dir().join("ExtendedConstants");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("Main.jack"),
+        "class Main {
+            const int LEFT = 130, RIGHT = LEFT + 2;
+            const char ZERO = '0';
+            static int a, b, c, d;
+            const boolean ON = true;
+
+            function void main() {
+                var int RIGHT;
+                var String s;
+                let RIGHT = 7;
+                let a = LEFT + RIGHT;
+                let b = ('7' - ZERO) * 2;
+                if (ON) { let c = -LEFT; }
+                let d = '\\\\';
+                if (false) { let s = \"hi\\\"\\n\"; }
+                do Sys.halt();
+                return;
+            }
+        }",
+    )
+    .unwrap();
+
+    for optimize in [false, true] {
+        JackCompiler::compile_with(&dir, optimize, Dialect::Extended).unwrap();
+        let vm_text = std::fs::read_to_string(dir.join("Main.vm")).unwrap();
+        assert!(vm_text.contains("push constant 130"));
+        // "hi\"\n" is four characters, the last one Jack's newline
+        assert!(vm_text.contains("push constant 4\ncall String.new 1"));
+        assert!(vm_text.contains("push constant 34\ncall String.appendChar 2"));
+        assert!(vm_text.contains("push constant 128\ncall String.appendChar 2"));
+
+        let program = VmProgram::load(&dir);
+        let mut vm = VmInterpreter::new(program);
+        vm.native_os = true;
+        vm.ram[0] = STACK_START as u16;
+        vm.ram[LCL] = STACK_START as u16;
+        vm.run_until(10_000);
+
+        assert!(vm.halted);
+        let statics: Vec<i16> = vm.ram[STATIC_START..STATIC_START + 5]
+            .iter()
+            .map(|&x| x as i16)
+            .collect();
+        assert_eq!(statics, [137, 14, -130, 92, 0]);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+/// errors come back as diagnostics for every file, and a file with errors isn't compiled
+fn test_compile_errors() {
+    let dir = std::env::temp_dir().join(format!("CompileErrors_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("Main.jack"),
+        "class Main {\n    function void main() {\n        let = 1;\n        return;\n    }\n}",
+    )
+    .unwrap();
+    std::fs::write(dir.join("Other.jack"), "class Wrong {\n}").unwrap();
+    std::fs::write(dir.join("Fine.jack"), "class Fine {\n}").unwrap();
+
+    let errors = JackCompiler::compile(&dir).unwrap_err();
+    let compiled = dir.join("Fine.vm").exists();
+    let main_compiled = dir.join("Main.vm").exists();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let mut summaries: Vec<_> = errors.iter().map(|e| e.summary()).collect();
+    summaries.sort();
+    assert_eq!(
+        summaries,
+        [
+            "Main.jack:3:13: expected variable name after 'let', got '='",
+            "Other.jack:1:7: class Wrong has to be in Wrong.jack",
+        ]
+    );
+    assert!(compiled);
+    assert!(!main_compiled);
+}
+
+/// Compiles `main` along with the parts of the Jack OS it needs, runs it on the native `Computer`
+/// until `Main.main` writes 1 to RAM[15100], and returns RAM[15000..15020], the number of cycles
+/// it took, and the VM code of `Main`. `name` is unique to the caller, the process id keeps
+/// parallel runs of the tests apart.
+fn run_on_computer(name: &str, main: &str, optimize: bool) -> (Vec<i16>, usize, String) {
+    let dir = std::env::temp_dir().join(format!("{name}_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("Main.jack"), main).unwrap();
+    // the full OS doesn't fit in 32K of ROM alongside the test, only Math and what it depends on
+    std::fs::write(
+        dir.join("Sys.jack"),
+        "class Sys {
+            function void init() {
+                do Memory.init();
+                do Math.init();
+                do Main.main();
+                while (true) {}
+                return;
+            }
+
+            function void error(int code) {
+                while (true) {}
+                return;
+            }
+        }",
+    )
+    .unwrap();
+    for os in ["Array", "Math", "Memory"] {
+        std::fs::copy(
+            test_data_path(&format!("./test_files/ch 11/Seven/{os}.vm")),
+            dir.join(format!("{os}.vm")),
+        )
+        .unwrap();
+    }
+
+    JackCompiler::compile_opt(&dir, optimize).unwrap();
+    let vm_text = std::fs::read_to_string(dir.join("Main.vm")).unwrap();
+    let asm = translate(&dir, true);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let program = assemble(asm.as_bytes())
+        .lines()
+        .map(|l| u16::from_str_radix(l, 2).unwrap())
+        .collect();
+    let mut cpu = Computer::new(program);
+    while cpu.ram[15100] != 1 {
+        assert!(cpu.time < 5_000_000, "program did not finish");
+        cpu.step(false, false);
+    }
+
+    let results = cpu.ram[15000..15020].iter().map(|&x| x as i16).collect();
+    (results, cpu.time, vm_text)
+}
+
+#[test]
+/// constant folding and strength reduction can't change what a program computes, only how fast
+fn test_optimized_results() {
+    let main = "class Main {
+        function void main() {
+            var Array out;
+            var int x, y, i;
+            let out = 15000;
+            let x = 5;
+            let y = -300;
+
+            let out[0] = 2 * 8;
+            let out[1] = x * 2;
+            let out[2] = 8 * x;
+            let out[3] = y * 16;
+            let out[4] = x * 1 + 0 - 0 / 1;
+            let out[5] = (7 + 3) * (20 - 4) / 4;
+            let out[6] = -(3 - 10) & ~0;
+            let out[7] = (1 < 2) | (3 = 4);
+            let out[8] = 5000 * 256;
+            let out[9] = x * 4096;
+            let out[10] = y / 8;
+            let out[11] = 0 + y * 2 * 2;
+            let out[13] = -32767 - 1;
+            let out[14] = 1 * (x + 1) * 128 - 3;
+            let out[15] = ~true + (x * 0);
+            let out[16] = (y & 32767) / 16;
+            let out[17] = (x + 1000 & 1023) / 8;
+            let out[18] = y / 4;
+            let out[19] = (y & 32767) / 2 / 8192;
+
+            let i = 0;
+            while (i < 50) {
+                let out[12] = out[12] + (i * 4);
+                let i = i + 1;
+            }
+
+            let out[100] = 1;
+            return;
+        }
+    }";
+
+    let (reference, reference_time, reference_vm) =
+        run_on_computer("OptimizedResultsRef", main, false);
+    let (optimized, optimized_time, optimized_vm) =
+        run_on_computer("OptimizedResultsOpt", main, true);
+
+    let expected = [
+        16, 10, 40, -4800, 5, 40, 7, -1, -30720, 20480, -37, -1200, 4900, -32768, 765, 0, 2029,
+        125, -75, 1,
+    ];
+    assert_eq!(reference, expected);
+    assert_eq!(optimized, reference);
+
+    let multiplies = |vm: &str| vm.matches("call Math.multiply").count();
+    assert_eq!(multiplies(&reference_vm), 14);
+    // only x * 4096 and x * 0 are left
+    assert_eq!(multiplies(&optimized_vm), 2);
+    let divides = |vm: &str| vm.matches("call Math.divide").count();
+    assert_eq!(divides(&reference_vm), 8);
+    // y / 8 and y / 4 might be negative, the rest are folded or shifted
+    assert_eq!(divides(&optimized_vm), 2);
+    assert!(
+        optimized_time < reference_time,
+        "{optimized_time} >= {reference_time}"
+    );
+}
+
@@ -0,0 +1,126 @@
+// File name: projects/03/a/PC.tst
+
+load PC.hdl,
+output-file PC.out,
+compare-to PC.cmp,
+output-list time%S0.4.0 in%D0.6.0 reset%B2.1.2 load%B2.1.2 inc%B2.1.2 out%D0.6.0;
+
+set in 0,
+set reset 0,
+set load 0,
+set inc 0,
+tick,
+output;
+
+tock,
+output;
+
+set in 0,
+set reset 0,
+set load 0,
+set inc 1,
+tick,
+output;
+
+tock,
+output;
+
+set in -32123,
+set reset 0,
+set load 0,
+set inc 1,
+tick,
+output;
+
+tock,
+output;
+
+set in -32123,
+set reset 0,
+set load 1,
+set inc 1,
+tick,
+output;
+
+tock,
+output;
+
+set in -32123,
+set reset 0,
+set load 1,
+set inc 0,
+tick,
+output;
+
+tock,
+output;
+
+set in 12345,
+set reset 0,
+set load 1,
+set inc 0,
+tick,
+output;
+
+tock,
+output;
+
+set in 12345,
+set reset 1,
+set load 1,
+set inc 1,
+tick,
+output;
+
+tock,
+output;
+
+set in 12345,
+set reset 0,
+set load 0,
+set inc 1,
+tick,
+output;
+
+tock,
+output;
+
+set in 0,
+set reset 0,
+set load 1,
+set inc 1,
+tick,
+output;
+
+tock,
+output;
+
+set in 0,
+set reset 0,
+set load 0,
+set inc 1,
+tick,
+output;
+
+tock,
+output;
+
+set in 22222,
+set reset 1,
+set load 0,
+set inc 0,
+tick,
+output;
+
+tock,
+output;
+
+set in 22222,
+set reset 0,
+set load 0,
+set inc 1,
+tick,
+output;
+
+tock,
+output;
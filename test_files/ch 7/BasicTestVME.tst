@@ -0,0 +1,23 @@
+// This file is part of www.nand2tetris.org
+// and the book "The Elements of Computing Systems"
+// by Nisan and Schocken, MIT Press.
+// File name: projects/07/MemoryAccess/BasicTest/BasicTestVME.tst
+
+// Tests BasicTest.vm on the VM emulator. Executes pop and push commands using the virtual memory segments.
+
+load BasicTest.vm,
+output-file BasicTest.out,
+compare-to BasicTest.cmp,
+output-list RAM[256]%D1.6.1 RAM[300]%D1.6.1 RAM[401]%D1.6.1 RAM[402]%D1.6.1 RAM[3006]%D1.6.1 RAM[3012]%D1.6.1 RAM[3015]%D1.6.1 RAM[11]%D1.6.1;
+
+set sp 256,
+set local 300,
+set argument 400,
+set this 3000,
+set that 3010;
+
+repeat 25 {  // BasicTest.vm has 25 commands
+  vmstep;
+}
+
+output;
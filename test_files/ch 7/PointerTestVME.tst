@@ -0,0 +1,19 @@
+// This file is part of www.nand2tetris.org
+// and the book "The Elements of Computing Systems"
+// by Nisan and Schocken, MIT Press.
+// File name: projects/07/MemoryAccess/PointerTest/PointerTestVME.tst
+
+// Tests PointerTest.vm on the VM emulator. Executes pop and push commands using the pointer, this, and that segments.
+
+load PointerTest.vm,
+output-file PointerTest.out,
+compare-to PointerTest.cmp,
+output-list RAM[256]%D1.6.1 RAM[3]%D1.6.1 RAM[4]%D1.6.1 RAM[3032]%D1.6.1 RAM[3046]%D1.6.1;
+
+set sp 256;
+
+repeat 15 {  // PointerTest.vm has 15 commands
+  vmstep;
+}
+
+output;
@@ -0,0 +1,19 @@
+// This file is part of www.nand2tetris.org
+// and the book "The Elements of Computing Systems"
+// by Nisan and Schocken, MIT Press.
+// File name: projects/07/StackArithmetic/SimpleAdd/SimpleAddVME.tst
+
+// Tests SimpleAdd.vm on the VM emulator. Pushes and adds two constants.
+
+load SimpleAdd.vm,
+output-file SimpleAdd.out,
+compare-to SimpleAdd.cmp,
+output-list RAM[0]%D2.6.2 RAM[256]%D2.6.2;
+
+set sp 256;
+
+repeat 3 {  // SimpleAdd.vm has 3 commands
+  vmstep;
+}
+
+output;
@@ -0,0 +1,19 @@
+// This file is part of www.nand2tetris.org
+// and the book "The Elements of Computing Systems"
+// by Nisan and Schocken, MIT Press.
+// File name: projects/07/MemoryAccess/StaticTest/StaticTestVME.tst
+
+// Tests StaticTest.vm on the VM emulator. Executes pop and push commands using the static segment.
+
+load StaticTest.vm,
+output-file StaticTest.out,
+compare-to StaticTest.cmp,
+output-list RAM[256]%D1.6.1;
+
+set sp 256;
+
+repeat 11 {  // StaticTest.vm has 11 commands
+  vmstep;
+}
+
+output;